@@ -1,13 +1,83 @@
 use jni::objects::{JClass, JString};
-use jni::sys::jstring;
+use jni::sys::{jboolean, jint, jlong, jstring};
 use jni::JNIEnv;
-use snartnet_core::{CoreService, SqliteStorage};
+use snartnet_core::{
+    AckedItem, ContentPreferences, ContentWarning, CoreService, EncryptedStorage, EncryptionOptions, FeedEntry, FeedQuery, GroupMember,
+    GroupRole, MaybeEncrypted, PostRevisions, ReceiptKind, MessageType, ReceiptPreferences, RelationalStore, SearchIndex, SearchKind, SearchQuery, SignedGroup, SignedInboxAck, SignedMessage, SignedPollTally, SignedPollVote, SignedPost,
+    SignedProfile, SignedSenderKeyDistribution, SqliteStorage,
+};
 use std::sync::{Mutex, OnceLock};
 
-static CORE: OnceLock<Mutex<CoreService<SqliteStorage>>> = OnceLock::new();
+/// The app database, encrypted or not.
+type Storage = MaybeEncrypted<SqliteStorage>;
 
-fn core() -> &'static Mutex<CoreService<SqliteStorage>> {
-    CORE.get_or_init(|| Mutex::new(CoreService::<SqliteStorage>::new()))
+/// The service over the app database; set by `nativeInit`.
+static CORE: OnceLock<Mutex<CoreService<Storage>>> = OnceLock::new();
+
+fn core() -> Result<&'static Mutex<CoreService<Storage>>, String> {
+    CORE.get().ok_or_else(|| "core not initialized; call nativeInit first".to_string())
+}
+
+/// Full-text index, kept in the app database, or in memory while the
+/// database is encrypted; set by `nativeInit`.
+static SEARCH: OnceLock<Mutex<SearchIndex>> = OnceLock::new();
+
+/// Bring the search index in line with the feed and own profile. A missing
+/// index (init not called yet) is not an error.
+fn reindex(svc: &CoreService<Storage>) -> Result<(), String> {
+    let Some(search) = SEARCH.get() else {
+        return Ok(());
+    };
+    let index = search.lock().map_err(|e| format!("lock failed: {e}"))?;
+    svc.reindex_search(&index).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Run `update` against the search index, if there is one yet.
+fn with_search<T>(update: impl FnOnce(&SearchIndex) -> Result<T, String>) -> Result<(), String> {
+    let Some(search) = SEARCH.get() else {
+        return Ok(());
+    };
+    let index = search.lock().map_err(|e| format!("lock failed: {e}"))?;
+    update(&index).map(|_| ())
+}
+
+/// Index a direct or group message we sent or accepted under its plaintext.
+/// Edits replace the text of their target and unsends drop it; other
+/// controls and receipts carry nothing worth finding.
+fn index_message(svc: &CoreService<Storage>, message: &SignedMessage) -> Result<(), String> {
+    let m = &message.message;
+    let mut indexed = m.clone();
+    match &m.message_type {
+        MessageType::Unsend { target_id } => {
+            return with_search(|index| index.remove(SearchKind::Message, &m.conversation_id(), target_id));
+        }
+        MessageType::Edit { target_id } => indexed.id = target_id.clone(),
+        _ if m.is_control() => return Ok(()),
+        _ => {}
+    }
+    let plaintext = if m.is_group() {
+        svc.open_group_message(message)
+    } else {
+        svc.open_message(message)
+    };
+    match plaintext {
+        Ok(plaintext) => with_search(|index| index.index_message(&indexed, &plaintext)),
+        // Not readable (yet); nothing to index.
+        Err(_) => Ok(()),
+    }
+}
+
+/// A poll post from the feed, with its author's public key.
+fn feed_poll(svc: &CoreService<Storage>, author: &str, post_id: &str) -> Result<FeedEntry, String> {
+    let entry = svc
+        .feed()
+        .get(author, post_id)
+        .ok_or_else(|| format!("no post with id {post_id}"))?;
+    if entry.post.post.poll.is_none() {
+        return Err(format!("post {post_id} is not a poll"));
+    }
+    Ok(entry.clone())
 }
 
 fn get_string(env: &mut JNIEnv, input: JString) -> Result<String, String> {
@@ -48,18 +118,98 @@ fn err_json(message: String) -> String {
     .to_string()
 }
 
+/// Open the database at `path` and make it the current service, replacing
+/// any earlier one along with its search index.
+fn install(path: &str, passphrase: Option<&str>) -> Result<(), String> {
+    let storage = MaybeEncrypted::open(SqliteStorage::open(path).map_err(|e| e.to_string())?, passphrase)
+        .map_err(|e| e.to_string())?;
+    // Indexed text and rows of encrypted data must not reach the disk
+    // unencrypted.
+    let (index, records) = if storage.is_encrypted() {
+        (SearchIndex::open_in_memory()?, RelationalStore::open_in_memory()?)
+    } else {
+        (SearchIndex::open(path)?, RelationalStore::open(path)?)
+    };
+    let mut fresh = CoreService::new(storage);
+    fresh.init().map_err(|e| e.to_string())?;
+    fresh.attach_records(records).map_err(|e| e.to_string())?;
+    // Initialising again, e.g. with another database, replaces the service.
+    if let Err(fresh) = CORE.set(Mutex::new(fresh)) {
+        let fresh = fresh.into_inner().map_err(|e| format!("lock failed: {e}"))?;
+        *core()?.lock().map_err(|e| format!("lock failed: {e}"))? = fresh;
+    }
+    if let Err(index) = SEARCH.set(Mutex::new(index)) {
+        let index = index.into_inner().map_err(|e| format!("lock failed: {e}"))?;
+        *SEARCH.get().ok_or("search index not initialized")?.lock().map_err(|e| format!("lock failed: {e}"))? = index;
+    }
+    let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+    reindex(&svc)
+}
+
+/// `passphrase` unlocks an encrypted database; pass an empty string for a
+/// plain one.
 #[no_mangle]
 pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeInit(
     mut env: JNIEnv,
     _class: JClass,
     db_path: JString,
+    passphrase: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let path = get_string(&mut env, db_path)?;
+        let passphrase = get_string(&mut env, passphrase)?;
+        install(&path, optional_text(passphrase).as_deref())?;
+        let encrypted = core()?.lock().map_err(|e| format!("lock failed: {e}"))?.storage().is_encrypted();
+        Ok(ok_json(serde_json::json!({ "initialized": true, "encrypted": encrypted })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Encrypt the plain database at `db_path` in place, drop its on-disk search
+/// index and reopen it unlocked.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeEncryptStorage(
+    mut env: JNIEnv,
+    _class: JClass,
+    db_path: JString,
+    passphrase: JString,
 ) -> jstring {
     let result = (|| -> Result<String, String> {
         let path = get_string(&mut env, db_path)?;
-        SqliteStorage::open(&path).map_err(|e| e.to_string())?;
-        let mut svc = core().lock().map_err(|e| format!("lock failed: {e}"))?;
-        svc.init().map_err(|e| e.to_string())?;
-        Ok(ok_json(serde_json::json!({ "initialized": true })))
+        let passphrase = get_string(&mut env, passphrase)?;
+        let plain = SqliteStorage::open(&path).map_err(|e| e.to_string())?;
+        if EncryptedStorage::is_initialized(&plain).map_err(|e| e.to_string())? {
+            return Err("storage is already encrypted".to_string());
+        }
+        let keys = plain.keys().map_err(|e| e.to_string())?;
+        let store = EncryptedStorage::create(plain, &passphrase, EncryptionOptions::default()).map_err(|e| e.to_string())?;
+        let converted = store.encrypt_existing(&keys).map_err(|e| e.to_string())?;
+        drop(store);
+        // Vacuuming also clears the pages that held the plaintext values.
+        RelationalStore::erase(&path)?;
+        SearchIndex::erase(&path)?;
+        install(&path, Some(&passphrase))?;
+        Ok(ok_json(serde_json::json!({ "encrypted": converted })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeChangePassphrase(
+    mut env: JNIEnv,
+    _class: JClass,
+    new_passphrase: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let new_passphrase = get_string(&mut env, new_passphrase)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let MaybeEncrypted::Encrypted(store) = svc.storage() else {
+            return Err("storage is not encrypted".to_string());
+        };
+        store.change_passphrase(&new_passphrase).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "changed": true })))
     })();
 
     make_jstring(&mut env, &result.unwrap_or_else(err_json))
@@ -78,11 +228,12 @@ pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreateProfil
         let display_name = optional_text(get_string(&mut env, display_name)?);
         let bio = optional_text(get_string(&mut env, bio)?);
 
-        let mut svc = core().lock().map_err(|e| format!("lock failed: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
         let magnet_uri = svc
             .create_profile(&username, display_name, bio)
             .map_err(|e| e.to_string())?;
         let profile = svc.get_profile().ok_or("profile missing after creation")?;
+        reindex(&svc)?;
 
         Ok(ok_json(serde_json::json!({
             "magnetUri": magnet_uri,
@@ -99,7 +250,7 @@ pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeGetProfileJs
     _class: JClass,
 ) -> jstring {
     let result = (|| -> Result<String, String> {
-        let svc = core().lock().map_err(|e| format!("lock failed: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
         match svc.get_profile() {
             Some(profile) => Ok(ok_json(serde_json::to_value(profile).map_err(|e| e.to_string())?)),
             None => Ok(ok_json(serde_json::json!({ "profile": null }))),
@@ -117,10 +268,38 @@ pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreatePost(
 ) -> jstring {
     let result = (|| -> Result<String, String> {
         let content = get_string(&mut env, content)?;
-        let svc = core().lock().map_err(|e| format!("lock failed: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
         let post = svc
             .create_post(&content, None, None)
             .map_err(|e| e.to_string())?;
+        svc.record_own_posts(vec![post.clone()], PostRevisions::new())
+            .map_err(|e| e.to_string())?;
+        reindex(&svc)?;
+        Ok(ok_json(serde_json::to_value(post).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `warning_json` is a `ContentWarning` (`summary` and/or `categories`).
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreatePostWithWarning(
+    mut env: JNIEnv,
+    _class: JClass,
+    content: JString,
+    warning_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let content = get_string(&mut env, content)?;
+        let warning: ContentWarning = serde_json::from_str(&get_string(&mut env, warning_json)?)
+            .map_err(|e| format!("invalid content warning: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let post = svc
+            .create_post_with_warning(&content, None, None, warning)
+            .map_err(|e| e.to_string())?;
+        svc.record_own_posts(vec![post.clone()], PostRevisions::new())
+            .map_err(|e| e.to_string())?;
+        reindex(&svc)?;
         Ok(ok_json(serde_json::to_value(post).map_err(|e| e.to_string())?))
     })();
 
@@ -138,12 +317,946 @@ pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreateMessag
         let recipient_fingerprint = get_string(&mut env, recipient_fingerprint)?;
         let content = get_string(&mut env, content)?;
 
-        let svc = core().lock().map_err(|e| format!("lock failed: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
         let msg = svc
             .create_message(&recipient_fingerprint, &content)
             .map_err(|e| e.to_string())?;
+        svc.record_sent_message(&msg).map_err(|e| e.to_string())?;
+        index_message(&svc, &msg)?;
+        Ok(ok_json(serde_json::to_value(msg).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreatePlaintextMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    recipient_fingerprint: JString,
+    content: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let recipient_fingerprint = get_string(&mut env, recipient_fingerprint)?;
+        let content = get_string(&mut env, content)?;
+
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let msg = svc
+            .create_plaintext_message(&recipient_fingerprint, &content)
+            .map_err(|e| e.to_string())?;
+        svc.record_sent_message(&msg).map_err(|e| e.to_string())?;
+        index_message(&svc, &msg)?;
         Ok(ok_json(serde_json::to_value(msg).map_err(|e| e.to_string())?))
     })();
 
     make_jstring(&mut env, &result.unwrap_or_else(err_json))
 }
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeOpenMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let message: SignedMessage = serde_json::from_str(&get_string(&mut env, message_json)?)
+            .map_err(|e| format!("invalid message: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let plaintext = svc.open_message(&message).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "content": plaintext })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeOrderThread(
+    mut env: JNIEnv,
+    _class: JClass,
+    messages_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let mut messages: Vec<SignedMessage> = serde_json::from_str(&get_string(&mut env, messages_json)?)
+            .map_err(|e| format!("invalid messages: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let missing = svc.order_thread(&mut messages);
+        Ok(ok_json(serde_json::json!({ "messages": messages, "missing": missing })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeLearnContact(
+    mut env: JNIEnv,
+    _class: JClass,
+    profile_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let profile: SignedProfile = serde_json::from_str(&get_string(&mut env, profile_json)?)
+            .map_err(|e| format!("invalid profile: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc.learn_contact(&profile).map_err(|e| e.to_string())?;
+        with_search(|index| index.index_profile(&profile))?;
+        Ok(ok_json(serde_json::json!({ "changed": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeSetPetname(
+    mut env: JNIEnv,
+    _class: JClass,
+    fingerprint: JString,
+    petname: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let fingerprint = get_string(&mut env, fingerprint)?;
+        let petname = optional_text(get_string(&mut env, petname)?);
+
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        svc.set_petname(&fingerprint, petname)
+            .map_err(|e| e.to_string())?;
+        let resolved = svc.resolve_name(&fingerprint);
+        Ok(ok_json(serde_json::to_value(resolved).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeResolveName(
+    mut env: JNIEnv,
+    _class: JClass,
+    fingerprint: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let fingerprint = get_string(&mut env, fingerprint)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let resolved = svc.resolve_name(&fingerprint);
+        Ok(ok_json(serde_json::to_value(resolved).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeParseMarkup(
+    mut env: JNIEnv,
+    _class: JClass,
+    content: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let content = get_string(&mut env, content)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let document = svc.parse_markup(&content);
+        Ok(ok_json(serde_json::to_value(document).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Merge a contact's posts blob (`{"posts": [...], "revisions": {...}}`)
+/// into the feed.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeIngestPosts(
+    mut env: JNIEnv,
+    _class: JClass,
+    author_public_key: JString,
+    posts_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let author_public_key = get_string(&mut env, author_public_key)?;
+        let mut blob: serde_json::Value = serde_json::from_str(&get_string(&mut env, posts_json)?)
+            .map_err(|e| format!("invalid posts JSON: {e}"))?;
+        let posts: Vec<SignedPost> = serde_json::from_value(blob["posts"].take())
+            .map_err(|e| format!("invalid posts JSON: {e}"))?;
+        let revisions: PostRevisions = match blob["revisions"].take() {
+            serde_json::Value::Null => PostRevisions::new(),
+            value => serde_json::from_value(value).map_err(|e| format!("invalid revisions JSON: {e}"))?,
+        };
+
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let added = svc
+            .ingest_posts(&author_public_key, posts, revisions)
+            .map_err(|e| e.to_string())?;
+        reindex(&svc)?;
+        Ok(ok_json(serde_json::json!({ "added": added })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `query_json` is a `FeedQuery`; an empty string means the first page.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeFeedPage(
+    mut env: JNIEnv,
+    _class: JClass,
+    query_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let query: FeedQuery = match optional_text(get_string(&mut env, query_json)?) {
+            Some(json) => serde_json::from_str(&json).map_err(|e| format!("invalid feed query: {e}"))?,
+            None => FeedQuery::default(),
+        };
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let page = svc.feed_page(&query).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(page).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeGetContentPreferences(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        Ok(ok_json(serde_json::to_value(svc.content_preferences()).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `preferences_json` is a `ContentPreferences`.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeSetContentPreferences(
+    mut env: JNIEnv,
+    _class: JClass,
+    preferences_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let preferences: ContentPreferences = serde_json::from_str(&get_string(&mut env, preferences_json)?)
+            .map_err(|e| format!("invalid content preferences: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        svc.set_content_preferences(preferences).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(svc.content_preferences()).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeMarkPostRead(
+    mut env: JNIEnv,
+    _class: JClass,
+    author_fingerprint: JString,
+    post_id: JString,
+    read: jboolean,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let author_fingerprint = get_string(&mut env, author_fingerprint)?;
+        let post_id = get_string(&mut env, post_id)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let found = svc
+            .mark_post_read(&author_fingerprint, &post_id, read != 0)
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "found": found })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeMarkAllRead(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc.mark_all_read().map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "changed": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `query_json` is a `SearchQuery`, e.g. `{"text": "heron", "kinds": ["post"]}`.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeSearch(
+    mut env: JNIEnv,
+    _class: JClass,
+    query_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let query: SearchQuery = serde_json::from_str(&get_string(&mut env, query_json)?)
+            .map_err(|e| format!("invalid search query: {e}"))?;
+        let search = SEARCH.get().ok_or("search index not initialized")?;
+        let index = search.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let hits = index.search(&query)?;
+        Ok(ok_json(serde_json::to_value(hits).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `options_json` is a JSON array of option strings.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreatePoll(
+    mut env: JNIEnv,
+    _class: JClass,
+    question: JString,
+    options_json: JString,
+    closes_in_hours: jint,
+    multiple_choice: jboolean,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let question = get_string(&mut env, question)?;
+        let options: Vec<String> = serde_json::from_str(&get_string(&mut env, options_json)?)
+            .map_err(|e| format!("invalid poll options: {e}"))?;
+        let closes_at = chrono::Utc::now() + chrono::Duration::hours(closes_in_hours.into());
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let post = svc
+            .create_poll(&question, options, closes_at, multiple_choice != 0)
+            .map_err(|e| e.to_string())?;
+        svc.record_own_posts(vec![post.clone()], PostRevisions::new())
+            .map_err(|e| e.to_string())?;
+        reindex(&svc)?;
+        Ok(ok_json(serde_json::to_value(post).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `choices_json` is a JSON array of zero-based option indexes. The returned
+/// vote should be delivered to the poll author.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeVotePoll(
+    mut env: JNIEnv,
+    _class: JClass,
+    author_fingerprint: JString,
+    post_id: JString,
+    choices_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let author_fingerprint = get_string(&mut env, author_fingerprint)?;
+        let post_id = get_string(&mut env, post_id)?;
+        let choices: Vec<usize> = serde_json::from_str(&get_string(&mut env, choices_json)?)
+            .map_err(|e| format!("invalid choices: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let poll = feed_poll(&svc, &author_fingerprint, &post_id)?;
+        let vote = svc.vote_in_poll(&poll.post, &choices).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(vote).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `vote_json` is a `SignedPollVote` from the inbox or a published feed.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeReceivePollVote(
+    mut env: JNIEnv,
+    _class: JClass,
+    vote_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let vote: SignedPollVote = serde_json::from_str(&get_string(&mut env, vote_json)?)
+            .map_err(|e| format!("invalid poll vote: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let poll = feed_poll(&svc, &vote.vote.poll_author_fingerprint, &vote.vote.poll_id)?;
+        let changed = svc.receive_poll_vote(&poll.post, vote).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "changed": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativePollResults(
+    mut env: JNIEnv,
+    _class: JClass,
+    author_fingerprint: JString,
+    post_id: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let author_fingerprint = get_string(&mut env, author_fingerprint)?;
+        let post_id = get_string(&mut env, post_id)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let poll = feed_poll(&svc, &author_fingerprint, &post_id)?;
+        let mine = svc
+            .get_fingerprint()
+            .and_then(|fp| svc.poll_votes().vote_of(&author_fingerprint, &post_id, fp))
+            .map(|v| v.vote.choices.clone());
+        Ok(ok_json(serde_json::json!({
+            "results": svc.poll_results(&poll.post),
+            "myChoices": mine,
+        })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Sign a tally of one of the user's own polls, to be published.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeTallyPoll(
+    mut env: JNIEnv,
+    _class: JClass,
+    post_id: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let post_id = get_string(&mut env, post_id)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let own = svc.get_fingerprint().ok_or("no identity loaded")?.to_string();
+        let poll = feed_poll(&svc, &own, &post_id)?;
+        let tally = svc.tally_poll(&poll.post).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(tally).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `tally_json` is a `SignedPollTally` published by the poll author.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCheckPollTally(
+    mut env: JNIEnv,
+    _class: JClass,
+    tally_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let tally: SignedPollTally = serde_json::from_str(&get_string(&mut env, tally_json)?)
+            .map_err(|e| format!("invalid poll tally: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let poll = feed_poll(&svc, &tally.tally.author_fingerprint, &tally.tally.poll_id)?;
+        let check = svc
+            .check_poll_tally(&poll.post, &tally, &poll.author_public_key)
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "check": check, "label": check.label() })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeCreateGroup(
+    mut env: JNIEnv,
+    _class: JClass,
+    name: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let name = get_string(&mut env, name)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let group = svc.create_group(&name).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(group).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeListGroups(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        Ok(ok_json(serde_json::to_value(&svc.groups().groups).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Every version of a group, oldest first, for delivery to a new member.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeGroupChain(
+    mut env: JNIEnv,
+    _class: JClass,
+    group_id: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let group_id = get_string(&mut env, group_id)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let chain = svc.groups().chain(&group_id);
+        Ok(ok_json(serde_json::to_value(chain).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Returns the signed group update, which must be delivered to every member.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeAddGroupMember(
+    mut env: JNIEnv,
+    _class: JClass,
+    group_id: JString,
+    public_key: JString,
+    encryption_public_key: JString,
+    admin: jboolean,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let group_id = get_string(&mut env, group_id)?;
+        let public_key = get_string(&mut env, public_key)?;
+        let encryption_public_key = get_string(&mut env, encryption_public_key)?;
+        let role = if admin != 0 { GroupRole::Admin } else { GroupRole::Member };
+        let member = GroupMember::new(public_key, encryption_public_key, role)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let update = svc.add_group_member(&group_id, member).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(update).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeRemoveGroupMember(
+    mut env: JNIEnv,
+    _class: JClass,
+    group_id: JString,
+    fingerprint: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let group_id = get_string(&mut env, group_id)?;
+        let fingerprint = get_string(&mut env, fingerprint)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let update = svc
+            .remove_group_member(&group_id, &fingerprint)
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(update).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeLeaveGroup(
+    mut env: JNIEnv,
+    _class: JClass,
+    group_id: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let group_id = get_string(&mut env, group_id)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let update = svc.leave_group(&group_id).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(update).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `update_json` is a `SignedGroup` received from another member.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeReceiveGroupUpdate(
+    mut env: JNIEnv,
+    _class: JClass,
+    update_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let update: SignedGroup = serde_json::from_str(&get_string(&mut env, update_json)?)
+            .map_err(|e| format!("invalid group update: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc.receive_group_update(update).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "changed": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Returns `{message, distribution, recipients}`; the caller delivers the
+/// message (and the sender key distribution, when present) to each recipient.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeSendGroupMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    group_id: JString,
+    content: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let group_id = get_string(&mut env, group_id)?;
+        let content = get_string(&mut env, content)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let send = svc
+            .create_group_message(&group_id, &content)
+            .map_err(|e| e.to_string())?;
+        with_search(|index| index.index_message(&send.message.message, &content))?;
+        Ok(ok_json(serde_json::to_value(send).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `distribution_json` is a `SignedSenderKeyDistribution` from the inbox.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeReceiveSenderKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    distribution_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let distribution: SignedSenderKeyDistribution =
+            serde_json::from_str(&get_string(&mut env, distribution_json)?)
+                .map_err(|e| format!("invalid sender key: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc.receive_sender_key(&distribution).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "changed": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeOpenGroupMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let message: SignedMessage = serde_json::from_str(&get_string(&mut env, message_json)?)
+            .map_err(|e| format!("invalid message: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let content = svc.open_group_message(&message).map_err(|e| e.to_string())?;
+        with_search(|index| index.index_message(&message.message, &content))?;
+        Ok(ok_json(serde_json::json!({ "content": content })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `kind` is "delivered" or "read"; `message_ids_json` lists ids received
+/// from `sender_fingerprint`. Returns the receipt to push to the sender's
+/// inbox, or null when there is nothing to acknowledge.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeAcknowledgeMessages(
+    mut env: JNIEnv,
+    _class: JClass,
+    sender_fingerprint: JString,
+    kind: JString,
+    message_ids_json: JString,
+    sender_encryption_public_key: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let sender_fingerprint = get_string(&mut env, sender_fingerprint)?;
+        let kind: ReceiptKind = serde_json::from_value(serde_json::Value::String(get_string(&mut env, kind)?))
+            .map_err(|e| format!("invalid receipt kind: {e}"))?;
+        let message_ids: Vec<String> = serde_json::from_str(&get_string(&mut env, message_ids_json)?)
+            .map_err(|e| format!("invalid message ids: {e}"))?;
+        let sender_encryption_public_key = optional_text(get_string(&mut env, sender_encryption_public_key)?);
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let receipt = svc
+            .acknowledge_messages(&sender_fingerprint, kind, &message_ids, sender_encryption_public_key.as_deref())
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(receipt).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `receipt_json` is a receipt `SignedMessage` from the inbox.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeReceiveMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_json: JString,
+    sender_public_key: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let message: SignedMessage = serde_json::from_str(&get_string(&mut env, message_json)?)
+            .map_err(|e| format!("invalid message: {e}"))?;
+        let sender_public_key = get_string(&mut env, sender_public_key)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        svc.receive_message(&message, &sender_public_key)
+            .map_err(|e| e.to_string())?;
+        index_message(&svc, &message)?;
+        Ok(ok_json(serde_json::json!({ "accepted": true, "messageId": message.message.id })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Sign an inbox acknowledgement for `items_json`, a list of
+/// `{sender_fingerprint, id}`, to publish with our inbox so relays delete
+/// those entries.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeAcknowledgeInbox(
+    mut env: JNIEnv,
+    _class: JClass,
+    items_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let items: Vec<AckedItem> = serde_json::from_str(&get_string(&mut env, items_json)?)
+            .map_err(|e| format!("invalid inbox items: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let ack = svc.acknowledge_inbox(&items).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(ack).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeReceiveReceipt(
+    mut env: JNIEnv,
+    _class: JClass,
+    receipt_json: JString,
+    sender_public_key: JString,
+    sender_encryption_public_key: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let receipt: SignedMessage = serde_json::from_str(&get_string(&mut env, receipt_json)?)
+            .map_err(|e| format!("invalid receipt: {e}"))?;
+        let sender_public_key = get_string(&mut env, sender_public_key)?;
+        let sender_encryption_public_key = optional_text(get_string(&mut env, sender_encryption_public_key)?);
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc
+            .receive_receipt(&receipt, &sender_public_key, sender_encryption_public_key.as_deref())
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "changed": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeMessageStatus(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_id: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let message_id = get_string(&mut env, message_id)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let status = svc.message_status(&message_id);
+        Ok(ok_json(serde_json::json!({
+            "status": status,
+            "label": status.map(|s| s.label()),
+        })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Every outbox item with its state, for showing delivery progress.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeOutbox(mut env: JNIEnv, _class: JClass) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let items: Vec<serde_json::Value> = svc
+            .outbox()
+            .items()
+            .iter()
+            .map(|item| {
+                serde_json::json!({
+                    "id": item.id(),
+                    "destination": item.payload.destination(),
+                    "state": item.state,
+                    "label": item.state.label(),
+                    "attempts": item.attempts,
+                    "nextAttemptAt": item.next_attempt_at,
+                    "lastError": item.last_error,
+                })
+            })
+            .collect();
+        Ok(ok_json(serde_json::json!({ "items": items, "queued": svc.outbox().queued() })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// Payloads to push now; report each with `nativeRecordOutboxPush`.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeOutboxDue(mut env: JNIEnv, _class: JClass) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        Ok(ok_json(serde_json::to_value(svc.outbox_due()).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeQueueMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let message: SignedMessage = serde_json::from_str(&get_string(&mut env, message_json)?)
+            .map_err(|e| format!("invalid message: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let added = svc.queue_message(&message).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "queued": added })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeQueuePost(
+    mut env: JNIEnv,
+    _class: JClass,
+    post_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let post: SignedPost = serde_json::from_str(&get_string(&mut env, post_json)?)
+            .map_err(|e| format!("invalid post: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let added = svc.queue_post(&post).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "queued": added })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `peers` is how many peers took the item; a non-empty `error` means it
+/// could not be written at all.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeRecordOutboxPush(
+    mut env: JNIEnv,
+    _class: JClass,
+    item_id: JString,
+    peers: jint,
+    error: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let item_id = get_string(&mut env, item_id)?;
+        let outcome = match optional_text(get_string(&mut env, error)?) {
+            Some(error) => Err(error),
+            None => Ok(peers.max(0) as usize),
+        };
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        svc.record_outbox_push(&item_id, outcome).map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "state": svc.outbox().state(&item_id) })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeOutboxPeersAppeared(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        svc.outbox_peers_appeared().map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "due": svc.outbox_due().len() })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `acks_json` is the `acks` list of the recipient's inbox blob.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeApplyInboxAcksToOutbox(
+    mut env: JNIEnv,
+    _class: JClass,
+    recipient_fingerprint: JString,
+    acks_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let recipient_fingerprint = get_string(&mut env, recipient_fingerprint)?;
+        let acks: Vec<SignedInboxAck> = serde_json::from_str(&get_string(&mut env, acks_json)?)
+            .map_err(|e| format!("invalid inbox acks: {e}"))?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc
+            .apply_inbox_acks_to_outbox(&recipient_fingerprint, &acks)
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::json!({ "acknowledged": changed })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeSetReadReceipts(
+    mut env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        svc.set_receipt_preferences(ReceiptPreferences { send_read_receipts: enabled != 0 })
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(svc.receipt_preferences()).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `original_json` is one of the user's own sent `SignedMessage`s. Returns
+/// the edit to push to the recipient's inbox.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeEditMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    original_json: JString,
+    content: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let original: SignedMessage = serde_json::from_str(&get_string(&mut env, original_json)?)
+            .map_err(|e| format!("invalid message: {e}"))?;
+        let content = get_string(&mut env, content)?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let edit = svc.edit_message(&original, &content).map_err(|e| e.to_string())?;
+        // Searchable by the new text under the original id.
+        with_search(|index| index.index_message(&original.message, &content))?;
+        Ok(ok_json(serde_json::to_value(edit).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeUnsendMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    original_json: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let original: SignedMessage = serde_json::from_str(&get_string(&mut env, original_json)?)
+            .map_err(|e| format!("invalid message: {e}"))?;
+        let svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let unsend = svc.unsend_message(&original).map_err(|e| e.to_string())?;
+        let conversation = original.message.conversation_id();
+        with_search(|index| index.remove(SearchKind::Message, &conversation, &original.message.id))?;
+        Ok(ok_json(serde_json::to_value(unsend).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+/// `ttl_secs` of zero or less turns the user's side of the timer off.
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeSetMessageTimer(
+    mut env: JNIEnv,
+    _class: JClass,
+    peer_fingerprint: JString,
+    ttl_secs: jlong,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let peer_fingerprint = get_string(&mut env, peer_fingerprint)?;
+        let ttl_secs = (ttl_secs > 0).then_some(ttl_secs as u64);
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let control = svc
+            .set_message_timer(&peer_fingerprint, ttl_secs)
+            .map_err(|e| e.to_string())?;
+        Ok(ok_json(serde_json::to_value(control).map_err(|e| e.to_string())?))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_com_snartnet_android_NativeBridge_nativeReceiveMessageTimer(
+    mut env: JNIEnv,
+    _class: JClass,
+    control_json: JString,
+    sender_public_key: JString,
+) -> jstring {
+    let result = (|| -> Result<String, String> {
+        let control: SignedMessage = serde_json::from_str(&get_string(&mut env, control_json)?)
+            .map_err(|e| format!("invalid timer: {e}"))?;
+        let sender_public_key = get_string(&mut env, sender_public_key)?;
+        let mut svc = core()?.lock().map_err(|e| format!("lock failed: {e}"))?;
+        let changed = svc
+            .receive_message_timer(&control, &sender_public_key)
+            .map_err(|e| e.to_string())?;
+        let ttl_secs = svc
+            .message_timer(&control.message.sender_fingerprint)
+            .map(|ttl| ttl.num_seconds());
+        Ok(ok_json(serde_json::json!({ "changed": changed, "ttlSecs": ttl_secs })))
+    })();
+
+    make_jstring(&mut env, &result.unwrap_or_else(err_json))
+}
@@ -26,7 +26,7 @@ class MainActivity : AppCompatActivity() {
 
         initBtn.setOnClickListener {
             val dbPath = File(filesDir, "snartnet_android.db").absolutePath
-            output.text = NativeBridge.nativeInit(dbPath)
+            output.text = NativeBridge.nativeInit(dbPath, "")
         }
 
         profileBtn.setOnClickListener {
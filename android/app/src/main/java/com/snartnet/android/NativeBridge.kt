@@ -5,9 +5,59 @@ object NativeBridge {
         System.loadLibrary("snartnet_android_bridge")
     }
 
-    external fun nativeInit(dbPath: String): String
+    external fun nativeInit(dbPath: String, passphrase: String): String
+    external fun nativeEncryptStorage(dbPath: String, passphrase: String): String
+    external fun nativeChangePassphrase(newPassphrase: String): String
     external fun nativeCreateProfile(username: String, displayName: String, bio: String): String
     external fun nativeGetProfileJson(): String
     external fun nativeCreatePost(content: String): String
+    external fun nativeCreatePostWithWarning(content: String, warningJson: String): String
     external fun nativeCreateMessage(recipientFingerprint: String, content: String): String
+    external fun nativeCreatePlaintextMessage(recipientFingerprint: String, content: String): String
+    external fun nativeOpenMessage(messageJson: String): String
+    external fun nativeLearnContact(profileJson: String): String
+    external fun nativeOrderThread(messagesJson: String): String
+    external fun nativeSetPetname(fingerprint: String, petname: String): String
+    external fun nativeResolveName(fingerprint: String): String
+    external fun nativeParseMarkup(content: String): String
+    external fun nativeIngestPosts(authorPublicKey: String, postsJson: String): String
+    external fun nativeFeedPage(queryJson: String): String
+    external fun nativeGetContentPreferences(): String
+    external fun nativeSetContentPreferences(preferencesJson: String): String
+    external fun nativeMarkPostRead(authorFingerprint: String, postId: String, read: Boolean): String
+    external fun nativeMarkAllRead(): String
+    external fun nativeSearch(queryJson: String): String
+    external fun nativeCreatePoll(question: String, optionsJson: String, closesInHours: Int, multipleChoice: Boolean): String
+    external fun nativeVotePoll(authorFingerprint: String, postId: String, choicesJson: String): String
+    external fun nativeReceivePollVote(voteJson: String): String
+    external fun nativePollResults(authorFingerprint: String, postId: String): String
+    external fun nativeTallyPoll(postId: String): String
+    external fun nativeCheckPollTally(tallyJson: String): String
+    external fun nativeCreateGroup(name: String): String
+    external fun nativeListGroups(): String
+    external fun nativeGroupChain(groupId: String): String
+    external fun nativeAddGroupMember(groupId: String, publicKey: String, encryptionPublicKey: String, admin: Boolean): String
+    external fun nativeRemoveGroupMember(groupId: String, fingerprint: String): String
+    external fun nativeLeaveGroup(groupId: String): String
+    external fun nativeReceiveGroupUpdate(updateJson: String): String
+    external fun nativeSendGroupMessage(groupId: String, content: String): String
+    external fun nativeReceiveSenderKey(distributionJson: String): String
+    external fun nativeOpenGroupMessage(messageJson: String): String
+    external fun nativeAcknowledgeMessages(senderFingerprint: String, kind: String, messageIdsJson: String, senderEncryptionPublicKey: String): String
+    external fun nativeReceiveMessage(messageJson: String, senderPublicKey: String): String
+    external fun nativeAcknowledgeInbox(itemsJson: String): String
+    external fun nativeReceiveReceipt(receiptJson: String, senderPublicKey: String, senderEncryptionPublicKey: String): String
+    external fun nativeMessageStatus(messageId: String): String
+    external fun nativeOutbox(): String
+    external fun nativeOutboxDue(): String
+    external fun nativeQueueMessage(messageJson: String): String
+    external fun nativeQueuePost(postJson: String): String
+    external fun nativeRecordOutboxPush(itemId: String, peers: Int, error: String): String
+    external fun nativeOutboxPeersAppeared(): String
+    external fun nativeApplyInboxAcksToOutbox(recipientFingerprint: String, acksJson: String): String
+    external fun nativeSetReadReceipts(enabled: Boolean): String
+    external fun nativeEditMessage(originalJson: String, content: String): String
+    external fun nativeUnsendMessage(originalJson: String): String
+    external fun nativeSetMessageTimer(peerFingerprint: String, ttlSecs: Long): String
+    external fun nativeReceiveMessageTimer(controlJson: String, senderPublicKey: String): String
 }
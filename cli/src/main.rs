@@ -1,11 +1,36 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use snartnet_core::{
+    ContentPreferences,
+    ContentWarning,
+    EncryptedStorage,
+    EncryptionOptions,
+    Expiring,
+    Feed,
+    FeedQuery,
+    FileStorage,
     KeyPair,
+    MaybeEncrypted,
+    PetnameBook,
+    Poll,
+    PollVote,
+    PollVotes,
     Post,
-    SignedPost,
+    PostDelete,
+    PostEdit,
+    PostRevisions,
     Profile,
+    RelationalStore,
+    SearchIndex,
+    SearchKind,
+    SearchQuery,
+    SensitiveCategory,
+    SignedPollTally,
+    SignedPollVote,
+    SignedPost,
+    SignedPostDelete,
+    SignedPostEdit,
     SignedProfile,
-    FileStorage,
+    StorageBackend,
 };
 
 // ---------------------------------------------------------------------------
@@ -54,11 +79,57 @@ enum Commands {
         action: PostAction,
     },
 
+    /// Timeline of your own and your contacts' posts
+    Feed {
+        #[command(subcommand)]
+        action: FeedAction,
+    },
+
+    /// Polls and votes
+    Poll {
+        #[command(subcommand)]
+        action: PollAction,
+    },
+
+    /// Content-warning preferences
+    Warnings {
+        #[command(subcommand)]
+        action: WarningsAction,
+    },
+
+    /// Full-text search over posts, messages and profiles
+    Search {
+        /// Words to find; prefix `#` to match hashtags only
+        #[arg(required = true)]
+        terms: Vec<String>,
+        /// Only this kind: post, message or profile (repeatable)
+        #[arg(short, long)]
+        kind: Vec<String>,
+        /// Only results by this fingerprint
+        #[arg(short, long)]
+        author: Option<String>,
+        /// Maximum number of results
+        #[arg(short, long, default_value_t = snartnet_core::DEFAULT_SEARCH_LIMIT)]
+        limit: usize,
+    },
+
     /// Key management
     Keys {
         #[command(subcommand)]
         action: KeysAction,
     },
+
+    /// Relational database maintenance
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+
+    /// Encryption of the data directory at rest
+    Storage {
+        #[command(subcommand)]
+        action: StorageAction,
+    },
 }
 
 #[derive(Subcommand)]
@@ -88,6 +159,130 @@ enum PostAction {
         /// Post ID to reply to
         #[arg(short, long)]
         reply_to: Option<String>,
+        /// Make the post disappear after this many hours (e.g. 24 for a status)
+        #[arg(long, value_name = "HOURS")]
+        expires_in: Option<u32>,
+        /// Content warning shown instead of the post until expanded
+        #[arg(long, value_name = "TEXT")]
+        cw: Option<String>,
+        /// Sensitive-content flag: nudity, violence, self_harm, substances,
+        /// spoiler (repeatable)
+        #[arg(long, value_name = "CATEGORY")]
+        flag: Vec<String>,
+    },
+    /// Publish a signed edit of one of your posts
+    Edit {
+        /// Post ID to edit
+        id: String,
+        /// Replacement content
+        content: String,
+    },
+    /// Retract one of your posts with a signed tombstone
+    Delete {
+        /// Post ID to delete
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PollAction {
+    /// Create a poll asking a question
+    Create {
+        /// The question
+        question: String,
+        /// An answer to choose from (repeat for each option)
+        #[arg(short, long = "option", required = true)]
+        options: Vec<String>,
+        /// Close the poll after this many hours
+        #[arg(long, value_name = "HOURS", default_value_t = 24)]
+        closes_in: u32,
+        /// Allow choosing more than one option
+        #[arg(short, long)]
+        multiple: bool,
+    },
+    /// Vote in a poll from your feed
+    Vote {
+        /// Poll post ID
+        id: String,
+        /// Option numbers, starting at 1
+        #[arg(required = true)]
+        choices: Vec<usize>,
+    },
+    /// Show a poll with the votes seen so far
+    Show {
+        /// Poll post ID
+        id: String,
+    },
+    /// Sign a tally of the votes on one of your polls
+    Tally {
+        /// Poll post ID
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum FeedAction {
+    /// Show a page of the feed, newest first
+    Show(FeedShowArgs),
+    /// Mark a post as read
+    Read {
+        /// Post ID
+        id: String,
+    },
+    /// Mark a post as unread
+    Unread {
+        /// Post ID
+        id: String,
+    },
+    /// Mark every post as read
+    ReadAll,
+}
+
+#[derive(Args)]
+struct FeedShowArgs {
+    /// Only posts by this fingerprint
+    #[arg(short, long)]
+    author: Option<String>,
+    /// Only posts with this hashtag
+    #[arg(short, long)]
+    tag: Option<String>,
+    /// Only posts created at or after this time (RFC 3339 or YYYY-MM-DD)
+    #[arg(long)]
+    since: Option<String>,
+    /// Only posts created before this time (RFC 3339 or YYYY-MM-DD)
+    #[arg(long)]
+    until: Option<String>,
+    /// Only unread posts
+    #[arg(short, long)]
+    unread: bool,
+    /// Leave out posts flagged with this category (repeatable)
+    #[arg(long, value_name = "CATEGORY")]
+    hide_flag: Vec<String>,
+    /// Leave out every post with a content warning
+    #[arg(long)]
+    hide_warned: bool,
+    /// Show the content of posts behind a content warning
+    #[arg(short, long)]
+    expand: bool,
+    /// Posts per page
+    #[arg(short, long, default_value_t = snartnet_core::DEFAULT_FEED_PAGE_SIZE)]
+    limit: usize,
+    /// Continue after a previous page
+    #[arg(long)]
+    cursor: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum WarningsAction {
+    /// Show which flagged posts are expanded automatically
+    Show,
+    /// Expand posts flagged with a category ("all" for every flagged post)
+    Expand {
+        category: String,
+    },
+    /// Keep posts flagged with a category collapsed ("all" to reset)
+    Collapse {
+        category: String,
     },
 }
 
@@ -97,6 +292,26 @@ enum KeysAction {
     Show,
 }
 
+#[derive(Subcommand)]
+enum DbAction {
+    /// Copy the JSON data files into the database (runs once)
+    Import,
+}
+
+/// Passphrases come from SNARTNET_PASSPHRASE (and SNARTNET_NEW_PASSPHRASE
+/// when changing it) or are read from standard input.
+#[derive(Subcommand)]
+enum StorageAction {
+    /// Encrypt every data file under a passphrase
+    Encrypt {
+        /// Also hide which kinds of data exist by hashing file names
+        #[arg(long)]
+        hide_names: bool,
+    },
+    /// Change the passphrase of an encrypted data directory
+    Passphrase,
+}
+
 // ---------------------------------------------------------------------------
 // Main
 // ---------------------------------------------------------------------------
@@ -113,13 +328,60 @@ fn main() {
             ProfileAction::Edit { name, bio } => cmd_profile_edit(&storage, name, bio),
         },
         Commands::Post { action } => match action {
-            PostAction::Create { content, tags, reply_to } => {
-                cmd_post_create(&storage, &content, tags, reply_to)
+            PostAction::Create { content, tags, reply_to, expires_in, cw, flag } => {
+                parse_content_warning(cw, &flag).and_then(|warning| {
+                    cmd_post_create(&storage, &content, tags, reply_to, expires_in, warning)
+                })
             }
+            PostAction::Edit { id, content } => cmd_post_edit(&storage, &id, &content),
+            PostAction::Delete { id } => cmd_post_delete(&storage, &id),
         },
+        Commands::Feed { action } => match action {
+            FeedAction::Show(args) => {
+                args.to_query().and_then(|query| cmd_feed_show(&storage, &query, args.expand))
+            }
+            FeedAction::Read { id } => cmd_feed_mark(&storage, &id, true),
+            FeedAction::Unread { id } => cmd_feed_mark(&storage, &id, false),
+            FeedAction::ReadAll => cmd_feed_read_all(&storage),
+        },
+        Commands::Poll { action } => match action {
+            PollAction::Create { question, options, closes_in, multiple } => {
+                cmd_poll_create(&storage, &question, options, closes_in, multiple)
+            }
+            PollAction::Vote { id, choices } => cmd_poll_vote(&storage, &id, &choices),
+            PollAction::Show { id } => cmd_poll_show(&storage, &id),
+            PollAction::Tally { id } => cmd_poll_tally(&storage, &id),
+        },
+        Commands::Warnings { action } => match action {
+            WarningsAction::Show => cmd_warnings_show(&storage),
+            WarningsAction::Expand { category } => cmd_warnings_set(&storage, &category, true),
+            WarningsAction::Collapse { category } => cmd_warnings_set(&storage, &category, false),
+        },
+        Commands::Search { terms, kind, author, limit } => kind
+            .iter()
+            .map(|k| SearchKind::parse(k))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|kinds| {
+                let query = SearchQuery { text: terms.join(" "), kinds, author, limit };
+                cmd_search(&storage, &query)
+            }),
         Commands::Keys { action } => match action {
             KeysAction::Show => cmd_keys_show(&storage),
         },
+        Commands::Db { action } => match action {
+            DbAction::Import => cmd_db_import(&storage),
+        },
+        Commands::Storage { action } => match action {
+            StorageAction::Encrypt { hide_names } => {
+                read_passphrase("SNARTNET_PASSPHRASE", "New passphrase")
+                    .and_then(|passphrase| {
+                        let options = EncryptionOptions { encrypt_names: hide_names, ..Default::default() };
+                        cmd_storage_encrypt(storage, &passphrase, options)
+                    })
+            }
+            StorageAction::Passphrase => read_passphrase("SNARTNET_NEW_PASSPHRASE", "New passphrase")
+                .and_then(|passphrase| cmd_storage_passphrase(&storage, &passphrase)),
+        },
     };
 
     if let Err(e) = result {
@@ -132,36 +394,56 @@ fn main() {
 // Storage helpers
 // ---------------------------------------------------------------------------
 
-fn open_storage(data_dir: Option<&str>) -> FileStorage {
-    let storage = match data_dir {
+/// The data directory, unlocked first if it is encrypted.
+type Storage = MaybeEncrypted<FileStorage>;
+
+fn open_storage(data_dir: Option<&str>) -> Storage {
+    let files = match data_dir {
         Some(dir) => FileStorage::new(dir),
         None => FileStorage::open_default(),
     };
+    let storage = files.map_err(|e| e.to_string()).and_then(|files| {
+        let passphrase = match EncryptedStorage::is_initialized(&files).map_err(|e| e.to_string())? {
+            true => Some(read_passphrase("SNARTNET_PASSPHRASE", "Passphrase")?),
+            false => None,
+        };
+        MaybeEncrypted::open(files, passphrase.as_deref()).map_err(|e| e.to_string())
+    });
     storage.unwrap_or_else(|e| {
         eprintln!("Fatal: could not open storage: {e}");
         std::process::exit(1);
     })
 }
 
-fn load_keypair(storage: &FileStorage) -> Result<KeyPair, String> {
+/// A passphrase from the environment variable `var`, or else typed at the
+/// terminal without echo.
+fn read_passphrase(var: &str, prompt: &str) -> Result<String, String> {
+    if let Ok(passphrase) = std::env::var(var) {
+        return Ok(passphrase);
+    }
+    rpassword::prompt_password(format!("{prompt}: "))
+        .map_err(|e| format!("Failed to read passphrase: {e}"))
+}
+
+fn load_keypair(storage: &Storage) -> Result<KeyPair, String> {
     storage
         .get_json::<KeyPair>("keypair")
         .map_err(|e| e.to_string())?
         .ok_or_else(|| "No identity found. Run `snartnet init <username>` first.".to_string())
 }
 
-fn load_profile(storage: &FileStorage) -> Result<SignedProfile, String> {
+fn load_profile(storage: &Storage) -> Result<SignedProfile, String> {
     storage
         .get_json::<SignedProfile>("profile")
         .map_err(|e| e.to_string())?
         .ok_or_else(|| "No profile found. Run `snartnet init <username>` first.".to_string())
 }
 
-fn save_keypair(storage: &FileStorage, kp: &KeyPair) -> Result<(), String> {
+fn save_keypair(storage: &Storage, kp: &KeyPair) -> Result<(), String> {
     storage.set_json("keypair", kp).map_err(|e| e.to_string())
 }
 
-fn save_profile(storage: &FileStorage, sp: &SignedProfile) -> Result<(), String> {
+fn save_profile(storage: &Storage, sp: &SignedProfile) -> Result<(), String> {
     storage.set_json("profile", sp).map_err(|e| e.to_string())
 }
 
@@ -170,7 +452,7 @@ fn save_profile(storage: &FileStorage, sp: &SignedProfile) -> Result<(), String>
 // ---------------------------------------------------------------------------
 
 fn cmd_init(
-    storage: &FileStorage,
+    storage: &Storage,
     username: &str,
     display_name: Option<String>,
     bio: Option<String>,
@@ -202,7 +484,7 @@ fn cmd_init(
     Ok(())
 }
 
-fn cmd_profile_show(storage: &FileStorage) -> Result<(), String> {
+fn cmd_profile_show(storage: &Storage) -> Result<(), String> {
     let sp = load_profile(storage)?;
     let p = &sp.profile;
     println!("Username    : @{}", p.username);
@@ -216,6 +498,10 @@ fn cmd_profile_show(storage: &FileStorage) -> Result<(), String> {
     println!("Version     : {}", p.version);
     println!("Created     : {}", p.created_at.format("%Y-%m-%d %H:%M UTC"));
     println!("Updated     : {}", p.updated_at.format("%Y-%m-%d %H:%M UTC"));
+    if let Some(ref exp) = p.expires_at {
+        let state = if p.is_expired() { " (expired)" } else { "" };
+        println!("Expires     : {}{state}", exp.format("%Y-%m-%d %H:%M UTC"));
+    }
     if let Some(ref uri) = p.magnet_uri {
         println!("Magnet URI  : {uri}");
     }
@@ -223,7 +509,7 @@ fn cmd_profile_show(storage: &FileStorage) -> Result<(), String> {
 }
 
 fn cmd_profile_edit(
-    storage: &FileStorage,
+    storage: &Storage,
     display_name: Option<String>,
     bio: Option<String>,
 ) -> Result<(), String> {
@@ -244,10 +530,12 @@ fn cmd_profile_edit(
 }
 
 fn cmd_post_create(
-    storage: &FileStorage,
+    storage: &Storage,
     content: &str,
     tags_raw: Option<String>,
     reply_to: Option<String>,
+    expires_in_hours: Option<u32>,
+    warning: Option<ContentWarning>,
 ) -> Result<(), String> {
     let kp = load_keypair(storage)?;
     let sp = load_profile(storage)?;
@@ -259,12 +547,21 @@ fn cmd_post_create(
             .collect()
     });
 
-    let post = Post::new(
+    let mut post = Post::new(
         sp.profile.fingerprint.clone(),
         content.to_string(),
         tags,
         reply_to,
     );
+    post.resolve_mentions(&load_petnames(storage)?);
+    match expires_in_hours {
+        Some(0) => return Err("--expires-in must be at least 1 hour".to_string()),
+        Some(hours) => post.expire_after(chrono::Duration::hours(hours.into())),
+        None => {}
+    }
+    if let Some(warning) = warning {
+        post.set_content_warning(warning);
+    }
 
     let signed = SignedPost::create(post, &kp)?;
 
@@ -273,6 +570,7 @@ fn cmd_post_create(
     storage
         .set_json(&post_key, &signed)
         .map_err(|e| e.to_string())?;
+    record_own(storage, &kp, vec![signed.clone()], PostRevisions::new())?;
 
     println!("✓ Post created");
     println!("  ID          : {}", signed.post.id);
@@ -281,16 +579,493 @@ fn cmd_post_create(
     if !signed.post.tags.is_empty() {
         println!("  Tags        : #{}", signed.post.tags.join(" #"));
     }
+    if let Some(expires_at) = signed.post.expires_at {
+        println!("  Expires     : {}", expires_at.format("%Y-%m-%d %H:%M UTC"));
+    }
+    if let Some(warning) = &signed.post.content_warning {
+        println!("  Warning     : {}", warning.label());
+    }
+    Ok(())
+}
+
+/// Expired posts are purged, together with their edits, on first access.
+fn load_post(storage: &Storage, id: &str) -> Result<SignedPost, String> {
+    let post = storage
+        .get_json::<SignedPost>(&format!("post_{id}"))
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No post found with ID {id}"))?;
+    if post.is_expired() {
+        for key in [format!("post_{id}"), format!("post_edits_{id}")] {
+            storage.remove_item(&key).map_err(|e| e.to_string())?;
+        }
+        return Err(format!("Post {id} has expired"));
+    }
+    Ok(post)
+}
+
+fn cmd_post_edit(storage: &Storage, id: &str, content: &str) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let original = load_post(storage, id)?;
+
+    let edit = PostEdit::new(&original.post, content.to_string(), None);
+    let signed = SignedPostEdit::create(edit, &original.post, &kp)?;
+
+    // Edits are kept alongside the untouched original, oldest first.
+    let edits_key = format!("post_edits_{id}");
+    let mut edits: Vec<SignedPostEdit> = storage
+        .get_json(&edits_key)
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    edits.push(signed.clone());
+    storage
+        .set_json(&edits_key, &edits)
+        .map_err(|e| e.to_string())?;
+    let mut revisions = PostRevisions::new();
+    revisions.add_edit(signed);
+    record_own(storage, &kp, vec![original], revisions)?;
+
+    println!("✓ Post edited");
+    println!("  ID          : {id}");
+    println!("  Content     : {content}");
+    println!("  Revisions   : {}", edits.len());
+    Ok(())
+}
+
+fn cmd_post_delete(storage: &Storage, id: &str) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let original = load_post(storage, id)?;
+
+    let signed = SignedPostDelete::create(PostDelete::new(&original.post), &kp)?;
+
+    // Keep only the tombstone so the deletion can still be republished.
+    storage
+        .set_json(&format!("post_delete_{id}"), &signed)
+        .map_err(|e| e.to_string())?;
+    for key in [format!("post_{id}"), format!("post_edits_{id}")] {
+        storage.remove_item(&key).map_err(|e| e.to_string())?;
+    }
+    let mut revisions = PostRevisions::new();
+    revisions.add_delete(signed);
+    record_own(storage, &kp, Vec::new(), revisions)?;
+
+    println!("✓ Post deleted");
+    println!("  ID          : {id}");
+    Ok(())
+}
+
+// The feed is shared with the desktop client when both use the default
+// storage directory.
+fn load_feed(storage: &Storage) -> Result<Feed, String> {
+    storage
+        .get_json::<Feed>("feed")
+        .map(|feed| feed.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+fn save_feed(storage: &Storage, feed: &Feed) -> Result<(), String> {
+    storage.set_json("feed", feed).map_err(|e| e.to_string())
+}
+
+fn record_own(
+    storage: &Storage,
+    kp: &KeyPair,
+    posts: Vec<SignedPost>,
+    revisions: PostRevisions,
+) -> Result<(), String> {
+    let mut feed = load_feed(storage)?;
+    feed.ingest_own(kp, posts, revisions)?;
+    save_feed(storage, &feed)
+}
+
+fn parse_time(value: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&chrono::Utc));
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc())
+        .map_err(|_| format!("Invalid time {value:?}: expected RFC 3339 or YYYY-MM-DD"))
+}
+
+impl FeedShowArgs {
+    fn to_query(&self) -> Result<FeedQuery, String> {
+        Ok(FeedQuery {
+            author: self.author.clone(),
+            tag: self.tag.clone(),
+            since: self.since.as_deref().map(parse_time).transpose()?,
+            until: self.until.as_deref().map(parse_time).transpose()?,
+            unread_only: self.unread,
+            hide_categories: parse_categories(&self.hide_flag)?,
+            hide_warned: self.hide_warned,
+            cursor: self.cursor.clone(),
+            limit: self.limit,
+        })
+    }
+}
+
+fn parse_categories(values: &[String]) -> Result<Vec<SensitiveCategory>, String> {
+    values.iter().map(|v| SensitiveCategory::parse(v)).collect()
+}
+
+fn parse_content_warning(summary: Option<String>, flags: &[String]) -> Result<Option<ContentWarning>, String> {
+    if summary.is_none() && flags.is_empty() {
+        return Ok(None);
+    }
+    let categories = parse_categories(flags)?;
+    ContentWarning::new(summary.as_deref().unwrap_or_default(), &categories).map(Some)
+}
+
+fn cmd_feed_show(storage: &Storage, query: &FeedQuery, expand: bool) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let mut feed = load_feed(storage)?;
+    if feed.purge_expired(chrono::Utc::now()) > 0 {
+        save_feed(storage, &feed)?;
+    }
+
+    let petnames = load_petnames(storage)?;
+    let mut page = feed.page(query, Some(&kp))?;
+    page.apply_preferences(&load_content_preferences(storage)?);
+    if page.items.is_empty() {
+        println!("No posts.");
+    }
+    for item in &page.items {
+        let post = &item.post.original.post;
+        let marker = if item.read { " " } else { "●" };
+        let edited = if item.post.is_edited() { " (edited)" } else { "" };
+        println!(
+            "{marker} {}  {}  {}{edited}",
+            post.created_at.format("%Y-%m-%d %H:%M"),
+            petnames.display_label(&post.author_fingerprint),
+            post.id
+        );
+        if let Some(warning) = &post.content_warning {
+            println!("    ⚠ {}", warning.label());
+        }
+        if item.collapsed && !expand {
+            println!("    (hidden; use --expand to show)");
+            continue;
+        }
+        println!("    {}", item.post.content);
+        if let Some(poll) = &post.poll {
+            for (i, option) in poll.options.iter().enumerate() {
+                println!("    [{}] {option}", i + 1);
+            }
+            println!("    (poll; snartnet poll show {})", post.id);
+        }
+        if !item.post.tags.is_empty() {
+            println!("    #{}", item.post.tags.join(" #"));
+        }
+    }
+    if let Some(cursor) = page.next_cursor {
+        println!();
+        println!("More: snartnet feed show --cursor '{cursor}'");
+    }
     Ok(())
 }
 
-fn cmd_keys_show(storage: &FileStorage) -> Result<(), String> {
+fn cmd_poll_create(
+    storage: &Storage,
+    question: &str,
+    options: Vec<String>,
+    closes_in_hours: u32,
+    multiple_choice: bool,
+) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    if closes_in_hours == 0 {
+        return Err("--closes-in must be at least 1 hour".to_string());
+    }
+    let closes_at = chrono::Utc::now() + chrono::Duration::hours(closes_in_hours.into());
+    let mut post = Post::new(kp.fingerprint.clone(), question.to_string(), None, None);
+    post.resolve_mentions(&load_petnames(storage)?);
+    post.set_poll(Poll::new(options, closes_at, multiple_choice)?)?;
+    let signed = SignedPost::create(post, &kp)?;
+
+    storage
+        .set_json(&format!("post_{}", signed.post.id), &signed)
+        .map_err(|e| e.to_string())?;
+    record_own(storage, &kp, vec![signed.clone()], PostRevisions::new())?;
+
+    println!("✓ Poll created");
+    println!("  ID          : {}", signed.post.id);
+    println!("  Question    : {}", signed.post.content);
+    println!("  Closes      : {}", closes_at.format("%Y-%m-%d %H:%M UTC"));
+    Ok(())
+}
+
+// Votes and tallies are shared with the desktop client, which delivers and
+// publishes them.
+fn load_poll_votes(storage: &Storage) -> Result<PollVotes, String> {
+    storage
+        .get_json::<PollVotes>("poll_votes")
+        .map(|votes| votes.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+fn load_poll_tallies(storage: &Storage) -> Result<Vec<SignedPollTally>, String> {
+    storage
+        .get_json::<Vec<SignedPollTally>>("poll_tallies")
+        .map(|tallies| tallies.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// A poll from the feed, which holds both own and contacts' posts.
+fn load_poll(storage: &Storage, id: &str) -> Result<SignedPost, String> {
+    let feed = load_feed(storage)?;
+    let entry = feed
+        .author_of(id)?
+        .and_then(|author| feed.get(author, id))
+        .ok_or_else(|| format!("No post found with ID {id}"))?;
+    if entry.post.post.poll.is_none() {
+        return Err(format!("Post {id} is not a poll"));
+    }
+    Ok(entry.post.clone())
+}
+
+fn cmd_poll_vote(storage: &Storage, id: &str, choices: &[usize]) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let poll = load_poll(storage, id)?;
+    // Options are numbered from 1 on the command line.
+    let choices: Vec<usize> = choices
+        .iter()
+        .map(|c| c.checked_sub(1).ok_or("Option numbers start at 1"))
+        .collect::<Result<_, _>>()?;
+    let vote = SignedPollVote::create(PollVote::new(&kp, &poll.post, &choices)?, &kp)?;
+
+    let mut votes = load_poll_votes(storage)?;
+    votes.add(vote, &poll.post, chrono::Utc::now())?;
+    storage.set_json("poll_votes", &votes).map_err(|e| e.to_string())?;
+
+    println!("✓ Vote recorded");
+    println!("  Poll        : {}", poll.post.content);
+    Ok(())
+}
+
+fn cmd_poll_show(storage: &Storage, id: &str) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let poll_post = load_poll(storage, id)?;
+    let Some(poll) = &poll_post.post.poll else {
+        return Err(format!("Post {id} is not a poll"));
+    };
+    let votes = load_poll_votes(storage)?;
+    let results = votes.results(&poll_post.post);
+    let mine = votes.vote_of(&poll_post.post.author_fingerprint, id, &kp.fingerprint).map(|v| v.vote.choices.clone()).unwrap_or_default();
+
+    println!("{}", poll_post.post.content);
+    for (i, option) in poll.options.iter().enumerate() {
+        let marker = if mine.contains(&i) { "✓" } else { " " };
+        println!("{marker} [{}] {option:<30} {}", i + 1, results.counts[i]);
+    }
+    let state = if poll.is_open() { "closes" } else { "closed" };
+    println!(
+        "{} voter(s) seen · {state} {}",
+        results.voters,
+        poll.closes_at.format("%Y-%m-%d %H:%M UTC")
+    );
+    if let Some(tally) = load_poll_tallies(storage)?.iter().find(|t| t.tally.poll_id == id) {
+        println!(
+            "Tally of {} ballot(s) signed {}: {}",
+            tally.tally.ballots.len(),
+            tally.tally.created_at.format("%Y-%m-%d %H:%M UTC"),
+            tally.tally.check(&poll_post.post, &votes)?.label()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_poll_tally(storage: &Storage, id: &str) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let poll = load_poll(storage, id)?;
+    if poll.post.author_fingerprint != kp.fingerprint {
+        return Err("Only the poll author can publish a tally".to_string());
+    }
+    let tally = SignedPollTally::create(load_poll_votes(storage)?.tally(&poll.post), &kp)?;
+
+    let mut tallies = load_poll_tallies(storage)?;
+    tallies.retain(|t| t.tally.poll_id != id);
+    tallies.push(tally.clone());
+    storage.set_json("poll_tallies", &tallies).map_err(|e| e.to_string())?;
+
+    println!("✓ Tally signed{}", if tally.tally.is_final { " (final)" } else { "" });
+    println!("  Ballots     : {}", tally.tally.ballots.len());
+    println!(
+        "  Counts      : {}",
+        tally.tally.counts.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" / ")
+    );
+    Ok(())
+}
+
+/// The petname book kept by `CoreService`, or else the desktop client's copy
+/// in the same directory, so authors show up under the same names.
+fn load_petnames(storage: &Storage) -> Result<PetnameBook, String> {
+    for key in ["snartnet_petnames", "petnames"] {
+        if let Some(book) = storage.get_json::<PetnameBook>(key).map_err(|e| e.to_string())? {
+            return Ok(book);
+        }
+    }
+    Ok(PetnameBook::new())
+}
+
+/// Shared with the desktop client, like the feed.
+fn load_content_preferences(storage: &Storage) -> Result<ContentPreferences, String> {
+    storage
+        .get_json::<ContentPreferences>("content_preferences")
+        .map(|prefs| prefs.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+fn cmd_warnings_show(storage: &Storage) -> Result<(), String> {
+    let prefs = load_content_preferences(storage)?;
+    if prefs.expand_all {
+        println!("All flagged posts are expanded.");
+        return Ok(());
+    }
+    for category in SensitiveCategory::ALL {
+        let state = if prefs.auto_expand.contains(&category) { "expanded" } else { "collapsed" };
+        println!("{:<12}{state}", category.as_str());
+    }
+    println!("{:<12}collapsed", "(text only)");
+    Ok(())
+}
+
+fn cmd_warnings_set(storage: &Storage, category: &str, expand: bool) -> Result<(), String> {
+    let mut prefs = load_content_preferences(storage)?;
+    if category.trim().eq_ignore_ascii_case("all") {
+        prefs.expand_all = expand;
+        if !expand {
+            prefs.auto_expand.clear();
+        }
+    } else {
+        prefs.set_auto_expand(SensitiveCategory::parse(category)?, expand);
+    }
+    storage
+        .set_json("content_preferences", &prefs)
+        .map_err(|e| e.to_string())?;
+    println!("✓ {} {}", if expand { "Expanding" } else { "Collapsing" }, category.trim());
+    Ok(())
+}
+
+fn cmd_feed_mark(storage: &Storage, id: &str, read: bool) -> Result<(), String> {
+    let mut feed = load_feed(storage)?;
+    let author = feed
+        .author_of(id)?
+        .map(str::to_string)
+        .ok_or_else(|| format!("No post found with ID {id}"))?;
+    if read {
+        feed.mark_read(&author, id);
+    } else {
+        feed.mark_unread(&author, id);
+    }
+    save_feed(storage, &feed)?;
+    println!("✓ Marked {}", if read { "read" } else { "unread" });
+    Ok(())
+}
+
+fn cmd_feed_read_all(storage: &Storage) -> Result<(), String> {
+    let mut feed = load_feed(storage)?;
+    let changed = feed.mark_all_read();
+    save_feed(storage, &feed)?;
+    println!("✓ Marked {changed} post(s) read");
+    Ok(())
+}
+
+/// The index lives beside the other data files and is shared with the
+/// desktop client, which also indexes contacts' profiles and messages.
+///
+/// An encrypted data directory keeps the index in memory instead; it is
+/// rebuilt from the feed on every search.
+fn open_search_index(storage: &Storage) -> Result<SearchIndex, String> {
+    if storage.is_encrypted() {
+        return SearchIndex::open_in_memory();
+    }
+    SearchIndex::open(storage.inner().dir().join("search.db"))
+}
+
+fn cmd_search(storage: &Storage, query: &SearchQuery) -> Result<(), String> {
+    let kp = load_keypair(storage)?;
+    let index = open_search_index(storage)?;
+    // Catch up with posts recorded since the last search.
+    index.sync_feed(&load_feed(storage)?, Some(&kp))?;
+    index.index_profile(&load_profile(storage)?)?;
+    index.purge_expired(chrono::Utc::now())?;
+
+    let petnames = load_petnames(storage)?;
+    let hits = index.search(query)?;
+    if hits.is_empty() {
+        println!("No results.");
+    }
+    for hit in &hits {
+        println!(
+            "[{}] {}  {}  {}",
+            hit.kind.as_str(),
+            hit.created_at.format("%Y-%m-%d %H:%M"),
+            petnames.display_label(&hit.author_fingerprint),
+            hit.id
+        );
+        println!("    {}", hit.snippet);
+    }
+    Ok(())
+}
+
+fn cmd_keys_show(storage: &Storage) -> Result<(), String> {
     let kp = load_keypair(storage)?;
     println!("Public key  : {}", kp.public_key);
     println!("Fingerprint : {}", kp.fingerprint);
     Ok(())
 }
 
+/// The relational store lives beside the JSON files it is imported from.
+/// It cannot be encrypted yet, so it is not written for an encrypted data
+/// directory.
+fn open_database(storage: &Storage) -> Result<RelationalStore, String> {
+    if storage.is_encrypted() {
+        return Err("The database is not encrypted at rest, so it is not used for an encrypted data directory".into());
+    }
+    RelationalStore::open(storage.inner().dir().join("snartnet.db"))
+}
+
+fn cmd_db_import(storage: &Storage) -> Result<(), String> {
+    let db = open_database(storage)?;
+    match db.import_legacy("files", storage)? {
+        Some(report) => println!(
+            "✓ Imported {} profile(s), {} post(s), {} message(s), {} contact(s)",
+            report.profiles, report.posts, report.messages, report.contacts
+        ),
+        None => println!("Already imported."),
+    }
+    println!("Schema version {}", db.schema_version()?);
+    Ok(())
+}
+
+/// Plaintext copies kept beside the data files, removed on encryption.
+const SIDE_STORES: &[&str] = &["search.db", "snartnet.db"];
+
+fn cmd_storage_encrypt(storage: Storage, passphrase: &str, options: EncryptionOptions) -> Result<(), String> {
+    let MaybeEncrypted::Plain(files) = storage else {
+        return Err("The data directory is already encrypted".into());
+    };
+    let keys = files.keys().map_err(|e| e.to_string())?;
+    let dir = files.dir().to_path_buf();
+    let store = EncryptedStorage::create(files, passphrase, options).map_err(|e| e.to_string())?;
+    let converted = store.encrypt_existing(&keys).map_err(|e| e.to_string())?;
+    for name in SIDE_STORES {
+        let path = dir.join(name);
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {name}: {e}"))?;
+            println!("  removed plaintext {name}");
+        }
+    }
+    println!("✓ Encrypted {converted} data file(s) in {}", dir.display());
+    Ok(())
+}
+
+fn cmd_storage_passphrase(storage: &Storage, passphrase: &str) -> Result<(), String> {
+    let MaybeEncrypted::Encrypted(store) = storage else {
+        return Err("The data directory is not encrypted. Run `snartnet storage encrypt` first.".into());
+    };
+    store.change_passphrase(passphrase).map_err(|e| e.to_string())?;
+    println!("✓ Passphrase changed");
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Validation helpers
 // ---------------------------------------------------------------------------
@@ -318,6 +1093,7 @@ fn validate_username(username: &str) -> Result<(), String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use snartnet_core::Recipient;
 
     #[test]
     fn validate_username_ok() {
@@ -345,7 +1121,7 @@ mod tests {
     #[test]
     fn cmd_init_creates_profile() {
         let dir = tempfile::tempdir().unwrap();
-        let storage = FileStorage::new(dir.path()).unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
         cmd_init(&storage, "testuser", Some("Test User".to_string()), None).unwrap();
         let sp = load_profile(&storage).unwrap();
         assert_eq!(sp.profile.username, "testuser");
@@ -355,7 +1131,7 @@ mod tests {
     #[test]
     fn cmd_init_rejects_duplicate() {
         let dir = tempfile::tempdir().unwrap();
-        let storage = FileStorage::new(dir.path()).unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
         cmd_init(&storage, "alice", None, None).unwrap();
         let result = cmd_init(&storage, "alice", None, None);
         assert!(result.is_err(), "should reject duplicate init");
@@ -364,18 +1140,241 @@ mod tests {
     #[test]
     fn cmd_post_create_stores_post() {
         let dir = tempfile::tempdir().unwrap();
-        let storage = FileStorage::new(dir.path()).unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
         cmd_init(&storage, "poster", None, None).unwrap();
-        cmd_post_create(&storage, "Hello world", Some("rust,test".to_string()), None).unwrap();
+        cmd_post_create(&storage, "Hello world", Some("rust,test".to_string()), None, None, None).unwrap();
+    }
+
+    #[test]
+    fn cmd_post_create_resolves_petname_mentions() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "mentioner", None, None).unwrap();
+        let mut book = PetnameBook::new();
+        book.set_petname("fp-alice", Some("alice".into())).unwrap();
+        storage.set_json("snartnet_petnames", &book).unwrap();
+
+        cmd_post_create(&storage, "hi @alice", None, None, None, None).unwrap();
+        let feed = load_feed(&storage).unwrap();
+        let post = &feed.entries()[0].post.post;
+        assert_eq!(post.mentions, vec!["fp-alice".to_string()]);
+    }
+
+    #[test]
+    fn expired_post_is_purged_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "status", None, None).unwrap();
+        assert!(cmd_post_create(&storage, "brb", None, None, Some(0), None).is_err());
+
+        let kp = load_keypair(&storage).unwrap();
+        let mut post = Post::new(kp.fingerprint.clone(), "brb".into(), None, None);
+        post.created_at -= chrono::Duration::hours(25);
+        post.expire_after(chrono::Duration::hours(24));
+        let post = SignedPost::create(post, &kp).unwrap();
+        let id = post.post.id.clone();
+        storage.set_json(&format!("post_{id}"), &post).unwrap();
+
+        assert!(load_post(&storage, &id).unwrap_err().contains("expired"));
+        assert!(storage.get_item(&format!("post_{id}")).unwrap().is_none());
+    }
+
+    #[test]
+    fn petnames_load_from_core_book_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        assert_eq!(load_petnames(&storage).unwrap().display_label("fp-a"), "fp-a");
+
+        let mut desktop = PetnameBook::new();
+        desktop.suggest_petname("fp-a", "Al");
+        storage.set_json("petnames", &desktop).unwrap();
+        assert_eq!(load_petnames(&storage).unwrap().display_label("fp-a"), "Al (unconfirmed)");
+
+        let mut core = PetnameBook::new();
+        core.set_petname("fp-a", Some("Alice".into())).unwrap();
+        storage.set_json("snartnet_petnames", &core).unwrap();
+        assert_eq!(load_petnames(&storage).unwrap().display_label("fp-a"), "Alice");
+    }
+
+    #[test]
+    fn cmd_post_edit_refuses_restricted_posts() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "circle", None, None).unwrap();
+        let kp = load_keypair(&storage).unwrap();
+        let mut post = Post::new(kp.fingerprint.clone(), "friends only".into(), None, None);
+        post.seal_for(&[Recipient::from_keypair(&kp).unwrap()]).unwrap();
+        let post = SignedPost::create(post, &kp).unwrap();
+        let id = post.post.id.clone();
+        storage.set_json(&format!("post_{id}"), &post).unwrap();
+
+        assert!(cmd_post_edit(&storage, &id, "now public").unwrap_err().contains("restricted"));
+        assert!(storage.get_item(&format!("post_edits_{id}")).unwrap().is_none());
+    }
+
+    #[test]
+    fn cmd_post_edit_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "reviser", None, None).unwrap();
+        let kp = load_keypair(&storage).unwrap();
+        let post = SignedPost::create(Post::new(kp.fingerprint.clone(), "teh".into(), None, None), &kp).unwrap();
+        let id = post.post.id.clone();
+        storage.set_json(&format!("post_{id}"), &post).unwrap();
+
+        cmd_post_edit(&storage, &id, "the").unwrap();
+        let edits: Vec<SignedPostEdit> = storage.get_json(&format!("post_edits_{id}")).unwrap().unwrap();
+        assert_eq!(edits.len(), 1);
+        assert!(edits[0].verify(&kp.public_key).unwrap());
+
+        cmd_post_delete(&storage, &id).unwrap();
+        assert!(load_post(&storage, &id).is_err());
+        let tombstone: SignedPostDelete = storage.get_json(&format!("post_delete_{id}")).unwrap().unwrap();
+        assert!(tombstone.verify(&kp.public_key).unwrap());
+        assert!(cmd_post_edit(&storage, &id, "again").is_err());
+    }
+
+    #[test]
+    fn feed_tracks_own_posts_and_read_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "reader", None, None).unwrap();
+        cmd_post_create(&storage, "first #rust", None, None, None, None).unwrap();
+        cmd_post_create(&storage, "second", None, None, None, None).unwrap();
+
+        let friend = KeyPair::generate().unwrap();
+        let theirs = SignedPost::create(Post::new(friend.fingerprint.clone(), "hi".into(), None, None), &friend).unwrap();
+        let mut feed = load_feed(&storage).unwrap();
+        feed.ingest(&friend.public_key, vec![theirs.clone()], PostRevisions::new()).unwrap();
+        save_feed(&storage, &feed).unwrap();
+
+        let args = FeedShowArgs {
+            author: None,
+            tag: Some("rust".into()),
+            since: Some("2020-01-01".into()),
+            until: None,
+            unread: false,
+            hide_flag: Vec::new(),
+            hide_warned: false,
+            expand: false,
+            limit: 10,
+            cursor: None,
+        };
+        let query = args.to_query().unwrap();
+        assert_eq!(load_feed(&storage).unwrap().page(&query, None).unwrap().items.len(), 1);
+        cmd_feed_show(&storage, &FeedQuery::default(), false).unwrap();
+        let bad_since = FeedShowArgs { since: Some("yesterday".into()), ..args };
+        assert!(bad_since.to_query().is_err());
+
+        assert_eq!(load_feed(&storage).unwrap().unread_count(), 1);
+        cmd_feed_mark(&storage, &theirs.post.id, true).unwrap();
+        assert_eq!(load_feed(&storage).unwrap().unread_count(), 0);
+        assert!(cmd_feed_mark(&storage, "missing", true).is_err());
+
+        let own = load_feed(&storage).unwrap().latest_by(&friend.fingerprint).map(|e| e.post.post.id.clone());
+        assert_eq!(own, Some(theirs.post.id));
+    }
+
+    #[test]
+    fn content_warnings_and_preferences() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "critic", None, None).unwrap();
+        assert!(parse_content_warning(None, &["gore".to_string()]).is_err());
+        assert!(parse_content_warning(None, &[]).unwrap().is_none());
+        let warning = parse_content_warning(Some("finale".into()), &["spoiler".to_string()]).unwrap();
+        cmd_post_create(&storage, "Everyone dies", None, None, None, warning).unwrap();
+
+        let kp = load_keypair(&storage).unwrap();
+        let page = |prefs: &ContentPreferences| {
+            let mut page = load_feed(&storage).unwrap().page(&FeedQuery::default(), Some(&kp)).unwrap();
+            page.apply_preferences(prefs);
+            page.items[0].collapsed
+        };
+        assert!(page(&load_content_preferences(&storage).unwrap()));
+        cmd_warnings_set(&storage, "spoiler", true).unwrap();
+        assert!(!page(&load_content_preferences(&storage).unwrap()));
+        assert!(cmd_warnings_set(&storage, "gore", true).is_err());
+        cmd_feed_show(&storage, &FeedQuery::default(), false).unwrap();
+    }
+
+    #[test]
+    fn polls_can_be_voted_on_and_tallied() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "pollster", None, None).unwrap();
+        assert!(cmd_poll_create(&storage, "Pick one", vec!["A".into()], 24, false).is_err());
+        cmd_poll_create(&storage, "Pick one", vec!["A".into(), "B".into()], 24, false).unwrap();
+        let kp = load_keypair(&storage).unwrap();
+        let id = load_feed(&storage).unwrap().latest_by(&kp.fingerprint).unwrap().post.post.id.clone();
+
+        assert!(cmd_poll_vote(&storage, &id, &[0]).is_err());
+        assert!(cmd_poll_vote(&storage, &id, &[1, 2]).is_err());
+        cmd_poll_vote(&storage, &id, &[2]).unwrap();
+        cmd_poll_tally(&storage, &id).unwrap();
+        cmd_poll_show(&storage, &id).unwrap();
+
+        let poll = load_poll(&storage, &id).unwrap();
+        let tallies = load_poll_tallies(&storage).unwrap();
+        assert_eq!(tallies[0].tally.counts, vec![0, 1]);
+        assert!(tallies[0].verify(&kp.public_key).unwrap());
+        assert_eq!(
+            tallies[0].tally.check(&poll.post, &load_poll_votes(&storage).unwrap()).unwrap(),
+            snartnet_core::TallyCheck::Verified
+        );
+    }
+
+    #[test]
+    fn search_finds_own_posts_and_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "searcher", None, Some("Birdwatching fan".to_string())).unwrap();
+        cmd_post_create(&storage, "Spotted a heron #birds", None, None, None, None).unwrap();
+        let query = |text: &str| SearchQuery { text: text.to_string(), ..Default::default() };
+
+        cmd_search(&storage, &query("heron")).unwrap();
+        let index = open_search_index(&storage).unwrap();
+        let hits = index.search(&query("heron")).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].kind, SearchKind::Post);
+        assert_eq!(index.search(&query("birdwatch")).unwrap()[0].kind, SearchKind::Profile);
     }
 
     #[test]
     fn cmd_profile_edit_updates_bio() {
         let dir = tempfile::tempdir().unwrap();
-        let storage = FileStorage::new(dir.path()).unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
         cmd_init(&storage, "editor", None, None).unwrap();
         cmd_profile_edit(&storage, None, Some("New bio".to_string())).unwrap();
         let sp = load_profile(&storage).unwrap();
         assert_eq!(sp.profile.bio.as_deref(), Some("New bio"));
     }
+
+    #[test]
+    fn cmd_storage_encrypt_converts_files_and_drops_side_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MaybeEncrypted::Plain(FileStorage::new(dir.path()).unwrap());
+        cmd_init(&storage, "secretive", None, None).unwrap();
+        cmd_post_create(&storage, "meet at the heron pond", None, None, None, None).unwrap();
+        cmd_search(&storage, &SearchQuery { text: "heron".into(), ..Default::default() }).unwrap();
+        assert!(dir.path().join("search.db").exists());
+
+        let fast = EncryptionOptions { iterations: snartnet_core::MIN_KDF_ITERATIONS, ..Default::default() };
+        cmd_storage_encrypt(storage, "hunter2", fast).unwrap();
+        assert!(!dir.path().join("search.db").exists());
+        for entry in std::fs::read_dir(dir.path()).unwrap() {
+            assert!(!std::fs::read_to_string(entry.unwrap().path()).unwrap().contains("heron"));
+        }
+
+        let files = || FileStorage::new(dir.path()).unwrap();
+        assert!(MaybeEncrypted::open(files(), None).is_err());
+        let storage = MaybeEncrypted::open(files(), Some("hunter2")).unwrap();
+        assert_eq!(load_profile(&storage).unwrap().profile.username, "secretive");
+        cmd_search(&storage, &SearchQuery { text: "heron".into(), ..Default::default() }).unwrap();
+        assert!(!dir.path().join("search.db").exists());
+        assert!(cmd_db_import(&storage).is_err());
+
+        cmd_storage_passphrase(&storage, "correct horse").unwrap();
+        assert!(MaybeEncrypted::open(files(), Some("correct horse")).is_ok());
+    }
 }
@@ -0,0 +1,516 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Size of every chunk except possibly the last.
+pub const ATTACHMENT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Largest attachment accepted for chunking or download.
+pub const MAX_ATTACHMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// Describes a chunked attachment. `Post.attachment_hashes` holds the
+/// manifest hash (see [`AttachmentManifest::hash`]), so a post's signature
+/// covers the exact bytes via the chunk hashes listed here.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentManifest {
+    pub version: u32,
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    pub chunk_size: u32,
+    /// BLAKE3 hash (hex) of each chunk, in order.
+    pub chunks: Vec<String>,
+    /// BLAKE3 tree hash (hex) of the whole content.
+    pub root_hash: String,
+}
+
+impl AttachmentManifest {
+    /// Split `data` into chunks and describe it. Returns the manifest and the
+    /// chunks in order.
+    pub fn build(
+        data: &[u8],
+        mime_type: Option<&str>,
+        file_name: Option<String>,
+    ) -> Result<(Self, Vec<Vec<u8>>), String> {
+        if data.is_empty() {
+            return Err("Attachment is empty".to_string());
+        }
+        if data.len() as u64 > MAX_ATTACHMENT_SIZE {
+            return Err(format!(
+                "Attachment exceeds {} MiB limit",
+                MAX_ATTACHMENT_SIZE / (1024 * 1024)
+            ));
+        }
+
+        let mime_type = mime_type
+            .map(str::to_string)
+            .unwrap_or_else(|| sniff_mime_type(data).to_string());
+        let (width, height) = match image_dimensions(data) {
+            Some((w, h)) => (Some(w), Some(h)),
+            None => (None, None),
+        };
+        let chunks: Vec<Vec<u8>> = data.chunks(ATTACHMENT_CHUNK_SIZE).map(<[u8]>::to_vec).collect();
+
+        let manifest = Self {
+            version: MANIFEST_VERSION,
+            mime_type,
+            file_name,
+            size: data.len() as u64,
+            width,
+            height,
+            chunk_size: ATTACHMENT_CHUNK_SIZE as u32,
+            chunks: chunks.iter().map(|c| content_hash(c)).collect(),
+            root_hash: content_hash(data),
+        };
+        Ok((manifest, chunks))
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize attachment manifest: {}", e))
+    }
+
+    /// Content address of this manifest: BLAKE3 of its canonical JSON.
+    pub fn hash(&self) -> Result<String, String> {
+        Ok(content_hash(self.to_canonical_json()?.as_bytes()))
+    }
+
+    /// Structural sanity checks for a manifest received from a peer.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version != MANIFEST_VERSION {
+            return Err(format!("Unsupported manifest version {}", self.version));
+        }
+        if self.size == 0 || self.size > MAX_ATTACHMENT_SIZE {
+            return Err("Attachment size out of range".to_string());
+        }
+        if self.chunk_size == 0 {
+            return Err("Chunk size must be positive".to_string());
+        }
+        let expected = self.size.div_ceil(self.chunk_size as u64);
+        if self.chunks.len() as u64 != expected {
+            return Err("Chunk count does not match size".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn verify_chunk(&self, index: usize, bytes: &[u8]) -> bool {
+        self.chunks
+            .get(index)
+            .is_some_and(|expected| *expected == content_hash(bytes))
+    }
+
+    pub fn verify_content(&self, data: &[u8]) -> bool {
+        data.len() as u64 == self.size && content_hash(data) == self.root_hash
+    }
+}
+
+/// BLAKE3 hex digest; the address of a chunk, attachment or manifest.
+pub fn content_hash(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+/// Best-effort MIME type from magic bytes.
+pub fn sniff_mime_type(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else if data.starts_with(b"%PDF-") {
+        "application/pdf"
+    } else if std::str::from_utf8(data).is_ok() {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Pixel dimensions for PNG, GIF and JPEG images.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be32 = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+    let be16 = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]) as u32;
+    let le16 = |b: &[u8]| u16::from_le_bytes([b[0], b[1]]) as u32;
+
+    match sniff_mime_type(data) {
+        "image/png" if data.len() >= 24 && &data[12..16] == b"IHDR" => {
+            Some((be32(&data[16..20]), be32(&data[20..24])))
+        }
+        "image/gif" if data.len() >= 10 => Some((le16(&data[6..8]), le16(&data[8..10]))),
+        "image/jpeg" => {
+            // Walk segments until a start-of-frame marker.
+            let mut i = 2;
+            while i + 9 < data.len() {
+                if data[i] != 0xFF {
+                    return None;
+                }
+                let marker = data[i + 1];
+                let len = be16(&data[i + 2..i + 4]) as usize;
+                let is_sof = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+                if is_sof {
+                    return Some((be16(&data[i + 7..i + 9]), be16(&data[i + 5..i + 7])));
+                }
+                i += 2 + len;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Local content-addressed storage for manifests and chunks.
+pub trait BlobStore {
+    fn put_chunk(&self, hash: &str, bytes: &[u8]) -> Result<(), String>;
+    fn get_chunk(&self, hash: &str) -> Option<Vec<u8>>;
+    fn put_manifest(&self, hash: &str, manifest: &AttachmentManifest) -> Result<(), String>;
+    fn get_manifest(&self, hash: &str) -> Option<AttachmentManifest>;
+
+    fn has_chunk(&self, hash: &str) -> bool {
+        self.get_chunk(hash).is_some()
+    }
+
+    /// Chunk and store `data`, returning the manifest hash to place in
+    /// `Post.attachment_hashes`.
+    fn import(
+        &self,
+        data: &[u8],
+        mime_type: Option<&str>,
+        file_name: Option<String>,
+    ) -> Result<String, String> {
+        let (manifest, chunks) = AttachmentManifest::build(data, mime_type, file_name)?;
+        for (hash, chunk) in manifest.chunks.iter().zip(&chunks) {
+            self.put_chunk(hash, chunk)?;
+        }
+        let manifest_hash = manifest.hash()?;
+        self.put_manifest(&manifest_hash, &manifest)?;
+        Ok(manifest_hash)
+    }
+
+    /// Indices of chunks not yet stored locally.
+    fn missing_chunks(&self, manifest: &AttachmentManifest) -> Vec<usize> {
+        manifest
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, hash)| !self.has_chunk(hash))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Reassemble a fully downloaded attachment, verifying the root hash.
+    fn assemble(&self, manifest_hash: &str) -> Result<Vec<u8>, String> {
+        let manifest = self
+            .get_manifest(manifest_hash)
+            .ok_or_else(|| "Unknown attachment".to_string())?;
+        let mut data = Vec::with_capacity(manifest.size as usize);
+        for hash in &manifest.chunks {
+            let chunk = self
+                .get_chunk(hash)
+                .ok_or_else(|| "Attachment is incomplete".to_string())?;
+            data.extend_from_slice(&chunk);
+        }
+        if !manifest.verify_content(&data) {
+            return Err("Attachment failed verification".to_string());
+        }
+        Ok(data)
+    }
+}
+
+/// Where missing manifests and chunks are fetched from, e.g. swarm peers.
+/// A source with several peers should check each answer against `hash`
+/// and move on to the next peer when it does not match; `fetch_attachment`
+/// checks again but cannot ask anyone else.
+pub trait ChunkSource {
+    fn fetch_manifest(&self, hash: &str) -> Option<AttachmentManifest>;
+    fn fetch_chunk(&self, hash: &str) -> Option<Vec<u8>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchProgress {
+    pub have_chunks: usize,
+    pub total_chunks: usize,
+}
+
+impl FetchProgress {
+    pub fn is_complete(&self) -> bool {
+        self.have_chunks == self.total_chunks
+    }
+}
+
+/// Download an attachment into `store`, verifying the manifest against its
+/// hash and every chunk against the manifest. Chunks already stored are
+/// skipped, so calling this again after an interruption resumes the download.
+/// Chunks no source can provide are left missing and reported in the result.
+pub fn fetch_attachment<B: BlobStore + ?Sized, S: ChunkSource + ?Sized>(
+    store: &B,
+    source: &S,
+    manifest_hash: &str,
+) -> Result<FetchProgress, String> {
+    let manifest = match store.get_manifest(manifest_hash) {
+        Some(m) => m,
+        None => {
+            let m = source
+                .fetch_manifest(manifest_hash)
+                .ok_or_else(|| "Manifest not available from any peer".to_string())?;
+            if m.hash()? != manifest_hash {
+                return Err("Manifest does not match its hash".to_string());
+            }
+            m.validate()?;
+            store.put_manifest(manifest_hash, &m)?;
+            m
+        }
+    };
+
+    for index in store.missing_chunks(&manifest) {
+        let hash = &manifest.chunks[index];
+        if let Some(bytes) = source.fetch_chunk(hash) {
+            if manifest.verify_chunk(index, &bytes) {
+                store.put_chunk(hash, &bytes)?;
+            }
+        }
+    }
+
+    let total_chunks = manifest.chunks.len();
+    Ok(FetchProgress {
+        have_chunks: total_chunks - store.missing_chunks(&manifest).len(),
+        total_chunks,
+    })
+}
+
+/// In-memory blob store, mainly for tests and short-lived hosts.
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+    chunks: std::cell::RefCell<HashMap<String, Vec<u8>>>,
+    manifests: std::cell::RefCell<HashMap<String, AttachmentManifest>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn put_chunk(&self, hash: &str, bytes: &[u8]) -> Result<(), String> {
+        self.chunks.borrow_mut().insert(hash.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+        self.chunks.borrow().get(hash).cloned()
+    }
+
+    fn put_manifest(&self, hash: &str, manifest: &AttachmentManifest) -> Result<(), String> {
+        self.manifests.borrow_mut().insert(hash.to_string(), manifest.clone());
+        Ok(())
+    }
+
+    fn get_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+        self.manifests.borrow().get(hash).cloned()
+    }
+}
+
+impl ChunkSource for MemoryBlobStore {
+    fn fetch_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+        self.get_manifest(hash)
+    }
+
+    fn fetch_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+        self.get_chunk(hash)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use file_store::FileBlobStore;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod file_store {
+    use super::{AttachmentManifest, BlobStore, ChunkSource};
+    use std::path::{Path, PathBuf};
+
+    /// Blob store on disk: `<dir>/chunks/<hash>` and
+    /// `<dir>/manifests/<hash>.json`.
+    #[derive(Debug, Clone)]
+    pub struct FileBlobStore {
+        dir: PathBuf,
+    }
+
+    impl FileBlobStore {
+        pub fn new(dir: impl AsRef<Path>) -> Result<Self, String> {
+            let dir = dir.as_ref().to_path_buf();
+            for sub in ["chunks", "manifests"] {
+                std::fs::create_dir_all(dir.join(sub))
+                    .map_err(|e| format!("create blob dir failed: {e}"))?;
+            }
+            Ok(Self { dir })
+        }
+
+        /// Hashes are hex, so anything else is rejected rather than being
+        /// allowed to escape the store directory.
+        fn path(&self, sub: &str, hash: &str, ext: &str) -> Option<PathBuf> {
+            if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            Some(self.dir.join(sub).join(format!("{hash}{ext}")))
+        }
+
+        fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, bytes).map_err(|e| format!("write blob failed: {e}"))?;
+            std::fs::rename(&tmp, path).map_err(|e| format!("write blob failed: {e}"))
+        }
+    }
+
+    impl BlobStore for FileBlobStore {
+        fn put_chunk(&self, hash: &str, bytes: &[u8]) -> Result<(), String> {
+            let path = self
+                .path("chunks", hash, "")
+                .ok_or_else(|| "invalid chunk hash".to_string())?;
+            Self::write_atomic(&path, bytes)
+        }
+
+        fn get_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+            std::fs::read(self.path("chunks", hash, "")?).ok()
+        }
+
+        fn has_chunk(&self, hash: &str) -> bool {
+            self.path("chunks", hash, "").is_some_and(|p| p.exists())
+        }
+
+        fn put_manifest(&self, hash: &str, manifest: &AttachmentManifest) -> Result<(), String> {
+            let path = self
+                .path("manifests", hash, ".json")
+                .ok_or_else(|| "invalid manifest hash".to_string())?;
+            Self::write_atomic(&path, manifest.to_canonical_json()?.as_bytes())
+        }
+
+        fn get_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+            let raw = std::fs::read(self.path("manifests", hash, ".json")?).ok()?;
+            serde_json::from_slice(&raw).ok()
+        }
+    }
+
+    impl ChunkSource for FileBlobStore {
+        fn fetch_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+            self.get_manifest(hash)
+        }
+
+        fn fetch_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+            self.get_chunk(hash)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn tiny_png() -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0]);
+        png
+    }
+
+    /// Serves from a store but fails every other chunk request while `flaky`.
+    struct FlakySource {
+        inner: MemoryBlobStore,
+        calls: Cell<usize>,
+        flaky: Cell<bool>,
+    }
+
+    impl ChunkSource for FlakySource {
+        fn fetch_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+            self.inner.get_manifest(hash)
+        }
+
+        fn fetch_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+            self.calls.set(self.calls.get() + 1);
+            if self.flaky.get() && self.calls.get().is_multiple_of(2) {
+                return None;
+            }
+            self.inner.get_chunk(hash)
+        }
+    }
+
+    #[test]
+    fn manifest_describes_chunks_and_image() {
+        let (manifest, chunks) = AttachmentManifest::build(&tiny_png(), None, None).unwrap();
+        assert_eq!(manifest.mime_type, "image/png");
+        assert_eq!((manifest.width, manifest.height), (Some(640), Some(480)));
+        assert_eq!(chunks.len(), 1);
+        manifest.validate().unwrap();
+
+        let data = sample(ATTACHMENT_CHUNK_SIZE * 2 + 10);
+        let (manifest, chunks) = AttachmentManifest::build(&data, Some("video/mp4"), None).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(manifest.verify_chunk(2, &chunks[2]));
+        assert!(!manifest.verify_chunk(1, &chunks[2]));
+        assert!(manifest.verify_content(&data));
+    }
+
+    #[test]
+    fn resumable_fetch_verifies_chunks() {
+        let data = sample(ATTACHMENT_CHUNK_SIZE * 3 + 1);
+        let origin = MemoryBlobStore::new();
+        let hash = origin.import(&data, None, Some("clip.bin".into())).unwrap();
+
+        let source = FlakySource {
+            inner: origin,
+            calls: Cell::new(0),
+            flaky: Cell::new(true),
+        };
+        let local = MemoryBlobStore::new();
+        let first = fetch_attachment(&local, &source, &hash).unwrap();
+        assert!(!first.is_complete());
+        assert!(first.have_chunks > 0);
+
+        source.flaky.set(false);
+        let second = fetch_attachment(&local, &source, &hash).unwrap();
+        assert!(second.is_complete());
+        assert_eq!(local.assemble(&hash).unwrap(), data);
+    }
+
+    #[test]
+    fn corrupted_chunks_and_manifests_are_rejected() {
+        let data = sample(1000);
+        let origin = MemoryBlobStore::new();
+        let hash = origin.import(&data, None, None).unwrap();
+        let manifest = origin.get_manifest(&hash).unwrap();
+        origin.put_chunk(&manifest.chunks[0], b"garbage").unwrap();
+
+        let local = MemoryBlobStore::new();
+        let progress = fetch_attachment(&local, &origin, &hash).unwrap();
+        assert_eq!(progress.have_chunks, 0);
+
+        let mut forged = manifest.clone();
+        forged.mime_type = "text/html".into();
+        let evil = MemoryBlobStore::new();
+        evil.put_manifest(&hash, &forged).unwrap();
+        assert!(fetch_attachment(&MemoryBlobStore::new(), &evil, &hash).is_err());
+    }
+
+    #[test]
+    fn file_blob_store_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileBlobStore::new(dir.path()).unwrap();
+        let data = sample(5000);
+        let hash = store.import(&data, None, None).unwrap();
+        assert_eq!(store.assemble(&hash).unwrap(), data);
+        assert!(store.get_manifest("../escape").is_none());
+    }
+}
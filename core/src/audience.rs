@@ -0,0 +1,296 @@
+use crate::crypto::{KeyPair, decrypt_with_key, encrypt_message, encrypt_with_key, ephemeral_encryption_keys, random_key};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+
+const AUDIENCE_ENC_ALG: &str = "chacha20poly1305-x25519-audience-v1";
+
+const MAX_CIRCLE_NAME_CHARS: usize = 64;
+
+/// A named group of contacts that posts can be restricted to.
+///
+/// Circles are private to their owner: neither the name nor the membership
+/// list is ever published. Only the resulting recipient set is visible on a
+/// restricted post.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Circle {
+    pub name: String,
+    /// Member fingerprints.
+    #[serde(default)]
+    pub members: Vec<String>,
+}
+
+impl Circle {
+    pub fn contains(&self, fingerprint: &str) -> bool {
+        self.members.iter().any(|m| m == fingerprint)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CircleBook {
+    #[serde(default)]
+    pub circles: Vec<Circle>,
+}
+
+impl CircleBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up a circle by name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&Circle> {
+        let folded = name.trim().to_lowercase();
+        self.circles.iter().find(|c| c.name.to_lowercase() == folded)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Circle> {
+        let folded = name.trim().to_lowercase();
+        self.circles.iter_mut().find(|c| c.name.to_lowercase() == folded)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.circles.iter().map(|c| c.name.clone()).collect()
+    }
+
+    /// Circles `fingerprint` belongs to.
+    pub fn circles_of(&self, fingerprint: &str) -> Vec<&str> {
+        self.circles
+            .iter()
+            .filter(|c| c.contains(fingerprint))
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    /// Add a member, creating the circle if it does not exist yet.
+    pub fn add_member(&mut self, name: &str, fingerprint: &str) -> Result<(), String> {
+        let name = validate_circle_name(name)?;
+        if self.get(&name).is_none() {
+            self.circles.push(Circle {
+                name: name.clone(),
+                members: Vec::new(),
+            });
+        }
+        let circle = self.get_mut(&name).expect("circle exists");
+        if !circle.contains(fingerprint) {
+            circle.members.push(fingerprint.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn remove_member(&mut self, name: &str, fingerprint: &str) {
+        if let Some(circle) = self.get_mut(name) {
+            circle.members.retain(|m| m != fingerprint);
+        }
+    }
+
+    /// Remove a whole circle. Posts already restricted to it are unaffected.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let folded = name.trim().to_lowercase();
+        let before = self.circles.len();
+        self.circles.retain(|c| c.name.to_lowercase() != folded);
+        self.circles.len() != before
+    }
+
+    /// Drop `fingerprint` from every circle, e.g. when the contact is removed.
+    pub fn forget(&mut self, fingerprint: &str) {
+        for circle in &mut self.circles {
+            circle.members.retain(|m| m != fingerprint);
+        }
+    }
+
+    /// Recipients for a circle, looking up each member's encryption key in
+    /// `known`. Fails rather than silently narrowing the audience when a
+    /// member's key is not known yet.
+    pub fn recipients(&self, name: &str, known: &[Recipient]) -> Result<Vec<Recipient>, String> {
+        let circle = self
+            .get(name)
+            .ok_or_else(|| format!("No circle named {}", name.trim()))?;
+        if circle.members.is_empty() {
+            return Err(format!("Circle {} has no members", circle.name));
+        }
+        let mut recipients = Vec::with_capacity(circle.members.len());
+        let mut missing = Vec::new();
+        for member in &circle.members {
+            match known.iter().find(|r| &r.fingerprint == member) {
+                Some(r) => recipients.push(r.clone()),
+                None => missing.push(member.as_str()),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(format!("No encryption key known for: {}", missing.join(", ")));
+        }
+        Ok(recipients)
+    }
+}
+
+fn validate_circle_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Circle name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_CIRCLE_NAME_CHARS {
+        return Err(format!("Circle name must be at most {} characters", MAX_CIRCLE_NAME_CHARS));
+    }
+    Ok(name.to_string())
+}
+
+/// Someone content can be sealed for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recipient {
+    pub fingerprint: String,
+    /// Base64 X25519 key, as published in `KeyInfo::encryption_public_key`.
+    pub encryption_public_key: String,
+}
+
+impl Recipient {
+    pub fn new(fingerprint: String, encryption_public_key: String) -> Self {
+        Self {
+            fingerprint,
+            encryption_public_key,
+        }
+    }
+
+    /// The holder of `keypair`, e.g. so authors can read their own posts.
+    pub fn from_keypair(keypair: &KeyPair) -> Result<Self, String> {
+        let key = keypair
+            .enc_public_key
+            .clone()
+            .ok_or_else(|| "missing local encryption public key".to_string())?;
+        Ok(Self::new(keypair.fingerprint.clone(), key))
+    }
+}
+
+/// The content key, encrypted for one recipient.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    pub recipient_fingerprint: String,
+    pub nonce: String,
+    pub wrapped_key: String,
+}
+
+/// Content readable only by a fixed set of recipients.
+///
+/// The content is encrypted once under a random key, and that key is wrapped
+/// for each recipient with an ephemeral X25519 key. Recipient fingerprints
+/// are visible so readers can find their entry without trial decryption.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedContent {
+    pub alg: String,
+    pub ephemeral_public_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub recipients: Vec<WrappedKey>,
+}
+
+impl SealedContent {
+    pub fn seal(plaintext: &str, recipients: &[Recipient]) -> Result<Self, String> {
+        if recipients.is_empty() {
+            return Err("audience must have at least one recipient".to_string());
+        }
+        let key = random_key();
+        let (ciphertext, nonce) = encrypt_with_key(&key, plaintext.as_bytes())?;
+        let (ephemeral_secret, ephemeral_public_key) = ephemeral_encryption_keys();
+        let key_b64 = BASE64.encode(key);
+
+        let mut wrapped: Vec<WrappedKey> = Vec::with_capacity(recipients.len());
+        for recipient in recipients {
+            if wrapped.iter().any(|w| w.recipient_fingerprint == recipient.fingerprint) {
+                continue;
+            }
+            let (wrapped_key, nonce, _) =
+                encrypt_message(&ephemeral_secret, &recipient.encryption_public_key, &key_b64)?;
+            wrapped.push(WrappedKey {
+                recipient_fingerprint: recipient.fingerprint.clone(),
+                nonce,
+                wrapped_key,
+            });
+        }
+
+        Ok(Self {
+            alg: AUDIENCE_ENC_ALG.to_string(),
+            ephemeral_public_key,
+            nonce,
+            ciphertext,
+            recipients: wrapped,
+        })
+    }
+
+    pub fn is_recipient(&self, fingerprint: &str) -> bool {
+        self.recipients.iter().any(|w| w.recipient_fingerprint == fingerprint)
+    }
+
+    /// Decrypt for the holder of `keypair`, or `None` if they are not in the
+    /// audience.
+    pub fn open(&self, keypair: &KeyPair) -> Result<Option<String>, String> {
+        if self.alg != AUDIENCE_ENC_ALG {
+            return Err(format!("unsupported audience encryption: {}", self.alg));
+        }
+        let Some(entry) = self
+            .recipients
+            .iter()
+            .find(|w| w.recipient_fingerprint == keypair.fingerprint)
+        else {
+            return Ok(None);
+        };
+        let key_b64 = keypair.decrypt_from_peer(&self.ephemeral_public_key, &entry.nonce, &entry.wrapped_key)?;
+        let key: [u8; 32] = BASE64
+            .decode(key_b64)
+            .map_err(|e| format!("content key decode failed: {e}"))?
+            .try_into()
+            .map_err(|_| "invalid content key length".to_string())?;
+        let plaintext = decrypt_with_key(&key, &self.nonce, &self.ciphertext)?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| format!("utf8 decode failed: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_recipients_can_open() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let eve = KeyPair::generate().unwrap();
+        let recipients = [
+            Recipient::from_keypair(&alice).unwrap(),
+            Recipient::from_keypair(&bob).unwrap(),
+            Recipient::from_keypair(&bob).unwrap(),
+        ];
+
+        let sealed = SealedContent::seal("for friends", &recipients).unwrap();
+        assert_eq!(sealed.recipients.len(), 2);
+        assert_eq!(sealed.open(&alice).unwrap().as_deref(), Some("for friends"));
+        assert_eq!(sealed.open(&bob).unwrap().as_deref(), Some("for friends"));
+        assert_eq!(sealed.open(&eve).unwrap(), None);
+        assert!(SealedContent::seal("nobody", &[]).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered.recipients[1].recipient_fingerprint = eve.fingerprint.clone();
+        assert!(tampered.open(&eve).is_err());
+    }
+
+    #[test]
+    fn circle_membership() {
+        let mut book = CircleBook::new();
+        book.add_member("Close Friends", "fp-a").unwrap();
+        book.add_member("close friends", "fp-b").unwrap();
+        book.add_member("Family", "fp-a").unwrap();
+        assert!(book.add_member("  ", "fp-c").is_err());
+
+        assert_eq!(book.names(), vec!["Close Friends", "Family"]);
+        assert_eq!(book.get("CLOSE FRIENDS").unwrap().members, vec!["fp-a", "fp-b"]);
+        assert_eq!(book.circles_of("fp-a"), vec!["Close Friends", "Family"]);
+
+        let known = [Recipient::new("fp-a".into(), "key-a".into())];
+        assert!(book.recipients("family", &known).is_ok());
+        assert!(book.recipients("close friends", &known).unwrap_err().contains("fp-b"));
+        assert!(book.recipients("work", &known).is_err());
+
+        book.forget("fp-a");
+        assert!(book.circles_of("fp-a").is_empty());
+        assert!(book.remove("family"));
+        assert_eq!(book.names(), vec!["Close Friends"]);
+    }
+}
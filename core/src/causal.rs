@@ -0,0 +1,246 @@
+use crate::message::{Message, MessageType, SignedMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Most predecessors a message refers to; concurrent branches beyond this
+/// are still ordered by their Lamport time.
+pub const MAX_PREVIOUS_IDS: usize = 8;
+
+/// Largest Lamport time accepted from a peer, so counters stay exact as
+/// JSON numbers everywhere and can never wrap.
+pub const MAX_LAMPORT: u64 = (1 << 53) - 1;
+
+/// Furthest one observed message may move the clock ahead, so a peer cannot
+/// push the counter to its limit in one step.
+pub const MAX_LAMPORT_JUMP: u64 = 1 << 20;
+
+pub(crate) fn is_unstamped(lamport: &u64) -> bool {
+    *lamport == 0
+}
+
+/// What causal ordering needs to know about an entry of a conversation,
+/// whether a signed message or a host's stored copy of one.
+pub trait CausalEntry {
+    fn causal_id(&self) -> &str;
+    fn lamport(&self) -> u64;
+    fn previous_ids(&self) -> &[String];
+    /// The signed send time, used only to break ties; `None` for stored
+    /// entries that never recorded it, which then keep their stored order.
+    fn sent_at(&self) -> Option<DateTime<Utc>>;
+}
+
+impl CausalEntry for Message {
+    fn causal_id(&self) -> &str {
+        &self.id
+    }
+
+    fn lamport(&self) -> u64 {
+        self.lamport
+    }
+
+    fn previous_ids(&self) -> &[String] {
+        &self.previous_ids
+    }
+
+    fn sent_at(&self) -> Option<DateTime<Utc>> {
+        Some(self.created_at)
+    }
+}
+
+impl CausalEntry for SignedMessage {
+    fn causal_id(&self) -> &str {
+        self.message.causal_id()
+    }
+
+    fn lamport(&self) -> u64 {
+        self.message.lamport
+    }
+
+    fn previous_ids(&self) -> &[String] {
+        &self.message.previous_ids
+    }
+
+    fn sent_at(&self) -> Option<DateTime<Utc>> {
+        self.message.sent_at()
+    }
+}
+
+/// Lamport clock of one two-party conversation. `heads` are the newest
+/// messages not yet referenced by a later one; a new message refers to them
+/// and gets a time above everything observed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LamportClock {
+    #[serde(default)]
+    counter: u64,
+    #[serde(default)]
+    heads: Vec<(u64, String)>,
+}
+
+impl LamportClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild the clock from a stored conversation.
+    pub fn from_entries<T: CausalEntry>(entries: &[T]) -> Self {
+        let mut clock = Self::new();
+        let referenced: HashSet<&str> = entries
+            .iter()
+            .flat_map(|e| e.previous_ids().iter().map(String::as_str))
+            .collect();
+        for entry in entries {
+            clock.counter = clock.counter.max(entry.lamport().min(MAX_LAMPORT));
+            if !referenced.contains(entry.causal_id()) {
+                clock.add_head(entry.lamport(), entry.causal_id());
+            }
+        }
+        clock
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Give a message we are about to sign its place after everything seen.
+    pub fn stamp(&mut self, message: &mut Message) {
+        self.counter = self.counter.saturating_add(1).min(MAX_LAMPORT);
+        message.lamport = self.counter;
+        message.previous_ids = self.heads.iter().map(|(_, id)| id.clone()).collect();
+        self.heads = vec![(message.lamport, message.id.clone())];
+    }
+
+    /// Take a sent or received conversation message into account. Jumps
+    /// beyond `MAX_LAMPORT_JUMP` only advance the clock that far.
+    pub fn observe<T: CausalEntry>(&mut self, entry: &T) {
+        let limit = self.counter.saturating_add(MAX_LAMPORT_JUMP).min(MAX_LAMPORT);
+        self.counter = self.counter.max(entry.lamport().min(limit));
+        let previous = entry.previous_ids();
+        self.heads.retain(|(_, id)| !previous.contains(id));
+        self.add_head(entry.lamport(), entry.causal_id());
+    }
+
+    fn add_head(&mut self, lamport: u64, id: &str) {
+        if self.heads.iter().any(|(_, h)| h == id) {
+            return;
+        }
+        self.heads.push((lamport, id.to_string()));
+        if self.heads.len() > MAX_PREVIOUS_IDS {
+            self.heads.sort_by(|a, b| b.cmp(a));
+            self.heads.truncate(MAX_PREVIOUS_IDS);
+        }
+    }
+}
+
+/// Whether a message takes part in a conversation's causal order. Controls
+/// and receipts refer to messages by id instead.
+pub fn is_causal(message: &Message) -> bool {
+    matches!(message.message_type, MessageType::Direct)
+}
+
+/// Order by Lamport time, then signed send time, then id, so both parties
+/// see the same sequence however messages arrived.
+pub fn causal_cmp<T: CausalEntry>(a: &T, b: &T) -> Ordering {
+    a.lamport().cmp(&b.lamport()).then_with(|| match (a.sent_at(), b.sent_at()) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.causal_id().cmp(b.causal_id())),
+        (x, y) => x.cmp(&y),
+    })
+}
+
+/// Sort a conversation causally. The sort is stable, so legacy entries
+/// without a Lamport time or send time keep their stored order.
+pub fn sort_causally<T: CausalEntry>(entries: &mut [T]) {
+    entries.sort_by(causal_cmp);
+}
+
+/// Predecessors referenced in the conversation that are not in it, for the
+/// client to ask the sender for again. `is_known` lets the host vouch for
+/// ids that were removed on purpose, e.g. unsent or expired messages.
+pub fn missing_predecessors<T: CausalEntry>(entries: &[T], is_known: impl Fn(&str) -> bool) -> Vec<String> {
+    let present: HashSet<&str> = entries.iter().map(|e| e.causal_id()).collect();
+    let mut reported = HashSet::new();
+    let mut missing: Vec<String> = Vec::new();
+    for entry in entries {
+        for id in entry.previous_ids() {
+            if !present.contains(id.as_str()) && reported.insert(id.as_str()) && !is_known(id) {
+                missing.push(id.clone());
+            }
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn direct(from: &str, to: &str) -> Message {
+        Message::new_direct(from.into(), to.into(), "hi".into())
+    }
+
+    #[test]
+    fn concurrent_messages_order_the_same_for_both_sides() {
+        let mut alice = LamportClock::new();
+        let mut bob = LamportClock::new();
+
+        let mut a1 = direct("alice", "bob");
+        alice.stamp(&mut a1);
+        bob.observe(&a1);
+        // Both reply at once, having seen only a1.
+        let mut a2 = direct("alice", "bob");
+        alice.stamp(&mut a2);
+        let mut b1 = direct("bob", "alice");
+        b1.created_at = a2.created_at - Duration::seconds(5);
+        bob.stamp(&mut b1);
+        assert_eq!((a2.lamport, b1.lamport), (2, 2));
+        assert_eq!(b1.previous_ids, vec![a1.id.clone()]);
+
+        alice.observe(&b1);
+        let mut a3 = direct("alice", "bob");
+        alice.stamp(&mut a3);
+        assert_eq!(a3.lamport, 3);
+        assert_eq!(a3.previous_ids.len(), 2);
+
+        let mut seen_by_alice = vec![a3.clone(), a1.clone(), b1.clone(), a2.clone()];
+        let mut seen_by_bob = vec![b1.clone(), a2.clone(), a3.clone(), a1.clone()];
+        sort_causally(&mut seen_by_alice);
+        sort_causally(&mut seen_by_bob);
+        let ids = |v: &[Message]| v.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&seen_by_alice), ids(&seen_by_bob));
+        assert_eq!(ids(&seen_by_alice), vec![a1.id.clone(), b1.id.clone(), a2.id.clone(), a3.id.clone()]);
+        assert_eq!(LamportClock::from_entries(&seen_by_bob), alice);
+    }
+
+    #[test]
+    fn gaps_are_reported_unless_known() {
+        let mut clock = LamportClock::new();
+        let mut first = direct("alice", "bob");
+        clock.stamp(&mut first);
+        let mut second = direct("alice", "bob");
+        clock.stamp(&mut second);
+
+        let thread = vec![second.clone()];
+        assert_eq!(missing_predecessors(&thread, |_| false), vec![first.id.clone()]);
+        assert!(missing_predecessors(&thread, |id| id == first.id).is_empty());
+        assert!(missing_predecessors(&[first, second], |_| false).is_empty());
+    }
+
+    #[test]
+    fn hostile_lamport_times_cannot_overflow_the_clock() {
+        let mut clock = LamportClock::new();
+        let mut hostile = direct("mallory", "alice");
+        hostile.lamport = u64::MAX;
+        clock.observe(&hostile);
+        assert_eq!(clock.counter(), MAX_LAMPORT_JUMP);
+
+        let mut reply = direct("alice", "mallory");
+        clock.stamp(&mut reply);
+        assert_eq!(reply.lamport, MAX_LAMPORT_JUMP + 1);
+
+        let mut saturated = LamportClock { counter: MAX_LAMPORT, heads: Vec::new() };
+        saturated.stamp(&mut reply);
+        assert_eq!(reply.lamport, MAX_LAMPORT);
+    }
+}
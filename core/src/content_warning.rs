@@ -0,0 +1,212 @@
+use crate::post::Post;
+use serde::{Deserialize, Serialize};
+
+const MAX_WARNING_CHARS: usize = 200;
+
+/// Kinds of sensitive content a post can be flagged with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensitiveCategory {
+    Nudity,
+    Violence,
+    SelfHarm,
+    Substances,
+    Spoiler,
+}
+
+impl SensitiveCategory {
+    pub const ALL: [SensitiveCategory; 5] = [
+        SensitiveCategory::Nudity,
+        SensitiveCategory::Violence,
+        SensitiveCategory::SelfHarm,
+        SensitiveCategory::Substances,
+        SensitiveCategory::Spoiler,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SensitiveCategory::Nudity => "nudity",
+            SensitiveCategory::Violence => "violence",
+            SensitiveCategory::SelfHarm => "self_harm",
+            SensitiveCategory::Substances => "substances",
+            SensitiveCategory::Spoiler => "spoiler",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SensitiveCategory::Nudity => "Nudity",
+            SensitiveCategory::Violence => "Violence",
+            SensitiveCategory::SelfHarm => "Self-harm",
+            SensitiveCategory::Substances => "Substances",
+            SensitiveCategory::Spoiler => "Spoiler",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let folded = value.trim().to_lowercase().replace('-', "_");
+        Self::ALL
+            .into_iter()
+            .find(|c| c.as_str() == folded)
+            .ok_or_else(|| format!("Unknown content category: {}", value.trim()))
+    }
+}
+
+/// A content warning shown in place of a post until the reader expands it.
+///
+/// Part of the signed post, so it cannot be stripped or added by peers. It
+/// stays in the clear on circle posts so readers can decide before opening.
+/// Deserializing goes through [`ContentWarning::new`], so warnings received
+/// from peers are held to the same limits as local ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedWarning")]
+pub struct ContentWarning {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub summary: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<SensitiveCategory>,
+}
+
+impl ContentWarning {
+    /// A warning needs a summary, at least one category, or both.
+    pub fn new(summary: &str, categories: &[SensitiveCategory]) -> Result<Self, String> {
+        let summary = summary.trim().to_string();
+        if summary.chars().count() > MAX_WARNING_CHARS {
+            return Err(format!("Content warning must be at most {} characters", MAX_WARNING_CHARS));
+        }
+        let mut categories = categories.to_vec();
+        categories.sort();
+        categories.dedup();
+        if summary.is_empty() && categories.is_empty() {
+            return Err("Content warning needs a summary or a category".to_string());
+        }
+        Ok(Self { summary, categories })
+    }
+
+    pub fn has_any(&self, categories: &[SensitiveCategory]) -> bool {
+        self.categories.iter().any(|c| categories.contains(c))
+    }
+
+    /// e.g. "CW: finale spoilers (Spoiler)".
+    pub fn label(&self) -> String {
+        let categories: Vec<&str> = self.categories.iter().map(|c| c.label()).collect();
+        match (self.summary.is_empty(), categories.is_empty()) {
+            (false, false) => format!("CW: {} ({})", self.summary, categories.join(", ")),
+            (false, true) => format!("CW: {}", self.summary),
+            _ => format!("CW: {}", categories.join(", ")),
+        }
+    }
+}
+
+/// A content warning as it arrives, before validation.
+#[derive(Deserialize)]
+struct UncheckedWarning {
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    categories: Vec<SensitiveCategory>,
+}
+
+impl TryFrom<UncheckedWarning> for ContentWarning {
+    type Error = String;
+
+    fn try_from(raw: UncheckedWarning) -> Result<Self, String> {
+        ContentWarning::new(&raw.summary, &raw.categories)
+    }
+}
+
+/// Which flagged posts a user wants expanded without a click.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentPreferences {
+    /// Expand every flagged post.
+    #[serde(default)]
+    pub expand_all: bool,
+    /// Expand posts whose flags are all among these. Posts with only a
+    /// free-text warning are still collapsed.
+    #[serde(default)]
+    pub auto_expand: Vec<SensitiveCategory>,
+}
+
+impl ContentPreferences {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_auto_expand(&mut self, category: SensitiveCategory, expand: bool) {
+        self.auto_expand.retain(|c| *c != category);
+        if expand {
+            self.auto_expand.push(category);
+            self.auto_expand.sort();
+        }
+    }
+
+    pub fn should_collapse(&self, post: &Post) -> bool {
+        let Some(warning) = &post.content_warning else {
+            return false;
+        };
+        if self.expand_all {
+            return false;
+        }
+        warning.categories.is_empty()
+            || !warning.categories.iter().all(|c| self.auto_expand.contains(c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::post::SignedPost;
+
+    #[test]
+    fn warning_is_signed_and_collapses_by_preference() {
+        let kp = KeyPair::generate().unwrap();
+        assert!(ContentWarning::new("  ", &[]).is_err());
+        let warning = ContentWarning::new(
+            "finale",
+            &[SensitiveCategory::Spoiler, SensitiveCategory::Violence, SensitiveCategory::Spoiler],
+        )
+        .unwrap();
+        assert_eq!(warning.categories, vec![SensitiveCategory::Violence, SensitiveCategory::Spoiler]);
+        assert_eq!(warning.label(), "CW: finale (Violence, Spoiler)");
+
+        let mut post = Post::new(kp.fingerprint.clone(), "he dies".into(), None, None);
+        post.set_content_warning(warning);
+        let signed = SignedPost::create(post, &kp).unwrap();
+        let mut stripped = signed.clone();
+        stripped.post.content_warning = None;
+        assert!(signed.verify(&kp.public_key).unwrap());
+        assert!(!stripped.verify(&kp.public_key).unwrap());
+
+        let mut prefs = ContentPreferences::new();
+        assert!(prefs.should_collapse(&signed.post));
+        prefs.set_auto_expand(SensitiveCategory::Spoiler, true);
+        assert!(prefs.should_collapse(&signed.post));
+        prefs.set_auto_expand(SensitiveCategory::Violence, true);
+        assert!(!prefs.should_collapse(&signed.post));
+        assert!(!prefs.should_collapse(&stripped.post));
+
+        let mut text_only = signed.post.clone();
+        text_only.content_warning = Some(ContentWarning::new("eye strain", &[]).unwrap());
+        assert!(prefs.should_collapse(&text_only));
+        prefs.expand_all = true;
+        assert!(!prefs.should_collapse(&text_only));
+        assert_eq!(SensitiveCategory::parse("Self-Harm").unwrap(), SensitiveCategory::SelfHarm);
+    }
+
+    #[test]
+    fn received_warnings_are_validated() {
+        let kp = KeyPair::generate().unwrap();
+        let mut post = Post::new(kp.fingerprint.clone(), "hi".into(), None, None);
+        post.content_warning = Some(ContentWarning {
+            summary: "x".repeat(MAX_WARNING_CHARS + 1),
+            categories: Vec::new(),
+        });
+        let json = serde_json::to_string(&SignedPost::create(post, &kp).unwrap()).unwrap();
+        assert!(serde_json::from_str::<SignedPost>(&json).is_err());
+        assert!(serde_json::from_str::<ContentWarning>("{}").is_err());
+
+        let warning: ContentWarning = serde_json::from_str(r#"{"categories":["spoiler","nudity","spoiler"]}"#).unwrap();
+        assert_eq!(warning.categories, vec![SensitiveCategory::Nudity, SensitiveCategory::Spoiler]);
+    }
+}
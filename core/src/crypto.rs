@@ -1,5 +1,5 @@
 use ed25519_dalek::{SigningKey, VerifyingKey, Signature, Signer, Verifier};
-use chacha20poly1305::{ChaCha20Poly1305, Nonce, aead::{Aead, KeyInit}};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce, aead::{Aead, KeyInit, Payload}};
 use rand::rngs::OsRng;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
@@ -42,11 +42,7 @@ impl KeyPair {
         let enc_secret = StaticSecret::random_from_rng(OsRng);
         let enc_public = X25519PublicKey::from(&enc_secret);
         
-        // Create fingerprint from public key hash
-        let mut hasher = Sha256::new();
-        hasher.update(verifying_key.as_bytes());
-        let hash = hasher.finalize();
-        let fingerprint = BASE64.encode(&hash[..16]); // First 16 bytes as fingerprint
+        let fingerprint = fingerprint_for_key_bytes(verifying_key.as_bytes());
         
         Ok(KeyPair {
             public_key,
@@ -126,21 +122,10 @@ pub fn encrypt_message(
     let peer_public = X25519PublicKey::from(peer_public);
     let shared = local_secret.diffie_hellman(&peer_public);
 
-    let key_bytes = Sha256::digest(shared.as_bytes());
-    let cipher = ChaCha20Poly1305::new_from_slice(&key_bytes)
-        .map_err(|e| format!("cipher init failed: {e}"))?;
+    let key_bytes: [u8; 32] = Sha256::digest(shared.as_bytes()).into();
+    let (ciphertext, nonce) = encrypt_with_key(&key_bytes, plaintext.as_bytes())?;
 
-    let mut nonce = [0u8; 12];
-    OsRng.fill_bytes(&mut nonce);
-    let ciphertext = cipher
-        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
-        .map_err(|e| format!("encrypt failed: {e}"))?;
-
-    Ok((
-        BASE64.encode(ciphertext),
-        BASE64.encode(nonce),
-        MESSAGE_ENC_ALG.to_string(),
-    ))
+    Ok((ciphertext, nonce, MESSAGE_ENC_ALG.to_string()))
 }
 
 pub fn decrypt_message(
@@ -151,23 +136,88 @@ pub fn decrypt_message(
 ) -> Result<String, String> {
     let local_secret = decode_32(local_secret_b64, "local encryption secret key")?;
     let peer_public = decode_32(peer_public_b64, "peer encryption public key")?;
-    let nonce = decode_nonce_12(nonce_b64)?;
-    let ciphertext = BASE64
-        .decode(ciphertext_b64)
-        .map_err(|e| format!("ciphertext decode failed: {e}"))?;
 
     let local_secret = StaticSecret::from(local_secret);
     let peer_public = X25519PublicKey::from(peer_public);
     let shared = local_secret.diffie_hellman(&peer_public);
-    let key_bytes = Sha256::digest(shared.as_bytes());
-    let cipher = ChaCha20Poly1305::new_from_slice(&key_bytes)
+    let key_bytes: [u8; 32] = Sha256::digest(shared.as_bytes()).into();
+    let plaintext = decrypt_with_key(&key_bytes, nonce_b64, ciphertext_b64)?;
+
+    String::from_utf8(plaintext).map_err(|e| format!("utf8 decode failed: {e}"))
+}
+
+/// A fresh random 32-byte symmetric key, e.g. a per-post content key.
+pub(crate) fn random_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// A one-off X25519 key pair as base64 `(secret, public)`, for wrapping keys
+/// without tying the ciphertext to the sender's long-term encryption key.
+pub(crate) fn ephemeral_encryption_keys() -> (String, String) {
+    let secret = StaticSecret::random_from_rng(OsRng);
+    let public = X25519PublicKey::from(&secret);
+    (BASE64.encode(secret.to_bytes()), BASE64.encode(public.as_bytes()))
+}
+
+/// ChaCha20-Poly1305 under `key` with a random nonce; returns base64
+/// `(ciphertext, nonce)`.
+pub(crate) fn encrypt_with_key(key: &[u8; 32], plaintext: &[u8]) -> Result<(String, String), String> {
+    encrypt_with_key_aad(key, plaintext, b"")
+}
+
+pub(crate) fn decrypt_with_key(
+    key: &[u8; 32],
+    nonce_b64: &str,
+    ciphertext_b64: &str,
+) -> Result<Vec<u8>, String> {
+    decrypt_with_key_aad(key, nonce_b64, ciphertext_b64, b"")
+}
+
+/// As `encrypt_with_key`, additionally authenticating `aad`, which must be
+/// passed again to decrypt.
+pub(crate) fn encrypt_with_key_aad(
+    key: &[u8; 32],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<(String, String), String> {
+    let cipher = ChaCha20Poly1305::new_from_slice(key)
         .map_err(|e| format!("cipher init failed: {e}"))?;
 
-    let plaintext = cipher
-        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
-        .map_err(|e| format!("decrypt failed: {e}"))?;
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|e| format!("encrypt failed: {e}"))?;
 
-    String::from_utf8(plaintext).map_err(|e| format!("utf8 decode failed: {e}"))
+    Ok((BASE64.encode(ciphertext), BASE64.encode(nonce)))
+}
+
+pub(crate) fn decrypt_with_key_aad(
+    key: &[u8; 32],
+    nonce_b64: &str,
+    ciphertext_b64: &str,
+    aad: &[u8],
+) -> Result<Vec<u8>, String> {
+    let nonce = decode_nonce_12(nonce_b64)?;
+    let ciphertext = BASE64
+        .decode(ciphertext_b64)
+        .map_err(|e| format!("ciphertext decode failed: {e}"))?;
+    let cipher = ChaCha20Poly1305::new_from_slice(key)
+        .map_err(|e| format!("cipher init failed: {e}"))?;
+
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad })
+        .map_err(|e| format!("decrypt failed: {e}"))
+}
+
+/// PBKDF2-HMAC-SHA256 (RFC 8018) producing one 32-byte block, for turning
+/// a passphrase into a key.
+pub(crate) fn pbkdf2_sha256(passphrase: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase, salt, iterations, &mut out);
+    out
 }
 
 fn decode_32(value_b64: &str, label: &str) -> Result<[u8; 32], String> {
@@ -186,6 +236,25 @@ fn decode_nonce_12(value_b64: &str) -> Result<[u8; 12], String> {
     value.try_into().map_err(|_| "invalid nonce length".to_string())
 }
 
+/// Fingerprint of an Ed25519 public key: base64 of the first 16 bytes of its SHA-256.
+fn fingerprint_for_key_bytes(public_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(public_key);
+    let hash = hasher.finalize();
+    BASE64.encode(&hash[..16])
+}
+
+/// Derive the fingerprint for a base64 public key, e.g. to check that a key
+/// really belongs to the fingerprint a record claims to be from.
+pub fn fingerprint_from_public_key(public_key: &str) -> Result<String, String> {
+    let public_bytes = BASE64.decode(public_key)
+        .map_err(|e| format!("Failed to decode public key: {}", e))?;
+    if public_bytes.len() != 32 {
+        return Err("Invalid public key length".to_string());
+    }
+    Ok(fingerprint_for_key_bytes(&public_bytes))
+}
+
 pub fn verify_signature(data: &str, signature: &str, public_key: &str) -> Result<bool, String> {
     let public_bytes = BASE64.decode(public_key)
         .map_err(|e| format!("Failed to decode public key: {}", e))?;
@@ -242,6 +311,20 @@ pub fn verify_signature_wasm(data: &str, signature: &str, public_key: &str) -> R
 mod tests {
     use super::*;
 
+    #[test]
+    fn pbkdf2_matches_reference_vectors() {
+        // RFC 7914 section 11, truncated to one block.
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        // Passphrases longer than a hash block are hashed first.
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(&[b'x'; 100], b"NaCl", 1000)),
+            "78a17750cf7a2cb60802967f2013b5da9bb57cb4112b684a3a4120cf08615e69"
+        );
+    }
+
     #[test]
     fn keypair_generate_produces_unique_keys() {
         let kp1 = KeyPair::generate().expect("first keygen failed");
@@ -276,6 +359,14 @@ mod tests {
         assert!(!valid, "signature should be invalid for wrong key");
     }
 
+    #[test]
+    fn fingerprint_from_public_key_matches_keypair() {
+        let kp = KeyPair::generate().expect("keygen failed");
+        let fp = fingerprint_from_public_key(&kp.public_key).expect("derive failed");
+        assert_eq!(fp, kp.fingerprint);
+        assert!(fingerprint_from_public_key("bm90LWEta2V5").is_err());
+    }
+
     #[test]
     fn get_public_info_hides_secret_key() {
         let kp = KeyPair::generate().expect("keygen failed");
@@ -0,0 +1,386 @@
+use crate::crypto::{
+    decrypt_with_key, decrypt_with_key_aad, encrypt_with_key, encrypt_with_key_aad, pbkdf2_sha256, random_key,
+};
+use crate::storage::{StorageBackend, StorageError};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+
+/// Plaintext key holding the passphrase-wrapped data key. It is the only
+/// item an encrypted store leaves readable.
+pub const STORAGE_KEY_HEADER: &str = "snartnet_storage_key";
+
+/// PBKDF2 rounds for new stores.
+pub const DEFAULT_KDF_ITERATIONS: u32 = 600_000;
+
+/// Fewest PBKDF2 rounds a store may be created with.
+pub const MIN_KDF_ITERATIONS: u32 = 10_000;
+
+/// Most PBKDF2 rounds a key header may ask for, so a tampered header can
+/// neither weaken the key nor make unlocking hang.
+pub const MAX_KDF_ITERATIONS: u32 = 10_000_000;
+
+const HEADER_VERSION: u32 = 1;
+const VALUE_PREFIX: &str = "snartnet-enc1:";
+const NAME_PREFIX: &str = "enc_";
+const NAME_KEY_CONTEXT: &str = "snartnet storage key names v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionOptions {
+    /// Also hide key names, so the files or rows do not reveal which kinds
+    /// of data exist. Names become keyed hashes and cannot be listed back.
+    pub encrypt_names: bool,
+    pub iterations: u32,
+}
+
+impl Default for EncryptionOptions {
+    fn default() -> Self {
+        Self { encrypt_names: false, iterations: DEFAULT_KDF_ITERATIONS }
+    }
+}
+
+/// Stored under `STORAGE_KEY_HEADER`. Values are encrypted with a random
+/// data key, and only that key is wrapped by the passphrase, so changing the
+/// passphrase does not rewrite the data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyHeader {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    wrapped_key: String,
+    nonce: String,
+    encrypt_names: bool,
+}
+
+impl KeyHeader {
+    fn wrap(data_key: &[u8; 32], passphrase: &str, iterations: u32, encrypt_names: bool) -> Result<Self, StorageError> {
+        let salt = random_key();
+        let kek = pbkdf2_sha256(passphrase.as_bytes(), &salt, iterations);
+        let (wrapped_key, nonce) = encrypt_with_key(&kek, data_key).map_err(StorageError::Backend)?;
+        Ok(Self { version: HEADER_VERSION, iterations, salt: BASE64.encode(salt), wrapped_key, nonce, encrypt_names })
+    }
+
+    fn unwrap(&self, passphrase: &str) -> Result<[u8; 32], StorageError> {
+        if !(MIN_KDF_ITERATIONS..=MAX_KDF_ITERATIONS).contains(&self.iterations) {
+            return Err(StorageError::Unavailable(format!(
+                "Storage key uses {} key derivation rounds, outside {}..={}",
+                self.iterations, MIN_KDF_ITERATIONS, MAX_KDF_ITERATIONS
+            )));
+        }
+        let salt = BASE64
+            .decode(&self.salt)
+            .map_err(|e| StorageError::Serialization(format!("Invalid storage key salt: {e}")))?;
+        let kek = pbkdf2_sha256(passphrase.as_bytes(), &salt, self.iterations);
+        let key = decrypt_with_key(&kek, &self.nonce, &self.wrapped_key)
+            .map_err(|_| StorageError::Unavailable("Wrong passphrase".to_string()))?;
+        key.try_into()
+            .map_err(|_| StorageError::Serialization("Invalid storage key length".to_string()))
+    }
+}
+
+/// Encrypts every value, and optionally every key name, before handing it
+/// to another backend, so a lost device's `FileStorage` directory,
+/// `SqliteStorage` database or browser storage shows nothing but the key
+/// header. Values are bound to their key name and cannot be swapped.
+pub struct EncryptedStorage<S: StorageBackend> {
+    inner: S,
+    data_key: [u8; 32],
+    name_key: Option<[u8; 32]>,
+}
+
+impl<S: StorageBackend> EncryptedStorage<S> {
+    /// Whether `inner` already holds an encrypted store to `unlock`.
+    pub fn is_initialized(inner: &S) -> Result<bool, StorageError> {
+        Ok(inner.get_item(STORAGE_KEY_HEADER)?.is_some())
+    }
+
+    /// Set up encryption on `inner` under a new random data key. Values
+    /// already in `inner` stay as they are until `encrypt_existing`.
+    pub fn create(inner: S, passphrase: &str, options: EncryptionOptions) -> Result<Self, StorageError> {
+        check_passphrase(passphrase)?;
+        if !(MIN_KDF_ITERATIONS..=MAX_KDF_ITERATIONS).contains(&options.iterations) {
+            return Err(StorageError::Backend(format!(
+                "Between {} and {} key derivation rounds are required",
+                MIN_KDF_ITERATIONS, MAX_KDF_ITERATIONS
+            )));
+        }
+        if Self::is_initialized(&inner)? {
+            return Err(StorageError::Backend("Storage is already encrypted".to_string()));
+        }
+        let data_key = random_key();
+        let header = KeyHeader::wrap(&data_key, passphrase, options.iterations, options.encrypt_names)?;
+        inner.set_json(STORAGE_KEY_HEADER, &header)?;
+        Ok(Self::with_key(inner, data_key, options.encrypt_names))
+    }
+
+    /// Open a store set up with `create`. Fails with
+    /// `StorageError::Unavailable` for a wrong passphrase.
+    pub fn unlock(inner: S, passphrase: &str) -> Result<Self, StorageError> {
+        let header: KeyHeader = inner
+            .get_json(STORAGE_KEY_HEADER)?
+            .ok_or_else(|| StorageError::Unavailable("Storage is not encrypted".to_string()))?;
+        if header.version != HEADER_VERSION {
+            return Err(StorageError::Unavailable(format!(
+                "Unsupported storage key version {}",
+                header.version
+            )));
+        }
+        let data_key = header.unwrap(passphrase)?;
+        Ok(Self::with_key(inner, data_key, header.encrypt_names))
+    }
+
+    fn with_key(inner: S, data_key: [u8; 32], encrypt_names: bool) -> Self {
+        let name_key = encrypt_names.then(|| blake3::derive_key(NAME_KEY_CONTEXT, &data_key));
+        Self { inner, data_key, name_key }
+    }
+
+    pub fn encrypts_names(&self) -> bool {
+        self.name_key.is_some()
+    }
+
+    /// Re-wrap the data key under a new passphrase and fresh salt.
+    pub fn change_passphrase(&self, new_passphrase: &str) -> Result<(), StorageError> {
+        check_passphrase(new_passphrase)?;
+        let header: KeyHeader = self
+            .inner
+            .get_json(STORAGE_KEY_HEADER)?
+            .ok_or_else(|| StorageError::Unavailable("Storage key header is missing".to_string()))?;
+        let header = KeyHeader::wrap(&self.data_key, new_passphrase, header.iterations, header.encrypt_names)?;
+        self.inner.set_json(STORAGE_KEY_HEADER, &header)
+    }
+
+    /// Encrypt values written before encryption was set up, e.g. every key
+    /// from `FileStorage::keys` or `SqliteStorage::keys`. Plaintext copies
+    /// are removed; returns how many keys were converted.
+    pub fn encrypt_existing<K: AsRef<str>>(&self, keys: &[K]) -> Result<usize, StorageError> {
+        let mut converted = 0;
+        for key in keys {
+            let key = key.as_ref();
+            if key == STORAGE_KEY_HEADER {
+                continue;
+            }
+            let Some(value) = self.inner.get_item(key)? else {
+                continue;
+            };
+            // Already converted, including hashed names listed back.
+            if value.starts_with(VALUE_PREFIX) {
+                continue;
+            }
+            self.set_item(key, &value)?;
+            if self.name_key.is_some() {
+                self.inner.remove_item(key)?;
+            }
+            converted += 1;
+        }
+        Ok(converted)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn physical_key(&self, key: &str) -> Result<String, StorageError> {
+        if key == STORAGE_KEY_HEADER {
+            return Err(StorageError::Backend(format!("{} is reserved", STORAGE_KEY_HEADER)));
+        }
+        Ok(match &self.name_key {
+            Some(name_key) => {
+                let hash = blake3::keyed_hash(name_key, key.as_bytes());
+                format!("{}{}", NAME_PREFIX, &hash.to_hex()[..32])
+            }
+            None => key.to_string(),
+        })
+    }
+}
+
+impl<S: StorageBackend> StorageBackend for EncryptedStorage<S> {
+    fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
+        let physical = self.physical_key(key)?;
+        let (ciphertext, nonce) =
+            encrypt_with_key_aad(&self.data_key, value.as_bytes(), key.as_bytes()).map_err(StorageError::Backend)?;
+        self.inner.set_item(&physical, &format!("{}{}.{}", VALUE_PREFIX, nonce, ciphertext))
+    }
+
+    fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
+        let Some(stored) = self.inner.get_item(&self.physical_key(key)?)? else {
+            return Ok(None);
+        };
+        // Plaintext is refused rather than returned, so nobody with access to
+        // the disk can plant values.
+        let (nonce, ciphertext) = stored
+            .strip_prefix(VALUE_PREFIX)
+            .and_then(|rest| rest.split_once('.'))
+            .ok_or_else(|| StorageError::Backend(format!("{} is not encrypted", key)))?;
+        let plaintext = decrypt_with_key_aad(&self.data_key, nonce, ciphertext, key.as_bytes())
+            .map_err(|e| StorageError::Backend(format!("Failed to decrypt {}: {}", key, e)))?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| StorageError::Serialization(e.to_string()))
+    }
+
+    fn remove_item(&self, key: &str) -> Result<(), StorageError> {
+        self.inner.remove_item(&self.physical_key(key)?)
+    }
+}
+
+/// A host's storage as found on disk: plain, or encrypted and unlocked.
+/// Hosts hold this rather than choosing at compile time, so the same build
+/// opens either kind.
+pub enum MaybeEncrypted<S: StorageBackend> {
+    Plain(S),
+    Encrypted(EncryptedStorage<S>),
+}
+
+impl<S: StorageBackend> MaybeEncrypted<S> {
+    /// Unlock `inner` if it holds an encrypted store, or use it as it is.
+    /// Fails with `StorageError::Unavailable` when it is encrypted and the
+    /// passphrase is missing or wrong.
+    pub fn open(inner: S, passphrase: Option<&str>) -> Result<Self, StorageError> {
+        if !EncryptedStorage::is_initialized(&inner)? {
+            return Ok(Self::Plain(inner));
+        }
+        let passphrase =
+            passphrase.ok_or_else(|| StorageError::Unavailable("Storage is encrypted; a passphrase is required".to_string()))?;
+        EncryptedStorage::unlock(inner, passphrase).map(Self::Encrypted)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::Encrypted(_))
+    }
+
+    /// The underlying backend, e.g. for `FileStorage::dir`.
+    pub fn inner(&self) -> &S {
+        match self {
+            Self::Plain(inner) => inner,
+            Self::Encrypted(store) => store.inner(),
+        }
+    }
+}
+
+impl<S: StorageBackend> StorageBackend for MaybeEncrypted<S> {
+    fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
+        match self {
+            Self::Plain(inner) => inner.set_item(key, value),
+            Self::Encrypted(store) => store.set_item(key, value),
+        }
+    }
+
+    fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
+        match self {
+            Self::Plain(inner) => inner.get_item(key),
+            Self::Encrypted(store) => store.get_item(key),
+        }
+    }
+
+    fn remove_item(&self, key: &str) -> Result<(), StorageError> {
+        match self {
+            Self::Plain(inner) => inner.remove_item(key),
+            Self::Encrypted(store) => store.remove_item(key),
+        }
+    }
+
+    /// Plain backends keep their own formatting, e.g. pretty-printed files.
+    fn set_json<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StorageError> {
+        match self {
+            Self::Plain(inner) => inner.set_json(key, value),
+            Self::Encrypted(store) => store.set_json(key, value),
+        }
+    }
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), StorageError> {
+    if passphrase.is_empty() {
+        return Err(StorageError::Backend("Passphrase must not be empty".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::storage::{FileStorage, SqliteStorage};
+
+    const FAST: EncryptionOptions = EncryptionOptions { encrypt_names: false, iterations: MIN_KDF_ITERATIONS };
+
+    #[test]
+    fn values_are_unreadable_without_the_passphrase() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let store = EncryptedStorage::create(FileStorage::new(dir.path()).unwrap(), "hunter2", FAST).unwrap();
+        store.set_json("threads", &vec!["meet at noon"]).unwrap();
+        store.set_item("contacts", "bob").unwrap();
+        assert!(EncryptedStorage::create(FileStorage::new(dir.path()).unwrap(), "again", FAST).is_err());
+
+        let raw = FileStorage::new(dir.path()).unwrap();
+        let on_disk = raw.get_item("threads").unwrap().unwrap();
+        assert!(on_disk.starts_with(VALUE_PREFIX) && !on_disk.contains("noon"));
+        // A value moved to another key no longer decrypts.
+        raw.set_item("contacts", &on_disk).unwrap();
+
+        let Err(StorageError::Unavailable(_)) = EncryptedStorage::unlock(raw, "hunter3") else {
+            panic!("unlocked with the wrong passphrase");
+        };
+        let store = EncryptedStorage::unlock(FileStorage::new(dir.path()).unwrap(), "hunter2").unwrap();
+        assert_eq!(store.get_json::<Vec<String>>("threads").unwrap().unwrap(), vec!["meet at noon"]);
+        assert!(store.get_item("contacts").is_err());
+        assert!(store.set_item(STORAGE_KEY_HEADER, "x").is_err());
+
+        store.change_passphrase("correct horse").unwrap();
+        let store = EncryptedStorage::unlock(store.into_inner(), "correct horse").unwrap();
+        assert!(store.get_item("threads").unwrap().is_some());
+        store.remove_item("threads").unwrap();
+        assert!(store.get_item("threads").unwrap().is_none());
+    }
+
+    #[test]
+    fn encrypted_names_hide_keys_and_existing_data_is_converted() {
+        let kv = SqliteStorage::open_in_memory().unwrap();
+        kv.set_item("snartnet_keyring", "{\"contacts\":{}}").unwrap();
+        let options = EncryptionOptions { encrypt_names: true, ..FAST };
+        let store = EncryptedStorage::create(kv, "hunter2", options).unwrap();
+        assert!(store.encrypts_names());
+        assert!(store.get_item("snartnet_keyring").unwrap().is_none());
+
+        assert_eq!(store.encrypt_existing(&["snartnet_keyring", "missing"]).unwrap(), 1);
+        assert_eq!(store.encrypt_existing(&store.inner().keys().unwrap()).unwrap(), 0);
+        assert!(store.inner().get_item("snartnet_keyring").unwrap().is_none());
+        assert_eq!(store.get_item("snartnet_keyring").unwrap().as_deref(), Some("{\"contacts\":{}}"));
+
+        let physical = store.physical_key("snartnet_keyring").unwrap();
+        assert!(physical.starts_with(NAME_PREFIX) && !physical.contains("keyring"));
+        assert!(store.inner().get_item(&physical).unwrap().is_some());
+    }
+
+    #[test]
+    fn hosts_open_plain_or_encrypted_and_refuse_tampered_rounds() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let files = || FileStorage::new(dir.path()).unwrap();
+        let plain = MaybeEncrypted::open(files(), Some("ignored")).unwrap();
+        assert!(!plain.is_encrypted());
+        plain.set_item("profile", "{}").unwrap();
+
+        let store = EncryptedStorage::create(files(), "hunter2", FAST).unwrap();
+        assert_eq!(store.encrypt_existing(&store.inner().keys().unwrap()).unwrap(), 1);
+        let Err(StorageError::Unavailable(_)) = MaybeEncrypted::open(files(), None) else {
+            panic!("opened an encrypted store without a passphrase");
+        };
+        let store = MaybeEncrypted::open(files(), Some("hunter2")).unwrap();
+        assert!(store.is_encrypted());
+        assert_eq!(store.get_item("profile").unwrap().as_deref(), Some("{}"));
+        assert!(!store.inner().get_item("profile").unwrap().unwrap().contains("{}"));
+
+        for iterations in [1, MAX_KDF_ITERATIONS + 1] {
+            let mut header: KeyHeader = files().get_json(STORAGE_KEY_HEADER).unwrap().unwrap();
+            header.iterations = iterations;
+            files().set_json(STORAGE_KEY_HEADER, &header).unwrap();
+            let Err(StorageError::Unavailable(e)) = EncryptedStorage::unlock(files(), "hunter2") else {
+                panic!("unlocked with {iterations} rounds");
+            };
+            assert!(e.contains("rounds"));
+        }
+        let too_many = EncryptionOptions { iterations: MAX_KDF_ITERATIONS + 1, ..FAST };
+        assert!(EncryptedStorage::create(SqliteStorage::open_in_memory().unwrap(), "hunter2", too_many).is_err());
+    }
+}
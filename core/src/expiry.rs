@@ -0,0 +1,91 @@
+use crate::message::{Message, SignedMessage};
+use crate::post::{Post, SignedPost};
+use chrono::{DateTime, Utc};
+
+/// Lifetime of a status-style post.
+pub const STATUS_POST_TTL_HOURS: i64 = 24;
+
+/// A record that may carry a signed `expires_at`.
+///
+/// The expiry is covered by the author's signature, so no peer can extend
+/// it. Expired items must not be displayed, stored or served; because every
+/// copy carries the same expiry, a replayed copy is just as dead and no
+/// tombstone is needed.
+pub trait Expiring {
+    fn expires_at(&self) -> Option<DateTime<Utc>>;
+
+    fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at().is_some_and(|exp| exp <= now)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+}
+
+impl Expiring for Post {
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+}
+
+impl Expiring for SignedPost {
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.post.expires_at
+    }
+}
+
+impl Expiring for Message {
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+}
+
+impl Expiring for SignedMessage {
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.message.expires_at
+    }
+}
+
+/// Drop expired items, returning how many were removed.
+pub fn purge_expired<T: Expiring>(items: &mut Vec<T>, now: DateTime<Utc>) -> usize {
+    let before = items.len();
+    items.retain(|item| !item.is_expired_at(now));
+    before - items.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use chrono::Duration;
+
+    #[test]
+    fn expiry_is_signed_and_purged() {
+        let kp = KeyPair::generate().unwrap();
+        let mut status = Post::new(kp.fingerprint.clone(), "brb".into(), None, None);
+        status.expire_after(Duration::hours(STATUS_POST_TTL_HOURS));
+        let status = SignedPost::create(status, &kp).unwrap();
+        let lasting = SignedPost::create(
+            Post::new(kp.fingerprint.clone(), "hello".into(), None, None),
+            &kp,
+        )
+        .unwrap();
+
+        let mut extended = status.clone();
+        extended.post.expires_at = extended.post.expires_at.map(|e| e + Duration::days(7));
+        assert!(!extended.verify(&kp.public_key).unwrap());
+
+        let mut posts = vec![status, lasting];
+        assert_eq!(purge_expired(&mut posts, Utc::now()), 0);
+        assert_eq!(purge_expired(&mut posts, Utc::now() + Duration::hours(25)), 1);
+        assert_eq!(posts[0].post.content, "hello");
+
+        let mut msg = Message::new_direct(kp.fingerprint.clone(), "peer".into(), "gone soon".into());
+        msg.expire_after(Duration::minutes(5));
+        let msg = SignedMessage::create(msg, &kp).unwrap();
+        assert!(msg.verify(&kp.public_key).unwrap());
+        assert!(!msg.is_expired());
+        assert!(msg.is_expired_at(Utc::now() + Duration::minutes(6)));
+    }
+}
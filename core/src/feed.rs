@@ -0,0 +1,554 @@
+use crate::content_warning::{ContentPreferences, SensitiveCategory};
+use crate::crypto::{KeyPair, fingerprint_from_public_key};
+use crate::expiry::Expiring;
+use crate::post::{Post, SignedPost};
+use crate::revision::{PostRevisions, RevisedPost};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Oldest entries are evicted beyond this many posts.
+pub const MAX_FEED_ENTRIES: usize = 5000;
+
+/// Posts dated further ahead than this are refused, so an author cannot pin
+/// posts to the top of the timeline or crowd out everyone else's.
+pub const FEED_MAX_FUTURE_SKEW_SECS: i64 = 5 * 60;
+
+pub const DEFAULT_FEED_PAGE_SIZE: usize = 50;
+
+/// A verified post held in the feed, with the key it was verified against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedEntry {
+    pub post: SignedPost,
+    pub author_public_key: String,
+    pub received_at: DateTime<Utc>,
+    #[serde(default)]
+    pub read: bool,
+}
+
+impl FeedEntry {
+    /// Sort key: newest first, ties broken by id so the order is stable.
+    fn key(&self) -> (DateTime<Utc>, &str) {
+        (self.post.post.created_at, self.post.post.id.as_str())
+    }
+}
+
+/// Which part of the timeline to return. All filters are optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedQuery {
+    /// Only posts by this fingerprint.
+    pub author: Option<String>,
+    /// Only posts carrying this tag (case-insensitive, leading `#` ignored).
+    pub tag: Option<String>,
+    /// Only posts created at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only posts created before this time.
+    pub until: Option<DateTime<Utc>>,
+    pub unread_only: bool,
+    /// Leave out posts flagged with any of these categories.
+    pub hide_categories: Vec<SensitiveCategory>,
+    /// Leave out every post with a content warning.
+    pub hide_warned: bool,
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// Page size; 0 means `DEFAULT_FEED_PAGE_SIZE`.
+    pub limit: usize,
+}
+
+/// A post ready for display.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedItem {
+    pub post: RevisedPost,
+    pub read: bool,
+    /// Whether to show only the content warning until the reader expands
+    /// the post. Every warned post starts collapsed; see
+    /// `FeedPage::apply_preferences`.
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedPage {
+    pub items: Vec<FeedItem>,
+    /// Set when more matching posts follow this page.
+    pub next_cursor: Option<String>,
+}
+
+impl FeedPage {
+    /// Expand the flagged posts the reader has opted in to seeing.
+    pub fn apply_preferences(&mut self, preferences: &ContentPreferences) {
+        for item in &mut self.items {
+            item.collapsed = preferences.should_collapse(&item.post.original.post);
+        }
+    }
+}
+
+/// The merged timeline of verified posts from all followed authors.
+///
+/// Posts are deduplicated by author and id and kept newest first. Edits and tombstones
+/// are kept alongside and applied when a page is read, so a later edit from
+/// any peer copy shows up without re-fetching the post.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Feed {
+    #[serde(default)]
+    entries: Vec<FeedEntry>,
+    #[serde(default)]
+    revisions: PostRevisions,
+}
+
+impl Feed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge posts and revisions published by the holder of
+    /// `author_public_key`. Posts by anyone else, posts with a bad signature,
+    /// expired posts and posts dated beyond `FEED_MAX_FUTURE_SKEW_SECS` are
+    /// skipped. Returns the ids of newly added posts.
+    pub fn ingest(
+        &mut self,
+        author_public_key: &str,
+        mut posts: Vec<SignedPost>,
+        mut revisions: PostRevisions,
+    ) -> Result<Vec<String>, String> {
+        let author = fingerprint_from_public_key(author_public_key)?;
+        let now = Utc::now();
+        let latest = now + Duration::seconds(FEED_MAX_FUTURE_SKEW_SECS);
+        posts.retain(|p| {
+            p.post.author_fingerprint == author
+                && p.post.created_at <= latest
+                && !p.is_expired_at(now)
+                && p.verify(author_public_key).unwrap_or(false)
+        });
+        revisions.prune(&mut posts, author_public_key);
+
+        self.entries.retain(|e| {
+            !revisions
+                .deletes
+                .iter()
+                .any(|d| d.applies_to(&e.post.post, &e.author_public_key))
+        });
+        self.revisions.merge(revisions);
+
+        let mut added = Vec::new();
+        for post in posts {
+            if self.get(&author, &post.post.id).is_some() || self.is_deleted(&post.post, author_public_key) {
+                continue;
+            }
+            added.push(post.post.id.clone());
+            self.entries.push(FeedEntry {
+                post,
+                author_public_key: author_public_key.to_string(),
+                received_at: now,
+                read: false,
+            });
+        }
+        self.sort_and_trim();
+        Ok(added)
+    }
+
+    /// Merge the local user's own posts, which never count as unread.
+    pub fn ingest_own(
+        &mut self,
+        keypair: &KeyPair,
+        posts: Vec<SignedPost>,
+        revisions: PostRevisions,
+    ) -> Result<Vec<String>, String> {
+        let added = self.ingest(&keypair.public_key, posts, revisions)?;
+        for id in &added {
+            self.set_read(&keypair.fingerprint, id, true);
+        }
+        Ok(added)
+    }
+
+    fn sort_and_trim(&mut self) {
+        self.entries.sort_by(|a, b| b.key().cmp(&a.key()));
+        if self.entries.len() > MAX_FEED_ENTRIES {
+            self.entries.truncate(MAX_FEED_ENTRIES);
+            self.drop_orphan_edits();
+        }
+    }
+
+    /// One page of the timeline, newest first, with edits applied.
+    ///
+    /// Restricted posts are decrypted for `reader` and left out when the
+    /// reader is not in their audience (or there is no reader).
+    pub fn page(&self, query: &FeedQuery, reader: Option<&KeyPair>) -> Result<FeedPage, String> {
+        let after = query.cursor.as_deref().map(parse_cursor).transpose()?;
+        let limit = if query.limit == 0 { DEFAULT_FEED_PAGE_SIZE } else { query.limit };
+        let tag = query
+            .tag
+            .as_deref()
+            .map(|t| t.trim().trim_start_matches('#').to_lowercase())
+            .filter(|t| !t.is_empty());
+
+        let mut page = FeedPage::default();
+        for entry in &self.entries {
+            let post = &entry.post.post;
+            if after
+                .as_ref()
+                .is_some_and(|(at, id)| (post.created_at, post.id.as_str()) >= (*at, id.as_str()))
+            {
+                continue;
+            }
+            if query.author.as_deref().is_some_and(|a| a != post.author_fingerprint)
+                || query.since.is_some_and(|s| post.created_at < s)
+                || query.until.is_some_and(|u| post.created_at >= u)
+                || (query.unread_only && entry.read)
+                || post.is_expired()
+                || post.content_warning.as_ref().is_some_and(|cw| {
+                    query.hide_warned || cw.has_any(&query.hide_categories)
+                })
+            {
+                continue;
+            }
+            let Some(revised) = self
+                .revisions
+                .apply(std::slice::from_ref(&entry.post), &entry.author_public_key)
+                .pop()
+            else {
+                continue;
+            };
+            let revised = if post.is_restricted() {
+                match reader.and_then(|r| revised.reveal(r)) {
+                    Some(revised) => revised,
+                    None => continue,
+                }
+            } else {
+                revised
+            };
+            if tag
+                .as_ref()
+                .is_some_and(|tag| !revised.tags.iter().any(|t| t.trim_start_matches('#').to_lowercase() == *tag))
+            {
+                continue;
+            }
+            if page.items.len() == limit {
+                page.next_cursor = page.items.last().map(|i| cursor_of(&i.post.original.post));
+                break;
+            }
+            page.items.push(FeedItem {
+                collapsed: post.content_warning.is_some(),
+                post: revised,
+                read: entry.read,
+            });
+        }
+        Ok(page)
+    }
+
+    pub fn get(&self, author: &str, post_id: &str) -> Option<&FeedEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.post.post.author_fingerprint == author && e.post.post.id == post_id)
+    }
+
+    /// The author of the post with `post_id`, for callers that only have an
+    /// id, such as one typed by the user. Errors when several authors used it.
+    pub fn author_of(&self, post_id: &str) -> Result<Option<&str>, String> {
+        let mut authors = self
+            .entries
+            .iter()
+            .filter(|e| e.post.post.id == post_id)
+            .map(|e| e.post.post.author_fingerprint.as_str());
+        let first = authors.next();
+        if authors.next().is_some() {
+            return Err(format!("Post ID {post_id} is used by more than one author"));
+        }
+        Ok(first)
+    }
+
+    /// Whether a verified tombstone by the post's author covers it.
+    pub(crate) fn is_deleted(&self, post: &Post, author_public_key: &str) -> bool {
+        self.revisions
+            .deletes
+            .iter()
+            .any(|d| d.applies_to(post, author_public_key))
+    }
+
+    /// All entries, newest first.
+    pub fn entries(&self) -> &[FeedEntry] {
+        &self.entries
+    }
+
+    pub fn revisions(&self) -> &PostRevisions {
+        &self.revisions
+    }
+
+    /// The newest post by `author`, ignoring deleted ones.
+    pub fn latest_by(&self, author: &str) -> Option<&FeedEntry> {
+        self.entries.iter().find(|e| {
+            e.post.post.author_fingerprint == author && !self.is_deleted(&e.post.post, &e.author_public_key)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn set_read(&mut self, author: &str, post_id: &str, read: bool) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.post.post.author_fingerprint == author && e.post.post.id == post_id)
+        {
+            Some(entry) => {
+                entry.read = read;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if the post is not in the feed.
+    pub fn mark_read(&mut self, author: &str, post_id: &str) -> bool {
+        self.set_read(author, post_id, true)
+    }
+
+    pub fn mark_unread(&mut self, author: &str, post_id: &str) -> bool {
+        self.set_read(author, post_id, false)
+    }
+
+    /// Returns how many posts changed state.
+    pub fn mark_all_read(&mut self) -> usize {
+        let mut changed = 0;
+        for entry in self.entries.iter_mut().filter(|e| !e.read) {
+            entry.read = true;
+            changed += 1;
+        }
+        changed
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.entries.iter().filter(|e| !e.read).count()
+    }
+
+    /// Drop expired posts and their edits, returning how many were removed.
+    pub fn purge_expired(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| !e.post.is_expired_at(now));
+        self.drop_orphan_edits();
+        before - self.entries.len()
+    }
+
+    /// Forget everything by `author`, e.g. when the contact is removed.
+    pub fn remove_author(&mut self, author: &str) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.post.post.author_fingerprint != author);
+        self.revisions.edits.retain(|e| e.edit.author_fingerprint != author);
+        self.revisions.deletes.retain(|d| d.delete.author_fingerprint != author);
+        before - self.entries.len()
+    }
+
+    fn drop_orphan_edits(&mut self) {
+        let held: HashSet<(&str, &str)> = self
+            .entries
+            .iter()
+            .map(|p| (p.post.post.author_fingerprint.as_str(), p.post.post.id.as_str()))
+            .collect();
+        self.revisions
+            .edits
+            .retain(|e| held.contains(&(e.edit.author_fingerprint.as_str(), e.edit.post_id.as_str())));
+    }
+}
+
+fn cursor_of(post: &Post) -> String {
+    format!("{}|{}", post.created_at.to_rfc3339(), post.id)
+}
+
+fn parse_cursor(cursor: &str) -> Result<(DateTime<Utc>, String), String> {
+    let (at, id) = cursor
+        .split_once('|')
+        .ok_or_else(|| format!("Invalid feed cursor: {}", cursor))?;
+    let at = DateTime::parse_from_rfc3339(at)
+        .map_err(|e| format!("Invalid feed cursor: {}", e))?
+        .with_timezone(&Utc);
+    Ok((at, id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_warning::ContentWarning;
+    use crate::revision::{PostDelete, PostEdit, SignedPostDelete, SignedPostEdit};
+
+    fn post_at(kp: &KeyPair, content: &str, minutes_ago: i64) -> SignedPost {
+        let mut post = Post::new(kp.fingerprint.clone(), content.to_string(), None, None);
+        post.created_at = Utc::now() - Duration::minutes(minutes_ago);
+        SignedPost::create(post, kp).unwrap()
+    }
+
+    #[test]
+    fn ingest_verifies_and_dedupes() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+
+        let a1 = post_at(&alice, "first", 10);
+        let a2 = post_at(&alice, "second", 5);
+        let mut forged = post_at(&alice, "forged", 1);
+        forged.post.content = "tampered".into();
+        let by_bob = post_at(&bob, "bob", 2);
+
+        let added = feed
+            .ingest(&alice.public_key, vec![a1.clone(), a2.clone(), forged, by_bob], PostRevisions::new())
+            .unwrap();
+        assert_eq!(added.len(), 2);
+        assert!(feed.ingest(&alice.public_key, vec![a1.clone()], PostRevisions::new()).unwrap().is_empty());
+
+        let ids: Vec<_> = feed.entries().iter().map(|e| e.post.post.id.clone()).collect();
+        assert_eq!(ids, vec![a2.post.id.clone(), a1.post.id.clone()]);
+        assert_eq!(feed.latest_by(&alice.fingerprint).unwrap().post.post.id, a2.post.id);
+    }
+
+    #[test]
+    fn future_dated_posts_are_refused() {
+        let alice = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+        let a1 = post_at(&alice, "hello", 10);
+        feed.ingest(&alice.public_key, vec![a1.clone()], PostRevisions::new()).unwrap();
+
+        let pinned = post_at(&mallory, "pinned", -60 * 24 * 365);
+        let within_skew = post_at(&mallory, "slightly ahead", -1);
+        let added = feed
+            .ingest(&mallory.public_key, vec![pinned, within_skew.clone()], PostRevisions::new())
+            .unwrap();
+        assert_eq!(added, vec![within_skew.post.id.clone()]);
+        let ids: Vec<_> = feed.entries().iter().map(|e| e.post.post.id.clone()).collect();
+        assert_eq!(ids, vec![within_skew.post.id, a1.post.id]);
+    }
+
+    #[test]
+    fn pages_and_filters() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+        let alice_posts: Vec<_> = (0..5).map(|i| post_at(&alice, &format!("a{} #rust", i), i * 2)).collect();
+        let bob_posts: Vec<_> = (0..5).map(|i| post_at(&bob, &format!("b{}", i), i * 2 + 1)).collect();
+        feed.ingest(&alice.public_key, alice_posts, PostRevisions::new()).unwrap();
+        feed.ingest(&bob.public_key, bob_posts, PostRevisions::new()).unwrap();
+
+        let mut query = FeedQuery {
+            limit: 4,
+            ..Default::default()
+        };
+        let mut seen = Vec::new();
+        loop {
+            let page = feed.page(&query, None).unwrap();
+            seen.extend(page.items.iter().map(|i| i.post.content.clone()));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec!["a0 #rust", "b0", "a1 #rust", "b1", "a2 #rust", "b2", "a3 #rust", "b3", "a4 #rust", "b4"]);
+
+        let by_bob = feed
+            .page(&FeedQuery { author: Some(bob.fingerprint.clone()), ..Default::default() }, None)
+            .unwrap();
+        assert_eq!(by_bob.items.len(), 5);
+        let tagged = feed
+            .page(&FeedQuery { tag: Some("#Rust".into()), ..Default::default() }, None)
+            .unwrap();
+        assert_eq!(tagged.items.len(), 5);
+        let recent = feed
+            .page(&FeedQuery { since: Some(Utc::now() - Duration::minutes(3) - Duration::seconds(30)), ..Default::default() }, None)
+            .unwrap();
+        assert_eq!(recent.items.len(), 4);
+        assert!(feed.page(&FeedQuery { cursor: Some("bogus".into()), ..Default::default() }, None).is_err());
+    }
+
+    #[test]
+    fn revisions_apply_and_tombstones_stick() {
+        let alice = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+        let keep = post_at(&alice, "typo", 2);
+        let gone = post_at(&alice, "oops", 1);
+        feed.ingest(&alice.public_key, vec![keep.clone(), gone.clone()], PostRevisions::new()).unwrap();
+
+        let mut revisions = PostRevisions::new();
+        revisions.add_edit(SignedPostEdit::create(PostEdit::new(&keep.post, "fixed".into(), None), &keep.post, &alice).unwrap());
+        revisions.add_delete(SignedPostDelete::create(PostDelete::new(&gone.post), &alice).unwrap());
+        feed.ingest(&alice.public_key, Vec::new(), revisions).unwrap();
+
+        let page = feed.page(&FeedQuery::default(), None).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].post.content, "fixed");
+        assert!(feed.ingest(&alice.public_key, vec![gone], PostRevisions::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn posts_are_keyed_by_author_and_id() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+        let theirs = post_at(&alice, "alice", 2);
+        let mut clash = Post::new(bob.fingerprint.clone(), "bob".into(), None, None);
+        clash.id = theirs.post.id.clone();
+        let clash = SignedPost::create(clash, &bob).unwrap();
+        feed.ingest(&alice.public_key, vec![theirs.clone()], PostRevisions::new()).unwrap();
+        assert_eq!(feed.ingest(&bob.public_key, vec![clash.clone()], PostRevisions::new()).unwrap().len(), 1);
+        assert!(feed.author_of(&theirs.post.id).is_err());
+
+        let mut revisions = PostRevisions::new();
+        revisions.add_delete(SignedPostDelete::create(PostDelete::new(&clash.post), &bob).unwrap());
+        feed.ingest(&bob.public_key, Vec::new(), revisions).unwrap();
+        assert!(feed.get(&bob.fingerprint, &clash.post.id).is_none());
+        assert!(feed.get(&alice.fingerprint, &theirs.post.id).is_some());
+        assert!(feed.ingest(&alice.public_key, vec![theirs.clone()], PostRevisions::new()).unwrap().is_empty());
+        assert_eq!(feed.author_of(&theirs.post.id).unwrap(), Some(alice.fingerprint.as_str()));
+        assert_eq!(feed.latest_by(&alice.fingerprint).unwrap().post.post.content, "alice");
+    }
+
+    #[test]
+    fn content_warnings_collapse_and_filter() {
+        let alice = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+        let mut flagged = Post::new(alice.fingerprint.clone(), "the butler did it".into(), None, None);
+        flagged.set_content_warning(ContentWarning::new("ending", &[SensitiveCategory::Spoiler]).unwrap());
+        let flagged = SignedPost::create(flagged, &alice).unwrap();
+        feed.ingest(&alice.public_key, vec![flagged, post_at(&alice, "plain", 1)], PostRevisions::new())
+            .unwrap();
+
+        let mut page = feed.page(&FeedQuery::default(), None).unwrap();
+        assert_eq!(page.items.iter().filter(|i| i.collapsed).count(), 1);
+        let mut prefs = ContentPreferences::new();
+        prefs.set_auto_expand(SensitiveCategory::Spoiler, true);
+        page.apply_preferences(&prefs);
+        assert!(page.items.iter().all(|i| !i.collapsed));
+
+        let hide_spoilers = FeedQuery {
+            hide_categories: vec![SensitiveCategory::Spoiler],
+            ..Default::default()
+        };
+        assert_eq!(feed.page(&hide_spoilers, None).unwrap().items.len(), 1);
+        let hide_all = FeedQuery { hide_warned: true, ..Default::default() };
+        assert_eq!(feed.page(&hide_all, None).unwrap().items[0].post.content, "plain");
+    }
+
+    #[test]
+    fn read_tracking() {
+        let alice = KeyPair::generate().unwrap();
+        let me = KeyPair::generate().unwrap();
+        let mut feed = Feed::new();
+        let theirs = post_at(&alice, "hi", 2);
+        feed.ingest(&alice.public_key, vec![theirs.clone(), post_at(&alice, "again", 1)], PostRevisions::new())
+            .unwrap();
+        feed.ingest_own(&me, vec![post_at(&me, "mine", 0)], PostRevisions::new()).unwrap();
+        assert_eq!(feed.unread_count(), 2);
+
+        assert!(!feed.mark_read(&me.fingerprint, &theirs.post.id));
+        assert!(feed.mark_read(&alice.fingerprint, &theirs.post.id));
+        let unread = feed.page(&FeedQuery { unread_only: true, ..Default::default() }, None).unwrap();
+        assert_eq!(unread.items.len(), 1);
+        assert!(feed.mark_unread(&alice.fingerprint, &theirs.post.id));
+        assert_eq!(feed.mark_all_read(), 2);
+        assert_eq!(feed.unread_count(), 0);
+        assert!(!feed.mark_read(&alice.fingerprint, "missing"));
+    }
+}
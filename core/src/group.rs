@@ -0,0 +1,601 @@
+use crate::audience::{Recipient, SealedContent};
+use crate::crypto::{KeyPair, decrypt_with_key, encrypt_with_key, fingerprint_from_public_key, random_key, verify_signature};
+use crate::message::{Message, MessageType};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub(crate) const GROUP_ENC_ALG: &str = "chacha20poly1305-sender-key-v1";
+
+const MAX_GROUP_NAME_CHARS: usize = 64;
+pub const MAX_GROUP_MEMBERS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupRole {
+    /// May rename the group and add, remove or promote members.
+    Admin,
+    Member,
+}
+
+/// A group member with the keys needed to check their updates and messages
+/// and to seal sender keys for them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub fingerprint: String,
+    pub public_key: String,
+    pub encryption_public_key: String,
+    pub role: GroupRole,
+}
+
+impl GroupMember {
+    /// The fingerprint is derived from `public_key`, so a member entry cannot
+    /// claim somebody else's identity.
+    pub fn new(public_key: String, encryption_public_key: String, role: GroupRole) -> Result<Self, String> {
+        Ok(Self {
+            fingerprint: fingerprint_from_public_key(&public_key)?,
+            public_key,
+            encryption_public_key,
+            role,
+        })
+    }
+
+    pub fn from_keypair(keypair: &KeyPair, role: GroupRole) -> Result<Self, String> {
+        let encryption_public_key = keypair
+            .enc_public_key
+            .clone()
+            .ok_or_else(|| "missing local encryption public key".to_string())?;
+        Self::new(keypair.public_key.clone(), encryption_public_key, role)
+    }
+
+    fn recipient(&self) -> Recipient {
+        Recipient::new(self.fingerprint.clone(), self.encryption_public_key.clone())
+    }
+}
+
+/// Group metadata. Every membership change produces a new version with the
+/// next `epoch`, signed by the member who made it; members then re-key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    pub members: Vec<GroupMember>,
+    pub epoch: u64,
+    /// Fingerprint of the member who signed this version.
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedGroup {
+    pub group: Group,
+    pub signature: String,
+}
+
+impl Group {
+    pub fn member(&self, fingerprint: &str) -> Option<&GroupMember> {
+        self.members.iter().find(|m| m.fingerprint == fingerprint)
+    }
+
+    pub fn is_member(&self, fingerprint: &str) -> bool {
+        self.member(fingerprint).is_some()
+    }
+
+    pub fn is_admin(&self, fingerprint: &str) -> bool {
+        self.member(fingerprint).is_some_and(|m| m.role == GroupRole::Admin)
+    }
+
+    /// Everyone but `fingerprint`, e.g. the inboxes a member fans out to.
+    pub fn others(&self, fingerprint: &str) -> Vec<&GroupMember> {
+        self.members.iter().filter(|m| m.fingerprint != fingerprint).collect()
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize group: {}", e))
+    }
+
+    fn next_version(&self, updated_by: &str) -> Group {
+        Group {
+            epoch: self.epoch + 1,
+            updated_by: updated_by.to_string(),
+            updated_at: Utc::now().max(self.updated_at),
+            ..self.clone()
+        }
+    }
+}
+
+fn validate_group_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Group name cannot be empty".to_string());
+    }
+    if name.chars().count() > MAX_GROUP_NAME_CHARS {
+        return Err(format!("Group name must be at most {} characters", MAX_GROUP_NAME_CHARS));
+    }
+    Ok(name.to_string())
+}
+
+impl SignedGroup {
+    /// A new group with `creator` as its only member and admin.
+    pub fn create(name: &str, creator: &KeyPair) -> Result<Self, String> {
+        let group = Group {
+            id: Uuid::new_v4().to_string(),
+            name: validate_group_name(name)?,
+            members: vec![GroupMember::from_keypair(creator, GroupRole::Admin)?],
+            epoch: 0,
+            updated_by: creator.fingerprint.clone(),
+            updated_at: Utc::now(),
+        };
+        Self::sign(group, creator)
+    }
+
+    fn sign(group: Group, keypair: &KeyPair) -> Result<Self, String> {
+        if group.updated_by != keypair.fingerprint {
+            return Err("group updates must be signed by their author".to_string());
+        }
+        let group_json = group.to_canonical_json()?;
+        let signature = keypair.sign(&group_json)?;
+        Ok(SignedGroup { group, signature })
+    }
+
+    fn require_admin(&self, admin: &KeyPair) -> Result<(), String> {
+        if !self.group.is_admin(&admin.fingerprint) {
+            return Err("Only group admins can change membership".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn add_member(&self, admin: &KeyPair, member: GroupMember) -> Result<Self, String> {
+        self.require_admin(admin)?;
+        if self.group.is_member(&member.fingerprint) {
+            return Err("Already a member of the group".to_string());
+        }
+        if self.group.members.len() >= MAX_GROUP_MEMBERS {
+            return Err(format!("A group can have at most {} members", MAX_GROUP_MEMBERS));
+        }
+        let mut next = self.group.next_version(&admin.fingerprint);
+        next.members.push(member);
+        Self::sign(next, admin)
+    }
+
+    pub fn remove_member(&self, admin: &KeyPair, fingerprint: &str) -> Result<Self, String> {
+        self.require_admin(admin)?;
+        if fingerprint == admin.fingerprint {
+            return self.leave(admin);
+        }
+        if !self.group.is_member(fingerprint) {
+            return Err("Not a member of the group".to_string());
+        }
+        let mut next = self.group.next_version(&admin.fingerprint);
+        next.members.retain(|m| m.fingerprint != fingerprint);
+        Self::sign(next, admin)
+    }
+
+    pub fn set_role(&self, admin: &KeyPair, fingerprint: &str, role: GroupRole) -> Result<Self, String> {
+        self.require_admin(admin)?;
+        let mut next = self.group.next_version(&admin.fingerprint);
+        let member = next
+            .members
+            .iter_mut()
+            .find(|m| m.fingerprint == fingerprint)
+            .ok_or("Not a member of the group")?;
+        member.role = role;
+        if !next.members.iter().any(|m| m.role == GroupRole::Admin) {
+            return Err("A group needs at least one admin".to_string());
+        }
+        Self::sign(next, admin)
+    }
+
+    pub fn rename(&self, admin: &KeyPair, name: &str) -> Result<Self, String> {
+        self.require_admin(admin)?;
+        let mut next = self.group.next_version(&admin.fingerprint);
+        next.name = validate_group_name(name)?;
+        Self::sign(next, admin)
+    }
+
+    /// Remove `member` themselves. The last admin must promote someone
+    /// first, unless they are the last member.
+    pub fn leave(&self, member: &KeyPair) -> Result<Self, String> {
+        if !self.group.is_member(&member.fingerprint) {
+            return Err("Not a member of the group".to_string());
+        }
+        let mut next = self.group.next_version(&member.fingerprint);
+        next.members.retain(|m| m.fingerprint != member.fingerprint);
+        if !next.members.is_empty() && !next.members.iter().any(|m| m.role == GroupRole::Admin) {
+            return Err("Promote another admin before leaving".to_string());
+        }
+        Self::sign(next, member)
+    }
+
+    /// Check this version against the one it replaces (`None` for a newly
+    /// created group): the signature must be by a member of the previous
+    /// version, admins may make any change, and other members may only
+    /// leave.
+    pub fn verify_update(&self, previous: Option<&SignedGroup>) -> Result<bool, String> {
+        let group = &self.group;
+        let Some(previous) = previous else {
+            return match group.member(&group.updated_by) {
+                Some(creator) if group.epoch == 0 && creator.role == GroupRole::Admin => {
+                    self.verify_signature(&creator.public_key)
+                }
+                _ => Ok(false),
+            };
+        };
+        let prev = &previous.group;
+        if group.id != prev.id || group.epoch != prev.epoch + 1 {
+            return Ok(false);
+        }
+        let Some(signer) = prev.member(&group.updated_by) else {
+            return Ok(false);
+        };
+        if !self.verify_signature(&signer.public_key)? {
+            return Ok(false);
+        }
+        for (i, member) in group.members.iter().enumerate() {
+            if fingerprint_from_public_key(&member.public_key)? != member.fingerprint
+                || group.members[..i].iter().any(|m| m.fingerprint == member.fingerprint)
+            {
+                return Ok(false);
+            }
+        }
+        if !group.members.is_empty() && !group.members.iter().any(|m| m.role == GroupRole::Admin) {
+            return Ok(false);
+        }
+        if signer.role == GroupRole::Admin {
+            return Ok(true);
+        }
+        let remaining: Vec<&GroupMember> = prev.others(&signer.fingerprint);
+        Ok(group.name == prev.name && group.members.iter().eq(remaining))
+    }
+
+    fn verify_signature(&self, public_key: &str) -> Result<bool, String> {
+        let group_json = self.group.to_canonical_json()?;
+        verify_signature(&group_json, &self.signature, public_key)
+    }
+}
+
+pub(crate) fn is_first_epoch(epoch: &u64) -> bool {
+    *epoch == 0
+}
+
+/// One member's symmetric key for one group epoch. Messages are encrypted
+/// once under the sender's key and fanned out unchanged to every member.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SenderKey {
+    pub group_id: String,
+    pub epoch: u64,
+    pub owner_fingerprint: String,
+    /// Base64 ChaCha20-Poly1305 key.
+    pub key: String,
+}
+
+impl SenderKey {
+    pub fn generate(group: &Group, owner_fingerprint: &str) -> Self {
+        Self {
+            group_id: group.id.clone(),
+            epoch: group.epoch,
+            owner_fingerprint: owner_fingerprint.to_string(),
+            key: BASE64.encode(random_key()),
+        }
+    }
+
+    fn key_bytes(&self) -> Result<[u8; 32], String> {
+        BASE64
+            .decode(&self.key)
+            .map_err(|e| format!("sender key decode failed: {e}"))?
+            .try_into()
+            .map_err(|_| "invalid sender key length".to_string())
+    }
+
+    /// Encrypt `plaintext`, returning base64 `(ciphertext, nonce)`.
+    pub(crate) fn encrypt(&self, plaintext: &str) -> Result<(String, String), String> {
+        encrypt_with_key(&self.key_bytes()?, plaintext.as_bytes())
+    }
+
+    pub(crate) fn decrypt(&self, nonce_b64: &str, ciphertext_b64: &str) -> Result<String, String> {
+        let plaintext = decrypt_with_key(&self.key_bytes()?, nonce_b64, ciphertext_b64)?;
+        String::from_utf8(plaintext).map_err(|e| format!("utf8 decode failed: {e}"))
+    }
+
+    /// Seal this key for every other member of `group`, which must be the
+    /// epoch it was generated for.
+    pub fn distribute(&self, group: &Group, owner: &KeyPair) -> Result<SignedSenderKeyDistribution, String> {
+        if group.id != self.group_id || group.epoch != self.epoch {
+            return Err("sender key is for a different group epoch".to_string());
+        }
+        if owner.fingerprint != self.owner_fingerprint {
+            return Err("sender keys are distributed by their owner".to_string());
+        }
+        let recipients: Vec<Recipient> = group
+            .others(&owner.fingerprint)
+            .into_iter()
+            .map(GroupMember::recipient)
+            .collect();
+        let distribution = SenderKeyDistribution {
+            group_id: self.group_id.clone(),
+            epoch: self.epoch,
+            sender_fingerprint: self.owner_fingerprint.clone(),
+            sealed: SealedContent::seal(&self.key, &recipients)?,
+            created_at: Utc::now(),
+        };
+        let json = distribution.to_canonical_json()?;
+        let signature = owner.sign(&json)?;
+        Ok(SignedSenderKeyDistribution { distribution, signature })
+    }
+}
+
+/// A member's sender key, sealed for the other members of one epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SenderKeyDistribution {
+    pub group_id: String,
+    pub epoch: u64,
+    pub sender_fingerprint: String,
+    pub sealed: SealedContent,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedSenderKeyDistribution {
+    pub distribution: SenderKeyDistribution,
+    pub signature: String,
+}
+
+impl SenderKeyDistribution {
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize sender key: {}", e))
+    }
+}
+
+impl SignedSenderKeyDistribution {
+    /// The sender key, if the distribution is signed by a member of the
+    /// current `group` epoch and sealed for `reader`.
+    pub fn open(&self, group: &Group, reader: &KeyPair) -> Result<Option<SenderKey>, String> {
+        let d = &self.distribution;
+        if d.group_id != group.id || d.epoch != group.epoch {
+            return Ok(None);
+        }
+        let Some(sender) = group.member(&d.sender_fingerprint) else {
+            return Ok(None);
+        };
+        let json = d.to_canonical_json()?;
+        if !verify_signature(&json, &self.signature, &sender.public_key)? {
+            return Err("sender key signature is invalid".to_string());
+        }
+        Ok(d.sealed.open(reader)?.map(|key| SenderKey {
+            group_id: d.group_id.clone(),
+            epoch: d.epoch,
+            owner_fingerprint: d.sender_fingerprint.clone(),
+            key,
+        }))
+    }
+}
+
+/// Groups the user belongs to (or has left), with their own sender keys and
+/// those received from other members.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupBook {
+    #[serde(default)]
+    pub groups: Vec<SignedGroup>,
+    /// Superseded versions, so new members can be sent the whole chain.
+    #[serde(default)]
+    history: Vec<SignedGroup>,
+    /// Own sender keys, one per epoch of each group.
+    #[serde(default)]
+    own_keys: Vec<SenderKey>,
+    /// Other members' keys, kept across epochs so history stays readable.
+    #[serde(default)]
+    peer_keys: Vec<SenderKey>,
+}
+
+impl GroupBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, group_id: &str) -> Option<&SignedGroup> {
+        self.groups.iter().find(|g| g.group.id == group_id)
+    }
+
+    /// Groups `fingerprint` is currently a member of.
+    pub fn joined(&self, fingerprint: &str) -> Vec<&SignedGroup> {
+        self.groups.iter().filter(|g| g.group.is_member(fingerprint)).collect()
+    }
+
+    /// Accept a new group or the next version of a known one. Returns false
+    /// for versions already held; errors on invalid or out-of-order updates.
+    pub fn apply(&mut self, update: SignedGroup) -> Result<bool, String> {
+        let index = self.groups.iter().position(|g| g.group.id == update.group.id);
+        let previous = index.map(|i| &self.groups[i]);
+        if previous.is_some_and(|p| update.group.epoch <= p.group.epoch) {
+            return Ok(false);
+        }
+        if !update.verify_update(previous)? {
+            return Err("Group update is not valid".to_string());
+        }
+        match index {
+            Some(i) => {
+                let previous = std::mem::replace(&mut self.groups[i], update);
+                self.history.push(previous);
+            }
+            None => self.groups.push(update),
+        }
+        Ok(true)
+    }
+
+    /// Every known version of a group, oldest first. A member joining later
+    /// needs all of them to verify the current one.
+    pub fn chain(&self, group_id: &str) -> Vec<SignedGroup> {
+        let mut chain: Vec<SignedGroup> = self
+            .history
+            .iter()
+            .chain(self.get(group_id))
+            .filter(|g| g.group.id == group_id)
+            .cloned()
+            .collect();
+        chain.sort_by_key(|g| g.group.epoch);
+        chain
+    }
+
+    /// Our sender key for the group's current epoch, generating it if needed.
+    /// A new key (the bool) must be distributed to the other members.
+    pub fn ensure_sender_key(&mut self, group_id: &str, owner: &KeyPair) -> Result<(SenderKey, bool), String> {
+        let group = &self.get(group_id).ok_or("Unknown group")?.group;
+        if !group.is_member(&owner.fingerprint) {
+            return Err("Not a member of the group".to_string());
+        }
+        if let Some(key) = self
+            .own_keys
+            .iter()
+            .find(|k| k.group_id == group_id && k.epoch == group.epoch)
+        {
+            return Ok((key.clone(), false));
+        }
+        // Keys from earlier epochs are kept to read history but never reused.
+        let key = SenderKey::generate(group, &owner.fingerprint);
+        self.own_keys.push(key.clone());
+        Ok((key, true))
+    }
+
+    /// Store another member's sender key. Returns whether it was new.
+    pub fn receive_sender_key(&mut self, distribution: &SignedSenderKeyDistribution, reader: &KeyPair) -> Result<bool, String> {
+        let group = &self
+            .get(&distribution.distribution.group_id)
+            .ok_or("Unknown group")?
+            .group;
+        let Some(key) = distribution.open(group, reader)? else {
+            return Ok(false);
+        };
+        if self.peer_keys.contains(&key) {
+            return Ok(false);
+        }
+        self.peer_keys.retain(|k| {
+            (k.group_id.as_str(), k.epoch, k.owner_fingerprint.as_str())
+                != (key.group_id.as_str(), key.epoch, key.owner_fingerprint.as_str())
+        });
+        self.peer_keys.push(key);
+        Ok(true)
+    }
+
+    /// Decrypt a group message with the sender's key for its epoch.
+    pub fn open(&self, message: &Message) -> Result<String, String> {
+        let MessageType::Group { group_id, epoch } = &message.message_type else {
+            return Err("not a group message".to_string());
+        };
+        let key = self
+            .own_keys
+            .iter()
+            .chain(&self.peer_keys)
+            .find(|k| &k.group_id == group_id && k.epoch == *epoch && k.owner_fingerprint == message.sender_fingerprint)
+            .ok_or("No sender key for this message yet")?;
+        message.open_group(key)
+    }
+
+    /// Forget a group entirely, with every key for it.
+    pub fn remove(&mut self, group_id: &str) -> bool {
+        let before = self.groups.len();
+        self.groups.retain(|g| g.group.id != group_id);
+        self.history.retain(|g| g.group.id != group_id);
+        self.own_keys.retain(|k| k.group_id != group_id);
+        self.peer_keys.retain(|k| k.group_id != group_id);
+        self.groups.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::SignedMessage;
+
+    fn keypair() -> KeyPair {
+        let mut kp = KeyPair::generate().unwrap();
+        kp.ensure_encryption_keys();
+        kp
+    }
+
+    #[test]
+    fn membership_changes_are_authorized() {
+        let admin = keypair();
+        let bob = keypair();
+        let carol = keypair();
+        let created = SignedGroup::create("Climbing", &admin).unwrap();
+        assert!(created.verify_update(None).unwrap());
+
+        let added = created
+            .add_member(&admin, GroupMember::from_keypair(&bob, GroupRole::Member).unwrap())
+            .unwrap();
+        assert!(added.verify_update(Some(&created)).unwrap());
+        assert!(!added.verify_update(None).unwrap());
+        // Plain members cannot add or remove anyone.
+        assert!(added
+            .add_member(&bob, GroupMember::from_keypair(&carol, GroupRole::Member).unwrap())
+            .is_err());
+        let mut forged = added.group.next_version(&bob.fingerprint);
+        forged.members.retain(|m| m.fingerprint == bob.fingerprint);
+        let forged = SignedGroup::sign(forged, &bob).unwrap();
+        assert!(!forged.verify_update(Some(&added)).unwrap());
+
+        let left = added.leave(&bob).unwrap();
+        assert!(left.verify_update(Some(&added)).unwrap());
+        assert!(!left.group.is_member(&bob.fingerprint));
+        assert!(created.leave(&admin).unwrap().group.members.is_empty());
+        let promoted = added.set_role(&admin, &bob.fingerprint, GroupRole::Admin).unwrap();
+        assert!(promoted.leave(&admin).is_ok());
+        assert!(added.leave(&admin).is_err());
+    }
+
+    #[test]
+    fn sender_keys_rekey_on_membership_change() {
+        let alice = keypair();
+        let bob = keypair();
+        let carol = keypair();
+        let mut alice_book = GroupBook::new();
+        let mut bob_book = GroupBook::new();
+
+        let v0 = SignedGroup::create("Book club", &alice).unwrap();
+        let v1 = v0.add_member(&alice, GroupMember::from_keypair(&bob, GroupRole::Member).unwrap()).unwrap();
+        let v2 = v1.add_member(&alice, GroupMember::from_keypair(&carol, GroupRole::Member).unwrap()).unwrap();
+        for update in [&v0, &v1, &v2] {
+            alice_book.apply(update.clone()).unwrap();
+        }
+        assert!(bob_book.apply(v1.clone()).is_err());
+        bob_book.apply(v0).unwrap();
+        bob_book.apply(v1).unwrap();
+        bob_book.apply(v2.clone()).unwrap();
+
+        let group_id = v2.group.id.clone();
+        let mut carol_book = GroupBook::new();
+        for update in alice_book.chain(&group_id) {
+            assert!(carol_book.apply(update).unwrap());
+        }
+        assert_eq!(carol_book.get(&group_id).unwrap().group.epoch, 2);
+        let (key, fresh) = alice_book.ensure_sender_key(&group_id, &alice).unwrap();
+        assert!(fresh);
+        let distribution = key.distribute(&v2.group, &alice).unwrap();
+        assert!(bob_book.receive_sender_key(&distribution, &bob).unwrap());
+
+        let message = Message::new_group(&key, "Chapter 3 tonight").unwrap();
+        assert!(message.encrypted);
+        assert_ne!(message.content, "Chapter 3 tonight");
+        let signed = SignedMessage::create(message, &alice).unwrap();
+        assert!(signed.verify(&alice.public_key).unwrap());
+        assert_eq!(bob_book.open(&signed.message).unwrap(), "Chapter 3 tonight");
+
+        // Removing Carol moves the epoch on and Alice must re-key; Carol's
+        // copy of the new key is never sealed for her.
+        let v3 = v2.remove_member(&alice, &carol.fingerprint).unwrap();
+        alice_book.apply(v3.clone()).unwrap();
+        let (rekeyed, fresh) = alice_book.ensure_sender_key(&group_id, &alice).unwrap();
+        assert!(fresh);
+        assert_ne!(rekeyed.key, key.key);
+        assert_eq!(alice_book.open(&signed.message).unwrap(), "Chapter 3 tonight");
+        let distribution = rekeyed.distribute(&v3.group, &alice).unwrap();
+        assert!(!distribution.distribution.sealed.is_recipient(&carol.fingerprint));
+        bob_book.apply(v3).unwrap();
+        assert!(bob_book.receive_sender_key(&distribution, &bob).unwrap());
+        let later = Message::new_group(&rekeyed, "Carol is gone").unwrap();
+        assert_eq!(bob_book.open(&later).unwrap(), "Carol is gone");
+    }
+}
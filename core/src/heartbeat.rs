@@ -0,0 +1,200 @@
+use crate::crypto::{KeyPair, verify_signature};
+use crate::profile::Profile;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How often (seconds) an author is expected to republish a heartbeat.
+pub const HEARTBEAT_INTERVAL_SECS: i64 = 5 * 60;
+
+/// Heartbeats older than this are reported as offline rather than online.
+pub const HEARTBEAT_OFFLINE_AFTER_SECS: i64 = 3 * HEARTBEAT_INTERVAL_SECS;
+
+/// Heartbeats older than this are reported as an abandoned identity.
+pub const HEARTBEAT_ABANDONED_AFTER_DAYS: i64 = 30;
+
+/// Tolerated clock skew for heartbeats that claim to come from the future.
+const HEARTBEAT_MAX_FUTURE_SKEW_SECS: i64 = 5 * 60;
+
+/// A small signed liveness record republished periodically by the author.
+///
+/// Unlike `SwarmProfileBlob.updated_at`, the timestamp here is covered by the
+/// author's signature, so relays cannot make an identity look fresher or
+/// staler than it is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub fingerprint: String,
+    pub issued_at: DateTime<Utc>,
+    /// Version of the profile the author was running when this was issued.
+    pub profile_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedHeartbeat {
+    pub heartbeat: Heartbeat,
+    pub signature: String,
+}
+
+/// Liveness of a remote identity derived from its heartbeat and profile expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    /// No heartbeat has been seen yet.
+    #[default]
+    Unknown,
+    Online,
+    Offline,
+    Abandoned,
+    /// The signed profile has passed its `expires_at`.
+    Expired,
+}
+
+impl Liveness {
+    pub fn label(self) -> &'static str {
+        match self {
+            Liveness::Unknown => "unknown",
+            Liveness::Online => "online",
+            Liveness::Offline => "offline",
+            Liveness::Abandoned => "abandoned",
+            Liveness::Expired => "expired",
+        }
+    }
+
+    /// Classify an identity from its last heartbeat time and profile expiry.
+    pub fn classify(
+        last_heartbeat: Option<DateTime<Utc>>,
+        profile_expires_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self::classify_at(last_heartbeat, profile_expires_at, Utc::now())
+    }
+
+    pub fn classify_at(
+        last_heartbeat: Option<DateTime<Utc>>,
+        profile_expires_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        if profile_expires_at.is_some_and(|exp| exp <= now) {
+            return Liveness::Expired;
+        }
+        let Some(seen) = last_heartbeat else {
+            return Liveness::Unknown;
+        };
+        let age = now.signed_duration_since(seen);
+        if age < Duration::seconds(HEARTBEAT_OFFLINE_AFTER_SECS) {
+            Liveness::Online
+        } else if age < Duration::days(HEARTBEAT_ABANDONED_AFTER_DAYS) {
+            Liveness::Offline
+        } else {
+            Liveness::Abandoned
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn new(fingerprint: String, profile_version: u32) -> Self {
+        Self {
+            fingerprint,
+            issued_at: Utc::now(),
+            profile_version,
+        }
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize heartbeat: {}", e))
+    }
+}
+
+impl SignedHeartbeat {
+    pub fn create(heartbeat: Heartbeat, keypair: &KeyPair) -> Result<Self, String> {
+        let heartbeat_json = heartbeat.to_canonical_json()?;
+        let signature = keypair.sign(&heartbeat_json)?;
+
+        Ok(SignedHeartbeat {
+            heartbeat,
+            signature,
+        })
+    }
+
+    pub fn verify(&self, public_key: &str) -> Result<bool, String> {
+        let heartbeat_json = self.heartbeat.to_canonical_json()?;
+        verify_signature(&heartbeat_json, &self.signature, public_key)
+    }
+
+    /// Verify the heartbeat belongs to `profile` and is not implausibly far in
+    /// the future.
+    pub fn verify_for_profile(&self, profile: &Profile) -> Result<bool, String> {
+        if self.heartbeat.fingerprint != profile.fingerprint {
+            return Ok(false);
+        }
+        let limit = Utc::now() + Duration::seconds(HEARTBEAT_MAX_FUTURE_SKEW_SECS);
+        if self.heartbeat.issued_at > limit {
+            return Ok(false);
+        }
+        self.verify(&profile.public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    fn make_profile() -> (KeyPair, Profile) {
+        let kp = KeyPair::generate().expect("keygen failed");
+        let p = Profile::new("hb".to_string(), kp.get_public_info());
+        (kp, p)
+    }
+
+    #[test]
+    fn signed_heartbeat_verifies_for_profile() {
+        let (kp, p) = make_profile();
+        let hb = Heartbeat::new(p.fingerprint.clone(), p.version);
+        let signed = SignedHeartbeat::create(hb, &kp).expect("sign failed");
+        assert!(signed.verify_for_profile(&p).expect("verify failed"));
+    }
+
+    #[test]
+    fn heartbeat_rejects_foreign_fingerprint() {
+        let (kp, p) = make_profile();
+        let hb = Heartbeat::new("someone-else".to_string(), 1);
+        let signed = SignedHeartbeat::create(hb, &kp).expect("sign failed");
+        assert!(!signed.verify_for_profile(&p).expect("verify failed"));
+    }
+
+    #[test]
+    fn heartbeat_rejects_tampered_timestamp() {
+        let (kp, p) = make_profile();
+        let hb = Heartbeat::new(p.fingerprint.clone(), p.version);
+        let mut signed = SignedHeartbeat::create(hb, &kp).expect("sign failed");
+        signed.heartbeat.issued_at -= Duration::days(2);
+        assert!(!signed.verify_for_profile(&p).expect("verify failed"));
+    }
+
+    #[test]
+    fn heartbeat_rejects_future_timestamp() {
+        let (kp, p) = make_profile();
+        let mut hb = Heartbeat::new(p.fingerprint.clone(), p.version);
+        hb.issued_at = Utc::now() + Duration::days(1);
+        let signed = SignedHeartbeat::create(hb, &kp).expect("sign failed");
+        assert!(!signed.verify_for_profile(&p).expect("verify failed"));
+    }
+
+    #[test]
+    fn liveness_classification() {
+        let now = Utc::now();
+        assert_eq!(Liveness::classify_at(None, None, now), Liveness::Unknown);
+        assert_eq!(Liveness::classify_at(Some(now), None, now), Liveness::Online);
+        assert_eq!(
+            Liveness::classify_at(Some(now - Duration::hours(2)), None, now),
+            Liveness::Offline
+        );
+        assert_eq!(
+            Liveness::classify_at(Some(now - Duration::days(60)), None, now),
+            Liveness::Abandoned
+        );
+        assert_eq!(
+            Liveness::classify_at(Some(now), Some(now - Duration::seconds(1)), now),
+            Liveness::Expired
+        );
+    }
+}
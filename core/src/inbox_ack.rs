@@ -0,0 +1,277 @@
+use crate::crypto::{fingerprint_from_public_key, verify_signature, KeyPair};
+use crate::group::{SignedGroup, SignedSenderKeyDistribution};
+use crate::message::SignedMessage;
+use crate::poll::SignedPollVote;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Most items a single acknowledgement may list.
+pub const MAX_INBOX_ACK_IDS: usize = 1000;
+
+/// Slack for clock differences when deciding that a watermark covers an
+/// older acknowledgement.
+const ACK_CLOCK_SLACK_SECS: i64 = 5 * 60;
+
+/// One inbox entry named by its sender and id. Ids are chosen by senders, so
+/// an ack never covers another sender's entry with the same id.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AckedItem {
+    pub sender_fingerprint: String,
+    pub id: String,
+}
+
+/// Anything delivered to an inbox that its owner can acknowledge.
+pub trait InboxItem {
+    fn acked_item(&self) -> AckedItem;
+    fn created_at(&self) -> DateTime<Utc>;
+}
+
+impl InboxItem for SignedMessage {
+    fn acked_item(&self) -> AckedItem {
+        AckedItem {
+            sender_fingerprint: self.message.sender_fingerprint.clone(),
+            id: self.message.id.clone(),
+        }
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.message.created_at
+    }
+}
+
+impl InboxItem for SignedPollVote {
+    fn acked_item(&self) -> AckedItem {
+        AckedItem {
+            sender_fingerprint: self.vote.voter_fingerprint.clone(),
+            id: self.vote.id.clone(),
+        }
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.vote.created_at
+    }
+}
+
+impl InboxItem for SignedGroup {
+    fn acked_item(&self) -> AckedItem {
+        AckedItem {
+            sender_fingerprint: self.group.updated_by.clone(),
+            id: format!("{}#{}", self.group.id, self.group.epoch),
+        }
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.group.updated_at
+    }
+}
+
+impl InboxItem for SignedSenderKeyDistribution {
+    fn acked_item(&self) -> AckedItem {
+        AckedItem {
+            sender_fingerprint: self.distribution.sender_fingerprint.clone(),
+            id: format!("{}#{}", self.distribution.group_id, self.distribution.epoch),
+        }
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.distribution.created_at
+    }
+}
+
+/// The owner of an inbox telling relays which entries it has taken, so they
+/// can be deleted from every copy of the inbox and not put back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InboxAck {
+    pub recipient_fingerprint: String,
+    /// Included so relays can check the ack without the owner's profile.
+    pub public_key: String,
+    #[serde(default)]
+    pub items: Vec<AckedItem>,
+    /// Every entry created at or before this instant is covered too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<DateTime<Utc>>,
+    pub issued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedInboxAck {
+    pub ack: InboxAck,
+    pub signature: String,
+}
+
+impl SignedInboxAck {
+    pub fn create(keypair: &KeyPair, items: &[AckedItem], watermark: Option<DateTime<Utc>>) -> Result<Self, String> {
+        if items.is_empty() && watermark.is_none() {
+            return Err("An inbox ack needs items or a watermark".to_string());
+        }
+        if items.len() > MAX_INBOX_ACK_IDS {
+            return Err(format!("An inbox ack can list at most {} items", MAX_INBOX_ACK_IDS));
+        }
+        let ack = InboxAck {
+            recipient_fingerprint: keypair.fingerprint.clone(),
+            public_key: keypair.public_key.clone(),
+            items: items.to_vec(),
+            watermark,
+            issued_at: Utc::now(),
+        };
+        let json = serde_json::to_string(&ack).map_err(|e| format!("Failed to serialize inbox ack: {}", e))?;
+        let signature = keypair.sign(&json)?;
+        Ok(Self { ack, signature })
+    }
+
+    /// Signed by the key the recipient fingerprint belongs to.
+    pub fn verify(&self) -> bool {
+        let Ok(fingerprint) = fingerprint_from_public_key(&self.ack.public_key) else {
+            return false;
+        };
+        if fingerprint != self.ack.recipient_fingerprint {
+            return false;
+        }
+        serde_json::to_string(&self.ack)
+            .ok()
+            .and_then(|json| verify_signature(&json, &self.signature, &self.ack.public_key).ok())
+            .unwrap_or(false)
+    }
+
+    /// Whether this ack covers `item`, by sender and id or by watermark.
+    pub fn covers<T: InboxItem>(&self, item: &T) -> bool {
+        self.ack.items.contains(&item.acked_item()) || self.ack.watermark.is_some_and(|w| item.created_at() <= w)
+    }
+}
+
+/// The acks of one inbox owner, compacted and indexed for pruning.
+#[derive(Debug, Clone, Default)]
+pub struct InboxAckSet {
+    items: HashSet<AckedItem>,
+    watermark: Option<DateTime<Utc>>,
+}
+
+impl InboxAckSet {
+    /// Drop acks that are not `recipient_fingerprint`'s or are covered by a
+    /// newer watermark, and index the rest.
+    pub fn compact(recipient_fingerprint: &str, acks: &mut Vec<SignedInboxAck>) -> Self {
+        let mut signatures = HashSet::new();
+        acks.retain(|a| {
+            a.ack.recipient_fingerprint == recipient_fingerprint && signatures.insert(a.signature.clone()) && a.verify()
+        });
+        if let Some(watermark) = acks.iter().filter_map(|a| a.ack.watermark).max() {
+            // An ack can only list entries written before it was issued, so
+            // ones issued before the watermark add nothing.
+            let covered_before = watermark - Duration::seconds(ACK_CLOCK_SLACK_SECS);
+            let mut kept_watermark = false;
+            acks.retain(|a| {
+                if a.ack.watermark == Some(watermark) && !kept_watermark {
+                    kept_watermark = true;
+                    return true;
+                }
+                a.ack.issued_at > covered_before
+            });
+        }
+
+        Self {
+            items: acks.iter().flat_map(|a| a.ack.items.iter().cloned()).collect(),
+            watermark: acks.iter().filter_map(|a| a.ack.watermark).max(),
+        }
+    }
+
+    pub fn covers<T: InboxItem>(&self, item: &T) -> bool {
+        self.watermark.is_some_and(|w| item.created_at() <= w) || self.items.contains(&item.acked_item())
+    }
+
+    /// Remove every entry of `items` the acks cover. Returns how many went.
+    pub fn prune<T: InboxItem>(&self, items: &mut Vec<T>) -> usize {
+        let before = items.len();
+        items.retain(|i| !self.covers(i));
+        before - items.len()
+    }
+}
+
+/// Honour the acks stored in `recipient_fingerprint`'s inbox: drop acks that
+/// are not the owner's or are covered by a newer watermark, then every
+/// message they cover. Returns how many messages were removed.
+pub fn apply_inbox_acks(
+    recipient_fingerprint: &str,
+    messages: &mut Vec<SignedMessage>,
+    acks: &mut Vec<SignedInboxAck>,
+) -> usize {
+    InboxAckSet::compact(recipient_fingerprint, acks).prune(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    fn message_at(to: &str, created_at: DateTime<Utc>) -> SignedMessage {
+        let sender = KeyPair::generate().unwrap();
+        let mut message = Message::new_direct(sender.fingerprint.clone(), to.into(), "hi".into());
+        message.created_at = created_at;
+        SignedMessage::create(message, &sender).unwrap()
+    }
+
+    #[test]
+    fn owner_acks_prune_messages_and_others_are_ignored() {
+        let bob = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let old = message_at(&bob.fingerprint, now - Duration::days(40));
+        let read = message_at(&bob.fingerprint, now);
+        let unread = message_at(&bob.fingerprint, now);
+        let mut messages = vec![old, read.clone(), unread.clone()];
+
+        let ids = vec![read.acked_item()];
+        let unread_ids = vec![unread.acked_item()];
+        let mut forged = SignedInboxAck::create(&mallory, &unread_ids, None).unwrap();
+        forged.ack.recipient_fingerprint = bob.fingerprint.clone();
+        let by_mallory = SignedInboxAck::create(&mallory, &unread_ids, None).unwrap();
+        let mut acks = vec![
+            SignedInboxAck::create(&bob, &ids, Some(now - Duration::days(30))).unwrap(),
+            forged,
+            by_mallory,
+        ];
+        assert_eq!(apply_inbox_acks(&bob.fingerprint, &mut messages, &mut acks), 2);
+        assert_eq!(acks.len(), 1);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message.id, unread.message.id);
+
+        // Re-adding an acked message does not stick.
+        messages.push(read);
+        assert_eq!(apply_inbox_acks(&bob.fingerprint, &mut messages, &mut acks), 1);
+        assert!(SignedInboxAck::create(&bob, &[], None).is_err());
+    }
+
+    #[test]
+    fn newer_watermark_compacts_older_acks() {
+        let bob = KeyPair::generate().unwrap();
+        let item = |id: &str| AckedItem {
+            sender_fingerprint: "fp-alice".to_string(),
+            id: id.to_string(),
+        };
+        let mut early = SignedInboxAck::create(&bob, &[item("a")], None).unwrap();
+        early.ack.issued_at = Utc::now() - Duration::days(2);
+        early.signature = bob.sign(&serde_json::to_string(&early.ack).unwrap()).unwrap();
+        let recent = SignedInboxAck::create(&bob, &[item("b")], None).unwrap();
+        let watermark = SignedInboxAck::create(&bob, &[], Some(Utc::now() - Duration::days(1))).unwrap();
+
+        let mut acks = vec![early, recent.clone(), watermark.clone()];
+        apply_inbox_acks(&bob.fingerprint, &mut Vec::new(), &mut acks);
+        assert_eq!(acks, vec![recent, watermark]);
+    }
+
+    #[test]
+    fn acks_match_sender_and_id() {
+        let bob = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let read = message_at(&bob.fingerprint, Utc::now());
+        let mut same_id = Message::new_direct(mallory.fingerprint.clone(), bob.fingerprint.clone(), "hi".into());
+        same_id.id = read.message.id.clone();
+        let same_id = SignedMessage::create(same_id, &mallory).unwrap();
+
+        let mut acks = vec![SignedInboxAck::create(&bob, &[read.acked_item()], None).unwrap()];
+        let set = InboxAckSet::compact(&bob.fingerprint, &mut acks);
+        let mut messages = vec![read, same_id];
+        assert_eq!(set.prune(&mut messages), 1);
+        assert_eq!(messages[0].message.sender_fingerprint, mallory.fingerprint);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::causal::{MAX_LAMPORT, MAX_PREVIOUS_IDS};
+use crate::crypto::fingerprint_from_public_key;
+use crate::expiry::Expiring;
+use crate::message::{Message, SignedMessage};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Tolerated clock skew for messages that claim to come from the future.
+pub const MESSAGE_MAX_FUTURE_SKEW_SECS: i64 = 5 * 60;
+
+/// Messages written longer ago than this are not accepted as new.
+pub const MESSAGE_MAX_AGE_DAYS: i64 = 30;
+
+/// How many message ids the replay cache remembers.
+pub const SEEN_MESSAGES_CAPACITY: usize = 10_000;
+
+/// Why an incoming message was not accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Rejection {
+    /// The signature does not verify against the sender's key.
+    BadSignature,
+    /// `sender_fingerprint` is not the fingerprint of the signing key.
+    SenderMismatch,
+    /// `created_at` is further ahead of our clock than the skew allows.
+    FromFuture { ahead_secs: i64 },
+    /// `created_at` is older than the ingestion window, or older than the
+    /// oldest id the replay cache still remembers.
+    TooOld { age_secs: i64 },
+    Expired,
+    /// The message id was accepted before.
+    Replayed,
+    /// The Lamport time or predecessor list is out of bounds.
+    BadCausality,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::BadSignature => write!(f, "signature does not verify"),
+            Rejection::SenderMismatch => write!(f, "sender fingerprint does not match the signing key"),
+            Rejection::FromFuture { ahead_secs } => write!(f, "dated {}s in the future", ahead_secs),
+            Rejection::TooOld { age_secs } => write!(f, "too old to accept ({}s)", age_secs),
+            Rejection::Expired => write!(f, "expired"),
+            Rejection::Replayed => write!(f, "already received"),
+            Rejection::BadCausality => write!(f, "causal order fields out of bounds"),
+        }
+    }
+}
+
+/// Replay cache entries for one sender.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SenderSeen {
+    /// Accepted message ids with their `created_at`.
+    #[serde(default)]
+    ids: BTreeMap<String, DateTime<Utc>>,
+    /// Newest `created_at` evicted from `ids` so far.
+    #[serde(default)]
+    horizon: Option<DateTime<Utc>>,
+}
+
+/// Checks every incoming `SignedMessage` goes through before it is shown:
+/// signature, sender binding, timestamp window and a persistent replay
+/// cache. The cache is bounded; once ids are evicted, anything not newer
+/// than the evicted ones is refused, so a replay can never slip through.
+///
+/// Ids are remembered per sender, and when the cache is full the sender
+/// holding the most ids gives one up, so a flood from one contact cannot
+/// push out, or move the horizon of, anyone else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageIngest {
+    #[serde(default)]
+    senders: BTreeMap<String, SenderSeen>,
+    #[serde(skip, default = "default_capacity")]
+    capacity: usize,
+}
+
+fn default_capacity() -> usize {
+    SEEN_MESSAGES_CAPACITY
+}
+
+impl Default for MessageIngest {
+    fn default() -> Self {
+        Self::with_capacity(SEEN_MESSAGES_CAPACITY)
+    }
+}
+
+impl MessageIngest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { senders: BTreeMap::new(), capacity: capacity.max(1) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.senders.values().map(|s| s.ids.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.senders.values().all(|s| s.ids.is_empty())
+    }
+
+    pub fn has_seen(&self, sender_fingerprint: &str, message_id: &str) -> bool {
+        self.senders
+            .get(sender_fingerprint)
+            .is_some_and(|s| s.ids.contains_key(message_id))
+    }
+
+    /// Whether any of `senders` sent `message_id`, for references such as
+    /// predecessor ids that do not say who wrote them.
+    pub fn has_seen_from<'a>(&self, senders: impl IntoIterator<Item = &'a str>, message_id: &str) -> bool {
+        senders.into_iter().any(|sender| self.has_seen(sender, message_id))
+    }
+
+    /// Everything except the replay cache, for records that are applied
+    /// idempotently and may be looked at more than once, such as receipts.
+    pub fn check(&self, message: &SignedMessage, sender_public_key: &str, now: DateTime<Utc>) -> Result<(), Rejection> {
+        let signer = fingerprint_from_public_key(sender_public_key).map_err(|_| Rejection::SenderMismatch)?;
+        if signer != message.message.sender_fingerprint {
+            return Err(Rejection::SenderMismatch);
+        }
+        if !message.verify(sender_public_key).unwrap_or(false) {
+            return Err(Rejection::BadSignature);
+        }
+        let created_at = message.message.created_at;
+        if created_at > now + Duration::seconds(MESSAGE_MAX_FUTURE_SKEW_SECS) {
+            return Err(Rejection::FromFuture { ahead_secs: (created_at - now).num_seconds() });
+        }
+        let age_secs = (now - created_at).num_seconds();
+        if now - created_at > Duration::days(MESSAGE_MAX_AGE_DAYS) {
+            return Err(Rejection::TooOld { age_secs });
+        }
+        if message.is_expired_at(now) {
+            return Err(Rejection::Expired);
+        }
+        if message.message.lamport > MAX_LAMPORT || message.message.previous_ids.len() > MAX_PREVIOUS_IDS {
+            return Err(Rejection::BadCausality);
+        }
+        Ok(())
+    }
+
+    /// Run all checks and remember the message so it is accepted only once.
+    pub fn admit(&mut self, message: &SignedMessage, sender_public_key: &str, now: DateTime<Utc>) -> Result<(), Rejection> {
+        self.check(message, sender_public_key, now)?;
+        let sender = &message.message.sender_fingerprint;
+        if self.has_seen(sender, &message.message.id) {
+            return Err(Rejection::Replayed);
+        }
+        let created_at = message.message.created_at;
+        let horizon = self.senders.get(sender).and_then(|s| s.horizon);
+        if horizon.is_some_and(|horizon| created_at <= horizon) {
+            return Err(Rejection::TooOld { age_secs: (now - created_at).num_seconds() });
+        }
+        self.remember(&message.message, now);
+        Ok(())
+    }
+
+    /// Remember a message we sent ourselves, so a conversation that refers
+    /// to it after we removed it locally is not reported as having a gap.
+    pub fn record_own(&mut self, message: &Message, now: DateTime<Utc>) {
+        self.remember(message, now);
+    }
+
+    /// Store the id under its sender with its real `created_at`, so it is
+    /// kept for as long as [`MessageIngest::check`] would still accept it.
+    fn remember(&mut self, message: &Message, now: DateTime<Utc>) {
+        self.senders
+            .entry(message.sender_fingerprint.clone())
+            .or_default()
+            .ids
+            .insert(message.id.clone(), message.created_at);
+        self.prune(now);
+    }
+
+    /// Forget ids that fell out of the age window, then, while over
+    /// capacity, the oldest id of whichever sender holds the most.
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::days(MESSAGE_MAX_AGE_DAYS);
+        for seen in self.senders.values_mut() {
+            seen.ids.retain(|_, created_at| *created_at >= cutoff);
+        }
+        let mut len = self.len();
+        while len > self.capacity {
+            let Some(seen) = self.senders.values_mut().max_by_key(|s| s.ids.len()) else {
+                break;
+            };
+            let Some((id, created_at)) = seen
+                .ids
+                .iter()
+                .min_by_key(|(_, created_at)| **created_at)
+                .map(|(id, created_at)| (id.clone(), *created_at))
+            else {
+                break;
+            };
+            seen.ids.remove(&id);
+            // Dates ahead of our clock are clamped, so evicting them can
+            // never move a horizon into the future.
+            let created_at = created_at.min(now);
+            seen.horizon = Some(seen.horizon.map_or(created_at, |h| h.max(created_at)));
+            len -= 1;
+        }
+        // A sender whose ids all aged out keeps only a horizon that the age
+        // window already enforces.
+        self.senders
+            .retain(|_, s| !s.ids.is_empty() || s.horizon.is_some_and(|h| h >= cutoff));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+
+    fn signed_at(kp: &KeyPair, created_at: DateTime<Utc>) -> SignedMessage {
+        let mut message = Message::new_direct(kp.fingerprint.clone(), "bob".into(), "hi".into());
+        message.created_at = created_at;
+        SignedMessage::create(message, kp).unwrap()
+    }
+
+    #[test]
+    fn rejects_forgeries_bad_timestamps_and_replays_with_reasons() {
+        let alice = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let mut ingest = MessageIngest::new();
+
+        let fresh = signed_at(&alice, now);
+        assert_eq!(ingest.admit(&fresh, &alice.public_key, now), Ok(()));
+        assert_eq!(ingest.admit(&fresh, &alice.public_key, now), Err(Rejection::Replayed));
+
+        let mut tampered = signed_at(&alice, now);
+        tampered.message.content = "changed".into();
+        assert_eq!(ingest.admit(&tampered, &alice.public_key, now), Err(Rejection::BadSignature));
+
+        // Signed by Mallory but claiming to be from Alice.
+        let mut claimed = Message::new_direct(alice.fingerprint.clone(), "bob".into(), "hi".into());
+        claimed.created_at = now;
+        let claimed = SignedMessage::create(claimed, &mallory).unwrap();
+        assert_eq!(ingest.admit(&claimed, &mallory.public_key, now), Err(Rejection::SenderMismatch));
+
+        let future = signed_at(&alice, now + Duration::hours(1));
+        assert!(matches!(ingest.admit(&future, &alice.public_key, now), Err(Rejection::FromFuture { .. })));
+        let ancient = signed_at(&alice, now - Duration::days(MESSAGE_MAX_AGE_DAYS + 1));
+        assert!(matches!(ingest.admit(&ancient, &alice.public_key, now), Err(Rejection::TooOld { .. })));
+        assert_eq!(Rejection::Replayed.to_string(), "already received");
+
+        let mut overflowing = Message::new_direct(alice.fingerprint.clone(), "bob".into(), "hi".into());
+        overflowing.lamport = u64::MAX;
+        let overflowing = SignedMessage::create(overflowing, &alice).unwrap();
+        assert_eq!(ingest.admit(&overflowing, &alice.public_key, now), Err(Rejection::BadCausality));
+        let mut branching = Message::new_direct(alice.fingerprint.clone(), "bob".into(), "hi".into());
+        branching.previous_ids = (0..=MAX_PREVIOUS_IDS).map(|i| i.to_string()).collect();
+        let branching = SignedMessage::create(branching, &alice).unwrap();
+        assert_eq!(ingest.admit(&branching, &alice.public_key, now), Err(Rejection::BadCausality));
+    }
+
+    #[test]
+    fn evicted_ids_move_the_horizon_so_replays_stay_rejected() {
+        let alice = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let mut ingest = MessageIngest::with_capacity(2);
+        let oldest = signed_at(&alice, now - Duration::minutes(3));
+        for message in [&oldest, &signed_at(&alice, now - Duration::minutes(2)), &signed_at(&alice, now)] {
+            ingest.admit(message, &alice.public_key, now).unwrap();
+        }
+        assert_eq!(ingest.len(), 2);
+        assert!(!ingest.has_seen(&alice.fingerprint, &oldest.message.id));
+        assert!(matches!(ingest.admit(&oldest, &alice.public_key, now), Err(Rejection::TooOld { .. })));
+
+        let restored: MessageIngest = serde_json::from_str(&serde_json::to_string(&ingest).unwrap()).unwrap();
+        assert!(matches!(restored.clone().admit(&oldest, &alice.public_key, now), Err(Rejection::TooOld { .. })));
+        assert_eq!(restored.len(), 2);
+    }
+
+    #[test]
+    fn future_dated_ids_are_kept_until_they_are_too_old() {
+        let alice = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let mut ingest = MessageIngest::new();
+        let ahead = signed_at(&alice, now + Duration::minutes(4));
+        ingest.admit(&ahead, &alice.public_key, now).unwrap();
+
+        // A minute before the message itself turns 30 days old.
+        let later = ahead.message.created_at + Duration::days(MESSAGE_MAX_AGE_DAYS) - Duration::minutes(1);
+        ingest.admit(&signed_at(&alice, later), &alice.public_key, later).unwrap();
+        assert!(ingest.has_seen(&alice.fingerprint, &ahead.message.id));
+        assert_eq!(ingest.admit(&ahead, &alice.public_key, later), Err(Rejection::Replayed));
+    }
+
+    #[test]
+    fn one_flooding_sender_cannot_evict_or_shadow_another() {
+        let alice = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let mut ingest = MessageIngest::with_capacity(4);
+
+        let from_alice = signed_at(&alice, now - Duration::minutes(10));
+        ingest.admit(&from_alice, &alice.public_key, now).unwrap();
+        // Mallory reuses Alice's id; it is a different sender's message.
+        let mut reused = Message::new_direct(mallory.fingerprint.clone(), "bob".into(), "hi".into());
+        reused.id = from_alice.message.id.clone();
+        reused.created_at = now;
+        let reused = SignedMessage::create(reused, &mallory).unwrap();
+        assert_eq!(ingest.admit(&reused, &mallory.public_key, now), Ok(()));
+
+        // Future-dated within the skew, then flooded out of the cache.
+        let ahead = signed_at(&mallory, now + Duration::minutes(4));
+        ingest.admit(&ahead, &mallory.public_key, now).unwrap();
+        for i in 1..=10 {
+            let at = now + Duration::seconds(i);
+            ingest.admit(&signed_at(&mallory, at), &mallory.public_key, at).unwrap();
+        }
+        assert_eq!(ingest.len(), 4);
+        assert!(ingest.has_seen(&alice.fingerprint, &from_alice.message.id));
+        assert_eq!(ingest.admit(&from_alice, &alice.public_key, now), Err(Rejection::Replayed));
+        assert!(ingest.has_seen_from([mallory.fingerprint.as_str(), alice.fingerprint.as_str()], &from_alice.message.id));
+
+        // Alice's next message is still accepted; only Mallory's horizon
+        // moved, and not as far as the date Mallory claimed.
+        let later = now + Duration::minutes(3);
+        assert_eq!(ingest.admit(&signed_at(&alice, now - Duration::minutes(1)), &alice.public_key, later), Ok(()));
+        assert!(matches!(
+            ingest.admit(&signed_at(&mallory, now), &mallory.public_key, later),
+            Err(Rejection::TooOld { .. })
+        ));
+        assert_eq!(ingest.admit(&signed_at(&mallory, later), &mallory.public_key, later), Ok(()));
+    }
+}
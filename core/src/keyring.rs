@@ -0,0 +1,120 @@
+use crate::audience::Recipient;
+use crate::crypto::fingerprint_from_public_key;
+use crate::profile::SignedProfile;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Keys a contact published in their latest verified profile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactKeys {
+    pub public_key: String,
+    #[serde(default)]
+    pub encryption_public_key: Option<String>,
+    pub profile_version: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Contacts' signing and encryption keys, learned only from verified
+/// profiles, so messages can be sealed and checked by fingerprint alone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyRing {
+    #[serde(default)]
+    contacts: BTreeMap<String, ContactKeys>,
+}
+
+impl KeyRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the keys of a verified profile whose fingerprint belongs to
+    /// its signing key, unless a newer version is
+    /// already known. Returns whether anything changed.
+    pub fn learn(&mut self, profile: &SignedProfile) -> Result<bool, String> {
+        if !profile.verify()? {
+            return Err("Profile signature invalid".to_string());
+        }
+        let p = &profile.profile;
+        if fingerprint_from_public_key(&p.public_key)? != p.fingerprint {
+            return Err("Profile fingerprint does not match its key".to_string());
+        }
+        let keys = ContactKeys {
+            public_key: p.public_key.clone(),
+            encryption_public_key: p.encryption_public_key.clone(),
+            profile_version: p.version,
+            updated_at: p.updated_at,
+        };
+        match self.contacts.get(&p.fingerprint) {
+            Some(current) if (current.profile_version, current.updated_at) >= (keys.profile_version, keys.updated_at) => {
+                Ok(false)
+            }
+            _ => {
+                self.contacts.insert(p.fingerprint.clone(), keys);
+                Ok(true)
+            }
+        }
+    }
+
+    pub fn get(&self, fingerprint: &str) -> Option<&ContactKeys> {
+        self.contacts.get(fingerprint)
+    }
+
+    pub fn public_key(&self, fingerprint: &str) -> Option<&str> {
+        self.get(fingerprint).map(|k| k.public_key.as_str())
+    }
+
+    pub fn encryption_key(&self, fingerprint: &str) -> Option<&str> {
+        self.get(fingerprint).and_then(|k| k.encryption_public_key.as_deref())
+    }
+
+    /// Every known contact by fingerprint, in fingerprint order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ContactKeys)> {
+        self.contacts.iter().map(|(fp, k)| (fp.as_str(), k))
+    }
+
+    /// Every contact that can be sealed for, e.g. to resolve a circle.
+    pub fn recipients(&self) -> Vec<Recipient> {
+        self.contacts
+            .iter()
+            .filter_map(|(fp, k)| Some(Recipient::new(fp.clone(), k.encryption_public_key.clone()?)))
+            .collect()
+    }
+
+    pub fn forget(&mut self, fingerprint: &str) -> bool {
+        self.contacts.remove(fingerprint).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::profile::Profile;
+
+    #[test]
+    fn learns_only_verified_and_newer_profiles() {
+        let kp = KeyPair::generate().unwrap();
+        let mut profile = Profile::new("carol".into(), kp.get_public_info());
+        let first = SignedProfile::create(profile.clone(), &kp).unwrap();
+        profile.update(Some("Carol".into()), None);
+        let second = SignedProfile::create(profile, &kp).unwrap();
+
+        let mut ring = KeyRing::new();
+        let mut forged = second.clone();
+        forged.profile.encryption_public_key = Some("attacker".into());
+        assert!(ring.learn(&forged).is_err());
+        let other = KeyPair::generate().unwrap();
+        let mut impostor = Profile::new("carol".into(), other.get_public_info());
+        impostor.fingerprint = kp.fingerprint.clone();
+        assert!(ring.learn(&SignedProfile::create(impostor, &other).unwrap()).is_err());
+
+        assert!(ring.learn(&second).unwrap());
+        assert!(!ring.learn(&first).unwrap());
+        assert_eq!(ring.public_key(&kp.fingerprint), Some(kp.public_key.as_str()));
+        assert_eq!(ring.encryption_key(&kp.fingerprint), kp.enc_public_key.as_deref());
+        assert_eq!(ring.recipients().len(), 1);
+        assert!(ring.forget(&kp.fingerprint));
+        assert!(ring.get(&kp.fingerprint).is_none());
+    }
+}
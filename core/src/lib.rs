@@ -1,22 +1,74 @@
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+mod attachment;
+mod audience;
+mod causal;
+mod content_warning;
 mod crypto;
+mod encrypted_storage;
+mod expiry;
+mod feed;
+mod group;
+mod heartbeat;
+mod inbox_ack;
+mod ingest;
 mod invite;
-mod profile;
-mod post;
+mod keyring;
+mod markup;
 mod message;
+mod message_control;
+mod outbox;
+mod petname;
+mod poll;
+mod post;
+mod profile;
+mod reaction;
+mod receipt;
+#[cfg(not(target_arch = "wasm32"))]
+mod relational;
+mod repost;
+mod revision;
+#[cfg(not(target_arch = "wasm32"))]
+mod search;
 mod storage;
+mod thread;
 pub mod service;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
 
+pub use attachment::*;
+pub use audience::*;
+pub use causal::*;
+pub use content_warning::*;
 pub use crypto::*;
+pub use encrypted_storage::*;
+pub use expiry::*;
+pub use feed::*;
+pub use group::*;
+pub use heartbeat::*;
+pub use inbox_ack::*;
+pub use ingest::*;
 pub use invite::*;
-pub use profile::*;
-pub use post::*;
+pub use keyring::*;
+pub use markup::*;
 pub use message::*;
+pub use message_control::*;
+pub use outbox::*;
+pub use petname::*;
+pub use poll::*;
+pub use post::*;
+pub use profile::*;
+pub use reaction::*;
+pub use receipt::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use relational::*;
+pub use repost::*;
+pub use revision::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use search::*;
 pub use storage::*;
+pub use thread::*;
 pub use service::{CoreService, ProfileEnvelope, CapabilityDescriptor, CreateProfileRequest, UpdateProfileRequest};
 #[cfg(target_arch = "wasm32")]
 pub use wasm::*;
@@ -0,0 +1,498 @@
+use crate::petname::PetnameBook;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+
+/// Deepest nesting of emphasis inside emphasis before markers are shown as text.
+const MAX_NESTING: usize = 4;
+
+/// URL schemes that may become links. Everything else stays plain text.
+const SAFE_SCHEMES: [&str; 3] = ["https://", "http://", "mailto:"];
+
+/// Inline content. Renderer-neutral: hosts map each variant to their own
+/// widgets (iced spans on desktop, Compose `AnnotatedString` on Android, …).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Inline {
+    Text { text: String },
+    Emphasis { children: Vec<Inline> },
+    Strong { children: Vec<Inline> },
+    Code { text: String },
+    Link { text: String, url: String },
+    /// `@handle`, where the handle is a fingerprint or one of the reader's
+    /// petnames. `fingerprint` is filled in when the handle could be resolved.
+    Mention { handle: String, fingerprint: Option<String> },
+    /// `#tag`, normalised to lowercase.
+    Hashtag { tag: String },
+    LineBreak,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Paragraph { children: Vec<Inline> },
+    CodeBlock { text: String },
+}
+
+/// A parsed post or message body.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+impl Document {
+    /// Parse markdown-lite text. Never fails: anything that is not valid
+    /// markup is kept as literal text, and no raw HTML is ever produced.
+    pub fn parse(source: &str) -> Self {
+        let mut blocks = Vec::new();
+        let mut paragraph: Vec<&str> = Vec::new();
+        let mut code: Option<Vec<&str>> = None;
+
+        let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+            if !paragraph.is_empty() {
+                let chars: Vec<char> = paragraph.join("\n").chars().collect();
+                blocks.push(Block::Paragraph {
+                    children: parse_inline(&chars, 0),
+                });
+                paragraph.clear();
+            }
+        };
+
+        for line in source.lines() {
+            if let Some(lines) = &mut code {
+                if line.trim_start().starts_with("```") {
+                    blocks.push(Block::CodeBlock {
+                        text: lines.join("\n"),
+                    });
+                    code = None;
+                } else {
+                    lines.push(line);
+                }
+            } else if line.trim_start().starts_with("```") {
+                flush(&mut paragraph, &mut blocks);
+                code = Some(Vec::new());
+            } else if line.trim().is_empty() {
+                flush(&mut paragraph, &mut blocks);
+            } else {
+                paragraph.push(line);
+            }
+        }
+        if let Some(lines) = code {
+            // Unterminated fence: keep what was typed as code.
+            blocks.push(Block::CodeBlock {
+                text: lines.join("\n"),
+            });
+        }
+        flush(&mut paragraph, &mut blocks);
+
+        Document { blocks }
+    }
+
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Inline)) {
+        fn walk<'a>(inlines: &'a [Inline], f: &mut impl FnMut(&'a Inline)) {
+            for inline in inlines {
+                f(inline);
+                if let Inline::Emphasis { children } | Inline::Strong { children } = inline {
+                    walk(children, f);
+                }
+            }
+        }
+        for block in &self.blocks {
+            if let Block::Paragraph { children } = block {
+                walk(children, f);
+            }
+        }
+    }
+
+    /// Hashtags in order of first appearance, without duplicates.
+    pub fn hashtags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+        self.visit(&mut |inline| {
+            if let Inline::Hashtag { tag } = inline {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        });
+        tags
+    }
+
+    /// Fingerprints of resolved mentions, without duplicates.
+    pub fn mentioned_fingerprints(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        self.visit(&mut |inline| {
+            if let Inline::Mention {
+                fingerprint: Some(fp),
+                ..
+            } = inline
+            {
+                if !out.contains(fp) {
+                    out.push(fp.clone());
+                }
+            }
+        });
+        out
+    }
+
+    /// Resolve `@petname` mentions against the author's petname book. Only
+    /// the author's own petnames are used, never self-suggested names, so a
+    /// stranger cannot capture mentions by picking a popular username.
+    pub fn resolve_mentions(&mut self, petnames: &PetnameBook) {
+        fn walk(inlines: &mut [Inline], petnames: &PetnameBook) {
+            for inline in inlines {
+                match inline {
+                    Inline::Mention {
+                        handle,
+                        fingerprint: fingerprint @ None,
+                    } => {
+                        let folded = handle.to_lowercase();
+                        *fingerprint = petnames
+                            .entries
+                            .iter()
+                            .find(|e| e.petname.as_ref().is_some_and(|p| p.to_lowercase() == folded))
+                            .map(|e| e.fingerprint.clone());
+                    }
+                    Inline::Emphasis { children } | Inline::Strong { children } => {
+                        walk(children, petnames)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for block in &mut self.blocks {
+            if let Block::Paragraph { children } = block {
+                walk(children, petnames);
+            }
+        }
+    }
+
+    /// The text with markup removed, e.g. for previews and search.
+    pub fn plain_text(&self) -> String {
+        fn push(inlines: &[Inline], out: &mut String) {
+            for inline in inlines {
+                match inline {
+                    Inline::Text { text } | Inline::Code { text } => out.push_str(text),
+                    Inline::Emphasis { children } | Inline::Strong { children } => push(children, out),
+                    Inline::Link { text, .. } => out.push_str(text),
+                    Inline::Mention { handle, .. } => {
+                        out.push('@');
+                        out.push_str(handle);
+                    }
+                    Inline::Hashtag { tag } => {
+                        out.push('#');
+                        out.push_str(tag);
+                    }
+                    Inline::LineBreak => out.push('\n'),
+                }
+            }
+        }
+        let mut out = String::new();
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                out.push_str("\n\n");
+            }
+            match block {
+                Block::Paragraph { children } => push(children, &mut out),
+                Block::CodeBlock { text } => out.push_str(text),
+            }
+        }
+        out
+    }
+}
+
+/// Whether `handle` is a well-formed fingerprint (base64 of 16 bytes).
+fn is_fingerprint(handle: &str) -> bool {
+    handle.len() == 24 && BASE64.decode(handle).is_ok_and(|b| b.len() == 16)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn at_word_start(chars: &[char], i: usize) -> bool {
+    i == 0 || !is_word_char(chars[i - 1])
+}
+
+fn starts_with(chars: &[char], i: usize, pat: &str) -> bool {
+    pat.chars()
+        .enumerate()
+        .all(|(k, p)| chars.get(i + k) == Some(&p))
+}
+
+/// For every position, the next position at or after it where a predicate
+/// holds. Built once per nesting level so that looking for a closing marker
+/// is constant time and unmatched markers do not rescan the rest of the text.
+struct NextMatch(Vec<usize>);
+
+impl NextMatch {
+    fn new(len: usize, is_match: impl Fn(usize) -> bool) -> Self {
+        let mut next = vec![len; len + 1];
+        for j in (0..len).rev() {
+            next[j] = if is_match(j) { j } else { next[j + 1] };
+        }
+        Self(next)
+    }
+
+    fn pattern(chars: &[char], pat: &str) -> Self {
+        Self::new(chars.len(), |j| starts_with(chars, j, pat))
+    }
+
+    fn from(&self, i: usize) -> Option<usize> {
+        let len = self.0.len() - 1;
+        let j = self.0[i.min(len)];
+        (j < len).then_some(j)
+    }
+}
+
+/// Where each kind of closing marker next occurs in one slice.
+struct Closers {
+    backtick: NextMatch,
+    strong_star: NextMatch,
+    strong_underscore: NextMatch,
+    emphasis_star: NextMatch,
+    emphasis_underscore: NextMatch,
+    link_middle: NextMatch,
+    link_end: NextMatch,
+}
+
+impl Closers {
+    fn new(chars: &[char]) -> Self {
+        // A single marker closes emphasis after a non-space; `_` must also
+        // end a word so snake_case stays text.
+        let emphasis = |c: char| {
+            NextMatch::new(chars.len(), move |j| {
+                j > 0
+                    && chars[j] == c
+                    && !chars[j - 1].is_whitespace()
+                    && (c == '*' || chars.get(j + 1).is_none_or(|n| !is_word_char(*n)))
+            })
+        };
+        Self {
+            backtick: NextMatch::pattern(chars, "`"),
+            strong_star: NextMatch::pattern(chars, "**"),
+            strong_underscore: NextMatch::pattern(chars, "__"),
+            emphasis_star: emphasis('*'),
+            emphasis_underscore: emphasis('_'),
+            link_middle: NextMatch::pattern(chars, "]("),
+            link_end: NextMatch::pattern(chars, ")"),
+        }
+    }
+}
+
+fn push_text(out: &mut Vec<Inline>, s: &str) {
+    if let Some(Inline::Text { text }) = out.last_mut() {
+        text.push_str(s);
+    } else {
+        out.push(Inline::Text { text: s.to_string() });
+    }
+}
+
+fn parse_inline(chars: &[char], depth: usize) -> Vec<Inline> {
+    let closers = Closers::new(chars);
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if i + 1 < chars.len() => {
+                push_text(&mut out, &chars[i + 1].to_string());
+                i += 2;
+                continue;
+            }
+            '\n' => {
+                out.push(Inline::LineBreak);
+                i += 1;
+                continue;
+            }
+            '`' => {
+                if let Some(end) = closers.backtick.from(i + 1).filter(|&e| e > i + 1) {
+                    out.push(Inline::Code {
+                        text: chars[i + 1..end].iter().collect(),
+                    });
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '*' | '_' if depth < MAX_NESTING && (c == '*' || at_word_start(chars, i)) => {
+                let (strong, emphasis) = match c {
+                    '*' => (&closers.strong_star, &closers.emphasis_star),
+                    _ => (&closers.strong_underscore, &closers.emphasis_underscore),
+                };
+                if chars.get(i + 1) == Some(&c) {
+                    if let Some(end) = strong.from(i + 2).filter(|&e| e > i + 2) {
+                        out.push(Inline::Strong {
+                            children: parse_inline(&chars[i + 2..end], depth + 1),
+                        });
+                        i = end + 2;
+                        continue;
+                    }
+                } else if chars.get(i + 1).is_some_and(|n| !n.is_whitespace()) {
+                    if let Some(end) = emphasis.from(i + 2) {
+                        out.push(Inline::Emphasis {
+                            children: parse_inline(&chars[i + 1..end], depth + 1),
+                        });
+                        i = end + 1;
+                        continue;
+                    }
+                }
+            }
+            '[' => {
+                if let Some(mid) = closers.link_middle.from(i + 1) {
+                    if let Some(end) = closers.link_end.from(mid + 2) {
+                        let text: String = chars[i + 1..mid].iter().collect();
+                        let url: String = chars[mid + 2..end].iter().collect();
+                        if !text.is_empty() && SAFE_SCHEMES.iter().any(|s| url.starts_with(s)) && !url.contains(char::is_whitespace) {
+                            out.push(Inline::Link { text, url });
+                            i = end + 1;
+                            continue;
+                        }
+                    }
+                }
+            }
+            'h' if at_word_start(chars, i)
+                && (starts_with(chars, i, "https://") || starts_with(chars, i, "http://")) =>
+            {
+                let mut end = i;
+                while end < chars.len() && !chars[end].is_whitespace() {
+                    end += 1;
+                }
+                while end > i && matches!(chars[end - 1], '.' | ',' | ';' | ':' | '!' | '?' | ')') {
+                    end -= 1;
+                }
+                let url: String = chars[i..end].iter().collect();
+                out.push(Inline::Link {
+                    text: url.clone(),
+                    url,
+                });
+                i = end;
+                continue;
+            }
+            '@' if at_word_start(chars, i) => {
+                let mut end = i + 1;
+                while end < chars.len()
+                    && (chars[end].is_ascii_alphanumeric() || matches!(chars[end], '+' | '/' | '=' | '_' | '-' | '.'))
+                {
+                    end += 1;
+                }
+                let run: String = chars[i + 1..end].iter().collect();
+                if is_fingerprint(&run) {
+                    out.push(Inline::Mention {
+                        handle: run.clone(),
+                        fingerprint: Some(run),
+                    });
+                    i = end;
+                    continue;
+                }
+                let handle: String = run
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+                    .collect::<String>()
+                    .trim_end_matches(['.', '-'])
+                    .to_string();
+                if !handle.is_empty() {
+                    i += 1 + handle.chars().count();
+                    out.push(Inline::Mention {
+                        handle,
+                        fingerprint: None,
+                    });
+                    continue;
+                }
+            }
+            '#' if at_word_start(chars, i) => {
+                let mut end = i + 1;
+                while end < chars.len() && (is_word_char(chars[end]) || chars[end] == '-') {
+                    end += 1;
+                }
+                while end > i + 1 && chars[end - 1] == '-' {
+                    end -= 1;
+                }
+                let tag: String = chars[i + 1..end].iter().collect();
+                if tag.chars().any(char::is_alphabetic) {
+                    out.push(Inline::Hashtag {
+                        tag: tag.to_lowercase(),
+                    });
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        push_text(&mut out, &c.to_string());
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn para(doc: &Document) -> &[Inline] {
+        match &doc.blocks[0] {
+            Block::Paragraph { children } => children,
+            other => panic!("expected paragraph, got {other:?}"),
+        }
+    }
+
+    fn text(s: &str) -> Inline {
+        Inline::Text { text: s.to_string() }
+    }
+
+    #[test]
+    fn parses_emphasis_code_and_links() {
+        let doc = Document::parse("**bold** and *it* `x*y` [site](https://example.org)");
+        assert_eq!(
+            para(&doc),
+            &[
+                Inline::Strong { children: vec![text("bold")] },
+                text(" and "),
+                Inline::Emphasis { children: vec![text("it")] },
+                text(" "),
+                Inline::Code { text: "x*y".into() },
+                text(" "),
+                Inline::Link { text: "site".into(), url: "https://example.org".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn unsafe_links_and_snake_case_stay_text() {
+        let doc = Document::parse("[x](javascript:alert(1)) snake_case_name <b>hi</b>");
+        assert_eq!(doc.plain_text(), "[x](javascript:alert(1)) snake_case_name <b>hi</b>");
+        assert!(para(&doc).iter().all(|i| matches!(i, Inline::Text { .. })));
+    }
+
+    #[test]
+    fn extracts_hashtags_and_mentions() {
+        let fp = "q83vEjRWeJCrze8SNFZ4kA==";
+        let source = format!("Hi @alice and @{fp}! #Rust #rust #p2p-net, issue #42 mail@host");
+        let mut doc = Document::parse(&source);
+        assert_eq!(doc.hashtags(), vec!["rust", "p2p-net"]);
+        assert_eq!(doc.mentioned_fingerprints(), vec![fp.to_string()]);
+
+        let mut book = PetnameBook::new();
+        book.set_petname("alice-fp", Some("Alice".into())).unwrap();
+        doc.resolve_mentions(&book);
+        assert_eq!(doc.mentioned_fingerprints(), vec!["alice-fp".to_string(), fp.to_string()]);
+    }
+
+    #[test]
+    fn blocks_and_autolinks() {
+        let doc = Document::parse("see https://x.io/a.\n\n```\nlet *a* = 1;\n```");
+        assert_eq!(doc.blocks.len(), 2);
+        assert!(para(&doc).contains(&Inline::Link {
+            text: "https://x.io/a".into(),
+            url: "https://x.io/a".into()
+        }));
+        assert_eq!(doc.blocks[1], Block::CodeBlock { text: "let *a* = 1;".into() });
+    }
+
+    #[test]
+    fn unmatched_markers_parse_in_linear_time() {
+        for hostile in ["[".repeat(40_000), "*a ".repeat(13_000), format!("`{}", "_x ".repeat(13_000))] {
+            let started = std::time::Instant::now();
+            let doc = Document::parse(&hostile);
+            assert_eq!(doc.plain_text(), hostile);
+            assert!(started.elapsed() < std::time::Duration::from_secs(1), "{:?}", started.elapsed());
+        }
+    }
+}
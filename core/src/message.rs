@@ -1,5 +1,7 @@
 use crate::crypto::{KeyPair, verify_signature};
-use chrono::{DateTime, Utc};
+use crate::group::{GROUP_ENC_ALG, SenderKey};
+use crate::receipt::ReceiptKind;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 #[cfg(target_arch = "wasm32")]
@@ -19,12 +21,38 @@ pub struct Message {
     #[serde(default)]
     pub nonce_b64: Option<String>,
     pub message_type: MessageType,
+    /// Signed expiry for disappearing messages; see `Expiring`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Lamport time within the conversation; 0 when the sender kept none.
+    #[serde(default, skip_serializing_if = "crate::causal::is_unstamped")]
+    pub lamport: u64,
+    /// Latest messages of the conversation the sender had seen; see
+    /// `LamportClock`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageType {
     Direct,
-    Group { group_id: String },
+    /// Encrypted under the sender's key for `epoch` of the group; see
+    /// `GroupBook`.
+    Group {
+        group_id: String,
+        /// Left out at epoch 0, so messages signed before epochs existed
+        /// still verify.
+        #[serde(default, skip_serializing_if = "crate::group::is_first_epoch")]
+        epoch: u64,
+    },
+    /// Acknowledges earlier direct messages; see `ReceiptLog`.
+    Receipt { kind: ReceiptKind },
+    /// New content for an earlier message from the same sender.
+    Edit { target_id: String },
+    /// Retracts an earlier message from the same sender.
+    Unsend { target_id: String },
+    /// The sender's disappearing-message preference; see `DisappearingTimer`.
+    Timer { ttl_secs: Option<u64> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,28 +77,100 @@ impl Message {
             body_enc: None,
             nonce_b64: None,
             message_type: MessageType::Direct,
+            expires_at: None,
+            lamport: 0,
+            previous_ids: Vec::new(),
         }
     }
     
-    pub fn new_group(
-        sender_fingerprint: String,
-        recipient_fingerprint: String,
-        group_id: String,
-        content: String,
-    ) -> Self {
-        Self {
+    /// A message to every member of a group, encrypted under the sender's
+    /// key. The same signed message is delivered to each member's inbox, so
+    /// the recipient is the group itself.
+    pub fn new_group(sender_key: &SenderKey, content: &str) -> Result<Self, String> {
+        let (ciphertext, nonce) = sender_key.encrypt(content)?;
+        Ok(Self {
             id: Uuid::new_v4().to_string(),
-            sender_fingerprint,
-            recipient_fingerprint,
-            content,
+            sender_fingerprint: sender_key.owner_fingerprint.clone(),
+            recipient_fingerprint: sender_key.group_id.clone(),
+            content: ciphertext,
             created_at: Utc::now(),
-            encrypted: false, // TODO: Implement encryption
-            body_enc: None,
-            nonce_b64: None,
-            message_type: MessageType::Group { group_id },
+            encrypted: true,
+            body_enc: Some(GROUP_ENC_ALG.to_string()),
+            nonce_b64: Some(nonce),
+            message_type: MessageType::Group {
+                group_id: sender_key.group_id.clone(),
+                epoch: sender_key.epoch,
+            },
+            expires_at: None,
+            lamport: 0,
+            previous_ids: Vec::new(),
+        })
+    }
+
+    pub fn is_group(&self) -> bool {
+        matches!(self.message_type, MessageType::Group { .. })
+    }
+
+    /// The conversation this message belongs to: its group, or the pair of
+    /// participants of a direct message.
+    pub fn conversation_id(&self) -> String {
+        match &self.message_type {
+            MessageType::Group { group_id, .. } => group_id.clone(),
+            _ => direct_conversation_id(&self.sender_fingerprint, &self.recipient_fingerprint),
         }
     }
+
+    /// Decrypt a group message with the sender key it was written under.
+    pub fn open_group(&self, sender_key: &SenderKey) -> Result<String, String> {
+        let MessageType::Group { group_id, epoch } = &self.message_type else {
+            return Err("not a group message".to_string());
+        };
+        if group_id != &sender_key.group_id
+            || *epoch != sender_key.epoch
+            || self.sender_fingerprint != sender_key.owner_fingerprint
+        {
+            return Err("sender key does not match the message".to_string());
+        }
+        if self.body_enc.as_deref() != Some(GROUP_ENC_ALG) {
+            return Err("unsupported group encryption".to_string());
+        }
+        let nonce = self.nonce_b64.as_deref().ok_or("missing nonce")?;
+        sender_key.decrypt(nonce, &self.content)
+    }
     
+    /// Encrypt the content for the recipient of a direct message. Must be
+    /// called before signing.
+    pub fn seal_for_recipient(&mut self, sender: &KeyPair, recipient_encryption_public_key: &str) -> Result<(), String> {
+        if self.encrypted {
+            return Err("message is already encrypted".to_string());
+        }
+        let (ciphertext, nonce, alg) = sender.encrypt_for_recipient(recipient_encryption_public_key, &self.content)?;
+        self.content = ciphertext;
+        self.encrypted = true;
+        self.body_enc = Some(alg);
+        self.nonce_b64 = Some(nonce);
+        Ok(())
+    }
+
+    /// Read a direct message sealed with `seal_for_recipient`, as either
+    /// party; `peer_encryption_public_key` is the other side's key.
+    pub fn open_direct(&self, reader: &KeyPair, peer_encryption_public_key: &str) -> Result<String, String> {
+        if !self.encrypted {
+            return Ok(self.content.clone());
+        }
+        if self.is_group() {
+            return Err("group messages are opened with a sender key".to_string());
+        }
+        let nonce = self.nonce_b64.as_deref().ok_or("missing nonce")?;
+        reader.decrypt_from_peer(peer_encryption_public_key, nonce, &self.content)
+    }
+
+    /// Make the message disappear `ttl` after it was written. Must be called
+    /// before signing.
+    pub fn expire_after(&mut self, ttl: Duration) {
+        self.expires_at = Some(self.created_at + ttl);
+    }
+
     pub fn to_canonical_json(&self) -> Result<String, String> {
         serde_json::to_string(self)
             .map_err(|e| format!("Failed to serialize message: {}", e))
@@ -138,6 +238,16 @@ pub fn verify_message(signed_message_json: &str, public_key: &str) -> Result<boo
         .map_err(|e| JsValue::from_str(&e))
 }
 
+/// The conversation id shared by direct messages between `a` and `b`,
+/// whichever of them sent it.
+pub fn direct_conversation_id(a: &str, b: &str) -> String {
+    if a <= b {
+        format!("{a}:{b}")
+    } else {
+        format!("{b}:{a}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,13 +268,32 @@ mod tests {
 
     #[test]
     fn group_message_sets_group_id() {
-        let m = Message::new_group(
-            "alice".to_string(),
-            "group1".to_string(),
-            "grp-42".to_string(),
-            "Hello group!".to_string(),
-        );
-        assert!(matches!(m.message_type, MessageType::Group { ref group_id } if group_id == "grp-42"));
+        let key = SenderKey {
+            group_id: "grp-42".to_string(),
+            epoch: 3,
+            owner_fingerprint: "alice".to_string(),
+            key: base64::Engine::encode(&base64::engine::general_purpose::STANDARD, [7u8; 32]),
+        };
+        let m = Message::new_group(&key, "Hello group!").unwrap();
+        assert!(matches!(m.message_type, MessageType::Group { ref group_id, epoch: 3 } if group_id == "grp-42"));
+        assert_eq!(m.sender_fingerprint, "alice");
+        assert!(m.encrypted);
+        assert_eq!(m.open_group(&key).unwrap(), "Hello group!");
+        let other_epoch = SenderKey { epoch: 4, ..key };
+        assert!(m.open_group(&other_epoch).is_err());
+    }
+
+    #[test]
+    fn first_epoch_group_messages_keep_their_pre_epoch_form() {
+        let kp = make_keypair();
+        // A group message as signed before epochs were added.
+        let mut legacy = Message::new_direct(kp.fingerprint.clone(), String::new(), "hi".to_string());
+        legacy.message_type = MessageType::Group { group_id: "grp-1".to_string(), epoch: 0 };
+        let json = serde_json::to_string(&legacy.message_type).unwrap();
+        assert_eq!(json, r#"{"Group":{"group_id":"grp-1"}}"#);
+        let signed = SignedMessage::create(legacy, &kp).unwrap();
+        let reparsed: SignedMessage = serde_json::from_str(&serde_json::to_string(&signed).unwrap()).unwrap();
+        assert!(reparsed.verify(&kp.public_key).unwrap());
     }
 
     #[test]
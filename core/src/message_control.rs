@@ -0,0 +1,251 @@
+use crate::crypto::KeyPair;
+use crate::message::{Message, MessageType, SignedMessage};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Longest disappearing-message timer either party may ask for.
+pub const MAX_DISAPPEARING_SECS: u64 = 28 * 24 * 60 * 60;
+
+impl Message {
+    /// Replace the content of the sender's earlier direct message
+    /// `target_id`, sealed for the recipient like any direct message. Give
+    /// it the original's `expires_at` before signing so the new text
+    /// disappears with it.
+    pub fn new_edit(
+        keypair: &KeyPair,
+        recipient_fingerprint: &str,
+        recipient_encryption_public_key: &str,
+        target_id: &str,
+        content: &str,
+    ) -> Result<Self, String> {
+        if content.trim().is_empty() {
+            return Err("Edited message cannot be empty".to_string());
+        }
+        let mut edit = Message::new_direct(
+            keypair.fingerprint.clone(),
+            recipient_fingerprint.to_string(),
+            content.to_string(),
+        );
+        edit.message_type = MessageType::Edit { target_id: target_id.to_string() };
+        edit.seal_for_recipient(keypair, recipient_encryption_public_key)?;
+        Ok(edit)
+    }
+
+    /// Retract the sender's earlier direct message `target_id`.
+    pub fn new_unsend(keypair: &KeyPair, recipient_fingerprint: &str, target_id: &str) -> Self {
+        let mut unsend = Message::new_direct(
+            keypair.fingerprint.clone(),
+            recipient_fingerprint.to_string(),
+            String::new(),
+        );
+        unsend.message_type = MessageType::Unsend { target_id: target_id.to_string() };
+        unsend
+    }
+
+    /// Whether `keypair` may edit or unsend this message.
+    pub fn can_be_changed_by(&self, keypair: &KeyPair) -> bool {
+        self.sender_fingerprint == keypair.fingerprint && matches!(self.message_type, MessageType::Direct)
+    }
+
+    /// State the sender's disappearing-message preference for the thread
+    /// with `recipient_fingerprint`; `None` turns it off on their side.
+    pub fn new_timer(keypair: &KeyPair, recipient_fingerprint: &str, ttl_secs: Option<u64>) -> Result<Self, String> {
+        if ttl_secs.is_some_and(|ttl| ttl == 0 || ttl > MAX_DISAPPEARING_SECS) {
+            return Err(format!(
+                "Disappearing timer must be between 1 second and {} days",
+                MAX_DISAPPEARING_SECS / 86_400
+            ));
+        }
+        let mut timer = Message::new_direct(
+            keypair.fingerprint.clone(),
+            recipient_fingerprint.to_string(),
+            String::new(),
+        );
+        timer.message_type = MessageType::Timer { ttl_secs };
+        Ok(timer)
+    }
+
+    /// The message an edit or unsend refers to.
+    pub fn control_target(&self) -> Option<&str> {
+        match &self.message_type {
+            MessageType::Edit { target_id } | MessageType::Unsend { target_id } => Some(target_id),
+            _ => None,
+        }
+    }
+
+    /// Control messages change a thread instead of being shown in it.
+    pub fn is_control(&self) -> bool {
+        !matches!(self.message_type, MessageType::Direct | MessageType::Group { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct TimerSetting {
+    ttl_secs: Option<u64>,
+    set_at: DateTime<Utc>,
+}
+
+/// The disappearing-message timer of a two-party thread. Each party states a
+/// preference; the shorter one applies, so either side can make messages
+/// disappear sooner but neither can keep them longer than the other wants.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisappearingTimer {
+    #[serde(default)]
+    settings: BTreeMap<String, TimerSetting>,
+}
+
+impl DisappearingTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a verified timer message. Older settings than the one held for
+    /// its sender are ignored; returns whether anything changed.
+    pub fn apply(&mut self, message: &Message) -> bool {
+        let MessageType::Timer { ttl_secs } = message.message_type else {
+            return false;
+        };
+        let setting = TimerSetting { ttl_secs, set_at: message.created_at };
+        match self.settings.get(&message.sender_fingerprint) {
+            Some(current) if current.set_at >= setting.set_at => false,
+            _ => {
+                self.settings.insert(message.sender_fingerprint.clone(), setting);
+                true
+            }
+        }
+    }
+
+    /// What `fingerprint` last asked for, if anything.
+    pub fn preference_of(&self, fingerprint: &str) -> Option<u64> {
+        self.settings.get(fingerprint).and_then(|s| s.ttl_secs)
+    }
+
+    /// Lifetime for new messages in the thread.
+    pub fn effective(&self) -> Option<Duration> {
+        self.settings
+            .values()
+            .filter_map(|s| s.ttl_secs)
+            .min()
+            .map(|secs| Duration::seconds(secs as i64))
+    }
+}
+
+/// Remove what `sender_fingerprint` has retracted from a list of stored
+/// messages, e.g. an inbox blob: unsent messages with their edits, and
+/// edits superseded by a newer one. Only controls that verify against
+/// `public_key` count. Returns how many messages were removed.
+pub fn apply_retractions(messages: &mut Vec<SignedMessage>, sender_fingerprint: &str, public_key: &str) -> usize {
+    let signed_by_sender = |m: &SignedMessage| {
+        m.message.sender_fingerprint == sender_fingerprint && m.verify(public_key).unwrap_or(false)
+    };
+    let unsent: HashSet<String> = messages
+        .iter()
+        .filter(|m| matches!(m.message.message_type, MessageType::Unsend { .. }))
+        .filter(|m| signed_by_sender(m))
+        .filter_map(|m| m.message.control_target().map(str::to_string))
+        .collect();
+    let mut newest_edit: BTreeMap<String, (DateTime<Utc>, String)> = BTreeMap::new();
+    for m in messages.iter() {
+        if let MessageType::Edit { target_id } = &m.message.message_type {
+            if signed_by_sender(m)
+                && newest_edit
+                    .get(target_id)
+                    .is_none_or(|(at, _)| m.message.created_at > *at)
+            {
+                newest_edit.insert(target_id.clone(), (m.message.created_at, m.message.id.clone()));
+            }
+        }
+    }
+
+    let before = messages.len();
+    messages.retain(|m| {
+        if m.message.sender_fingerprint != sender_fingerprint {
+            return true;
+        }
+        match &m.message.message_type {
+            MessageType::Direct => !unsent.contains(&m.message.id),
+            MessageType::Edit { target_id } => {
+                !unsent.contains(target_id)
+                    && newest_edit.get(target_id).is_none_or(|(_, id)| id == &m.message.id)
+            }
+            _ => true,
+        }
+    });
+    before - messages.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retractions_remove_unsent_messages_and_stale_edits() {
+        let mut alice = KeyPair::generate().unwrap();
+        let mut bob = KeyPair::generate().unwrap();
+        alice.ensure_encryption_keys();
+        bob.ensure_encryption_keys();
+        let bob_key = bob.enc_public_key.clone().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let sign = |m: Message, kp: &KeyPair| SignedMessage::create(m, kp).unwrap();
+
+        let first = Message::new_direct(alice.fingerprint.clone(), "bob".into(), "one".into());
+        let second = Message::new_direct(alice.fingerprint.clone(), "bob".into(), "two".into());
+        assert!(!first.can_be_changed_by(&mallory));
+        assert!(Message::new_edit(&alice, "bob", &bob_key, &second.id, " ").is_err());
+        let mut edit1 = Message::new_edit(&alice, "bob", &bob_key, &second.id, "two!").unwrap();
+        edit1.created_at = second.created_at + Duration::seconds(1);
+        let mut edit2 = Message::new_edit(&alice, "bob", &bob_key, &second.id, "two!!").unwrap();
+        edit2.created_at = second.created_at + Duration::seconds(2);
+        // Edits are sealed like the messages they replace.
+        assert!(edit2.encrypted);
+        assert_eq!(edit2.open_direct(&bob, alice.enc_public_key.as_deref().unwrap()).unwrap(), "two!!");
+        let unsend = Message::new_unsend(&alice, "bob", &first.id);
+        assert_eq!(unsend.control_target(), Some(first.id.as_str()));
+
+        // A forged unsend claiming to be from Alice is ignored.
+        let mut forged = Message::new_unsend(&alice, "bob", &second.id);
+        forged.id = "forged".into();
+        let forged = sign(forged, &mallory);
+
+        let edit2_id = edit2.id.clone();
+        let mut inbox = vec![
+            sign(first, &alice),
+            sign(second.clone(), &alice),
+            sign(edit1, &alice),
+            sign(edit2, &alice),
+            sign(unsend, &alice),
+            forged,
+        ];
+        assert_eq!(apply_retractions(&mut inbox, &alice.fingerprint, &alice.public_key), 2);
+        let ids: Vec<&str> = inbox.iter().map(|m| m.message.id.as_str()).collect();
+        assert!(ids.contains(&second.id.as_str()));
+        assert!(ids.contains(&edit2_id.as_str()));
+        assert_eq!(inbox.len(), 4);
+    }
+
+    #[test]
+    fn shorter_timer_wins_and_old_settings_are_ignored() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let mut timer = DisappearingTimer::new();
+        assert!(Message::new_timer(&alice, &bob.fingerprint, Some(0)).is_err());
+
+        let day = Message::new_timer(&alice, &bob.fingerprint, Some(86_400)).unwrap();
+        assert!(timer.apply(&day));
+        assert!(!timer.apply(&day));
+        assert_eq!(timer.effective(), Some(Duration::days(1)));
+
+        let mut hour = Message::new_timer(&bob, &alice.fingerprint, Some(3_600)).unwrap();
+        hour.created_at = day.created_at + Duration::seconds(1);
+        assert!(timer.apply(&hour));
+        assert_eq!(timer.effective(), Some(Duration::hours(1)));
+
+        let mut off = Message::new_timer(&bob, &alice.fingerprint, None).unwrap();
+        off.created_at = hour.created_at + Duration::seconds(1);
+        assert!(timer.apply(&off));
+        assert!(!timer.apply(&hour));
+        assert_eq!(timer.effective(), Some(Duration::days(1)));
+        assert_eq!(timer.preference_of(&bob.fingerprint), None);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::expiry::Expiring;
+use crate::inbox_ack::SignedInboxAck;
+use crate::ingest::MESSAGE_MAX_AGE_DAYS;
+use crate::message::SignedMessage;
+use crate::post::SignedPost;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Wait before the first retry of an item no peer took.
+pub const OUTBOX_BASE_BACKOFF_SECS: i64 = 5;
+
+/// Longest wait between retries.
+pub const OUTBOX_MAX_BACKOFF_SECS: i64 = 10 * 60;
+
+/// Something signed waiting to reach at least one peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutboxPayload {
+    /// Pushed to the recipient's inbox.
+    Message { message: SignedMessage },
+    /// Pushed to the author's post list.
+    Post { post: SignedPost },
+}
+
+impl OutboxPayload {
+    pub fn id(&self) -> &str {
+        match self {
+            OutboxPayload::Message { message } => &message.message.id,
+            OutboxPayload::Post { post } => &post.post.id,
+        }
+    }
+
+    /// Fingerprint whose blob the payload is written to.
+    pub fn destination(&self) -> &str {
+        match self {
+            OutboxPayload::Message { message } => &message.message.recipient_fingerprint,
+            OutboxPayload::Post { post } => &post.post.author_fingerprint,
+        }
+    }
+
+    fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            OutboxPayload::Message { message } => message.is_expired_at(now),
+            OutboxPayload::Post { post } => post.is_expired_at(now),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum OutboxState {
+    /// Saved locally only; retried with backoff.
+    Queued,
+    /// Taken by this many peers, the most reached in one attempt.
+    Pushed { peers: usize },
+    /// The recipient confirmed it, by receipt or inbox acknowledgement.
+    Acknowledged,
+}
+
+impl OutboxState {
+    pub fn label(&self) -> String {
+        match self {
+            OutboxState::Queued => "queued".to_string(),
+            OutboxState::Pushed { peers: 1 } => "pushed to 1 peer".to_string(),
+            OutboxState::Pushed { peers } => format!("pushed to {} peers", peers),
+            OutboxState::Acknowledged => "acknowledged".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub payload: OutboxPayload,
+    pub state: OutboxState,
+    #[serde(default)]
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl OutboxItem {
+    pub fn id(&self) -> &str {
+        self.payload.id()
+    }
+}
+
+/// Outgoing messages and posts, kept until peers have them so nothing sent
+/// while offline is lost. Hosts push what `due` returns and report back
+/// with `record_push`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Outbox {
+    #[serde(default)]
+    items: Vec<OutboxItem>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &[OutboxItem] {
+        &self.items
+    }
+
+    pub fn get(&self, id: &str) -> Option<&OutboxItem> {
+        self.items.iter().find(|i| i.id() == id)
+    }
+
+    pub fn state(&self, id: &str) -> Option<OutboxState> {
+        self.get(id).map(|i| i.state)
+    }
+
+    /// How many items no peer has taken yet.
+    pub fn queued(&self) -> usize {
+        self.items.iter().filter(|i| i.state == OutboxState::Queued).count()
+    }
+
+    /// Queue a payload for an immediate first attempt. Returns false if it
+    /// is already queued.
+    pub fn enqueue(&mut self, payload: OutboxPayload, now: DateTime<Utc>) -> bool {
+        if self.get(payload.id()).is_some() {
+            return false;
+        }
+        self.items.push(OutboxItem {
+            payload,
+            state: OutboxState::Queued,
+            attempts: 0,
+            enqueued_at: now,
+            last_attempt_at: None,
+            next_attempt_at: now,
+            last_error: None,
+        });
+        true
+    }
+
+    /// Queued items whose next attempt is due, oldest first.
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<OutboxPayload> {
+        self.items
+            .iter()
+            .filter(|i| i.state == OutboxState::Queued && i.next_attempt_at <= now)
+            .map(|i| i.payload.clone())
+            .collect()
+    }
+
+    /// Record an attempt: how many peers took the item, or why it could not
+    /// be written at all. Items no peer took are retried with backoff.
+    pub fn record_push(&mut self, id: &str, result: Result<usize, String>, now: DateTime<Utc>) -> bool {
+        let Some(item) = self.items.iter_mut().find(|i| i.id() == id) else {
+            return false;
+        };
+        item.attempts = item.attempts.saturating_add(1);
+        item.last_attempt_at = Some(now);
+        match result {
+            Ok(peers) if peers > 0 => {
+                item.last_error = None;
+                item.state = match item.state {
+                    OutboxState::Pushed { peers: before } => OutboxState::Pushed { peers: before.max(peers) },
+                    OutboxState::Acknowledged => OutboxState::Acknowledged,
+                    OutboxState::Queued => OutboxState::Pushed { peers },
+                };
+            }
+            Ok(_) => {
+                item.last_error = Some("no peer reachable".to_string());
+                item.next_attempt_at = now + backoff(item.attempts);
+            }
+            Err(e) => {
+                item.last_error = Some(e);
+                item.next_attempt_at = now + backoff(item.attempts);
+            }
+        }
+        true
+    }
+
+    /// Peers just became reachable: make every queued item due now.
+    pub fn peers_appeared(&mut self, now: DateTime<Utc>) {
+        for item in &mut self.items {
+            if item.state == OutboxState::Queued {
+                item.next_attempt_at = item.next_attempt_at.min(now);
+            }
+        }
+    }
+
+    /// Mark messages to `recipient_fingerprint` that they confirmed with a
+    /// receipt. Items for anyone else are left alone. Returns how many changed.
+    pub fn acknowledge(&mut self, recipient_fingerprint: &str, ids: &[String]) -> usize {
+        let mut changed = 0;
+        for item in &mut self.items {
+            if item.state != OutboxState::Acknowledged
+                && matches!(item.payload, OutboxPayload::Message { .. })
+                && item.payload.destination() == recipient_fingerprint
+                && ids.iter().any(|id| id == item.id())
+            {
+                item.state = OutboxState::Acknowledged;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Mark messages to `recipient_fingerprint` that a verified inbox
+    /// acknowledgement of theirs covers.
+    pub fn acknowledge_inbox(&mut self, recipient_fingerprint: &str, acks: &[SignedInboxAck]) -> usize {
+        let acks: Vec<&SignedInboxAck> = acks
+            .iter()
+            .filter(|a| a.ack.recipient_fingerprint == recipient_fingerprint && a.verify())
+            .collect();
+        let mut changed = 0;
+        for item in &mut self.items {
+            let OutboxPayload::Message { message } = &item.payload else {
+                continue;
+            };
+            if item.state != OutboxState::Acknowledged
+                && message.message.recipient_fingerprint == recipient_fingerprint
+                && acks.iter().any(|a| a.covers(message))
+            {
+                item.state = OutboxState::Acknowledged;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// Drop expired payloads and anything older than recipients accept.
+    /// Returns how many items were removed.
+    pub fn prune(&mut self, now: DateTime<Utc>) -> usize {
+        let cutoff = now - Duration::days(MESSAGE_MAX_AGE_DAYS);
+        let before = self.items.len();
+        self.items
+            .retain(|i| i.enqueued_at >= cutoff && !i.payload.is_expired_at(now));
+        before - self.items.len()
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+    Duration::seconds((OUTBOX_BASE_BACKOFF_SECS << exponent).min(OUTBOX_MAX_BACKOFF_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::inbox_ack::InboxItem;
+    use crate::message::Message;
+
+    fn message(kp: &KeyPair) -> OutboxPayload {
+        let message = Message::new_direct(kp.fingerprint.clone(), "bob".into(), "hi".into());
+        OutboxPayload::Message { message: SignedMessage::create(message, kp).unwrap() }
+    }
+
+    #[test]
+    fn retries_with_backoff_until_a_peer_takes_it() {
+        let kp = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let mut outbox = Outbox::new();
+        let payload = message(&kp);
+        let id = payload.id().to_string();
+        assert!(outbox.enqueue(payload.clone(), now));
+        assert!(!outbox.enqueue(payload, now));
+        assert_eq!(outbox.queued(), 1);
+        assert_eq!(outbox.due(now).len(), 1);
+
+        outbox.record_push(&id, Ok(0), now);
+        outbox.record_push(&id, Err("disk full".into()), now);
+        assert_eq!(outbox.get(&id).unwrap().next_attempt_at, now + Duration::seconds(10));
+        assert!(outbox.due(now + Duration::seconds(9)).is_empty());
+        assert_eq!(outbox.state(&id), Some(OutboxState::Queued));
+
+        outbox.peers_appeared(now);
+        assert_eq!(outbox.due(now).len(), 1);
+        outbox.record_push(&id, Ok(2), now);
+        assert_eq!(outbox.state(&id), Some(OutboxState::Pushed { peers: 2 }));
+        assert!(outbox.due(now + Duration::hours(1)).is_empty());
+
+        let ids = std::slice::from_ref(&id);
+        assert_eq!(outbox.acknowledge("mallory", ids), 0);
+        assert_eq!(outbox.acknowledge("bob", ids), 1);
+        assert_eq!(outbox.state(&id).unwrap().label(), "acknowledged");
+        assert_eq!(backoff(30), Duration::seconds(OUTBOX_MAX_BACKOFF_SECS));
+        assert_eq!(outbox.prune(now + Duration::days(MESSAGE_MAX_AGE_DAYS + 1)), 1);
+    }
+
+    #[test]
+    fn inbox_acks_from_the_recipient_acknowledge() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let now = Utc::now();
+        let message = Message::new_direct(alice.fingerprint.clone(), bob.fingerprint.clone(), "hi".into());
+        let signed = SignedMessage::create(message, &alice).unwrap();
+        let id = signed.message.id.clone();
+        let items = [signed.acked_item()];
+        let mut outbox = Outbox::new();
+        outbox.enqueue(OutboxPayload::Message { message: signed }, now);
+
+        let not_bob = SignedInboxAck::create(&alice, &items, None).unwrap();
+        assert_eq!(outbox.acknowledge_inbox(&bob.fingerprint, &[not_bob]), 0);
+        let by_bob = SignedInboxAck::create(&bob, &items, None).unwrap();
+        assert_eq!(outbox.acknowledge_inbox(&bob.fingerprint, &[by_bob]), 1);
+        assert_eq!(outbox.state(&id), Some(OutboxState::Acknowledged));
+    }
+}
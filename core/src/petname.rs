@@ -0,0 +1,457 @@
+use serde::{Deserialize, Serialize};
+
+/// Where a resolved name came from, in decreasing order of trust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameSource {
+    /// The user's own private name for this fingerprint.
+    Petname,
+    /// A name given to this fingerprint by one of the user's named contacts.
+    Introduced,
+    /// A name carried over from elsewhere that the user has not confirmed yet.
+    Unconfirmed,
+    /// A self-chosen name from the identity's own profile.
+    Suggested,
+    /// Nothing better is known; the fingerprint itself.
+    Fingerprint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// Exactly the same name after case folding.
+    Identical,
+    /// Different strings that look alike (homoglyphs, digit swaps, …).
+    Confusable,
+}
+
+/// Another identity whose name could be mistaken for the one being checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameConflict {
+    pub other_fingerprint: String,
+    pub other_name: String,
+    pub kind: ConflictKind,
+}
+
+/// A name for a fingerprint as introduced by one of the user's contacts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Introduction {
+    pub introducer_fingerprint: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PetnameEntry {
+    pub fingerprint: String,
+    #[serde(default)]
+    pub petname: Option<String>,
+    #[serde(default)]
+    pub suggested_username: Option<String>,
+    #[serde(default)]
+    pub suggested_display_name: Option<String>,
+    #[serde(default)]
+    pub introductions: Vec<Introduction>,
+    /// A petname proposed on the user's behalf (e.g. a legacy contact alias)
+    /// that only becomes a petname once confirmed.
+    #[serde(default)]
+    pub pending_petname: Option<String>,
+}
+
+/// The best available name for a fingerprint plus any impersonation warnings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedName {
+    pub fingerprint: String,
+    pub name: String,
+    pub source: NameSource,
+    pub conflicts: Vec<NameConflict>,
+}
+
+impl ResolvedName {
+    /// Render a label that makes the name's provenance visible: petnames are
+    /// shown bare, introduced names with their introducer, unconfirmed names
+    /// with a marker, self-suggested
+    /// names with a leading `~`, and any conflict with a trailing warning.
+    pub fn label(&self) -> String {
+        let base = match self.source {
+            NameSource::Petname | NameSource::Fingerprint => self.name.clone(),
+            NameSource::Introduced => format!("{} (introduced)", self.name),
+            NameSource::Unconfirmed => format!("{} (unconfirmed)", self.name),
+            NameSource::Suggested => format!("~{}", self.name),
+        };
+        if self.conflicts.is_empty() {
+            base
+        } else {
+            format!("{base} ⚠")
+        }
+    }
+}
+
+/// Local, private mapping from fingerprints to human names.
+///
+/// Usernames are global and unauthenticated; petnames are the user's own and
+/// therefore unique within this book. Every UI surface should go through
+/// [`PetnameBook::resolve`] rather than showing profile names directly.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PetnameBook {
+    pub entries: Vec<PetnameEntry>,
+}
+
+impl PetnameBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, fingerprint: &str) -> Option<&PetnameEntry> {
+        self.entries.iter().find(|e| e.fingerprint == fingerprint)
+    }
+
+    fn entry_mut(&mut self, fingerprint: &str) -> &mut PetnameEntry {
+        if let Some(idx) = self.entries.iter().position(|e| e.fingerprint == fingerprint) {
+            return &mut self.entries[idx];
+        }
+        self.entries.push(PetnameEntry {
+            fingerprint: fingerprint.to_string(),
+            ..PetnameEntry::default()
+        });
+        self.entries.last_mut().expect("entry just pushed")
+    }
+
+    /// Set (or clear with `None`) the user's own name for `fingerprint`.
+    ///
+    /// Fails if another fingerprint already has the same petname, so that the
+    /// user's namespace stays unambiguous.
+    pub fn set_petname(&mut self, fingerprint: &str, petname: Option<String>) -> Result<(), String> {
+        let petname = petname.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+        if let Some(name) = &petname {
+            let folded = name.to_lowercase();
+            if let Some(other) = self.entries.iter().find(|e| {
+                e.fingerprint != fingerprint
+                    && e.petname.as_ref().is_some_and(|p| p.to_lowercase() == folded)
+            }) {
+                return Err(format!("petname '{name}' is already used for {}", other.fingerprint));
+            }
+        }
+        let entry = self.entry_mut(fingerprint);
+        entry.petname = petname;
+        entry.pending_petname = None;
+        Ok(())
+    }
+
+    /// Propose `name` as the petname for `fingerprint` without trusting it.
+    ///
+    /// The proposal is shown as unconfirmed until [`PetnameBook::confirm_petname`]
+    /// is called; it is ignored if the user already chose a petname.
+    pub fn suggest_petname(&mut self, fingerprint: &str, name: &str) {
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+        let entry = self.entry_mut(fingerprint);
+        if entry.petname.is_none() {
+            entry.pending_petname = Some(name.to_string());
+        }
+    }
+
+    /// Promote the pending petname for `fingerprint` to a real petname.
+    pub fn confirm_petname(&mut self, fingerprint: &str) -> Result<(), String> {
+        let pending = self
+            .get(fingerprint)
+            .and_then(|e| e.pending_petname.clone())
+            .ok_or_else(|| format!("no pending petname for {fingerprint}"))?;
+        self.set_petname(fingerprint, Some(pending))
+    }
+
+    /// Record the names an identity suggests for itself in its verified profile.
+    pub fn set_suggested(
+        &mut self,
+        fingerprint: &str,
+        username: Option<String>,
+        display_name: Option<String>,
+    ) {
+        let entry = self.entry_mut(fingerprint);
+        entry.suggested_username = username.filter(|u| !u.trim().is_empty());
+        entry.suggested_display_name = display_name.filter(|d| !d.trim().is_empty());
+    }
+
+    /// Record the name a contact uses for `fingerprint`.
+    ///
+    /// Only introductions from fingerprints the user has given a petname to are
+    /// accepted; one introduction is kept per introducer.
+    pub fn add_introduction(
+        &mut self,
+        fingerprint: &str,
+        introducer_fingerprint: &str,
+        name: &str,
+    ) -> Result<(), String> {
+        if fingerprint == introducer_fingerprint {
+            return Err("an identity cannot introduce itself".to_string());
+        }
+        let introducer_known = self
+            .get(introducer_fingerprint)
+            .is_some_and(|e| e.petname.is_some());
+        if !introducer_known {
+            return Err("introductions are only accepted from named contacts".to_string());
+        }
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("introduced name is empty".to_string());
+        }
+
+        let entry = self.entry_mut(fingerprint);
+        entry
+            .introductions
+            .retain(|i| i.introducer_fingerprint != introducer_fingerprint);
+        entry.introductions.push(Introduction {
+            introducer_fingerprint: introducer_fingerprint.to_string(),
+            name: name.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Forget everything known about `fingerprint`, including introductions it made.
+    pub fn remove(&mut self, fingerprint: &str) {
+        self.entries.retain(|e| e.fingerprint != fingerprint);
+        for entry in &mut self.entries {
+            entry
+                .introductions
+                .retain(|i| i.introducer_fingerprint != fingerprint);
+        }
+    }
+
+    /// Resolve the best name for `fingerprint` and flag lookalikes.
+    pub fn resolve(&self, fingerprint: &str) -> ResolvedName {
+        let entry = self.get(fingerprint);
+        let (name, source) = match entry {
+            Some(PetnameEntry { petname: Some(p), .. }) => (p.clone(), NameSource::Petname),
+            Some(e) if !e.introductions.is_empty() => {
+                (e.introductions[0].name.clone(), NameSource::Introduced)
+            }
+            Some(PetnameEntry { pending_petname: Some(p), .. }) => {
+                (p.clone(), NameSource::Unconfirmed)
+            }
+            Some(e) if e.suggested_display_name.is_some() || e.suggested_username.is_some() => (
+                e.suggested_display_name
+                    .clone()
+                    .or_else(|| e.suggested_username.clone())
+                    .unwrap_or_default(),
+                NameSource::Suggested,
+            ),
+            _ => (short_fingerprint(fingerprint), NameSource::Fingerprint),
+        };
+
+        let mut conflicts = Vec::new();
+        if let Some(entry) = entry {
+            // Self-chosen and unconfirmed names are what an impersonator could
+            // control; check them all, not just the one that won resolution.
+            let claimed = [
+                entry.pending_petname.as_deref(),
+                entry.suggested_username.as_deref(),
+                entry.suggested_display_name.as_deref(),
+            ];
+            for candidate in claimed.into_iter().flatten() {
+                for conflict in self.check_name(fingerprint, candidate) {
+                    if !conflicts.contains(&conflict) {
+                        conflicts.push(conflict);
+                    }
+                }
+            }
+        }
+
+        ResolvedName {
+            fingerprint: fingerprint.to_string(),
+            name,
+            source,
+            conflicts,
+        }
+    }
+
+    /// Convenience for UI code: `resolve(fp).label()`.
+    pub fn display_label(&self, fingerprint: &str) -> String {
+        self.resolve(fingerprint).label()
+    }
+
+    /// Find identities other than `fingerprint` whose petname or suggested
+    /// names look like `candidate`.
+    pub fn check_name(&self, fingerprint: &str, candidate: &str) -> Vec<NameConflict> {
+        let folded = candidate.trim().to_lowercase();
+        let skeleton = name_skeleton(candidate);
+        if skeleton.is_empty() {
+            return Vec::new();
+        }
+
+        let mut out = Vec::new();
+        for other in self.entries.iter().filter(|e| e.fingerprint != fingerprint) {
+            let names = [
+                other.petname.as_deref(),
+                other.pending_petname.as_deref(),
+                other.suggested_username.as_deref(),
+                other.suggested_display_name.as_deref(),
+            ];
+            for other_name in names.into_iter().flatten() {
+                let kind = if other_name.trim().to_lowercase() == folded {
+                    ConflictKind::Identical
+                } else if name_skeleton(other_name) == skeleton {
+                    ConflictKind::Confusable
+                } else {
+                    continue;
+                };
+                out.push(NameConflict {
+                    other_fingerprint: other.fingerprint.clone(),
+                    other_name: other_name.to_string(),
+                    kind,
+                });
+                break;
+            }
+        }
+        out
+    }
+}
+
+/// Reduce a name to a "skeleton" so that visually confusable names compare
+/// equal: case is folded, common homoglyphs and digit substitutions are
+/// mapped to one ASCII letter, and separators are dropped.
+pub fn name_skeleton(name: &str) -> String {
+    let mapped: String = name
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            ' ' | '_' | '-' | '.' | '\u{200b}' | '\u{200c}' | '\u{200d}' => None,
+            '0' | 'о' | 'ο' => Some('o'),
+            '1' | 'i' | '|' | '!' | 'і' | 'ι' | 'ӏ' => Some('l'),
+            '3' | 'е' | 'ε' => Some('e'),
+            '4' | '@' | 'а' | 'α' => Some('a'),
+            '5' | '$' | 'ѕ' => Some('s'),
+            '7' => Some('t'),
+            '8' | 'в' | 'β' => Some('b'),
+            'р' | 'ρ' => Some('p'),
+            'с' | 'ϲ' => Some('c'),
+            'у' | 'γ' => Some('y'),
+            'х' | 'χ' => Some('x'),
+            'ј' => Some('j'),
+            'к' | 'κ' => Some('k'),
+            'м' => Some('m'),
+            'н' | 'η' => Some('h'),
+            'т' | 'τ' => Some('t'),
+            'ν' => Some('v'),
+            c => Some(c),
+        })
+        .collect();
+    mapped.replace("rn", "m").replace("vv", "w")
+}
+
+fn short_fingerprint(fp: &str) -> String {
+    if fp.chars().count() <= 12 {
+        return fp.to_string();
+    }
+    let head: String = fp.chars().take(8).collect();
+    let tail: String = fp.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    format!("{head}...{tail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn petname_wins_over_suggested() {
+        let mut book = PetnameBook::new();
+        book.set_suggested("fp-a", Some("alice".into()), Some("Alice A.".into()));
+        assert_eq!(book.resolve("fp-a").source, NameSource::Suggested);
+        assert_eq!(book.display_label("fp-a"), "~Alice A.");
+
+        book.set_petname("fp-a", Some("Mum".into())).unwrap();
+        let resolved = book.resolve("fp-a");
+        assert_eq!(resolved.source, NameSource::Petname);
+        assert_eq!(resolved.name, "Mum");
+    }
+
+    #[test]
+    fn unknown_fingerprint_falls_back_to_short_fp() {
+        let book = PetnameBook::new();
+        let resolved = book.resolve("ABCDEFGHIJKLMNOP");
+        assert_eq!(resolved.source, NameSource::Fingerprint);
+        assert_eq!(resolved.name, "ABCDEFGH...MNOP");
+    }
+
+    #[test]
+    fn duplicate_petname_rejected() {
+        let mut book = PetnameBook::new();
+        book.set_petname("fp-a", Some("Bob".into())).unwrap();
+        assert!(book.set_petname("fp-b", Some("bob".into())).is_err());
+        // Renaming the same fingerprint is fine.
+        book.set_petname("fp-a", Some("BOB".into())).unwrap();
+    }
+
+    #[test]
+    fn detects_username_matching_existing_contact() {
+        let mut book = PetnameBook::new();
+        book.set_petname("fp-real", Some("alice".into())).unwrap();
+        book.set_suggested("fp-real", Some("alice".into()), None);
+        book.set_suggested("fp-fake", Some("alice".into()), None);
+
+        let resolved = book.resolve("fp-fake");
+        assert_eq!(resolved.conflicts.len(), 1);
+        assert_eq!(resolved.conflicts[0].other_fingerprint, "fp-real");
+        assert_eq!(resolved.conflicts[0].kind, ConflictKind::Identical);
+        assert!(resolved.label().ends_with('⚠'));
+    }
+
+    #[test]
+    fn detects_confusable_names() {
+        let mut book = PetnameBook::new();
+        book.set_petname("fp-real", Some("carmen".into())).unwrap();
+        // Cyrillic 'а' and "rn" → "m" tricks.
+        book.set_suggested("fp-fake", Some("cаrrnen".into()), None);
+        let resolved = book.resolve("fp-fake");
+        assert_eq!(resolved.conflicts.len(), 1);
+        assert_eq!(resolved.conflicts[0].kind, ConflictKind::Confusable);
+
+        assert_eq!(name_skeleton("P4ul_0"), name_skeleton("paulo"));
+        assert_ne!(name_skeleton("paul"), name_skeleton("saul"));
+    }
+
+    #[test]
+    fn pending_petname_needs_confirmation() {
+        let mut book = PetnameBook::new();
+        book.set_petname("fp-real", Some("Bob".into())).unwrap();
+        book.suggest_petname("fp-x", "bob");
+        let resolved = book.resolve("fp-x");
+        assert_eq!(resolved.source, NameSource::Unconfirmed);
+        assert_eq!(resolved.conflicts.len(), 1);
+        assert!(book.confirm_petname("fp-x").is_err());
+
+        book.suggest_petname("fp-x", "Robert");
+        book.confirm_petname("fp-x").unwrap();
+        assert_eq!(book.resolve("fp-x").source, NameSource::Petname);
+        assert!(book.get("fp-x").unwrap().pending_petname.is_none());
+
+        // An existing petname is never replaced by a suggestion.
+        book.suggest_petname("fp-x", "Bobby");
+        assert!(book.get("fp-x").unwrap().pending_petname.is_none());
+
+        book.suggest_petname("fp-y", "Yan");
+        book.set_petname("fp-y", None).unwrap();
+        assert_eq!(book.resolve("fp-y").source, NameSource::Fingerprint);
+    }
+
+    #[test]
+    fn introductions_require_named_introducer() {
+        let mut book = PetnameBook::new();
+        assert!(book.add_introduction("fp-x", "fp-stranger", "Xavier").is_err());
+
+        book.set_petname("fp-friend", Some("Friend".into())).unwrap();
+        book.add_introduction("fp-x", "fp-friend", "Xavier").unwrap();
+        book.add_introduction("fp-x", "fp-friend", "Xav").unwrap();
+        let entry = book.get("fp-x").unwrap();
+        assert_eq!(entry.introductions.len(), 1);
+        assert_eq!(book.resolve("fp-x").source, NameSource::Introduced);
+        assert_eq!(book.resolve("fp-x").name, "Xav");
+
+        // A confirmed petname still takes precedence over an introduction.
+        book.set_petname("fp-x", Some("X".into())).unwrap();
+        assert_eq!(book.resolve("fp-x").source, NameSource::Petname);
+
+        book.remove("fp-friend");
+        assert!(book.get("fp-x").unwrap().introductions.is_empty());
+    }
+}
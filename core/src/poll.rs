@@ -0,0 +1,549 @@
+use crate::crypto::{KeyPair, fingerprint_from_public_key, verify_signature};
+use crate::post::Post;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+pub const MAX_POLL_OPTIONS: usize = 10;
+const MAX_OPTION_CHARS: usize = 100;
+/// How far a vote's signed time may run ahead of our clock.
+const VOTE_CLOCK_SLACK_SECS: i64 = 5 * 60;
+
+/// A poll attached to a post; the post's content is the question.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Poll {
+    pub options: Vec<String>,
+    /// Votes cast, or received, at or after this time are not counted.
+    pub closes_at: DateTime<Utc>,
+    #[serde(default)]
+    pub multiple_choice: bool,
+}
+
+impl Poll {
+    pub fn new(options: Vec<String>, closes_at: DateTime<Utc>, multiple_choice: bool) -> Result<Self, String> {
+        let options: Vec<String> = options.iter().map(|o| o.trim().to_string()).collect();
+        if options.len() < 2 || options.len() > MAX_POLL_OPTIONS {
+            return Err(format!("A poll needs between 2 and {} options", MAX_POLL_OPTIONS));
+        }
+        for (i, option) in options.iter().enumerate() {
+            if option.is_empty() {
+                return Err("Poll options cannot be empty".to_string());
+            }
+            if option.chars().count() > MAX_OPTION_CHARS {
+                return Err(format!("Poll options must be at most {} characters", MAX_OPTION_CHARS));
+            }
+            if options[..i].iter().any(|o| o.eq_ignore_ascii_case(option)) {
+                return Err(format!("Duplicate poll option: {}", option));
+            }
+        }
+        Ok(Self {
+            options,
+            closes_at,
+            multiple_choice,
+        })
+    }
+
+    pub fn is_open_at(&self, at: DateTime<Utc>) -> bool {
+        at < self.closes_at
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open_at(Utc::now())
+    }
+
+    fn validate_choices(&self, choices: &[usize]) -> Result<(), String> {
+        if choices.is_empty() {
+            return Err("A vote needs at least one choice".to_string());
+        }
+        if !self.multiple_choice && choices.len() > 1 {
+            return Err("This poll allows a single choice".to_string());
+        }
+        if let Some(bad) = choices.iter().find(|c| **c >= self.options.len()) {
+            return Err(format!("Poll has no option {}", bad + 1));
+        }
+        Ok(())
+    }
+}
+
+/// One identity's vote in a poll.
+///
+/// The voter's public key travels with the vote so the poll author and
+/// readers of a tally can check it without knowing the voter; the key is
+/// only accepted if it hashes to `voter_fingerprint`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollVote {
+    pub id: String,
+    pub poll_id: String,
+    pub poll_author_fingerprint: String,
+    pub voter_fingerprint: String,
+    pub voter_public_key: String,
+    /// Zero-based option indexes, sorted and deduplicated.
+    pub choices: Vec<usize>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPollVote {
+    pub vote: PollVote,
+    pub signature: String,
+}
+
+impl PollVote {
+    pub fn new(voter: &KeyPair, poll_post: &Post, choices: &[usize]) -> Result<Self, String> {
+        let poll = poll_post.poll.as_ref().ok_or("Post is not a poll")?;
+        let mut choices = choices.to_vec();
+        choices.sort_unstable();
+        choices.dedup();
+        poll.validate_choices(&choices)?;
+        let created_at = Utc::now();
+        if !poll.is_open_at(created_at) {
+            return Err("Poll is closed".to_string());
+        }
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            poll_id: poll_post.id.clone(),
+            poll_author_fingerprint: poll_post.author_fingerprint.clone(),
+            voter_fingerprint: voter.fingerprint.clone(),
+            voter_public_key: voter.public_key.clone(),
+            choices,
+            created_at,
+        })
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize poll vote: {}", e))
+    }
+
+    fn supersedes(&self, other: &PollVote) -> bool {
+        (self.created_at, &self.id) > (other.created_at, &other.id)
+    }
+}
+
+impl SignedPollVote {
+    pub fn create(vote: PollVote, keypair: &KeyPair) -> Result<Self, String> {
+        if vote.voter_fingerprint != keypair.fingerprint || vote.voter_public_key != keypair.public_key {
+            return Err("votes must be signed by the voting identity".to_string());
+        }
+        let vote_json = vote.to_canonical_json()?;
+        let signature = keypair.sign(&vote_json)?;
+        Ok(SignedPollVote { vote, signature })
+    }
+
+    pub fn verify(&self) -> Result<bool, String> {
+        if fingerprint_from_public_key(&self.vote.voter_public_key)? != self.vote.voter_fingerprint {
+            return Ok(false);
+        }
+        let vote_json = self.vote.to_canonical_json()?;
+        verify_signature(&vote_json, &self.signature, &self.vote.voter_public_key)
+    }
+}
+
+/// Per-option counts for one poll.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PollResults {
+    pub counts: Vec<usize>,
+    pub voters: usize,
+}
+
+/// Verified votes across polls, one per voter per poll.
+///
+/// A voter may change their mind while the poll is open; the newest vote
+/// replaces the older one, and a replayed older vote is ignored. Since the
+/// voter picks `created_at`, a vote only counts if it also reached us before
+/// the poll closed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PollVotes {
+    #[serde(default)]
+    pub votes: Vec<SignedPollVote>,
+    /// When each stored vote reached us, by `slot_key`. Votes stored before
+    /// this was kept count as received when cast.
+    #[serde(default)]
+    received: BTreeMap<String, DateTime<Utc>>,
+}
+
+/// One voter's place in one poll.
+fn slot_key(vote: &PollVote) -> String {
+    format!("{}/{}/{}", vote.poll_author_fingerprint, vote.poll_id, vote.voter_fingerprint)
+}
+
+impl PollVotes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a vote on `poll_post` that reached us at `received_at`.
+    /// Returns whether it changed the stored votes; errors if the vote is
+    /// forged, late, dated ahead or does not fit the poll.
+    pub fn add(&mut self, signed: SignedPollVote, poll_post: &Post, received_at: DateTime<Utc>) -> Result<bool, String> {
+        let poll = poll_post.poll.as_ref().ok_or("Post is not a poll")?;
+        let vote = &signed.vote;
+        if vote.poll_id != poll_post.id || vote.poll_author_fingerprint != poll_post.author_fingerprint {
+            return Err("Vote is for a different poll".to_string());
+        }
+        if !signed.verify()? {
+            return Err("Vote signature is invalid".to_string());
+        }
+        poll.validate_choices(&vote.choices)?;
+        if !poll.is_open_at(vote.created_at) {
+            return Err("Vote was cast after the poll closed".to_string());
+        }
+        if !poll.is_open_at(received_at) {
+            return Err("Vote arrived after the poll closed".to_string());
+        }
+        if vote.created_at > received_at + Duration::seconds(VOTE_CLOCK_SLACK_SECS) {
+            return Err("Vote is dated in the future".to_string());
+        }
+        let key = slot_key(vote);
+        match self.votes.iter_mut().find(|v| slot_key(&v.vote) == key) {
+            Some(existing) if !vote.supersedes(&existing.vote) => return Ok(false),
+            Some(existing) => *existing = signed,
+            None => self.votes.push(signed),
+        }
+        self.received.insert(key, received_at);
+        Ok(true)
+    }
+
+    /// Votes in the poll `poll_id` by `poll_author_fingerprint`.
+    pub fn for_poll<'a>(
+        &'a self,
+        poll_author_fingerprint: &'a str,
+        poll_id: &'a str,
+    ) -> impl Iterator<Item = &'a SignedPollVote> + 'a {
+        self.votes
+            .iter()
+            .filter(move |v| v.vote.poll_author_fingerprint == poll_author_fingerprint && v.vote.poll_id == poll_id)
+    }
+
+    pub fn vote_of(&self, poll_author_fingerprint: &str, poll_id: &str, voter_fingerprint: &str) -> Option<&SignedPollVote> {
+        self.votes.iter().find(|v| {
+            v.vote.poll_author_fingerprint == poll_author_fingerprint
+                && v.vote.poll_id == poll_id
+                && v.vote.voter_fingerprint == voter_fingerprint
+        })
+    }
+
+    /// When the stored `vote` reached us.
+    pub fn received_at(&self, vote: &PollVote) -> DateTime<Utc> {
+        self.received.get(&slot_key(vote)).copied().unwrap_or(vote.created_at)
+    }
+
+    /// Votes cast by `voter_fingerprint`, e.g. to publish one's own ballots.
+    pub fn cast_by(&self, voter_fingerprint: &str) -> Vec<SignedPollVote> {
+        self.votes
+            .iter()
+            .filter(|v| v.vote.voter_fingerprint == voter_fingerprint)
+            .cloned()
+            .collect()
+    }
+
+    pub fn results(&self, poll_post: &Post) -> PollResults {
+        let options = poll_post.poll.as_ref().map_or(0, |p| p.options.len());
+        let mut counts = vec![0; options];
+        let mut voters = 0;
+        for signed in self.for_poll(&poll_post.author_fingerprint, &poll_post.id) {
+            voters += 1;
+            for choice in &signed.vote.choices {
+                if let Some(count) = counts.get_mut(*choice) {
+                    *count += 1;
+                }
+            }
+        }
+        PollResults { counts, voters }
+    }
+
+    /// The author's tally of the votes held for `poll_post`.
+    pub fn tally(&self, poll_post: &Post) -> PollTally {
+        let mut ballots: Vec<TalliedBallot> = self
+            .for_poll(&poll_post.author_fingerprint, &poll_post.id)
+            .map(|v| TalliedBallot {
+                voter_fingerprint: v.vote.voter_fingerprint.clone(),
+                vote_id: v.vote.id.clone(),
+            })
+            .collect();
+        ballots.sort_by(|a, b| a.voter_fingerprint.cmp(&b.voter_fingerprint));
+        let created_at = Utc::now();
+        PollTally {
+            poll_id: poll_post.id.clone(),
+            author_fingerprint: poll_post.author_fingerprint.clone(),
+            counts: self.results(poll_post).counts,
+            ballots,
+            is_final: poll_post.poll.as_ref().is_some_and(|p| !p.is_open_at(created_at)),
+            created_at,
+        }
+    }
+}
+
+/// A ballot counted in a tally.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TalliedBallot {
+    pub voter_fingerprint: String,
+    pub vote_id: String,
+}
+
+/// Results published by the poll author, listing every ballot counted so
+/// readers can check them against the votes they have seen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollTally {
+    pub poll_id: String,
+    pub author_fingerprint: String,
+    pub counts: Vec<usize>,
+    pub ballots: Vec<TalliedBallot>,
+    /// True once the poll had closed when the tally was made.
+    pub is_final: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPollTally {
+    pub tally: PollTally,
+    pub signature: String,
+}
+
+/// How a published tally compares with the votes a reader has seen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum TallyCheck {
+    /// Every counted ballot was seen and the counts match them.
+    Verified,
+    /// Consistent with the votes seen, but `unseen` counted ballots could
+    /// not be checked.
+    Partial { unseen: usize },
+    /// Voters whose votes, which reached us before the tally was made, it
+    /// leaves out.
+    MissingVotes { voters: Vec<String> },
+    /// The counts cannot be produced by the listed ballots.
+    CountMismatch,
+}
+
+impl TallyCheck {
+    pub fn label(&self) -> String {
+        match self {
+            TallyCheck::Verified => "verified against every ballot".to_string(),
+            TallyCheck::Partial { unseen } => format!("consistent; {unseen} ballot(s) not seen here"),
+            TallyCheck::MissingVotes { voters } => format!("leaves out {} vote(s) seen here", voters.len()),
+            TallyCheck::CountMismatch => "counts do not match the ballots".to_string(),
+        }
+    }
+}
+
+impl PollTally {
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize poll tally: {}", e))
+    }
+
+    /// Compare the tally with the verified votes in `seen`. A vote only
+    /// counts against the tally if it reached us before the tally was made;
+    /// its own date is chosen by the voter.
+    pub fn check(&self, poll_post: &Post, seen: &PollVotes) -> Result<TallyCheck, String> {
+        let poll = poll_post.poll.as_ref().ok_or("Post is not a poll")?;
+        if self.poll_id != poll_post.id || self.author_fingerprint != poll_post.author_fingerprint {
+            return Err("Tally is for a different poll".to_string());
+        }
+        let mut listed: HashMap<&str, &str> = HashMap::new();
+        for ballot in &self.ballots {
+            if listed
+                .insert(&ballot.voter_fingerprint, &ballot.vote_id)
+                .is_some()
+            {
+                return Ok(TallyCheck::CountMismatch);
+            }
+        }
+        if self.counts.len() != poll.options.len() {
+            return Ok(TallyCheck::CountMismatch);
+        }
+
+        let mut missing = Vec::new();
+        let mut seen_counts = vec![0usize; poll.options.len()];
+        let mut checked = 0;
+        for signed in seen.for_poll(&poll_post.author_fingerprint, &poll_post.id) {
+            let vote = &signed.vote;
+            match listed.get(vote.voter_fingerprint.as_str()) {
+                Some(vote_id) if *vote_id == vote.id => {
+                    checked += 1;
+                    for choice in &vote.choices {
+                        seen_counts[*choice] += 1;
+                    }
+                }
+                // A different vote by the same voter that we have not seen.
+                Some(_) => {}
+                None if seen.received_at(vote) <= self.created_at => {
+                    missing.push(vote.voter_fingerprint.clone());
+                }
+                None => {}
+            }
+        }
+        if !missing.is_empty() {
+            missing.sort();
+            return Ok(TallyCheck::MissingVotes { voters: missing });
+        }
+
+        let unseen = self.ballots.len() - checked;
+        if unseen == 0 {
+            return Ok(if self.counts == seen_counts {
+                TallyCheck::Verified
+            } else {
+                TallyCheck::CountMismatch
+            });
+        }
+        // Each unseen ballot holds at least one choice, and more than one
+        // only in a multiple-choice poll.
+        let max_per_ballot = if poll.multiple_choice { poll.options.len() } else { 1 };
+        let claimed: usize = self.counts.iter().sum();
+        let accounted: usize = seen_counts.iter().sum();
+        let consistent = self.counts.iter().zip(&seen_counts).all(|(c, s)| c >= s)
+            && claimed >= accounted + unseen
+            && claimed <= accounted + unseen * max_per_ballot;
+        Ok(if consistent {
+            TallyCheck::Partial { unseen }
+        } else {
+            TallyCheck::CountMismatch
+        })
+    }
+}
+
+impl SignedPollTally {
+    pub fn create(tally: PollTally, keypair: &KeyPair) -> Result<Self, String> {
+        if tally.author_fingerprint != keypair.fingerprint {
+            return Err("tallies must be signed by the poll author".to_string());
+        }
+        let tally_json = tally.to_canonical_json()?;
+        let signature = keypair.sign(&tally_json)?;
+        Ok(SignedPollTally { tally, signature })
+    }
+
+    /// `public_key` must belong to the poll author.
+    pub fn verify(&self, public_key: &str) -> Result<bool, String> {
+        if fingerprint_from_public_key(public_key)? != self.tally.author_fingerprint {
+            return Ok(false);
+        }
+        let tally_json = self.tally.to_canonical_json()?;
+        verify_signature(&tally_json, &self.signature, public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::post::SignedPost;
+    use chrono::Duration;
+
+    fn poll_post(author: &KeyPair, multiple_choice: bool) -> Post {
+        let mut post = Post::new(author.fingerprint.clone(), "Tabs or spaces?".into(), None, None);
+        let poll = Poll::new(
+            vec!["Tabs".into(), "Spaces".into(), "Both".into()],
+            Utc::now() + Duration::hours(1),
+            multiple_choice,
+        )
+        .unwrap();
+        post.set_poll(poll).unwrap();
+        SignedPost::create(post, author).unwrap().post
+    }
+
+    #[test]
+    fn votes_are_validated_and_deduplicated() {
+        let author = KeyPair::generate().unwrap();
+        let voter = KeyPair::generate().unwrap();
+        let post = poll_post(&author, false);
+        assert!(Poll::new(vec!["Yes".into(), " yes ".into()], Utc::now(), false).is_err());
+        assert!(PollVote::new(&voter, &post, &[0, 1]).is_err());
+        assert!(PollVote::new(&voter, &post, &[3]).is_err());
+
+        let mut first = PollVote::new(&voter, &post, &[0]).unwrap();
+        first.created_at -= Duration::minutes(5);
+        let first = SignedPollVote::create(first, &voter).unwrap();
+        let second = SignedPollVote::create(PollVote::new(&voter, &post, &[1]).unwrap(), &voter).unwrap();
+
+        let mut votes = PollVotes::new();
+        assert!(votes.add(first.clone(), &post, Utc::now()).unwrap());
+        assert!(votes.add(second, &post, Utc::now()).unwrap());
+        // Replaying the older vote does not bring it back.
+        assert!(!votes.add(first, &post, Utc::now()).unwrap());
+        assert_eq!(votes.results(&post), PollResults { counts: vec![0, 1, 0], voters: 1 });
+
+        let mut forged = PollVote::new(&voter, &post, &[2]).unwrap();
+        forged.voter_fingerprint = author.fingerprint.clone();
+        let forged = SignedPollVote { vote: forged, signature: String::new() };
+        assert!(votes.add(forged, &post, Utc::now()).is_err());
+
+        let mut late = PollVote::new(&author, &post, &[2]).unwrap();
+        late.created_at = post.poll.as_ref().unwrap().closes_at;
+        assert!(votes.add(SignedPollVote::create(late, &author).unwrap(), &post, Utc::now()).is_err());
+    }
+
+    #[test]
+    fn tally_checks_against_seen_votes() {
+        let author = KeyPair::generate().unwrap();
+        let voters: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate().unwrap()).collect();
+        let post = poll_post(&author, true);
+
+        let mut all = PollVotes::new();
+        for (i, kp) in voters.iter().enumerate() {
+            let vote = SignedPollVote::create(PollVote::new(kp, &post, &[i % 3, 2]).unwrap(), kp).unwrap();
+            all.add(vote, &post, Utc::now()).unwrap();
+        }
+        let tally = SignedPollTally::create(all.tally(&post), &author).unwrap();
+        assert!(tally.verify(&author.public_key).unwrap());
+        assert!(!tally.verify(&voters[0].public_key).unwrap());
+        assert_eq!(tally.tally.counts, vec![1, 1, 3]);
+        assert_eq!(tally.tally.check(&post, &all).unwrap(), TallyCheck::Verified);
+
+        let mut some = PollVotes::new();
+        some.votes.push(all.votes[0].clone());
+        assert_eq!(tally.tally.check(&post, &some).unwrap(), TallyCheck::Partial { unseen: 2 });
+
+        let mut inflated = tally.tally.clone();
+        inflated.counts[0] += 1;
+        assert_eq!(inflated.check(&post, &all).unwrap(), TallyCheck::CountMismatch);
+
+        let mut dropped = tally.tally.clone();
+        let removed = dropped.ballots.remove(0);
+        dropped.counts = vec![0, 0, 0];
+        assert_eq!(
+            dropped.check(&post, &all).unwrap(),
+            TallyCheck::MissingVotes { voters: vec![removed.voter_fingerprint] }
+        );
+    }
+
+    #[test]
+    fn backdated_votes_after_the_close_do_not_count() {
+        let author = KeyPair::generate().unwrap();
+        let voter = KeyPair::generate().unwrap();
+        let post = poll_post(&author, false);
+        let closes_at = post.poll.as_ref().unwrap().closes_at;
+        let vote = SignedPollVote::create(PollVote::new(&voter, &post, &[0]).unwrap(), &voter).unwrap();
+
+        let mut votes = PollVotes::new();
+        assert!(votes.add(vote.clone(), &post, closes_at + Duration::minutes(1)).is_err());
+        let mut ahead = vote.vote.clone();
+        ahead.created_at += Duration::minutes(30);
+        let ahead = SignedPollVote::create(ahead, &voter).unwrap();
+        assert!(votes.add(ahead, &post, Utc::now()).is_err());
+
+        // A tally made before the vote reached us does not leave it out,
+        // whatever date the voter put on it.
+        let tally = votes.tally(&post);
+        assert!(votes.add(vote, &post, Utc::now() + Duration::seconds(1)).unwrap());
+        assert_eq!(tally.check(&post, &votes).unwrap(), TallyCheck::Verified);
+    }
+
+    #[test]
+    fn polls_are_keyed_by_author_and_id() {
+        let alice = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let voter = KeyPair::generate().unwrap();
+        let post = poll_post(&alice, false);
+        let mut copy = post.clone();
+        copy.author_fingerprint = mallory.fingerprint.clone();
+
+        let mut votes = PollVotes::new();
+        let real = SignedPollVote::create(PollVote::new(&voter, &post, &[0]).unwrap(), &voter).unwrap();
+        let other = SignedPollVote::create(PollVote::new(&voter, &copy, &[1]).unwrap(), &voter).unwrap();
+        assert!(votes.add(real, &post, Utc::now()).unwrap());
+        assert!(votes.add(other, &copy, Utc::now()).unwrap());
+        assert_eq!(votes.results(&post), PollResults { counts: vec![1, 0, 0], voters: 1 });
+        assert_eq!(votes.vote_of(&mallory.fingerprint, &post.id, &voter.fingerprint).unwrap().vote.choices, vec![1]);
+    }
+}
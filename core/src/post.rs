@@ -1,5 +1,11 @@
+use crate::audience::{Recipient, SealedContent};
+use crate::content_warning::ContentWarning;
 use crate::crypto::{KeyPair, verify_signature};
-use chrono::{DateTime, Utc};
+use crate::markup::Document;
+use crate::petname::PetnameBook;
+use crate::poll::Poll;
+use crate::repost::EmbeddedPost;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 #[cfg(target_arch = "wasm32")]
@@ -14,6 +20,33 @@ pub struct Post {
     pub created_at: DateTime<Utc>,
     pub reply_to: Option<String>,
     pub attachment_hashes: Vec<String>,
+    /// Fingerprints mentioned in `content`, extracted at creation time.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+    /// The post being reposted (empty `content`) or quoted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedded: Option<Box<EmbeddedPost>>,
+    /// Content, tags and mentions encrypted for an audience. When set, the
+    /// plaintext fields are left empty; see `Post::unseal`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed: Option<SealedContent>,
+    /// Signed expiry for ephemeral posts; see `Expiring`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Shown instead of the content until the reader expands the post.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_warning: Option<ContentWarning>,
+    /// Makes the post a poll; `content` holds the question.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<Poll>,
+}
+
+/// The fields of a post that are hidden from readers outside its audience.
+#[derive(Serialize, Deserialize)]
+struct SealedPostBody {
+    content: String,
+    tags: Vec<String>,
+    mentions: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,15 +62,147 @@ impl Post {
         tags: Option<Vec<String>>,
         reply_to: Option<String>,
     ) -> Self {
+        // Hashtags in the content become tags alongside any given explicitly.
+        let document = Document::parse(&content);
+        let mut tags = tags.unwrap_or_default();
+        for tag in document.hashtags() {
+            if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                tags.push(tag);
+            }
+        }
         Self {
             id: Uuid::new_v4().to_string(),
             author_fingerprint,
             content,
-            tags: tags.unwrap_or_default(),
+            tags,
             created_at: Utc::now(),
             reply_to,
             attachment_hashes: Vec::new(),
+            mentions: document.mentioned_fingerprints(),
+            embedded: None,
+            sealed: None,
+            expires_at: None,
+            content_warning: None,
+            poll: None,
+        }
+    }
+
+    /// Re-extract mentions, resolving `@petname` handles with the author's
+    /// petname book. Must be called before signing.
+    pub fn resolve_mentions(&mut self, petnames: &PetnameBook) {
+        let mut document = self.document();
+        document.resolve_mentions(petnames);
+        self.mentions = document.mentioned_fingerprints();
+    }
+
+    /// Parsed markup of `content`, for rendering.
+    pub fn document(&self) -> Document {
+        Document::parse(&self.content)
+    }
+
+    /// A plain repost. Reposting a repost shares the underlying original.
+    pub fn repost(author_fingerprint: String, original: EmbeddedPost) -> Self {
+        let original = original.innermost();
+        let mut post = Self::new(author_fingerprint, String::new(), None, None);
+        post.embedded = Some(Box::new(original));
+        post
+    }
+
+    /// A quote post: new commentary with the original embedded below it.
+    pub fn quote(
+        author_fingerprint: String,
+        content: String,
+        tags: Option<Vec<String>>,
+        original: EmbeddedPost,
+    ) -> Self {
+        let mut post = Self::new(author_fingerprint, content, tags, None);
+        post.embedded = Some(Box::new(original));
+        post
+    }
+
+    /// True for a repost without commentary of its own.
+    pub fn is_repost(&self) -> bool {
+        self.embedded.is_some() && self.sealed.is_none() && self.content.trim().is_empty()
+    }
+
+    pub fn is_quote(&self) -> bool {
+        self.embedded.is_some() && (self.sealed.is_some() || !self.content.trim().is_empty())
+    }
+
+    /// Restrict the post to `recipients` by encrypting its content, tags and
+    /// mentions. Must be the last change before signing. The author should
+    /// be among the recipients to be able to read the post back.
+    ///
+    /// Reply links and embedded originals stay in the clear. Attachments are
+    /// not supported, since blobs are served to anyone who knows the hash.
+    pub fn seal_for(&mut self, recipients: &[Recipient]) -> Result<(), String> {
+        if self.sealed.is_some() {
+            return Err("post is already restricted".to_string());
+        }
+        if !self.attachment_hashes.is_empty() {
+            return Err("attachments cannot be restricted to an audience".to_string());
+        }
+        if self.poll.is_some() {
+            return Err("polls cannot be restricted to an audience".to_string());
         }
+        let body = SealedPostBody {
+            content: std::mem::take(&mut self.content),
+            tags: std::mem::take(&mut self.tags),
+            mentions: std::mem::take(&mut self.mentions),
+        };
+        let body_json = serde_json::to_string(&body)
+            .map_err(|e| format!("Failed to serialize post body: {}", e))?;
+        self.sealed = Some(SealedContent::seal(&body_json, recipients)?);
+        Ok(())
+    }
+
+    /// Make the post disappear `ttl` after its creation. Must be called
+    /// before signing.
+    pub fn expire_after(&mut self, ttl: Duration) {
+        self.expires_at = Some(self.created_at + ttl);
+    }
+
+    /// Flag the post as sensitive. Must be called before signing.
+    pub fn set_content_warning(&mut self, warning: ContentWarning) {
+        self.content_warning = Some(warning);
+    }
+
+    /// Turn the post into a poll asking `content`. Must be called before
+    /// signing.
+    pub fn set_poll(&mut self, poll: Poll) -> Result<(), String> {
+        if self.content.trim().is_empty() {
+            return Err("A poll needs a question".to_string());
+        }
+        if self.sealed.is_some() {
+            return Err("polls cannot be restricted to an audience".to_string());
+        }
+        self.poll = Some(poll);
+        Ok(())
+    }
+
+    pub fn is_restricted(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    /// The post as `reader` sees it: public posts unchanged, restricted ones
+    /// with their plaintext fields filled in, or `None` when the reader is
+    /// not in the audience. Verify the signed post first; the result no
+    /// longer matches its signature.
+    pub fn unseal(&self, reader: &KeyPair) -> Result<Option<Post>, String> {
+        let Some(sealed) = &self.sealed else {
+            return Ok(Some(self.clone()));
+        };
+        let Some(body_json) = sealed.open(reader)? else {
+            return Ok(None);
+        };
+        let body: SealedPostBody = serde_json::from_str(&body_json)
+            .map_err(|e| format!("Invalid post body: {}", e))?;
+        Ok(Some(Post {
+            content: body.content,
+            tags: body.tags,
+            mentions: body.mentions,
+            ..self.clone()
+        }))
     }
     
     pub fn to_canonical_json(&self) -> Result<String, String> {
@@ -45,6 +210,7 @@ impl Post {
             .map_err(|e| format!("Failed to serialize post: {}", e))
     }
     
+    /// Attach content by manifest hash, as returned by `BlobStore::import`.
     pub fn add_attachment(&mut self, hash: String) {
         self.attachment_hashes.push(hash);
     }
@@ -61,9 +227,17 @@ impl SignedPost {
         })
     }
     
+    /// Verify the post and, for reposts and quotes, the embedded original
+    /// against its own author's key.
     pub fn verify(&self, public_key: &str) -> Result<bool, String> {
         let post_json = self.post.to_canonical_json()?;
-        verify_signature(&post_json, &self.signature, public_key)
+        if !verify_signature(&post_json, &self.signature, public_key)? {
+            return Ok(false);
+        }
+        match &self.post.embedded {
+            Some(embedded) => embedded.verify(),
+            None => Ok(true),
+        }
     }
 }
 
@@ -136,6 +310,45 @@ mod tests {
         assert!(p.reply_to.is_none());
     }
 
+    #[test]
+    fn hashtags_and_mentions_extracted_at_creation() {
+        let mut p = Post::new(
+            "fp".to_string(),
+            "Ping @bob about #Rust".to_string(),
+            Some(vec!["rust".to_string(), "p2p".to_string()]),
+            None,
+        );
+        assert_eq!(p.tags, vec!["rust", "p2p"]);
+        assert!(p.mentions.is_empty());
+
+        let mut book = PetnameBook::new();
+        book.set_petname("bob-fp", Some("bob".to_string())).unwrap();
+        p.resolve_mentions(&book);
+        assert_eq!(p.mentions, vec!["bob-fp"]);
+    }
+
+    #[test]
+    fn restricted_post_readable_by_audience_only() {
+        let alice = make_keypair();
+        let bob = make_keypair();
+        let eve = make_keypair();
+        let mut p = Post::new(alice.fingerprint.clone(), "Party at mine #secret".to_string(), None, None);
+        p.seal_for(&[
+            Recipient::from_keypair(&alice).unwrap(),
+            Recipient::from_keypair(&bob).unwrap(),
+        ])
+        .unwrap();
+        assert!(p.content.is_empty() && p.tags.is_empty());
+
+        let sp = SignedPost::create(p, &alice).unwrap();
+        assert!(sp.verify(&alice.public_key).unwrap());
+        let seen = sp.post.unseal(&bob).unwrap().expect("bob is in the audience");
+        assert_eq!(seen.content, "Party at mine #secret");
+        assert_eq!(seen.tags, vec!["secret"]);
+        assert!(sp.post.unseal(&alice).unwrap().is_some());
+        assert!(sp.post.unseal(&eve).unwrap().is_none());
+    }
+
     #[test]
     fn signed_post_verifies() {
         let kp = make_keypair();
@@ -1,5 +1,5 @@
 use crate::crypto::{KeyPair, KeyInfo, verify_signature};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use uuid::Uuid;
@@ -24,6 +24,9 @@ pub struct Profile {
     pub updated_at: DateTime<Utc>,
     pub version: u32,
     pub magnet_uri: Option<String>,
+    /// Signed expiry; peers stop serving the profile after this instant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +54,7 @@ impl Profile {
             updated_at: now,
             version: 1,
             magnet_uri: None,
+            expires_at: None,
         }
     }
     
@@ -65,6 +69,28 @@ impl Profile {
         self.version += 1;
     }
     
+    /// Set the profile to expire `ttl` from now. Callers must re-sign afterwards.
+    pub fn renew_expiry(&mut self, ttl: Duration) {
+        let now = Utc::now();
+        self.expires_at = Some(now + ttl);
+        self.updated_at = now;
+        self.version += 1;
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|exp| exp <= now)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+
+    /// Whether the profile expires within `window` and should be renewed.
+    pub fn needs_renewal(&self, window: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|exp| exp <= Utc::now() + window)
+    }
+
     pub fn to_canonical_json(&self) -> Result<String, String> {
         serde_json::to_string(self)
             .map_err(|e| format!("Failed to serialize profile: {}", e))
@@ -275,4 +301,27 @@ mod tests {
         assert!(uri.starts_with("magnet:?xt=urn:btih:"));
         assert!(uri.contains("profile_dave"));
     }
+
+    #[test]
+    fn expiry_is_covered_by_signature() {
+        let kp = make_keypair();
+        let mut p = Profile::new("erin".to_string(), kp.get_public_info());
+        p.renew_expiry(Duration::days(30));
+        let mut sp = SignedProfile::create(p, &kp).expect("signing failed");
+        assert!(sp.verify().expect("verify failed"));
+        sp.profile.expires_at = Some(Utc::now() + Duration::days(3650));
+        assert!(!sp.verify().expect("verify failed"));
+    }
+
+    #[test]
+    fn expiry_helpers() {
+        let kp = make_keypair();
+        let mut p = Profile::new("fay".to_string(), kp.get_public_info());
+        assert!(!p.is_expired());
+        assert!(!p.needs_renewal(Duration::days(7)));
+        p.renew_expiry(Duration::days(3));
+        assert!(!p.is_expired());
+        assert!(p.needs_renewal(Duration::days(7)));
+        assert!(p.is_expired_at(Utc::now() + Duration::days(4)));
+    }
 }
\ No newline at end of file
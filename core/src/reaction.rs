@@ -0,0 +1,357 @@
+use crate::crypto::{KeyPair, fingerprint_from_public_key, verify_signature};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Emoji used for a plain "like".
+pub const LIKE_EMOJI: &str = "👍";
+
+/// Reactions dated further ahead than this are refused, so a record with a
+/// far-future `created_at` cannot pin the latest-wins state.
+pub const REACTION_MAX_FUTURE_SKEW_SECS: i64 = 5 * 60;
+
+/// Upper bound on a reaction's emoji, in characters. Generous enough for
+/// ZWJ sequences and skin-tone modifiers, small enough to prevent abuse.
+const MAX_EMOJI_CHARS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReactionTarget {
+    Post,
+    Message,
+}
+
+/// A reaction by one identity to a post or message.
+///
+/// Reactions are state, not events: for each (reactor, target, emoji) only the
+/// newest record counts. Un-reacting publishes a newer record with
+/// `removed: true`, so the retraction propagates like any other reaction and
+/// an older copy cannot resurrect it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub id: String,
+    pub reactor_fingerprint: String,
+    pub target_kind: ReactionTarget,
+    pub target_id: String,
+    pub emoji: String,
+    pub removed: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedReaction {
+    pub reaction: Reaction,
+    pub signature: String,
+}
+
+impl Reaction {
+    pub fn new(
+        reactor_fingerprint: String,
+        target_kind: ReactionTarget,
+        target_id: String,
+        emoji: String,
+    ) -> Result<Self, String> {
+        validate_emoji(&emoji)?;
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            reactor_fingerprint,
+            target_kind,
+            target_id,
+            emoji,
+            removed: false,
+            created_at: Utc::now(),
+        })
+    }
+
+    pub fn like(reactor_fingerprint: String, target_kind: ReactionTarget, target_id: String) -> Self {
+        Self::new(reactor_fingerprint, target_kind, target_id, LIKE_EMOJI.to_string())
+            .expect("like emoji is valid")
+    }
+
+    /// A newer record retracting this reaction.
+    pub fn retraction(&self) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            removed: true,
+            created_at: Utc::now().max(self.created_at),
+            ..self.clone()
+        }
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize reaction: {}", e))
+    }
+
+    fn key(&self) -> (String, ReactionTarget, String, String) {
+        (
+            self.reactor_fingerprint.clone(),
+            self.target_kind,
+            self.target_id.clone(),
+            self.emoji.clone(),
+        )
+    }
+
+    fn supersedes(&self, other: &Reaction) -> bool {
+        (self.created_at, &self.id) > (other.created_at, &other.id)
+    }
+
+    fn is_from_future(&self, now: DateTime<Utc>) -> bool {
+        self.created_at > now + Duration::seconds(REACTION_MAX_FUTURE_SKEW_SECS)
+    }
+}
+
+fn validate_emoji(emoji: &str) -> Result<(), String> {
+    if emoji.is_empty() {
+        return Err("Reaction cannot be empty".to_string());
+    }
+    if emoji.chars().count() > MAX_EMOJI_CHARS {
+        return Err(format!("Reaction must be at most {} characters", MAX_EMOJI_CHARS));
+    }
+    if emoji.chars().any(|c| c.is_whitespace() || c.is_control() || c.is_alphanumeric()) {
+        return Err("Reaction must be an emoji".to_string());
+    }
+    Ok(())
+}
+
+impl SignedReaction {
+    pub fn create(reaction: Reaction, keypair: &KeyPair) -> Result<Self, String> {
+        if reaction.reactor_fingerprint != keypair.fingerprint {
+            return Err("reactions must be signed by the reacting identity".to_string());
+        }
+        let reaction_json = reaction.to_canonical_json()?;
+        let signature = keypair.sign(&reaction_json)?;
+
+        Ok(SignedReaction {
+            reaction,
+            signature,
+        })
+    }
+
+    /// `public_key` must belong to the reactor; otherwise anyone could publish
+    /// reactions in somebody else's name.
+    pub fn verify(&self, public_key: &str) -> Result<bool, String> {
+        if fingerprint_from_public_key(public_key)? != self.reaction.reactor_fingerprint {
+            return Ok(false);
+        }
+        let reaction_json = self.reaction.to_canonical_json()?;
+        verify_signature(&reaction_json, &self.signature, public_key)
+    }
+}
+
+/// Aggregate count for one emoji on one target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: usize,
+    pub reactors: Vec<String>,
+}
+
+/// The newest reaction record per (reactor, target, emoji).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReactionSet {
+    #[serde(default)]
+    pub reactions: Vec<SignedReaction>,
+}
+
+impl ReactionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a reaction, replacing an older record with the same key and
+    /// ignoring one that is not newer than what is already held or is dated
+    /// in the future. Callers verify the signature first; see
+    /// `merge_verified`.
+    pub fn add(&mut self, signed: SignedReaction) {
+        if signed.reaction.is_from_future(Utc::now()) {
+            return;
+        }
+        let key = signed.reaction.key();
+        match self.reactions.iter_mut().find(|r| r.reaction.key() == key) {
+            Some(existing) => {
+                if signed.reaction.supersedes(&existing.reaction) {
+                    *existing = signed;
+                }
+            }
+            None => self.reactions.push(signed),
+        }
+    }
+
+    /// Latest-wins union of records that are already verified.
+    pub fn merge(&mut self, other: ReactionSet) {
+        for reaction in other.reactions {
+            self.add(reaction);
+        }
+    }
+
+    /// Union `other` into this set taking only records signed by
+    /// `public_key`, checked before they can replace anything.
+    pub fn merge_verified(&mut self, other: ReactionSet, public_key: &str) {
+        self.retain_signed_by(public_key);
+        for reaction in other.reactions {
+            if reaction.verify(public_key).unwrap_or(false) {
+                self.add(reaction);
+            }
+        }
+    }
+
+    /// Add records for keys not held yet without replacing any, for copies
+    /// that cannot be verified yet.
+    pub fn fill_from(&mut self, other: ReactionSet) {
+        let now = Utc::now();
+        for reaction in other.reactions {
+            let key = reaction.reaction.key();
+            if !reaction.reaction.is_from_future(now) && !self.reactions.iter().any(|r| r.reaction.key() == key) {
+                self.reactions.push(reaction);
+            }
+        }
+    }
+
+    /// Drop records not signed by `public_key`, e.g. when the set is one
+    /// author's published reactions, and records dated in the future.
+    pub fn retain_signed_by(&mut self, public_key: &str) {
+        let now = Utc::now();
+        self.reactions
+            .retain(|r| !r.reaction.is_from_future(now) && r.verify(public_key).unwrap_or(false));
+    }
+
+    /// Current (non-removed) record for this key, if any.
+    pub fn active(
+        &self,
+        reactor_fingerprint: &str,
+        target_kind: ReactionTarget,
+        target_id: &str,
+        emoji: &str,
+    ) -> Option<&SignedReaction> {
+        self.reactions.iter().find(|r| {
+            !r.reaction.removed
+                && r.reaction.reactor_fingerprint == reactor_fingerprint
+                && r.reaction.target_kind == target_kind
+                && r.reaction.target_id == target_id
+                && r.reaction.emoji == emoji
+        })
+    }
+
+    pub fn has_reacted(&self, reactor_fingerprint: &str, target_kind: ReactionTarget, target_id: &str, emoji: &str) -> bool {
+        self.active(reactor_fingerprint, target_kind, target_id, emoji).is_some()
+    }
+
+    /// Per-emoji counts for one target, most popular first.
+    pub fn summary(&self, target_kind: ReactionTarget, target_id: &str) -> Vec<ReactionCount> {
+        let mut by_emoji: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for r in &self.reactions {
+            if r.reaction.removed || r.reaction.target_kind != target_kind || r.reaction.target_id != target_id {
+                continue;
+            }
+            by_emoji
+                .entry(r.reaction.emoji.as_str())
+                .or_default()
+                .push(r.reaction.reactor_fingerprint.clone());
+        }
+        let mut counts: Vec<ReactionCount> = by_emoji
+            .into_iter()
+            .map(|(emoji, mut reactors)| {
+                reactors.sort();
+                ReactionCount {
+                    emoji: emoji.to_string(),
+                    count: reactors.len(),
+                    reactors,
+                }
+            })
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn reaction_sign_and_verify() {
+        let kp = KeyPair::generate().unwrap();
+        let other = KeyPair::generate().unwrap();
+        let r = Reaction::like(kp.fingerprint.clone(), ReactionTarget::Post, "p1".into());
+        let signed = SignedReaction::create(r.clone(), &kp).unwrap();
+        assert!(signed.verify(&kp.public_key).unwrap());
+        assert!(!signed.verify(&other.public_key).unwrap());
+        assert!(SignedReaction::create(r, &other).is_err());
+    }
+
+    #[test]
+    fn duplicates_collapse_and_unreact_wins() {
+        let kp = KeyPair::generate().unwrap();
+        let mut like = Reaction::like(kp.fingerprint.clone(), ReactionTarget::Post, "p1".into());
+        like.created_at = Utc::now() - Duration::minutes(1);
+        let like = SignedReaction::create(like, &kp).unwrap();
+        let again = SignedReaction::create(
+            Reaction::like(kp.fingerprint.clone(), ReactionTarget::Post, "p1".into()),
+            &kp,
+        )
+        .unwrap();
+
+        let mut set = ReactionSet::new();
+        set.add(like.clone());
+        set.add(again.clone());
+        assert_eq!(set.reactions.len(), 1);
+        assert_eq!(set.summary(ReactionTarget::Post, "p1")[0].count, 1);
+
+        let undo = SignedReaction::create(again.reaction.retraction(), &kp).unwrap();
+        set.add(undo);
+        // A replayed older like must not resurrect the reaction.
+        set.add(like);
+        assert!(!set.has_reacted(&kp.fingerprint, ReactionTarget::Post, "p1", LIKE_EMOJI));
+        assert!(set.summary(ReactionTarget::Post, "p1").is_empty());
+    }
+
+    #[test]
+    fn summary_counts_per_emoji() {
+        let a = KeyPair::generate().unwrap();
+        let b = KeyPair::generate().unwrap();
+        let mut set = ReactionSet::new();
+        for kp in [&a, &b] {
+            let r = Reaction::like(kp.fingerprint.clone(), ReactionTarget::Post, "p".into());
+            set.add(SignedReaction::create(r, kp).unwrap());
+        }
+        let fire = Reaction::new(a.fingerprint.clone(), ReactionTarget::Post, "p".into(), "🔥".into()).unwrap();
+        set.add(SignedReaction::create(fire, &a).unwrap());
+
+        let summary = set.summary(ReactionTarget::Post, "p");
+        assert_eq!(summary[0].emoji, LIKE_EMOJI);
+        assert_eq!(summary[0].count, 2);
+        assert_eq!(summary[1].emoji, "🔥");
+        assert!(Reaction::new(a.fingerprint.clone(), ReactionTarget::Post, "p".into(), "lol".into()).is_err());
+    }
+
+    #[test]
+    fn forged_or_future_reactions_do_not_replace_real_ones() {
+        let author = KeyPair::generate().unwrap();
+        let attacker = KeyPair::generate().unwrap();
+        let like = SignedReaction::create(
+            Reaction::like(author.fingerprint.clone(), ReactionTarget::Post, "p".into()),
+            &author,
+        )
+        .unwrap();
+        let mut future = like.reaction.retraction();
+        future.created_at = Utc::now() + Duration::days(365);
+        let forged = SignedReaction { signature: attacker.sign(&future.to_canonical_json().unwrap()).unwrap(), reaction: future.clone() };
+        let signed_future = SignedReaction::create(future, &author).unwrap();
+
+        let mut stored = ReactionSet::new();
+        stored.add(like);
+        let mut incoming = ReactionSet::new();
+        incoming.reactions = vec![forged, signed_future];
+        stored.merge_verified(incoming.clone(), &author.public_key);
+        assert!(stored.has_reacted(&author.fingerprint, ReactionTarget::Post, "p", LIKE_EMOJI));
+        stored.fill_from(incoming);
+        assert_eq!(stored.reactions.len(), 1);
+
+        // The same id on a message is a different target.
+        assert!(!stored.has_reacted(&author.fingerprint, ReactionTarget::Message, "p", LIKE_EMOJI));
+        assert!(stored.summary(ReactionTarget::Message, "p").is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::crypto::KeyPair;
+use crate::message::{Message, MessageType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Most message ids acknowledged by a single receipt.
+pub const MAX_RECEIPT_IDS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+/// Where one of our own direct messages has got to, as far as the
+/// recipient has told us.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    #[default]
+    Sent,
+    Delivered,
+    Read,
+}
+
+impl DeliveryStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Read => "read",
+        }
+    }
+
+    /// Move forward to what `kind` reports; a status never goes back.
+    pub fn advance(&mut self, kind: ReceiptKind) -> bool {
+        let next = match kind {
+            ReceiptKind::Delivered => DeliveryStatus::Delivered,
+            ReceiptKind::Read => DeliveryStatus::Read,
+        };
+        if next > *self {
+            *self = next;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReceiptBody {
+    message_ids: Vec<String>,
+}
+
+impl Message {
+    /// A receipt for messages received from `recipient_fingerprint`. Pass the
+    /// original sender's encryption key to hide which messages are
+    /// acknowledged from relays.
+    pub fn new_receipt(
+        keypair: &KeyPair,
+        recipient_fingerprint: &str,
+        kind: ReceiptKind,
+        message_ids: &[String],
+        recipient_encryption_public_key: Option<&str>,
+    ) -> Result<Self, String> {
+        if message_ids.is_empty() {
+            return Err("A receipt must acknowledge at least one message".to_string());
+        }
+        if message_ids.len() > MAX_RECEIPT_IDS {
+            return Err(format!("A receipt can acknowledge at most {} messages", MAX_RECEIPT_IDS));
+        }
+        let body = serde_json::to_string(&ReceiptBody { message_ids: message_ids.to_vec() })
+            .map_err(|e| format!("Failed to serialize receipt: {}", e))?;
+        let mut message = Message::new_direct(keypair.fingerprint.clone(), recipient_fingerprint.to_string(), body);
+        message.message_type = MessageType::Receipt { kind };
+        if let Some(key) = recipient_encryption_public_key {
+            message.seal_for_recipient(keypair, key)?;
+        }
+        Ok(message)
+    }
+
+    pub fn receipt_kind(&self) -> Option<ReceiptKind> {
+        match self.message_type {
+            MessageType::Receipt { kind } => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// The message ids a receipt acknowledges. Encrypted receipts need the
+    /// reader's keypair and the receipt sender's encryption key.
+    pub fn receipt_message_ids(
+        &self,
+        reader: Option<&KeyPair>,
+        sender_encryption_public_key: Option<&str>,
+    ) -> Result<Vec<String>, String> {
+        if self.receipt_kind().is_none() {
+            return Err("not a receipt".to_string());
+        }
+        let body = if self.encrypted {
+            let reader = reader.ok_or("missing local keypair")?;
+            let key = sender_encryption_public_key.ok_or("missing sender encryption key")?;
+            let nonce = self.nonce_b64.as_deref().ok_or("missing nonce")?;
+            reader.decrypt_from_peer(key, nonce, &self.content)?
+        } else {
+            self.content.clone()
+        };
+        let body: ReceiptBody =
+            serde_json::from_str(&body).map_err(|e| format!("Invalid receipt body: {}", e))?;
+        Ok(body.message_ids)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Outgoing {
+    recipient_fingerprint: String,
+    status: DeliveryStatus,
+}
+
+/// Delivery status of sent direct messages and the receipts already sent
+/// for received ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReceiptLog {
+    #[serde(default)]
+    outgoing: BTreeMap<String, Outgoing>,
+    #[serde(default)]
+    acknowledged: BTreeMap<String, ReceiptKind>,
+}
+
+impl ReceiptLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a direct message we sent.
+    pub fn track_sent(&mut self, message: &Message) {
+        self.outgoing.entry(message.id.clone()).or_insert_with(|| Outgoing {
+            recipient_fingerprint: message.recipient_fingerprint.clone(),
+            status: DeliveryStatus::Sent,
+        });
+    }
+
+    pub fn status(&self, message_id: &str) -> Option<DeliveryStatus> {
+        self.outgoing.get(message_id).map(|o| o.status)
+    }
+
+    /// Apply a verified receipt from `from_fingerprint`. Only messages that
+    /// were sent to that peer move; returns how many did.
+    pub fn apply(&mut self, from_fingerprint: &str, kind: ReceiptKind, message_ids: &[String]) -> usize {
+        let mut changed = 0;
+        for id in message_ids {
+            if let Some(outgoing) = self.outgoing.get_mut(id) {
+                if outgoing.recipient_fingerprint == from_fingerprint && outgoing.status.advance(kind) {
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Which of `message_ids` still need a `kind` receipt. A read receipt
+    /// also counts as delivered.
+    pub fn pending_acks(&self, kind: ReceiptKind, message_ids: &[String]) -> Vec<String> {
+        message_ids
+            .iter()
+            .filter(|id| self.acknowledged.get(id.as_str()).is_none_or(|sent| *sent < kind))
+            .cloned()
+            .collect()
+    }
+
+    pub fn mark_acknowledged(&mut self, kind: ReceiptKind, message_ids: &[String]) {
+        for id in message_ids {
+            let sent = self.acknowledged.entry(id.clone()).or_insert(kind);
+            *sent = (*sent).max(kind);
+        }
+    }
+
+    /// Forget messages that no longer exist, e.g. after they expired.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.outgoing.retain(|id, _| keep(id));
+        self.acknowledged.retain(|id, _| keep(id));
+    }
+}
+
+/// Whether the user tells senders when their messages were read.
+/// Delivery receipts are always sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptPreferences {
+    #[serde(default = "default_send_read")]
+    pub send_read_receipts: bool,
+}
+
+fn default_send_read() -> bool {
+    true
+}
+
+impl Default for ReceiptPreferences {
+    fn default() -> Self {
+        Self { send_read_receipts: default_send_read() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::SignedMessage;
+
+    #[test]
+    fn encrypted_receipts_advance_only_the_recipients_messages() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let sent = Message::new_direct(alice.fingerprint.clone(), bob.fingerprint.clone(), "hi".into());
+        let mut log = ReceiptLog::new();
+        log.track_sent(&sent);
+        assert_eq!(log.status(&sent.id), Some(DeliveryStatus::Sent));
+
+        let ids = vec![sent.id.clone()];
+        let receipt = Message::new_receipt(
+            &bob,
+            &alice.fingerprint,
+            ReceiptKind::Read,
+            &ids,
+            alice.enc_public_key.as_deref(),
+        )
+        .unwrap();
+        assert!(receipt.encrypted);
+        assert!(!receipt.content.contains(&sent.id));
+        let signed = SignedMessage::create(receipt, &bob).unwrap();
+        assert!(signed.verify(&bob.public_key).unwrap());
+        let opened = signed
+            .message
+            .receipt_message_ids(Some(&alice), bob.enc_public_key.as_deref())
+            .unwrap();
+        assert_eq!(opened, ids);
+
+        assert_eq!(log.apply(&alice.fingerprint, ReceiptKind::Read, &opened), 0);
+        assert_eq!(log.apply(&bob.fingerprint, ReceiptKind::Read, &opened), 1);
+        assert_eq!(log.apply(&bob.fingerprint, ReceiptKind::Delivered, &opened), 0);
+        assert_eq!(log.status(&sent.id), Some(DeliveryStatus::Read));
+
+        let mut acks = ReceiptLog::new();
+        assert_eq!(acks.pending_acks(ReceiptKind::Delivered, &ids), ids);
+        acks.mark_acknowledged(ReceiptKind::Read, &ids);
+        assert!(acks.pending_acks(ReceiptKind::Delivered, &ids).is_empty());
+        assert!(Message::new_receipt(&bob, &alice.fingerprint, ReceiptKind::Read, &[], None).is_err());
+    }
+}
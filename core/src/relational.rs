@@ -0,0 +1,901 @@
+use crate::attachment::{AttachmentManifest, BlobStore, ChunkSource};
+use crate::feed::Feed;
+use crate::keyring::{ContactKeys, KeyRing};
+use crate::message::SignedMessage;
+use crate::post::SignedPost;
+use crate::profile::SignedProfile;
+use crate::storage::StorageBackend;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Forward migrations in order. `PRAGMA user_version` records how many have
+/// run, so append new steps here and never edit old ones.
+const MIGRATIONS: &[&str] = &[
+    // Post ids are only unique per author and message ids per conversation.
+    "CREATE TABLE profiles (
+         fingerprint  TEXT PRIMARY KEY,
+         username     TEXT NOT NULL,
+         display_name TEXT,
+         version      INTEGER NOT NULL,
+         updated_at   INTEGER NOT NULL,
+         expires_at   INTEGER,
+         data         TEXT NOT NULL
+     );
+     CREATE INDEX profiles_username ON profiles (username);
+     CREATE TABLE posts (
+         id                 TEXT NOT NULL,
+         author_fingerprint TEXT NOT NULL,
+         created_at         INTEGER NOT NULL,
+         expires_at         INTEGER,
+         reply_to           TEXT,
+         data               TEXT NOT NULL,
+         PRIMARY KEY (author_fingerprint, id)
+     );
+     CREATE INDEX posts_author_created ON posts (author_fingerprint, created_at);
+     CREATE INDEX posts_created ON posts (created_at);
+     CREATE INDEX posts_reply_to ON posts (reply_to) WHERE reply_to IS NOT NULL;
+     CREATE TABLE messages (
+         id                 TEXT NOT NULL,
+         conversation       TEXT NOT NULL,
+         sender_fingerprint TEXT NOT NULL,
+         incoming           INTEGER NOT NULL,
+         created_at         INTEGER NOT NULL,
+         lamport            INTEGER NOT NULL DEFAULT 0,
+         expires_at         INTEGER,
+         data               TEXT NOT NULL,
+         PRIMARY KEY (conversation, id)
+     );
+     CREATE INDEX messages_conversation ON messages (conversation, lamport, created_at);
+     CREATE INDEX messages_expires ON messages (expires_at) WHERE expires_at IS NOT NULL;
+     CREATE TABLE contacts (
+         fingerprint           TEXT PRIMARY KEY,
+         name                  TEXT,
+         public_key            TEXT,
+         encryption_public_key TEXT,
+         updated_at            INTEGER NOT NULL,
+         data                  TEXT NOT NULL
+     );
+     CREATE TABLE blobs (
+         hash      TEXT NOT NULL,
+         kind      TEXT NOT NULL,
+         bytes     BLOB NOT NULL,
+         stored_at INTEGER NOT NULL,
+         PRIMARY KEY (hash, kind)
+     );
+     CREATE TABLE imports (
+         source      TEXT PRIMARY KEY,
+         imported_at INTEGER NOT NULL,
+         report      TEXT NOT NULL
+     );",
+];
+
+/// Schema version a freshly opened store is migrated to.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// One chat message row. `data` is the host's stored form of the message,
+/// e.g. a `SignedMessage` or a desktop chat item, as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRecord {
+    pub id: String,
+    /// The peer's fingerprint for direct chats, the group id for groups.
+    pub conversation: String,
+    pub sender_fingerprint: String,
+    pub incoming: bool,
+    pub created_at: DateTime<Utc>,
+    pub lamport: u64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub data: String,
+}
+
+impl MessageRecord {
+    pub fn from_signed(conversation: &str, incoming: bool, message: &SignedMessage) -> Result<Self, String> {
+        let m = &message.message;
+        Ok(Self {
+            id: m.id.clone(),
+            conversation: conversation.to_string(),
+            sender_fingerprint: m.sender_fingerprint.clone(),
+            incoming,
+            created_at: m.created_at,
+            lamport: m.lamport,
+            expires_at: m.expires_at,
+            data: to_json(message)?,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_str(&self.data).map_err(|e| format!("Failed to decode message {}: {}", self.id, e))
+    }
+}
+
+/// One contact row. Keys come from verified profiles; `data` holds whatever
+/// else the host keeps about the contact, as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactRecord {
+    pub fingerprint: String,
+    pub name: Option<String>,
+    pub public_key: Option<String>,
+    pub encryption_public_key: Option<String>,
+    pub updated_at: DateTime<Utc>,
+    pub data: String,
+}
+
+impl ContactRecord {
+    /// A row for keys learned from a verified profile, with no name.
+    pub fn from_keys(fingerprint: &str, keys: &ContactKeys) -> Result<Self, String> {
+        Ok(Self {
+            fingerprint: fingerprint.to_string(),
+            name: None,
+            public_key: Some(keys.public_key.clone()),
+            encryption_public_key: keys.encryption_public_key.clone(),
+            updated_at: keys.updated_at,
+            data: to_json(keys)?,
+        })
+    }
+}
+
+/// How many rows a legacy import wrote, per table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub profiles: usize,
+    pub posts: usize,
+    pub messages: usize,
+    pub contacts: usize,
+}
+
+/// Typed store for profiles, posts, messages, contacts and attachment
+/// blobs, one row per item. `CoreService` writes each row as the item
+/// arrives or changes once a store is attached; `import_legacy` brings over
+/// what was kept under whole-JSON keys before.
+pub struct RelationalStore {
+    conn: Mutex<Connection>,
+}
+
+impl RelationalStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open database: {}", e))?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        let conn = Connection::open_in_memory().map_err(|e| format!("Failed to open database: {}", e))?;
+        Self::init(conn)
+    }
+
+    /// Drop the store's tables from the database at `path` and vacuum it,
+    /// so no plaintext rows stay on disk, e.g. once the data they mirror is
+    /// encrypted. Other tables in the file are kept.
+    pub fn erase(path: impl AsRef<Path>) -> Result<(), String> {
+        if !path.as_ref().exists() {
+            return Ok(());
+        }
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open database: {}", e))?;
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS profiles;
+             DROP TABLE IF EXISTS posts;
+             DROP TABLE IF EXISTS messages;
+             DROP TABLE IF EXISTS contacts;
+             DROP TABLE IF EXISTS blobs;
+             DROP TABLE IF EXISTS imports;
+             PRAGMA user_version = 0;
+             VACUUM;",
+        )
+        .map_err(|e| format!("Failed to erase database: {}", e))
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, String> {
+        migrate(&mut conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>, String> {
+        self.conn.lock().map_err(|e| format!("Database lock poisoned: {}", e))
+    }
+
+    pub fn schema_version(&self) -> Result<u32, String> {
+        user_version(&*self.conn()?)
+    }
+
+    pub fn put_profile(&self, profile: &SignedProfile) -> Result<(), String> {
+        insert_profile(&*self.conn()?, profile)
+    }
+
+    pub fn profile(&self, fingerprint: &str) -> Result<Option<SignedProfile>, String> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row("SELECT data FROM profiles WHERE fingerprint = ?1", params![fingerprint], |r| r.get(0))
+            .optional()
+            .map_err(db_err)?;
+        data.map(|d| from_json(&d)).transpose()
+    }
+
+    pub fn put_post(&self, post: &SignedPost) -> Result<(), String> {
+        insert_post(&*self.conn()?, post)
+    }
+
+    pub fn post(&self, author: &str, id: &str) -> Result<Option<SignedPost>, String> {
+        let data: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT data FROM posts WHERE author_fingerprint = ?1 AND id = ?2",
+                params![author, id],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(db_err)?;
+        data.map(|d| from_json(&d)).transpose()
+    }
+
+    /// Posts by `author`, newest first.
+    pub fn posts_by(&self, author: &str, limit: usize) -> Result<Vec<SignedPost>, String> {
+        self.query_json(
+            "SELECT data FROM posts WHERE author_fingerprint = ?1 ORDER BY created_at DESC LIMIT ?2",
+            params![author, limit as i64],
+        )
+    }
+
+    /// Posts from everyone, newest first.
+    pub fn recent_posts(&self, limit: usize) -> Result<Vec<SignedPost>, String> {
+        self.query_json("SELECT data FROM posts ORDER BY created_at DESC LIMIT ?1", params![limit as i64])
+    }
+
+    /// Direct replies to `author`'s post `post_id`, oldest first. `reply_to`
+    /// names only an id, so as in `ThreadBuilder` a reply belongs to its own
+    /// author's post with that id, or else to the only post using it.
+    pub fn replies(&self, author: &str, post_id: &str) -> Result<Vec<SignedPost>, String> {
+        self.query_json(
+            "SELECT data FROM posts r
+             WHERE r.reply_to = ?2 AND NOT (r.author_fingerprint = ?1 AND r.id = ?2)
+               AND (r.author_fingerprint = ?1
+                    OR (NOT EXISTS (SELECT 1 FROM posts s WHERE s.author_fingerprint = r.author_fingerprint AND s.id = ?2)
+                        AND EXISTS (SELECT 1 FROM posts p WHERE p.author_fingerprint = ?1 AND p.id = ?2)
+                        AND NOT EXISTS (SELECT 1 FROM posts o WHERE o.author_fingerprint != ?1 AND o.id = ?2)))
+             ORDER BY r.created_at, r.rowid",
+            params![author, post_id],
+        )
+    }
+
+    pub fn remove_post(&self, author: &str, id: &str) -> Result<bool, String> {
+        let n = self
+            .conn()?
+            .execute("DELETE FROM posts WHERE author_fingerprint = ?1 AND id = ?2", params![author, id])
+            .map_err(db_err)?;
+        Ok(n > 0)
+    }
+
+    pub fn put_message(&self, record: &MessageRecord) -> Result<(), String> {
+        insert_message(&*self.conn()?, record)
+    }
+
+    pub fn message(&self, conversation: &str, id: &str) -> Result<Option<MessageRecord>, String> {
+        self.conn()?
+            .query_row(
+                &format!("{} WHERE conversation = ?1 AND id = ?2", MESSAGE_SELECT),
+                params![conversation, id],
+                message_row,
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    /// The latest `limit` messages of a conversation in causal order.
+    pub fn conversation(&self, conversation: &str, limit: usize) -> Result<Vec<MessageRecord>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT * FROM ({} WHERE conversation = ?1
+                 ORDER BY lamport DESC, created_at DESC, rowid DESC LIMIT ?2)
+                 ORDER BY lamport, created_at, rid",
+                MESSAGE_SELECT
+            ))
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map(params![conversation, limit as i64], message_row)
+            .map_err(db_err)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(db_err)
+    }
+
+    /// Conversation keys with the time of their latest message, most recent
+    /// first.
+    pub fn conversations(&self) -> Result<Vec<(String, DateTime<Utc>)>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT conversation, MAX(created_at) AS latest FROM messages GROUP BY conversation ORDER BY latest DESC")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get::<_, String>(0)?, from_millis(r.get(1)?))))
+            .map_err(db_err)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(db_err)
+    }
+
+    pub fn remove_message(&self, conversation: &str, id: &str) -> Result<bool, String> {
+        let n = self
+            .conn()?
+            .execute("DELETE FROM messages WHERE conversation = ?1 AND id = ?2", params![conversation, id])
+            .map_err(db_err)?;
+        Ok(n > 0)
+    }
+
+    pub fn put_contact(&self, contact: &ContactRecord) -> Result<(), String> {
+        insert_contact(&*self.conn()?, contact)
+    }
+
+    pub fn contact(&self, fingerprint: &str) -> Result<Option<ContactRecord>, String> {
+        self.conn()?
+            .query_row(&format!("{} WHERE fingerprint = ?1", CONTACT_SELECT), params![fingerprint], contact_row)
+            .optional()
+            .map_err(db_err)
+    }
+
+    pub fn contacts(&self) -> Result<Vec<ContactRecord>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!("{} ORDER BY COALESCE(name, fingerprint) COLLATE NOCASE", CONTACT_SELECT))
+            .map_err(db_err)?;
+        let rows = stmt.query_map([], contact_row).map_err(db_err)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(db_err)
+    }
+
+    pub fn remove_contact(&self, fingerprint: &str) -> Result<bool, String> {
+        let n = self
+            .conn()?
+            .execute("DELETE FROM contacts WHERE fingerprint = ?1", params![fingerprint])
+            .map_err(db_err)?;
+        Ok(n > 0)
+    }
+
+    /// Drop expired posts, messages and profiles. Returns how many rows
+    /// were removed.
+    pub fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, String> {
+        let now = now.timestamp_millis();
+        let conn = self.conn()?;
+        let mut removed = 0;
+        for table in ["posts", "messages", "profiles"] {
+            removed += conn
+                .execute(&format!("DELETE FROM {} WHERE expires_at IS NOT NULL AND expires_at <= ?1", table), params![now])
+                .map_err(db_err)?;
+        }
+        Ok(removed)
+    }
+
+    /// Copy everything the old whole-JSON-value keys hold into the tables,
+    /// once per `source`. Works with a `FileStorage` directory as well as a
+    /// `SqliteStorage` `kv_store`. The source is left untouched, and
+    /// `None` means it had already been imported.
+    pub fn import_legacy<S: StorageBackend>(&self, source: &str, storage: &S) -> Result<Option<ImportReport>, String> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().map_err(db_err)?;
+        let done: bool = tx
+            .query_row("SELECT EXISTS(SELECT 1 FROM imports WHERE source = ?1)", params![source], |r| r.get(0))
+            .map_err(db_err)?;
+        if done {
+            return Ok(None);
+        }
+        let now = Utc::now();
+        let mut report = ImportReport::default();
+
+        let mut own_fingerprint = String::new();
+        for key in ["profile", "snartnet_current_profile"] {
+            if let Some(profile) = legacy::<_, SignedProfile>(storage, key)? {
+                own_fingerprint = profile.profile.fingerprint.clone();
+                insert_profile(&tx, &profile)?;
+                report.profiles += 1;
+            }
+        }
+
+        let mut posts: Vec<SignedPost> = legacy(storage, "local_posts")?.unwrap_or_default();
+        for key in ["feed", "snartnet_feed"] {
+            if let Some(feed) = legacy::<_, Feed>(storage, key)? {
+                posts.extend(feed.entries().iter().map(|e| e.post.clone()));
+            }
+        }
+        for post in &posts {
+            insert_post(&tx, post)?;
+        }
+        report.posts = posts.len();
+
+        if let Some(keyring) = legacy::<_, KeyRing>(storage, "snartnet_keyring")? {
+            for (fingerprint, keys) in keyring.iter() {
+                insert_contact(&tx, &ContactRecord::from_keys(fingerprint, keys)?)?;
+                report.contacts += 1;
+            }
+        }
+        let contacts: Vec<serde_json::Value> = legacy(storage, "contacts")?.unwrap_or_default();
+        for value in contacts {
+            let contact: LegacyContact = serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid legacy contact: {}", e))?;
+            insert_contact(&tx, &ContactRecord {
+                fingerprint: contact.fingerprint,
+                name: Some(contact.alias).filter(|a| !a.trim().is_empty()),
+                public_key: contact.known_public_key,
+                encryption_public_key: contact.known_encryption_public_key,
+                updated_at: now,
+                data: value.to_string(),
+            })?;
+            report.contacts += 1;
+        }
+
+        let threads: Vec<LegacyThread> = legacy(storage, "threads")?.unwrap_or_default();
+        for thread in threads {
+            let items = thread
+                .messages
+                .iter()
+                .map(|value| {
+                    serde_json::from_value::<LegacyChatItem>(value.clone())
+                        .map_err(|e| format!("Invalid legacy chat item: {}", e))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            // Items saved before send times were kept are in insertion
+            // order, so they take the time of the closest earlier item, or
+            // of the first later one, rather than a made-up date.
+            let mut last_sent = items.iter().find_map(|i| i.sent_at).unwrap_or(now);
+            for (item, value) in items.into_iter().zip(thread.messages) {
+                let sent_at = item.sent_at.unwrap_or(last_sent);
+                last_sent = sent_at;
+                let sender = if item.incoming { &thread.contact_fingerprint } else { &own_fingerprint };
+                insert_message(&tx, &MessageRecord {
+                    id: item.id,
+                    conversation: thread.contact_fingerprint.clone(),
+                    sender_fingerprint: sender.clone(),
+                    incoming: item.incoming,
+                    created_at: sent_at,
+                    lamport: item.lamport,
+                    expires_at: item.expires_at,
+                    data: value.to_string(),
+                })?;
+                report.messages += 1;
+            }
+        }
+        let group_threads: Vec<LegacyGroupThread> = legacy(storage, "group_threads")?.unwrap_or_default();
+        for thread in group_threads {
+            for message in &thread.messages {
+                let incoming = message.message.sender_fingerprint != own_fingerprint;
+                insert_message(&tx, &MessageRecord::from_signed(&thread.group_id, incoming, message)?)?;
+                report.messages += 1;
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO imports (source, imported_at, report) VALUES (?1, ?2, ?3)",
+            params![source, now.timestamp_millis(), to_json(&report)?],
+        )
+        .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+        Ok(Some(report))
+    }
+
+    fn query_json<T: DeserializeOwned>(&self, sql: &str, args: impl rusqlite::Params) -> Result<Vec<T>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(sql).map_err(db_err)?;
+        let rows = stmt.query_map(args, |r| r.get::<_, String>(0)).map_err(db_err)?;
+        rows.map(|r| r.map_err(db_err).and_then(|d| from_json(&d))).collect()
+    }
+
+    fn put_blob(&self, hash: &str, kind: &str, bytes: &[u8]) -> Result<(), String> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO blobs (hash, kind, bytes, stored_at) VALUES (?1, ?2, ?3, ?4)",
+                params![hash, kind, bytes, Utc::now().timestamp_millis()],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    fn get_blob(&self, hash: &str, kind: &str) -> Option<Vec<u8>> {
+        self.conn()
+            .ok()?
+            .query_row("SELECT bytes FROM blobs WHERE hash = ?1 AND kind = ?2", params![hash, kind], |r| r.get(0))
+            .optional()
+            .ok()
+            .flatten()
+    }
+}
+
+impl BlobStore for RelationalStore {
+    fn put_chunk(&self, hash: &str, bytes: &[u8]) -> Result<(), String> {
+        self.put_blob(hash, "chunk", bytes)
+    }
+
+    fn get_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+        self.get_blob(hash, "chunk")
+    }
+
+    fn put_manifest(&self, hash: &str, manifest: &AttachmentManifest) -> Result<(), String> {
+        self.put_blob(hash, "manifest", manifest.to_canonical_json()?.as_bytes())
+    }
+
+    fn get_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+        serde_json::from_slice(&self.get_blob(hash, "manifest")?).ok()
+    }
+}
+
+impl ChunkSource for RelationalStore {
+    fn fetch_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+        self.get_manifest(hash)
+    }
+
+    fn fetch_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+        self.get_chunk(hash)
+    }
+}
+
+/// The fields the importer needs from a desktop contact; the rest is kept
+/// verbatim in `ContactRecord::data`.
+#[derive(Deserialize)]
+struct LegacyContact {
+    fingerprint: String,
+    #[serde(default)]
+    alias: String,
+    #[serde(default)]
+    known_public_key: Option<String>,
+    #[serde(default)]
+    known_encryption_public_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LegacyThread {
+    contact_fingerprint: String,
+    #[serde(default)]
+    messages: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct LegacyChatItem {
+    id: String,
+    #[serde(default)]
+    incoming: bool,
+    #[serde(default)]
+    sent_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    lamport: u64,
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct LegacyGroupThread {
+    group_id: String,
+    #[serde(default)]
+    messages: Vec<SignedMessage>,
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version = user_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Database schema version {} is newer than this build supports ({})",
+            version, SCHEMA_VERSION
+        ));
+    }
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute_batch(step)
+            .map_err(|e| format!("Migration to schema version {} failed: {}", i + 1, e))?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64).map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+    }
+    Ok(())
+}
+
+fn user_version(conn: &Connection) -> Result<u32, String> {
+    conn.query_row("PRAGMA user_version", [], |r| r.get(0)).map_err(db_err)
+}
+
+fn insert_profile(conn: &Connection, signed: &SignedProfile) -> Result<(), String> {
+    let p = &signed.profile;
+    conn.execute(
+        "INSERT INTO profiles (fingerprint, username, display_name, version, updated_at, expires_at, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(fingerprint) DO UPDATE SET
+             username = excluded.username, display_name = excluded.display_name,
+             version = excluded.version, updated_at = excluded.updated_at,
+             expires_at = excluded.expires_at, data = excluded.data
+         WHERE (excluded.version, excluded.updated_at) >= (profiles.version, profiles.updated_at)",
+        params![
+            p.fingerprint,
+            p.username,
+            p.display_name,
+            p.version,
+            p.updated_at.timestamp_millis(),
+            p.expires_at.map(|t| t.timestamp_millis()),
+            to_json(signed)?,
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+fn insert_post(conn: &Connection, signed: &SignedPost) -> Result<(), String> {
+    let p = &signed.post;
+    conn.execute(
+        "INSERT OR REPLACE INTO posts (id, author_fingerprint, created_at, expires_at, reply_to, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            p.id,
+            p.author_fingerprint,
+            p.created_at.timestamp_millis(),
+            p.expires_at.map(|t| t.timestamp_millis()),
+            p.reply_to,
+            to_json(signed)?,
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+fn insert_message(conn: &Connection, m: &MessageRecord) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO messages (id, conversation, sender_fingerprint, incoming, created_at, lamport, expires_at, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(conversation, id) DO UPDATE SET
+             sender_fingerprint = excluded.sender_fingerprint,
+             incoming = excluded.incoming, created_at = excluded.created_at, lamport = excluded.lamport,
+             expires_at = excluded.expires_at, data = excluded.data",
+        params![
+            m.id,
+            m.conversation,
+            m.sender_fingerprint,
+            m.incoming,
+            m.created_at.timestamp_millis(),
+            m.lamport as i64,
+            m.expires_at.map(|t| t.timestamp_millis()),
+            m.data,
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+/// Upsert that keeps known keys and names when the new record lacks them.
+fn insert_contact(conn: &Connection, c: &ContactRecord) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO contacts (fingerprint, name, public_key, encryption_public_key, updated_at, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(fingerprint) DO UPDATE SET
+             name = COALESCE(excluded.name, contacts.name),
+             public_key = COALESCE(excluded.public_key, contacts.public_key),
+             encryption_public_key = COALESCE(excluded.encryption_public_key, contacts.encryption_public_key),
+             updated_at = MAX(excluded.updated_at, contacts.updated_at),
+             data = excluded.data",
+        params![
+            c.fingerprint,
+            c.name,
+            c.public_key,
+            c.encryption_public_key,
+            c.updated_at.timestamp_millis(),
+            c.data,
+        ],
+    )
+    .map_err(db_err)?;
+    Ok(())
+}
+
+// `rowid AS rid` lets `conversation` restore insertion order after limiting.
+const MESSAGE_SELECT: &str = "SELECT id, conversation, sender_fingerprint, incoming, created_at, lamport, expires_at, data, rowid AS rid FROM messages";
+
+const CONTACT_SELECT: &str =
+    "SELECT fingerprint, name, public_key, encryption_public_key, updated_at, data FROM contacts";
+
+fn message_row(r: &Row<'_>) -> rusqlite::Result<MessageRecord> {
+    Ok(MessageRecord {
+        id: r.get(0)?,
+        conversation: r.get(1)?,
+        sender_fingerprint: r.get(2)?,
+        incoming: r.get(3)?,
+        created_at: from_millis(r.get(4)?),
+        lamport: r.get::<_, i64>(5)? as u64,
+        expires_at: r.get::<_, Option<i64>>(6)?.map(from_millis),
+        data: r.get(7)?,
+    })
+}
+
+fn contact_row(r: &Row<'_>) -> rusqlite::Result<ContactRecord> {
+    Ok(ContactRecord {
+        fingerprint: r.get(0)?,
+        name: r.get(1)?,
+        public_key: r.get(2)?,
+        encryption_public_key: r.get(3)?,
+        updated_at: from_millis(r.get(4)?),
+        data: r.get(5)?,
+    })
+}
+
+fn legacy<S: StorageBackend, T: DeserializeOwned>(storage: &S, key: &str) -> Result<Option<T>, String> {
+    storage
+        .get_json(key)
+        .map_err(|e| format!("Failed to read legacy key {}: {}", key, e))
+}
+
+fn from_millis(ms: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(ms).unwrap_or_default()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| format!("Failed to serialize: {}", e))
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> Result<T, String> {
+    serde_json::from_str(data).map_err(|e| format!("Failed to decode stored row: {}", e))
+}
+
+fn db_err(e: rusqlite::Error) -> String {
+    format!("Database error: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::message::Message;
+    use crate::post::Post;
+    use crate::profile::Profile;
+    use crate::storage::{FileStorage, SqliteStorage};
+    use serde_json::json;
+
+    fn profile(kp: &KeyPair) -> SignedProfile {
+        SignedProfile::create(Profile::new("alice".into(), kp.get_public_info()), kp).unwrap()
+    }
+
+    fn post(kp: &KeyPair, content: &str, reply_to: Option<String>) -> SignedPost {
+        SignedPost::create(Post::new(kp.fingerprint.clone(), content.into(), None, reply_to), kp).unwrap()
+    }
+
+    #[test]
+    fn migrates_once_and_refuses_newer_schemas() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let path = dir.path().join("snartnet.db");
+        let kp = KeyPair::generate().unwrap();
+        {
+            let store = RelationalStore::open(&path).unwrap();
+            assert_eq!(store.schema_version().unwrap(), SCHEMA_VERSION);
+            store.put_profile(&profile(&kp)).unwrap();
+        }
+        let store = RelationalStore::open(&path).unwrap();
+        assert_eq!(store.profile(&kp.fingerprint).unwrap().unwrap().profile.username, "alice");
+        store.conn().unwrap().pragma_update(None, "user_version", SCHEMA_VERSION as i64 + 1).unwrap();
+        drop(store);
+        let Err(e) = RelationalStore::open(&path) else {
+            panic!("opened a newer schema");
+        };
+        assert!(e.contains("newer"));
+    }
+
+    #[test]
+    fn ids_are_scoped_to_author_and_conversation() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let store = RelationalStore::open_in_memory().unwrap();
+        let theirs = post(&alice, "alice", None);
+        store.put_post(&theirs).unwrap();
+        let mut clash = Post::new(bob.fingerprint.clone(), "bob".into(), None, None);
+        clash.id = theirs.post.id.clone();
+        store.put_post(&SignedPost::create(clash, &bob).unwrap()).unwrap();
+        assert_eq!(store.post(&alice.fingerprint, &theirs.post.id).unwrap().unwrap().post.content, "alice");
+        assert_eq!(store.post(&bob.fingerprint, &theirs.post.id).unwrap().unwrap().post.content, "bob");
+
+        // A reply goes to its author's post with the id, or to the only one.
+        let carol = KeyPair::generate().unwrap();
+        let bobs_reply = post(&bob, "mine", Some(theirs.post.id.clone()));
+        let carols_reply = post(&carol, "which one?", Some(theirs.post.id.clone()));
+        store.put_post(&bobs_reply).unwrap();
+        store.put_post(&carols_reply).unwrap();
+        let ids = |author: &str| {
+            store.replies(author, &theirs.post.id).unwrap().into_iter().map(|p| p.post.id).collect::<Vec<_>>()
+        };
+        assert!(ids(&alice.fingerprint).is_empty());
+        assert_eq!(ids(&bob.fingerprint), vec![bobs_reply.post.id.clone()]);
+        assert!(store.remove_post(&bob.fingerprint, &theirs.post.id).unwrap());
+        assert_eq!(ids(&alice.fingerprint), vec![bobs_reply.post.id.clone(), carols_reply.post.id.clone()]);
+
+        let message = Message::new_direct(alice.fingerprint.clone(), "bob".into(), "hi".into());
+        let signed = SignedMessage::create(message, &alice).unwrap();
+        store.put_message(&MessageRecord::from_signed("bob", false, &signed).unwrap()).unwrap();
+        store.put_message(&MessageRecord::from_signed("carol", false, &signed).unwrap()).unwrap();
+        assert!(store.remove_message("bob", &signed.message.id).unwrap());
+        assert!(store.message("bob", &signed.message.id).unwrap().is_none());
+        assert!(store.message("carol", &signed.message.id).unwrap().is_some());
+    }
+
+    #[test]
+    fn typed_rows_round_trip() {
+        let kp = KeyPair::generate().unwrap();
+        let store = RelationalStore::open_in_memory().unwrap();
+        let root = post(&kp, "root", None);
+        let reply = post(&kp, "reply", Some(root.post.id.clone()));
+        store.put_post(&root).unwrap();
+        store.put_post(&reply).unwrap();
+        assert_eq!(store.posts_by(&kp.fingerprint, 10).unwrap().len(), 2);
+        assert_eq!(store.replies(&kp.fingerprint, &root.post.id).unwrap()[0].post.id, reply.post.id);
+        assert!(!store.remove_post("someone else", &reply.post.id).unwrap());
+        assert!(store.remove_post(&kp.fingerprint, &reply.post.id).unwrap());
+        assert_eq!(store.recent_posts(10).unwrap().len(), 1);
+
+        let mut ids = Vec::new();
+        for lamport in [2, 1, 3] {
+            let mut message = Message::new_direct(kp.fingerprint.clone(), "bob".into(), lamport.to_string());
+            message.lamport = lamport;
+            let signed = SignedMessage::create(message, &kp).unwrap();
+            ids.push(signed.message.id.clone());
+            store.put_message(&MessageRecord::from_signed("bob", false, &signed).unwrap()).unwrap();
+        }
+        let latest = store.conversation("bob", 2).unwrap();
+        assert_eq!(latest.iter().map(|m| m.lamport).collect::<Vec<_>>(), vec![2, 3]);
+        let decoded: SignedMessage = latest[0].decode().unwrap();
+        assert_eq!(decoded.message.id, ids[0]);
+        assert_eq!(store.conversations().unwrap()[0].0, "bob");
+
+        store.put_chunk("abc", b"bytes").unwrap();
+        assert!(store.has_chunk("abc"));
+        assert!(store.get_manifest("abc").is_none());
+    }
+
+    #[test]
+    fn erase_leaves_no_rows_on_disk() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let path = dir.path().join("snartnet.db");
+        let kp = KeyPair::generate().unwrap();
+        RelationalStore::open(&path).unwrap().put_post(&post(&kp, "the secret rendezvous", None)).unwrap();
+        RelationalStore::erase(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(10).any(|w| w == b"rendezvous"));
+        assert!(RelationalStore::open(&path).unwrap().posts_by(&kp.fingerprint, 10).unwrap().is_empty());
+        RelationalStore::erase(dir.path().join("missing.db")).unwrap();
+    }
+
+    #[test]
+    fn imports_legacy_keys_once() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let files = FileStorage::new(dir.path()).unwrap();
+        let own = profile(&alice);
+        files.set_json("profile", &own).unwrap();
+        files.set_json("local_posts", &vec![post(&alice, "hello", None)]).unwrap();
+        files
+            .set_json("contacts", &json!([{ "fingerprint": bob.fingerprint, "alias": "Bob", "trust_score": 3 }]))
+            .unwrap();
+        files
+            .set_json(
+                "threads",
+                &json!([{
+                    "contact_fingerprint": bob.fingerprint,
+                    "messages": [
+                        { "id": "m1", "incoming": true, "content": "hi" },
+                        { "id": "m2", "incoming": false, "content": "hey", "lamport": 2, "sent_at": "2024-05-01T12:00:00Z" },
+                        { "id": "m3", "incoming": true, "content": "bye", "lamport": 3 }
+                    ],
+                    "unread_count": 0
+                }]),
+            )
+            .unwrap();
+
+        let store = RelationalStore::open_in_memory().unwrap();
+        let report = store.import_legacy("desktop", &files).unwrap().unwrap();
+        assert_eq!(report, ImportReport { profiles: 1, posts: 1, messages: 3, contacts: 1 });
+        assert!(store.import_legacy("desktop", &files).unwrap().is_none());
+        assert!(files.get_item("threads").unwrap().is_some());
+
+        let thread = store.conversation(&bob.fingerprint, 10).unwrap();
+        assert_eq!(thread.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), vec!["m1", "m2", "m3"]);
+        // Items without a send time borrow their neighbours', not 1970.
+        assert!(thread.iter().all(|m| m.created_at == thread[1].created_at));
+        assert_eq!(store.conversations().unwrap()[0].1.to_rfc3339(), "2024-05-01T12:00:00+00:00");
+        assert_eq!(thread[0].sender_fingerprint, bob.fingerprint);
+        assert_eq!(thread[1].sender_fingerprint, alice.fingerprint);
+        assert_eq!(thread[1].decode::<serde_json::Value>().unwrap()["content"], "hey");
+
+        // The core service's kv_store fills in keys learned from profiles.
+        let kv = SqliteStorage::open_in_memory().unwrap();
+        let mut keyring = KeyRing::new();
+        keyring.learn(&profile(&bob)).unwrap();
+        kv.set_json("snartnet_keyring", &keyring).unwrap();
+        let report = store.import_legacy("core", &kv).unwrap().unwrap();
+        assert_eq!(report.contacts, 1);
+        let contact = store.contact(&bob.fingerprint).unwrap().unwrap();
+        assert_eq!(contact.name.as_deref(), Some("Bob"));
+        assert_eq!(contact.public_key.as_deref(), Some(bob.public_key.as_str()));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::crypto::{fingerprint_from_public_key, verify_signature};
+use crate::post::SignedPost;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How deeply quotes of quotes are followed before verification gives up.
+const MAX_EMBED_DEPTH: usize = 4;
+
+/// An original post carried inside a repost or quote.
+///
+/// The author's public key travels with it so that followers of the reposter
+/// can check the original without knowing its author; the key is only
+/// accepted if it hashes to the original's `author_fingerprint`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddedPost {
+    pub original: SignedPost,
+    pub author_public_key: String,
+}
+
+impl EmbeddedPost {
+    pub fn new(original: SignedPost, author_public_key: String) -> Self {
+        Self {
+            original,
+            author_public_key,
+        }
+    }
+
+    pub fn verify(&self) -> Result<bool, String> {
+        self.verify_at_depth(1)
+    }
+
+    fn verify_at_depth(&self, depth: usize) -> Result<bool, String> {
+        if depth > MAX_EMBED_DEPTH {
+            return Ok(false);
+        }
+        if fingerprint_from_public_key(&self.author_public_key)?
+            != self.original.post.author_fingerprint
+        {
+            return Ok(false);
+        }
+        let post_json = self.original.post.to_canonical_json()?;
+        if !verify_signature(&post_json, &self.original.signature, &self.author_public_key)? {
+            return Ok(false);
+        }
+        match &self.original.post.embedded {
+            Some(inner) => inner.verify_at_depth(depth + 1),
+            None => Ok(true),
+        }
+    }
+
+    /// Follow plain reposts down to the post that actually has content.
+    pub fn innermost(self) -> EmbeddedPost {
+        let mut current = self;
+        while current.original.post.is_repost() {
+            match current.original.post.embedded.take() {
+                Some(inner) => current = *inner,
+                None => break,
+            }
+        }
+        current
+    }
+}
+
+/// One feed entry after collapsing reposts: the post to display, credited to
+/// its original author, plus everyone who reposted it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollapsedPost {
+    pub post: SignedPost,
+    /// Fingerprints of reposters, in the order their reposts were seen.
+    pub reposted_by: Vec<String>,
+    /// Newest of the original's and its reposts' `created_at`.
+    pub last_activity: DateTime<Utc>,
+}
+
+impl CollapsedPost {
+    pub fn is_reposted(&self) -> bool {
+        !self.reposted_by.is_empty()
+    }
+}
+
+/// Collapse plain reposts of the same original into a single entry.
+///
+/// Posts are expected to be verified already (`SignedPost::verify` also
+/// checks embedded originals). Quote posts stay separate entries since they
+/// carry their own content. Originals are told apart by author and id.
+/// Order follows the first appearance of each entry.
+pub fn collapse_reposts(posts: &[SignedPost]) -> Vec<CollapsedPost> {
+    let mut out: Vec<CollapsedPost> = Vec::new();
+    for post in posts {
+        let (shown, reposter) = match &post.post.embedded {
+            Some(embedded) if post.post.is_repost() => (
+                &embedded.original,
+                Some(post.post.author_fingerprint.clone()),
+            ),
+            _ => (post, None),
+        };
+        let seen_at = post.post.created_at;
+
+        match out
+            .iter_mut()
+            .find(|c| c.post.post.id == shown.post.id && c.post.post.author_fingerprint == shown.post.author_fingerprint)
+        {
+            Some(existing) => {
+                if let Some(fp) = reposter {
+                    if !existing.reposted_by.contains(&fp) {
+                        existing.reposted_by.push(fp);
+                    }
+                }
+                existing.last_activity = existing.last_activity.max(seen_at);
+            }
+            None => out.push(CollapsedPost {
+                post: shown.clone(),
+                reposted_by: reposter.into_iter().collect(),
+                last_activity: seen_at.max(shown.post.created_at),
+            }),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::post::Post;
+
+    fn original(kp: &KeyPair) -> SignedPost {
+        let p = Post::new(kp.fingerprint.clone(), "original thought".into(), None, None);
+        SignedPost::create(p, kp).unwrap()
+    }
+
+    #[test]
+    fn repost_verifies_embedded_original() {
+        let author = KeyPair::generate().unwrap();
+        let reposter = KeyPair::generate().unwrap();
+        let orig = original(&author);
+
+        let repost = Post::repost(
+            reposter.fingerprint.clone(),
+            EmbeddedPost::new(orig.clone(), author.public_key.clone()),
+        );
+        assert!(repost.is_repost());
+        let signed = SignedPost::create(repost, &reposter).unwrap();
+        assert!(signed.verify(&reposter.public_key).unwrap());
+
+        // Swapping in a key that does not match the original's fingerprint fails.
+        let forged = Post::repost(
+            reposter.fingerprint.clone(),
+            EmbeddedPost::new(orig, reposter.public_key.clone()),
+        );
+        let forged = SignedPost::create(forged, &reposter).unwrap();
+        assert!(!forged.verify(&reposter.public_key).unwrap());
+    }
+
+    #[test]
+    fn tampered_original_is_rejected() {
+        let author = KeyPair::generate().unwrap();
+        let reposter = KeyPair::generate().unwrap();
+        let mut orig = original(&author);
+        orig.post.content = "words they never said".into();
+
+        let quote = Post::quote(
+            reposter.fingerprint.clone(),
+            "look at this".into(),
+            None,
+            EmbeddedPost::new(orig, author.public_key.clone()),
+        );
+        assert!(quote.is_quote());
+        let signed = SignedPost::create(quote, &reposter).unwrap();
+        assert!(!signed.verify(&reposter.public_key).unwrap());
+    }
+
+    #[test]
+    fn reposts_collapse_and_credit_original_author() {
+        let author = KeyPair::generate().unwrap();
+        let a = KeyPair::generate().unwrap();
+        let b = KeyPair::generate().unwrap();
+        let orig = original(&author);
+        let embed = || EmbeddedPost::new(orig.clone(), author.public_key.clone());
+
+        let ra = SignedPost::create(Post::repost(a.fingerprint.clone(), embed()), &a).unwrap();
+        let rb = SignedPost::create(Post::repost(b.fingerprint.clone(), embed()), &b).unwrap();
+        // Reposting a repost shares the original, not the repost.
+        let inner = EmbeddedPost::new(ra.clone(), a.public_key.clone());
+        let rr = SignedPost::create(Post::repost(b.fingerprint.clone(), inner), &b).unwrap();
+        assert!(rr.verify(&b.public_key).unwrap());
+
+        let collapsed = collapse_reposts(&[ra, rb, rr, orig.clone()]);
+        assert_eq!(collapsed.len(), 1);
+        assert_eq!(collapsed[0].post.post.id, orig.post.id);
+        assert_eq!(collapsed[0].post.post.author_fingerprint, author.fingerprint);
+        assert_eq!(collapsed[0].reposted_by, vec![a.fingerprint.clone(), b.fingerprint.clone()]);
+    }
+
+    #[test]
+    fn same_id_from_another_author_is_not_collapsed() {
+        let author = KeyPair::generate().unwrap();
+        let mallory = KeyPair::generate().unwrap();
+        let reposter = KeyPair::generate().unwrap();
+        let orig = original(&author);
+        let mut copy = Post::new(mallory.fingerprint.clone(), "something else".into(), None, None);
+        copy.id = orig.post.id.clone();
+        let copy = SignedPost::create(copy, &mallory).unwrap();
+
+        let repost = Post::repost(
+            reposter.fingerprint.clone(),
+            EmbeddedPost::new(copy.clone(), mallory.public_key.clone()),
+        );
+        let repost = SignedPost::create(repost, &reposter).unwrap();
+        let collapsed = collapse_reposts(&[orig, repost]);
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(collapsed[0].post.post.author_fingerprint, author.fingerprint);
+        assert!(collapsed[0].reposted_by.is_empty());
+        assert_eq!(collapsed[1].post.post.author_fingerprint, mallory.fingerprint);
+        assert_eq!(collapsed[1].reposted_by, vec![reposter.fingerprint]);
+    }
+}
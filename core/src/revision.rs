@@ -0,0 +1,456 @@
+use crate::crypto::{KeyPair, fingerprint_from_public_key, verify_signature};
+use crate::post::{Post, SignedPost};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// A correction to an earlier post. The original stays intact so its
+/// signature remains verifiable; readers show the newest edit instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostEdit {
+    pub id: String,
+    pub post_id: String,
+    pub author_fingerprint: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub edited_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPostEdit {
+    pub edit: PostEdit,
+    pub signature: String,
+}
+
+/// A tombstone retracting an earlier post. Peers drop the post's content and
+/// keep only the tombstone so the deletion keeps propagating.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostDelete {
+    pub post_id: String,
+    pub author_fingerprint: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPostDelete {
+    pub delete: PostDelete,
+    pub signature: String,
+}
+
+impl PostEdit {
+    pub fn new(original: &Post, content: String, tags: Option<Vec<String>>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            post_id: original.id.clone(),
+            author_fingerprint: original.author_fingerprint.clone(),
+            content,
+            tags: tags.unwrap_or_else(|| original.tags.clone()),
+            edited_at: Utc::now(),
+        }
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize post edit: {}", e))
+    }
+}
+
+impl PostDelete {
+    pub fn new(original: &Post) -> Self {
+        Self {
+            post_id: original.id.clone(),
+            author_fingerprint: original.author_fingerprint.clone(),
+            deleted_at: Utc::now(),
+        }
+    }
+
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize post delete: {}", e))
+    }
+}
+
+/// `public_key` must hash to `author_fingerprint`; otherwise anyone could sign
+/// a revision that names somebody else as the author.
+fn verify_author_signature(
+    data: &str,
+    signature: &str,
+    author_fingerprint: &str,
+    public_key: &str,
+) -> Result<bool, String> {
+    if fingerprint_from_public_key(public_key)? != author_fingerprint {
+        return Ok(false);
+    }
+    verify_signature(data, signature, public_key)
+}
+
+impl SignedPostEdit {
+    /// Sign `edit` of `original`. Edits are published in the clear, so posts
+    /// restricted to an audience cannot be edited.
+    pub fn create(edit: PostEdit, original: &Post, keypair: &KeyPair) -> Result<Self, String> {
+        if edit.post_id != original.id || edit.author_fingerprint != original.author_fingerprint {
+            return Err("edit does not belong to the post".to_string());
+        }
+        if original.is_restricted() {
+            return Err("restricted posts cannot be edited".to_string());
+        }
+        if edit.author_fingerprint != keypair.fingerprint {
+            return Err("only the original author may edit a post".to_string());
+        }
+        let edit_json = edit.to_canonical_json()?;
+        let signature = keypair.sign(&edit_json)?;
+
+        Ok(SignedPostEdit { edit, signature })
+    }
+
+    pub fn verify(&self, public_key: &str) -> Result<bool, String> {
+        let edit_json = self.edit.to_canonical_json()?;
+        verify_author_signature(
+            &edit_json,
+            &self.signature,
+            &self.edit.author_fingerprint,
+            public_key,
+        )
+    }
+
+    /// Whether this edit is a valid, author-signed revision of `original`.
+    /// Edits of restricted posts never apply; they would show plaintext that
+    /// should have stayed within the audience.
+    pub fn applies_to(&self, original: &Post, public_key: &str) -> bool {
+        !original.is_restricted()
+            && self.edit.post_id == original.id
+            && self.edit.author_fingerprint == original.author_fingerprint
+            && self.verify(public_key).unwrap_or(false)
+    }
+}
+
+impl SignedPostDelete {
+    pub fn create(delete: PostDelete, keypair: &KeyPair) -> Result<Self, String> {
+        if delete.author_fingerprint != keypair.fingerprint {
+            return Err("only the original author may delete a post".to_string());
+        }
+        let delete_json = delete.to_canonical_json()?;
+        let signature = keypair.sign(&delete_json)?;
+
+        Ok(SignedPostDelete { delete, signature })
+    }
+
+    pub fn verify(&self, public_key: &str) -> Result<bool, String> {
+        let delete_json = self.delete.to_canonical_json()?;
+        verify_author_signature(
+            &delete_json,
+            &self.signature,
+            &self.delete.author_fingerprint,
+            public_key,
+        )
+    }
+
+    pub fn applies_to(&self, original: &Post, public_key: &str) -> bool {
+        self.delete.post_id == original.id
+            && self.delete.author_fingerprint == original.author_fingerprint
+            && self.verify(public_key).unwrap_or(false)
+    }
+}
+
+/// Edits and tombstones published alongside an author's posts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PostRevisions {
+    #[serde(default)]
+    pub edits: Vec<SignedPostEdit>,
+    #[serde(default)]
+    pub deletes: Vec<SignedPostDelete>,
+}
+
+/// A post as it should be displayed after applying its edits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisedPost {
+    pub original: SignedPost,
+    pub content: String,
+    pub tags: Vec<String>,
+    /// Time of the newest applied edit, if any.
+    pub edited_at: Option<DateTime<Utc>>,
+    /// All applied edits, oldest first.
+    pub history: Vec<PostEdit>,
+}
+
+impl RevisedPost {
+    pub fn is_edited(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// Decrypt an audience-restricted post for `reader`. Public posts are
+    /// returned unchanged; `None` means the reader is not in the audience.
+    /// Restricted posts always show their sealed content, never an edit.
+    pub fn reveal(mut self, reader: &KeyPair) -> Option<Self> {
+        if !self.original.post.is_restricted() {
+            return Some(self);
+        }
+        let post = self.original.post.unseal(reader).ok()??;
+        self.content = post.content;
+        self.tags = post.tags;
+        self.edited_at = None;
+        self.history.clear();
+        Some(self)
+    }
+}
+
+impl PostRevisions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records are deduplicated by signature as well, so before they are
+    /// verified a forged copy cannot keep out the real one.
+    pub fn add_edit(&mut self, edit: SignedPostEdit) {
+        if !self
+            .edits
+            .iter()
+            .any(|e| e.edit.id == edit.edit.id && e.signature == edit.signature)
+        {
+            self.edits.push(edit);
+        }
+    }
+
+    pub fn add_delete(&mut self, delete: SignedPostDelete) {
+        if !self.deletes.iter().any(|d| {
+            d.delete.post_id == delete.delete.post_id
+                && d.delete.author_fingerprint == delete.delete.author_fingerprint
+                && d.signature == delete.signature
+        }) {
+            self.deletes.push(delete);
+        }
+    }
+
+    /// Union another set of revisions into this one (e.g. from a peer copy).
+    /// Nothing is verified; use `merge_verified` once the author's key is known.
+    pub fn merge(&mut self, other: PostRevisions) {
+        for edit in other.edits {
+            self.add_edit(edit);
+        }
+        for delete in other.deletes {
+            self.add_delete(delete);
+        }
+    }
+
+    /// Union `other` into this set keeping only records signed with
+    /// `author_public_key`. Records already held win over incoming ones.
+    pub fn merge_verified(&mut self, other: PostRevisions, author_public_key: &str) {
+        self.retain_signed_by(author_public_key);
+        for edit in other.edits {
+            if !self.edits.iter().any(|e| e.edit.id == edit.edit.id) && edit.verify(author_public_key).unwrap_or(false) {
+                self.edits.push(edit);
+            }
+        }
+        for delete in other.deletes {
+            if !self.is_deleted(&delete.delete.post_id) && delete.verify(author_public_key).unwrap_or(false) {
+                self.deletes.push(delete);
+            }
+        }
+    }
+
+    /// Drop records not signed with `author_public_key`, then duplicates,
+    /// keeping the first verified record for each edit id and post.
+    pub fn retain_signed_by(&mut self, author_public_key: &str) {
+        let mut edit_ids = HashSet::new();
+        self.edits.retain(|e| e.verify(author_public_key).unwrap_or(false) && edit_ids.insert(e.edit.id.clone()));
+        let mut deleted = HashSet::new();
+        self.deletes
+            .retain(|d| d.verify(author_public_key).unwrap_or(false) && deleted.insert(d.delete.post_id.clone()));
+    }
+
+    pub fn is_deleted(&self, post_id: &str) -> bool {
+        self.deletes.iter().any(|d| d.delete.post_id == post_id)
+    }
+
+    /// Enforce tombstones on `posts`: deleted posts and any edits of them are
+    /// removed, and revisions not signed by `author_public_key` are discarded.
+    ///
+    /// All posts are expected to come from the author that owns the key, as
+    /// in a per-author `posts_<fp>.json` blob.
+    pub fn prune(&mut self, posts: &mut Vec<SignedPost>, author_public_key: &str) {
+        self.retain_signed_by(author_public_key);
+
+        let deleted: Vec<String> = self.deletes.iter().map(|d| d.delete.post_id.clone()).collect();
+        posts.retain(|p| !deleted.contains(&p.post.id));
+        self.edits.retain(|e| !deleted.contains(&e.edit.post_id));
+    }
+
+    /// Produce display views of `posts` with tombstones enforced and the newest
+    /// valid edit applied. Posts are returned in their original order.
+    pub fn apply(&self, posts: &[SignedPost], author_public_key: &str) -> Vec<RevisedPost> {
+        posts
+            .iter()
+            .filter(|p| {
+                !self
+                    .deletes
+                    .iter()
+                    .any(|d| d.applies_to(&p.post, author_public_key))
+            })
+            .map(|p| {
+                let mut history: Vec<PostEdit> = self
+                    .edits
+                    .iter()
+                    .filter(|e| e.applies_to(&p.post, author_public_key))
+                    .map(|e| e.edit.clone())
+                    .collect();
+                history.sort_by(|a, b| a.edited_at.cmp(&b.edited_at).then_with(|| a.id.cmp(&b.id)));
+
+                let (content, tags, edited_at) = match history.last() {
+                    Some(latest) => (latest.content.clone(), latest.tags.clone(), Some(latest.edited_at)),
+                    None => (p.post.content.clone(), p.post.tags.clone(), None),
+                };
+                RevisedPost {
+                    original: p.clone(),
+                    content,
+                    tags,
+                    edited_at,
+                    history,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audience::Recipient;
+    use chrono::Duration;
+
+    fn signed_post(kp: &KeyPair, content: &str) -> SignedPost {
+        let p = Post::new(kp.fingerprint.clone(), content.to_string(), None, None);
+        SignedPost::create(p, kp).expect("sign post")
+    }
+
+    #[test]
+    fn latest_edit_wins_and_history_is_kept() {
+        let kp = KeyPair::generate().unwrap();
+        let post = signed_post(&kp, "teh original");
+
+        let mut first = PostEdit::new(&post.post, "the original".into(), None);
+        first.edited_at = Utc::now() - Duration::minutes(5);
+        let second = PostEdit::new(&post.post, "the original, revised".into(), None);
+
+        let mut revs = PostRevisions::new();
+        revs.add_edit(SignedPostEdit::create(second, &post.post, &kp).unwrap());
+        revs.add_edit(SignedPostEdit::create(first, &post.post, &kp).unwrap());
+
+        let views = revs.apply(std::slice::from_ref(&post), &kp.public_key);
+        assert_eq!(views.len(), 1);
+        assert_eq!(views[0].content, "the original, revised");
+        assert_eq!(views[0].history.len(), 2);
+        assert_eq!(views[0].history[0].content, "the original");
+        assert!(views[0].original.verify(&kp.public_key).unwrap());
+    }
+
+    #[test]
+    fn restricted_posts_cannot_be_edited() {
+        let kp = KeyPair::generate().unwrap();
+        let mut post = Post::new(kp.fingerprint.clone(), "circle only".into(), None, None);
+        post.seal_for(&[Recipient::from_keypair(&kp).unwrap()]).unwrap();
+        let post = SignedPost::create(post, &kp).unwrap();
+
+        let edit = PostEdit::new(&post.post, "now in the clear".into(), None);
+        assert!(SignedPostEdit::create(edit.clone(), &post.post, &kp).is_err());
+
+        // One signed by an older host is ignored when displaying the post.
+        let leaked = SignedPostEdit { signature: kp.sign(&edit.to_canonical_json().unwrap()).unwrap(), edit };
+        let mut revs = PostRevisions::new();
+        revs.add_edit(leaked);
+        let views = revs.apply(std::slice::from_ref(&post), &kp.public_key);
+        assert!(!views[0].is_edited());
+        let revealed = views[0].clone().reveal(&kp).unwrap();
+        assert_eq!(revealed.content, "circle only");
+    }
+
+    #[test]
+    fn delete_removes_post_and_edits() {
+        let kp = KeyPair::generate().unwrap();
+        let keep = signed_post(&kp, "keep");
+        let gone = signed_post(&kp, "gone");
+
+        let mut revs = PostRevisions::new();
+        let edit = PostEdit::new(&gone.post, "gone (edited)".into(), None);
+        revs.add_edit(SignedPostEdit::create(edit, &gone.post, &kp).unwrap());
+        revs.add_delete(SignedPostDelete::create(PostDelete::new(&gone.post), &kp).unwrap());
+
+        let mut posts = vec![keep.clone(), gone];
+        revs.prune(&mut posts, &kp.public_key);
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].post.id, keep.post.id);
+        assert!(revs.edits.is_empty());
+        assert_eq!(revs.deletes.len(), 1);
+    }
+
+    #[test]
+    fn non_author_revisions_are_rejected() {
+        let author = KeyPair::generate().unwrap();
+        let attacker = KeyPair::generate().unwrap();
+        let post = signed_post(&author, "mine");
+
+        // Attacker cannot even create a record naming the author.
+        let edit = PostEdit::new(&post.post, "hijacked".into(), None);
+        assert!(SignedPostEdit::create(edit.clone(), &post.post, &attacker).is_err());
+
+        // A forged record signed with the attacker's key fails verification
+        // under either key, since the key does not match the fingerprint.
+        let forged = SignedPostEdit {
+            signature: attacker.sign(&edit.to_canonical_json().unwrap()).unwrap(),
+            edit,
+        };
+        assert!(!forged.verify(&attacker.public_key).unwrap());
+        assert!(!forged.verify(&author.public_key).unwrap());
+
+        let mut revs = PostRevisions::new();
+        revs.add_edit(forged);
+        let views = revs.apply(std::slice::from_ref(&post), &author.public_key);
+        assert_eq!(views[0].content, "mine");
+        assert!(!views[0].is_edited());
+    }
+
+    #[test]
+    fn merge_dedupes() {
+        let kp = KeyPair::generate().unwrap();
+        let post = signed_post(&kp, "x");
+        let delete = SignedPostDelete::create(PostDelete::new(&post.post), &kp).unwrap();
+
+        let mut a = PostRevisions::new();
+        a.add_delete(delete.clone());
+        let mut b = PostRevisions::new();
+        b.add_delete(delete);
+        a.merge(b);
+        assert_eq!(a.deletes.len(), 1);
+    }
+
+    #[test]
+    fn forged_tombstone_does_not_displace_the_real_one() {
+        let author = KeyPair::generate().unwrap();
+        let attacker = KeyPair::generate().unwrap();
+        let post = signed_post(&author, "x");
+        let real = SignedPostDelete::create(PostDelete::new(&post.post), &author).unwrap();
+        let forged = SignedPostDelete {
+            signature: attacker.sign(&real.delete.to_canonical_json().unwrap()).unwrap(),
+            delete: real.delete.clone(),
+        };
+
+        // Unverified merge keeps both until pruning picks the real one.
+        let mut incoming = PostRevisions::new();
+        incoming.add_delete(forged.clone());
+        incoming.add_delete(real.clone());
+        let mut posts = vec![post.clone()];
+        incoming.prune(&mut posts, &author.public_key);
+        assert!(posts.is_empty());
+        assert_eq!(incoming.deletes.len(), 1);
+
+        // Stored records win and forged incoming ones never get in.
+        let mut stored = PostRevisions::new();
+        stored.add_delete(forged.clone());
+        let mut from_peer = PostRevisions::new();
+        from_peer.add_delete(forged);
+        from_peer.add_delete(real);
+        stored.merge_verified(from_peer, &author.public_key);
+        assert_eq!(stored.deletes.len(), 1);
+        assert!(stored.deletes[0].verify(&author.public_key).unwrap());
+    }
+}
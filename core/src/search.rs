@@ -0,0 +1,599 @@
+use crate::crypto::{KeyPair, fingerprint_from_public_key};
+use crate::feed::{Feed, FeedQuery, MAX_FEED_ENTRIES};
+use crate::message::Message;
+use crate::profile::SignedProfile;
+use crate::revision::RevisedPost;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// Matched terms in `SearchHit::snippet` are wrapped in these, which the
+/// markup renderer shows as bold.
+pub const SNIPPET_HIGHLIGHT: (&str, &str) = ("**", "**");
+
+const SNIPPET_TOKENS: i32 = 12;
+
+/// Column weights for bm25: title, body, tags.
+const RANK_WEIGHTS: (f64, f64, f64) = (4.0, 1.0, 2.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Post,
+    Message,
+    Profile,
+}
+
+impl SearchKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SearchKind::Post => "post",
+            SearchKind::Message => "message",
+            SearchKind::Profile => "profile",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "post" | "posts" => Ok(SearchKind::Post),
+            "message" | "messages" => Ok(SearchKind::Message),
+            "profile" | "profiles" => Ok(SearchKind::Profile),
+            other => Err(format!("Unknown search kind: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchQuery {
+    /// Words to match, each as a prefix. `#word` matches tags only.
+    pub text: String,
+    /// Kinds to include; empty means all.
+    pub kinds: Vec<SearchKind>,
+    /// Only documents by this fingerprint.
+    pub author: Option<String>,
+    /// Maximum hits; 0 means `DEFAULT_SEARCH_LIMIT`.
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    /// Post or message id, or the fingerprint for profiles.
+    pub id: String,
+    /// For messages, the conversation they belong to; see
+    /// [`Message::conversation_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation: Option<String>,
+    pub author_fingerprint: String,
+    pub created_at: DateTime<Utc>,
+    /// Best-matching fragment with matches highlighted; see `SNIPPET_HIGHLIGHT`.
+    pub snippet: String,
+    /// Higher is more relevant.
+    pub score: f64,
+}
+
+/// One document as stored in the index.
+struct IndexDoc<'a> {
+    kind: SearchKind,
+    /// Namespace of `id`: the author of a post, the conversation of a
+    /// message, the fingerprint of a profile. Ids are only unique within it.
+    scope: &'a str,
+    id: &'a str,
+    author: &'a str,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    title: String,
+    body: &'a str,
+    tags: String,
+}
+
+impl IndexDoc<'_> {
+    fn digest(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for part in [self.author, &self.title, self.body, &self.tags] {
+            hasher.update(part.as_bytes());
+            hasher.update(&[0]);
+        }
+        hasher.update(&self.created_at.timestamp_millis().to_le_bytes());
+        if let Some(expires_at) = self.expires_at {
+            hasher.update(&expires_at.timestamp_millis().to_le_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+/// Local full-text index over posts, decrypted messages and profiles.
+///
+/// The index holds plaintext of private content and must never leave the
+/// device. Updates are incremental: re-indexing an unchanged document is a
+/// no-op, so callers can simply re-index after every sync.
+pub struct SearchIndex {
+    conn: Connection,
+}
+
+impl SearchIndex {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open search index: {}", e))?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self, String> {
+        let conn = Connection::open_in_memory().map_err(|e| format!("Failed to open search index: {}", e))?;
+        Self::init(conn)
+    }
+
+    /// Drop the index from the database at `path` and vacuum it, so no
+    /// indexed plaintext stays on disk, e.g. once the data it mirrors is
+    /// encrypted. Other tables in the file are kept.
+    pub fn erase(path: impl AsRef<Path>) -> Result<(), String> {
+        if !path.as_ref().exists() {
+            return Ok(());
+        }
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open search index: {}", e))?;
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS search_fts;
+             DROP TABLE IF EXISTS search_docs;
+             VACUUM;",
+        )
+        .map_err(|e| format!("Failed to erase search index: {}", e))
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        // Indexes from before documents were scoped are rebuilt from scratch;
+        // callers re-index on startup anyway.
+        if conn.prepare("SELECT scope FROM search_docs LIMIT 0").is_err() {
+            conn.execute_batch(
+                "DROP TABLE IF EXISTS search_fts;
+                 DROP TABLE IF EXISTS search_docs;",
+            )
+            .map_err(|e| format!("Failed to create search schema: {}", e))?;
+        }
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS search_fts USING fts5(
+                 title, body, tags,
+                 tokenize = 'unicode61 remove_diacritics 2'
+             );
+             CREATE TABLE IF NOT EXISTS search_docs (
+                 kind       TEXT NOT NULL,
+                 scope      TEXT NOT NULL,
+                 doc_id     TEXT NOT NULL,
+                 fts_rowid  INTEGER NOT NULL,
+                 author     TEXT NOT NULL,
+                 created_at INTEGER NOT NULL,
+                 expires_at INTEGER,
+                 digest     TEXT NOT NULL,
+                 PRIMARY KEY (kind, scope, doc_id)
+             );
+             CREATE INDEX IF NOT EXISTS search_docs_rowid ON search_docs (fts_rowid);",
+        )
+        .map_err(|e| format!("Failed to create search schema: {}", e))?;
+        Ok(Self { conn })
+    }
+
+    /// Index a post as displayed, i.e. with edits applied and, for circle
+    /// posts, decrypted. The original must verify against
+    /// `author_public_key`. Returns whether the index changed.
+    pub fn index_post(&self, post: &RevisedPost, author_public_key: &str) -> Result<bool, String> {
+        let original = &post.original.post;
+        if fingerprint_from_public_key(author_public_key)? != original.author_fingerprint
+            || !post.original.verify(author_public_key)?
+        {
+            return Err(format!("Post {} failed verification", original.id));
+        }
+        self.upsert(&IndexDoc {
+            kind: SearchKind::Post,
+            scope: &original.author_fingerprint,
+            id: &original.id,
+            author: &original.author_fingerprint,
+            created_at: original.created_at,
+            expires_at: original.expires_at,
+            title: String::new(),
+            body: &post.content,
+            tags: post.tags.join(" "),
+        })
+    }
+
+    /// Index a message by its plaintext; for encrypted messages the caller
+    /// decrypts and verifies first.
+    pub fn index_message(&self, message: &Message, plaintext: &str) -> Result<bool, String> {
+        self.upsert(&IndexDoc {
+            kind: SearchKind::Message,
+            scope: &message.conversation_id(),
+            id: &message.id,
+            author: &message.sender_fingerprint,
+            created_at: message.created_at,
+            expires_at: message.expires_at,
+            title: String::new(),
+            body: plaintext,
+            tags: String::new(),
+        })
+    }
+
+    /// Index a verified profile, replacing any older version.
+    pub fn index_profile(&self, profile: &SignedProfile) -> Result<bool, String> {
+        if !profile.verify()? {
+            return Err("Profile signature invalid".to_string());
+        }
+        let p = &profile.profile;
+        let title = match &p.display_name {
+            Some(name) => format!("{} {}", p.username, name),
+            None => p.username.clone(),
+        };
+        self.upsert(&IndexDoc {
+            kind: SearchKind::Profile,
+            scope: &p.fingerprint,
+            id: &p.fingerprint,
+            author: &p.fingerprint,
+            created_at: p.updated_at,
+            expires_at: None,
+            title,
+            body: p.bio.as_deref().unwrap_or_default(),
+            tags: String::new(),
+        })
+    }
+
+    /// Bring indexed posts in line with `feed`: new and edited posts are
+    /// (re)indexed and posts no longer in the feed are dropped. Returns the
+    /// number of documents changed.
+    pub fn sync_feed(&self, feed: &Feed, reader: Option<&KeyPair>) -> Result<usize, String> {
+        let all = FeedQuery {
+            limit: MAX_FEED_ENTRIES,
+            ..Default::default()
+        };
+        let mut changed = 0;
+        let mut present = HashSet::new();
+        for item in feed.page(&all, reader)?.items {
+            let post = &item.post.original.post;
+            let Some(entry) = feed.get(&post.author_fingerprint, &post.id) else {
+                continue;
+            };
+            if self.index_post(&item.post, &entry.author_public_key)? {
+                changed += 1;
+            }
+            present.insert((post.author_fingerprint.clone(), post.id.clone()));
+        }
+        for key in self.keys(SearchKind::Post)? {
+            if !present.contains(&key) && self.remove(SearchKind::Post, &key.0, &key.1)? {
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// `(scope, id)` of every indexed document of `kind`.
+    fn keys(&self, kind: SearchKind) -> Result<Vec<(String, String)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT scope, doc_id FROM search_docs WHERE kind = ?1")
+            .map_err(|e| format!("Search query failed: {}", e))?;
+        let rows = stmt
+            .query_map(params![kind.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("Search query failed: {}", e))?;
+        rows.collect::<Result<Vec<(String, String)>, _>>()
+            .map_err(|e| format!("Search query failed: {}", e))
+    }
+
+    fn upsert(&self, doc: &IndexDoc) -> Result<bool, String> {
+        let digest = doc.digest();
+        let existing: Option<(i64, String)> = self
+            .conn
+            .query_row(
+                "SELECT fts_rowid, digest FROM search_docs WHERE kind = ?1 AND scope = ?2 AND doc_id = ?3",
+                params![doc.kind.as_str(), doc.scope, doc.id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| format!("Search lookup failed: {}", e))?;
+        if existing.as_ref().is_some_and(|(_, d)| *d == digest) {
+            return Ok(false);
+        }
+
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Search update failed: {}", e))?;
+        if let Some((rowid, _)) = existing {
+            tx.execute("DELETE FROM search_fts WHERE rowid = ?1", params![rowid])
+                .map_err(|e| format!("Search update failed: {}", e))?;
+        }
+        tx.execute(
+            "INSERT INTO search_fts (title, body, tags) VALUES (?1, ?2, ?3)",
+            params![doc.title, doc.body, doc.tags],
+        )
+        .map_err(|e| format!("Search update failed: {}", e))?;
+        let rowid = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT OR REPLACE INTO search_docs
+                 (kind, scope, doc_id, fts_rowid, author, created_at, expires_at, digest)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                doc.kind.as_str(),
+                doc.scope,
+                doc.id,
+                rowid,
+                doc.author,
+                doc.created_at.timestamp_millis(),
+                doc.expires_at.map(|t| t.timestamp_millis()),
+                digest,
+            ],
+        )
+        .map_err(|e| format!("Search update failed: {}", e))?;
+        tx.commit().map_err(|e| format!("Search update failed: {}", e))?;
+        Ok(true)
+    }
+
+    /// `scope` is the author for posts, the conversation for messages and
+    /// the fingerprint for profiles. Returns false if the document was not
+    /// indexed.
+    pub fn remove(&self, kind: SearchKind, scope: &str, id: &str) -> Result<bool, String> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Search update failed: {}", e))?;
+        tx.execute(
+            "DELETE FROM search_fts WHERE rowid IN
+                 (SELECT fts_rowid FROM search_docs WHERE kind = ?1 AND scope = ?2 AND doc_id = ?3)",
+            params![kind.as_str(), scope, id],
+        )
+        .map_err(|e| format!("Search update failed: {}", e))?;
+        let removed = tx
+            .execute(
+                "DELETE FROM search_docs WHERE kind = ?1 AND scope = ?2 AND doc_id = ?3",
+                params![kind.as_str(), scope, id],
+            )
+            .map_err(|e| format!("Search update failed: {}", e))?;
+        tx.commit().map_err(|e| format!("Search update failed: {}", e))?;
+        Ok(removed > 0)
+    }
+
+    /// Drop expired posts and messages, returning how many were removed.
+    pub fn purge_expired(&self, now: DateTime<Utc>) -> Result<usize, String> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| format!("Search update failed: {}", e))?;
+        let now = now.timestamp_millis();
+        tx.execute(
+            "DELETE FROM search_fts WHERE rowid IN
+                 (SELECT fts_rowid FROM search_docs WHERE expires_at <= ?1)",
+            params![now],
+        )
+        .map_err(|e| format!("Search update failed: {}", e))?;
+        let removed = tx
+            .execute("DELETE FROM search_docs WHERE expires_at <= ?1", params![now])
+            .map_err(|e| format!("Search update failed: {}", e))?;
+        tx.commit().map_err(|e| format!("Search update failed: {}", e))?;
+        Ok(removed)
+    }
+
+    /// Best matches first. An empty query matches nothing.
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, String> {
+        let Some(expression) = match_expression(&query.text) else {
+            return Ok(Vec::new());
+        };
+        let limit = if query.limit == 0 { DEFAULT_SEARCH_LIMIT } else { query.limit };
+        let kinds: String = query.kinds.iter().map(|k| format!(",{},", k.as_str())).collect();
+        let (w_title, w_body, w_tags) = RANK_WEIGHTS;
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT d.kind, d.scope, d.doc_id, d.author, d.created_at,
+                        snippet(search_fts, -1, ?1, ?2, '…', ?3),
+                        bm25(search_fts, ?4, ?5, ?6) AS rank
+                 FROM search_fts
+                 JOIN search_docs d ON d.fts_rowid = search_fts.rowid
+                 WHERE search_fts MATCH ?7
+                   AND (d.expires_at IS NULL OR d.expires_at > ?8)
+                   AND (?9 IS NULL OR d.author = ?9)
+                   AND (?10 = '' OR instr(?10, ',' || d.kind || ',') > 0)
+                 ORDER BY rank
+                 LIMIT ?11",
+            )
+            .map_err(|e| format!("Search query failed: {}", e))?;
+        let rows = stmt
+            .query_map(
+                params![
+                    SNIPPET_HIGHLIGHT.0,
+                    SNIPPET_HIGHLIGHT.1,
+                    SNIPPET_TOKENS,
+                    w_title,
+                    w_body,
+                    w_tags,
+                    expression,
+                    Utc::now().timestamp_millis(),
+                    query.author,
+                    kinds,
+                    limit as i64,
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, f64>(6)?,
+                    ))
+                },
+            )
+            .map_err(|e| format!("Search query failed: {}", e))?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            let (kind, scope, id, author, created_at, snippet, rank) =
+                row.map_err(|e| format!("Search query failed: {}", e))?;
+            let kind = SearchKind::parse(&kind)?;
+            hits.push(SearchHit {
+                kind,
+                id,
+                conversation: (kind == SearchKind::Message).then_some(scope),
+                author_fingerprint: author,
+                created_at: DateTime::from_timestamp_millis(created_at).unwrap_or_default(),
+                snippet,
+                // bm25 is lower for better matches.
+                score: -rank,
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Turn user input into an FTS5 expression without exposing its query
+/// syntax: every word is quoted and prefix-matched, and all must match.
+fn match_expression(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .filter_map(|raw| {
+            let (column, word) = match raw.strip_prefix('#') {
+                Some(tag) => ("tags : ", tag),
+                None => ("", raw),
+            };
+            let word: String = word.chars().filter(|c| *c != '"').collect();
+            if word.is_empty() {
+                return None;
+            }
+            Some(format!("{column}\"{word}\"*"))
+        })
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::post::{Post, SignedPost};
+    use crate::profile::Profile;
+    use crate::revision::{PostDelete, PostEdit, PostRevisions, SignedPostDelete, SignedPostEdit};
+    use chrono::Duration;
+
+    fn query(text: &str) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn signed_post(kp: &KeyPair, content: &str) -> SignedPost {
+        SignedPost::create(Post::new(kp.fingerprint.clone(), content.to_string(), None, None), kp).unwrap()
+    }
+
+    #[test]
+    fn indexes_and_ranks_all_kinds() {
+        let index = SearchIndex::open_in_memory().unwrap();
+        let alice = KeyPair::generate().unwrap();
+
+        let mut profile = Profile::new("gardener".into(), alice.get_public_info());
+        profile.bio = Some("Tomatoes and compost".into());
+        let profile = SignedProfile::create(profile, &alice).unwrap();
+        assert!(index.index_profile(&profile).unwrap());
+        assert!(!index.index_profile(&profile).unwrap());
+
+        let mut feed = Feed::new();
+        feed.ingest(
+            &alice.public_key,
+            vec![
+                signed_post(&alice, "Planted tomatoes today #garden"),
+                signed_post(&alice, "Nothing to see here"),
+            ],
+            PostRevisions::new(),
+        )
+        .unwrap();
+        assert_eq!(index.sync_feed(&feed, None).unwrap(), 2);
+        assert_eq!(index.sync_feed(&feed, None).unwrap(), 0);
+
+        let msg = Message::new_direct("bob-fp".into(), alice.fingerprint.clone(), "ciphertext".into());
+        index.index_message(&msg, "Want some tomato seedlings?").unwrap();
+
+        let hits = index.search(&query("tomato")).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+        let post_hit = hits.iter().find(|h| h.kind == SearchKind::Post).unwrap();
+        assert!(post_hit.snippet.contains("**tomatoes**"));
+
+        let only_messages = SearchQuery {
+            kinds: vec![SearchKind::Message],
+            ..query("tomato")
+        };
+        assert_eq!(index.search(&only_messages).unwrap()[0].id, msg.id);
+        assert_eq!(index.search(&query("#garden")).unwrap().len(), 1);
+        assert!(index.search(&query("\"unbalanced AND (")).unwrap().is_empty());
+        assert!(index.search(&query("   ")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn follows_edits_deletes_and_expiry() {
+        let index = SearchIndex::open_in_memory().unwrap();
+        let alice = KeyPair::generate().unwrap();
+        let keep = signed_post(&alice, "teh quick fox");
+        let gone = signed_post(&alice, "regrettable fox");
+        let mut feed = Feed::new();
+        feed.ingest(&alice.public_key, vec![keep.clone(), gone.clone()], PostRevisions::new())
+            .unwrap();
+        index.sync_feed(&feed, None).unwrap();
+
+        let mut revisions = PostRevisions::new();
+        revisions.add_edit(SignedPostEdit::create(PostEdit::new(&keep.post, "the quick fox".into(), None), &keep.post, &alice).unwrap());
+        revisions.add_delete(SignedPostDelete::create(PostDelete::new(&gone.post), &alice).unwrap());
+        feed.ingest(&alice.public_key, Vec::new(), revisions).unwrap();
+        assert_eq!(index.sync_feed(&feed, None).unwrap(), 2);
+
+        assert!(index.search(&query("teh")).unwrap().is_empty());
+        assert_eq!(index.search(&query("fox")).unwrap().len(), 1);
+
+        let mut msg = Message::new_direct("bob-fp".into(), alice.fingerprint.clone(), "x".into());
+        msg.expire_after(Duration::minutes(5));
+        index.index_message(&msg, "self destructing fox").unwrap();
+        assert_eq!(index.search(&query("fox")).unwrap().len(), 2);
+        assert_eq!(index.purge_expired(Utc::now() + Duration::minutes(6)).unwrap(), 1);
+        assert!(!index.remove(SearchKind::Message, &msg.conversation_id(), &msg.id).unwrap());
+
+        let forged = RevisedPost {
+            content: "forged".into(),
+            ..feed.page(&FeedQuery::default(), None).unwrap().items[0].post.clone()
+        };
+        let mallory = KeyPair::generate().unwrap();
+        assert!(index.index_post(&forged, &mallory.public_key).is_err());
+    }
+
+    #[test]
+    fn messages_are_keyed_by_conversation() {
+        let index = SearchIndex::open_in_memory().unwrap();
+        let to_bob = Message::new_direct("alice-fp".into(), "bob-fp".into(), "x".into());
+        let mut to_carol = Message::new_direct("alice-fp".into(), "carol-fp".into(), "x".into());
+        to_carol.id = to_bob.id.clone();
+        index.index_message(&to_bob, "lunch on friday").unwrap();
+        index.index_message(&to_carol, "lunch on monday").unwrap();
+
+        let hits = index.search(&query("lunch")).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().any(|h| h.conversation.as_deref() == Some("alice-fp:carol-fp")));
+        let reply = Message::new_direct("bob-fp".into(), "alice-fp".into(), "x".into());
+        assert_eq!(reply.conversation_id(), to_bob.conversation_id());
+
+        assert!(index.remove(SearchKind::Message, &to_bob.conversation_id(), &to_bob.id).unwrap());
+        assert_eq!(index.search(&query("monday")).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn erase_leaves_no_indexed_text_on_disk() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let path = dir.path().join("search.db");
+        {
+            let index = SearchIndex::open(&path).unwrap();
+            let msg = Message::new_direct("bob-fp".into(), "alice-fp".into(), "x".into());
+            index.index_message(&msg, "the secret rendezvous").unwrap();
+        }
+        SearchIndex::erase(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(10).any(|w| w == b"rendezvous"));
+        assert!(SearchIndex::open(&path).unwrap().search(&query("rendezvous")).unwrap().is_empty());
+        SearchIndex::erase(dir.path().join("missing.db")).unwrap();
+    }
+}
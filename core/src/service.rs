@@ -1,10 +1,33 @@
+use crate::audience::{CircleBook, Recipient};
+use crate::causal::{is_causal, missing_predecessors, sort_causally, LamportClock};
+use crate::content_warning::{ContentPreferences, ContentWarning};
 use crate::crypto::KeyPair;
+use chrono::{DateTime, Duration, Utc};
+use crate::feed::{Feed, FeedPage, FeedQuery};
+use crate::group::{GroupBook, GroupMember, SignedGroup, SignedSenderKeyDistribution};
+use crate::heartbeat::{Heartbeat, SignedHeartbeat};
+use crate::inbox_ack::{AckedItem, SignedInboxAck};
+use crate::ingest::{MessageIngest, MESSAGE_MAX_AGE_DAYS};
+use crate::keyring::KeyRing;
+use crate::markup::Document;
+use crate::petname::{PetnameBook, ResolvedName};
 use crate::profile::{Profile, SignedProfile};
+use crate::poll::{Poll, PollResults, PollVote, PollVotes, SignedPollTally, SignedPollVote, TallyCheck};
 use crate::post::{Post, SignedPost};
-use crate::message::{Message, SignedMessage};
+use crate::message::{Message, MessageType, SignedMessage};
+use crate::message_control::DisappearingTimer;
+use crate::outbox::{Outbox, OutboxPayload};
+use crate::reaction::{Reaction, ReactionTarget, SignedReaction};
+use crate::receipt::{DeliveryStatus, ReceiptKind, ReceiptLog, ReceiptPreferences};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::relational::{ContactRecord, ImportReport, MessageRecord, RelationalStore};
+use crate::repost::EmbeddedPost;
+use crate::revision::{PostDelete, PostEdit, PostRevisions, SignedPostDelete, SignedPostEdit};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::search::SearchIndex;
 use crate::storage::{StorageBackend, StorageError};
 use serde::{Serialize, Deserialize};
-use std::marker::PhantomData;
+use std::collections::{BTreeMap, HashSet};
 
 // ----- Shared JSON API structs (additive, forward-compatible) -----
 #[derive(Serialize, Deserialize)]
@@ -29,6 +52,16 @@ pub struct ProfileEnvelope {
     pub version: u32,
 }
 
+/// A signed group message plus what must be delivered alongside it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupSend {
+    pub message: SignedMessage,
+    /// Present when the sender key was (re)generated for this epoch.
+    pub distribution: Option<SignedSenderKeyDistribution>,
+    /// Fingerprints of the members whose inboxes get the message.
+    pub recipients: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CapabilityDescriptor {
     #[serde(rename = "profileJsonApi")] pub profile_json_api: bool,
@@ -44,29 +77,167 @@ pub struct CapabilityDescriptor {
 pub struct CoreService<S: StorageBackend> {
     current_profile: Option<SignedProfile>,
     keypair: Option<KeyPair>,
-    _storage: PhantomData<S>,
+    petnames: PetnameBook,
+    circles: CircleBook,
+    feed: Feed,
+    content_preferences: ContentPreferences,
+    poll_votes: PollVotes,
+    groups: GroupBook,
+    receipts: ReceiptLog,
+    receipt_preferences: ReceiptPreferences,
+    /// Disappearing-message timer per peer fingerprint.
+    message_timers: BTreeMap<String, DisappearingTimer>,
+    /// Replay cache and checks for incoming direct messages.
+    ingest: MessageIngest,
+    /// Contacts' keys from their verified profiles.
+    keyring: KeyRing,
+    /// Lamport clock per peer fingerprint.
+    conversation_clocks: BTreeMap<String, LamportClock>,
+    /// Signed messages and posts not yet taken by a peer.
+    outbox: Outbox,
+    /// One row per post, message, contact and profile; see `attach_records`.
+    #[cfg(not(target_arch = "wasm32"))]
+    records: Option<RelationalStore>,
+    storage: S,
 }
 
 impl<S: StorageBackend> CoreService<S> {
-    pub fn new() -> Self {
+    /// A service persisting to `storage`; call `init` to load what it holds.
+    pub fn new(storage: S) -> Self {
         Self {
             current_profile: None,
             keypair: None,
-            _storage: PhantomData,
+            petnames: PetnameBook::new(),
+            circles: CircleBook::new(),
+            feed: Feed::new(),
+            content_preferences: ContentPreferences::new(),
+            poll_votes: PollVotes::new(),
+            groups: GroupBook::new(),
+            receipts: ReceiptLog::new(),
+            receipt_preferences: ReceiptPreferences::default(),
+            message_timers: BTreeMap::new(),
+            ingest: MessageIngest::new(),
+            keyring: KeyRing::new(),
+            conversation_clocks: BTreeMap::new(),
+            outbox: Outbox::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            records: None,
+            storage,
         }
     }
 
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Give the backend back, e.g. to open it again with `new` and `init`.
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
     /// Load persisted keypair and profile from storage.
     pub fn init(&mut self) -> Result<(), StorageError> {
-        if let Some(keypair) = S::get_json::<KeyPair>("snartnet_keypair")? {
+        if let Some(keypair) = self.storage.get_json::<KeyPair>("snartnet_keypair")? {
             self.keypair = Some(keypair);
         }
-        if let Some(profile) = S::get_json::<SignedProfile>("snartnet_current_profile")? {
+        if let Some(profile) = self.storage.get_json::<SignedProfile>("snartnet_current_profile")? {
             self.current_profile = Some(profile);
         }
+        if let Some(petnames) = self.storage.get_json::<PetnameBook>("snartnet_petnames")? {
+            self.petnames = petnames;
+        }
+        if let Some(circles) = self.storage.get_json::<CircleBook>("snartnet_circles")? {
+            self.circles = circles;
+        }
+        if let Some(feed) = self.storage.get_json::<Feed>("snartnet_feed")? {
+            self.feed = feed;
+        }
+        if let Some(preferences) = self.storage.get_json::<ContentPreferences>("snartnet_content_preferences")? {
+            self.content_preferences = preferences;
+        }
+        if let Some(votes) = self.storage.get_json::<PollVotes>("snartnet_poll_votes")? {
+            self.poll_votes = votes;
+        }
+        if let Some(groups) = self.storage.get_json::<GroupBook>("snartnet_groups")? {
+            self.groups = groups;
+        }
+        if let Some(receipts) = self.storage.get_json::<ReceiptLog>("snartnet_receipts")? {
+            self.receipts = receipts;
+        }
+        if let Some(preferences) = self.storage.get_json::<ReceiptPreferences>("snartnet_receipt_preferences")? {
+            self.receipt_preferences = preferences;
+        }
+        if let Some(timers) = self.storage.get_json::<BTreeMap<String, DisappearingTimer>>("snartnet_message_timers")? {
+            self.message_timers = timers;
+        }
+        if let Some(ingest) = self.storage.get_json::<MessageIngest>("snartnet_seen_messages")? {
+            self.ingest = ingest;
+        }
+        if let Some(keyring) = self.storage.get_json::<KeyRing>("snartnet_keyring")? {
+            self.keyring = keyring;
+        }
+        if let Some(clocks) = self.storage.get_json::<BTreeMap<String, LamportClock>>("snartnet_conversation_clocks")? {
+            self.conversation_clocks = clocks;
+        }
+        if let Some(outbox) = self.storage.get_json::<Outbox>("snartnet_outbox")? {
+            self.outbox = outbox;
+        }
         Ok(())
     }
 
+    /// Keep posts, messages, contacts and profiles in `records` from now on,
+    /// one row each, written as they arrive or change. Call after `init`;
+    /// what this storage held before is imported into it once.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn attach_records(&mut self, records: RelationalStore) -> Result<Option<ImportReport>, StorageError> {
+        let report = records
+            .import_legacy("core", &self.storage)
+            .map_err(StorageError::Backend)?;
+        self.records = Some(records);
+        Ok(report)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn records(&self) -> Option<&RelationalStore> {
+        self.records.as_ref()
+    }
+
+    /// Run `write` against the attached records, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write_records(&self, write: impl FnOnce(&RelationalStore) -> Result<(), String>) -> Result<(), StorageError> {
+        match &self.records {
+            Some(records) => write(records).map_err(StorageError::Backend),
+            None => Ok(()),
+        }
+    }
+
+    /// Mirror a feed merge into the records: a row for each post it added,
+    /// and none left for posts a verified tombstone now covers.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn record_feed_merge(
+        &self,
+        author_public_key: &str,
+        added: &[String],
+        deleted: &[String],
+    ) -> Result<(), StorageError> {
+        let author = crate::crypto::fingerprint_from_public_key(author_public_key).map_err(StorageError::Backend)?;
+        self.write_records(|records| {
+            for id in added {
+                if let Some(entry) = self.feed.get(&author, id) {
+                    records.put_post(&entry.post)?;
+                }
+            }
+            for id in deleted {
+                if let Some(post) = records.post(&author, id)? {
+                    if self.feed.is_deleted(&post.post, author_public_key) {
+                        records.remove_post(&author, id)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
     /// Create (or replace) the user profile, persist keypair + profile, return magnet URI.
     pub fn create_profile(
         &mut self,
@@ -91,8 +262,10 @@ impl<S: StorageBackend> CoreService<S> {
         let magnet_uri = signed_profile.profile.generate_magnet_uri();
         signed_profile.profile.magnet_uri = Some(magnet_uri.clone());
 
-        S::set_json("snartnet_keypair", keypair)?;
-        S::set_json("snartnet_current_profile", &signed_profile)?;
+        self.storage.set_json("snartnet_keypair", keypair)?;
+        self.storage.set_json("snartnet_current_profile", &signed_profile)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| records.put_profile(&signed_profile))?;
 
         self.current_profile = Some(signed_profile);
         Ok(magnet_uri)
@@ -112,7 +285,9 @@ impl<S: StorageBackend> CoreService<S> {
                         .map_err(|e| StorageError::Backend(format!("sign failed: {e}")))?;
                 let magnet_uri = new_signed.profile.generate_magnet_uri();
                 new_signed.profile.magnet_uri = Some(magnet_uri);
-                S::set_json("snartnet_current_profile", &new_signed)?;
+                self.storage.set_json("snartnet_current_profile", &new_signed)?;
+                #[cfg(not(target_arch = "wasm32"))]
+                self.write_records(|records| records.put_profile(&new_signed))?;
                 self.current_profile = Some(new_signed);
                 Ok(())
             }
@@ -142,13 +317,15 @@ impl<S: StorageBackend> CoreService<S> {
         self.current_profile.is_some()
     }
 
-    /// Create and sign a post for the current user.
-    pub fn create_post(
+    /// An unsigned post by the current user with its mentions resolved, and
+    /// the key to sign it with. Every kind of post starts here, so they all
+    /// need a profile and accept the same options.
+    fn draft_post(
         &self,
         content: &str,
         tags: Option<Vec<String>>,
         reply_to: Option<String>,
-    ) -> Result<SignedPost, StorageError> {
+    ) -> Result<(Post, &KeyPair), StorageError> {
         let keypair = self
             .keypair
             .as_ref()
@@ -158,21 +335,285 @@ impl<S: StorageBackend> CoreService<S> {
             .as_ref()
             .ok_or_else(|| StorageError::Backend("no current profile".into()))?;
 
-        let post = Post::new(
+        let mut post = Post::new(
             profile.profile.fingerprint.clone(),
             content.to_string(),
             tags,
             reply_to,
         );
+        post.resolve_mentions(&self.petnames);
+        Ok((post, keypair))
+    }
+
+    /// Create and sign a post for the current user.
+    pub fn create_post(
+        &self,
+        content: &str,
+        tags: Option<Vec<String>>,
+        reply_to: Option<String>,
+    ) -> Result<SignedPost, StorageError> {
+        let (post, keypair) = self.draft_post(content, tags, reply_to)?;
         SignedPost::create(post, keypair)
             .map_err(|e| StorageError::Backend(format!("sign post failed: {e}")))
     }
 
-    /// Create and sign a direct message.
-    pub fn create_message(
+    /// Create and sign a post behind a content warning.
+    pub fn create_post_with_warning(
+        &self,
+        content: &str,
+        tags: Option<Vec<String>>,
+        reply_to: Option<String>,
+        warning: ContentWarning,
+    ) -> Result<SignedPost, StorageError> {
+        let (mut post, keypair) = self.draft_post(content, tags, reply_to)?;
+        post.set_content_warning(warning);
+        SignedPost::create(post, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post failed: {e}")))
+    }
+
+    /// Create and sign a post that disappears `ttl` after creation.
+    pub fn create_ephemeral_post(
+        &self,
+        content: &str,
+        tags: Option<Vec<String>>,
+        reply_to: Option<String>,
+        ttl: Duration,
+    ) -> Result<SignedPost, StorageError> {
+        if ttl <= Duration::zero() {
+            return Err(StorageError::Backend("expiry must be in the future".into()));
+        }
+        let (mut post, keypair) = self.draft_post(content, tags, reply_to)?;
+        post.expire_after(ttl);
+        SignedPost::create(post, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post failed: {e}")))
+    }
+
+    /// Create and sign a post readable only by the members of `circle` and the
+    /// current user. `known` supplies contacts' encryption keys; every member
+    /// of the circle must be in it.
+    pub fn create_circle_post(
+        &self,
+        content: &str,
+        tags: Option<Vec<String>>,
+        reply_to: Option<String>,
+        circle: &str,
+        known: &[Recipient],
+    ) -> Result<SignedPost, StorageError> {
+        let (mut post, keypair) = self.draft_post(content, tags, reply_to)?;
+        let mut recipients = self
+            .circles
+            .recipients(circle, known)
+            .map_err(StorageError::Backend)?;
+        recipients.push(Recipient::from_keypair(keypair).map_err(StorageError::Backend)?);
+        post.seal_for(&recipients)
+            .map_err(|e| StorageError::Backend(format!("seal post failed: {e}")))?;
+        SignedPost::create(post, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post failed: {e}")))
+    }
+
+    /// Repost `original` (or quote it when `content` is non-empty) as the
+    /// current user.
+    pub fn share_post(
+        &self,
+        original: EmbeddedPost,
+        content: &str,
+    ) -> Result<SignedPost, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let valid = original
+            .verify()
+            .map_err(|e| StorageError::Backend(format!("verify original failed: {e}")))?;
+        if !valid {
+            return Err(StorageError::Backend("original post failed verification".into()));
+        }
+        if original.original.post.is_restricted() {
+            return Err(StorageError::Backend("restricted posts cannot be shared".into()));
+        }
+
+        let fingerprint = keypair.fingerprint.clone();
+        let post = if content.trim().is_empty() {
+            Post::repost(fingerprint, original)
+        } else {
+            Post::quote(fingerprint, content.to_string(), None, original)
+        };
+        SignedPost::create(post, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post failed: {e}")))
+    }
+
+    /// Create a signed edit of one of the current user's posts.
+    pub fn edit_post(
+        &self,
+        original: &SignedPost,
+        content: &str,
+        tags: Option<Vec<String>>,
+    ) -> Result<SignedPostEdit, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let edit = PostEdit::new(&original.post, content.to_string(), tags);
+        SignedPostEdit::create(edit, &original.post, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post edit failed: {e}")))
+    }
+
+    /// Create a signed tombstone for one of the current user's posts.
+    pub fn delete_post(&self, original: &SignedPost) -> Result<SignedPostDelete, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        SignedPostDelete::create(PostDelete::new(&original.post), keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post delete failed: {e}")))
+    }
+
+    /// Create a signed reaction by the current user to a post or message.
+    pub fn react(
+        &self,
+        target_kind: ReactionTarget,
+        target_id: &str,
+        emoji: &str,
+    ) -> Result<SignedReaction, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let reaction = Reaction::new(
+            keypair.fingerprint.clone(),
+            target_kind,
+            target_id.to_string(),
+            emoji.to_string(),
+        )
+        .map_err(StorageError::Backend)?;
+        SignedReaction::create(reaction, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign reaction failed: {e}")))
+    }
+
+    /// Create a signed retraction of one of the current user's reactions.
+    pub fn unreact(&self, reaction: &Reaction) -> Result<SignedReaction, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        SignedReaction::create(reaction.retraction(), keypair)
+            .map_err(|e| StorageError::Backend(format!("sign reaction failed: {e}")))
+    }
+
+    /// Create and sign a poll asking `question`.
+    pub fn create_poll(
+        &self,
+        question: &str,
+        options: Vec<String>,
+        closes_at: DateTime<Utc>,
+        multiple_choice: bool,
+    ) -> Result<SignedPost, StorageError> {
+        if closes_at <= Utc::now() {
+            return Err(StorageError::Backend("poll must close in the future".into()));
+        }
+        let poll = Poll::new(options, closes_at, multiple_choice).map_err(StorageError::Backend)?;
+        let (mut post, keypair) = self.draft_post(question, None, None)?;
+        post.set_poll(poll).map_err(StorageError::Backend)?;
+        SignedPost::create(post, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign post failed: {e}")))
+    }
+
+    /// Cast (or change) the current user's vote in a poll. The returned vote
+    /// should be sent to the poll author's inbox or published.
+    pub fn vote_in_poll(&mut self, poll: &SignedPost, choices: &[usize]) -> Result<SignedPollVote, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let vote = PollVote::new(keypair, &poll.post, choices).map_err(StorageError::Backend)?;
+        let signed = SignedPollVote::create(vote, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign vote failed: {e}")))?;
+        self.poll_votes
+            .add(signed.clone(), &poll.post, Utc::now())
+            .map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_poll_votes", &self.poll_votes)?;
+        Ok(signed)
+    }
+
+    /// Record a vote received from the inbox or seen published. Returns
+    /// whether it changed the stored votes.
+    pub fn receive_poll_vote(&mut self, poll: &SignedPost, vote: SignedPollVote) -> Result<bool, StorageError> {
+        let changed = self.poll_votes.add(vote, &poll.post, Utc::now()).map_err(StorageError::Backend)?;
+        if changed {
+            self.storage.set_json("snartnet_poll_votes", &self.poll_votes)?;
+        }
+        Ok(changed)
+    }
+
+    pub fn poll_votes(&self) -> &PollVotes {
+        &self.poll_votes
+    }
+
+    pub fn poll_results(&self, poll: &SignedPost) -> PollResults {
+        self.poll_votes.results(&poll.post)
+    }
+
+    /// Sign a tally of the votes received for one of the user's own polls.
+    pub fn tally_poll(&self, poll: &SignedPost) -> Result<SignedPollTally, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        if poll.post.poll.is_none() {
+            return Err(StorageError::Backend("post is not a poll".into()));
+        }
+        SignedPollTally::create(self.poll_votes.tally(&poll.post), keypair)
+            .map_err(|e| StorageError::Backend(format!("sign tally failed: {e}")))
+    }
+
+    /// Check a published tally against the votes the user has seen.
+    pub fn check_poll_tally(
         &self,
+        poll: &SignedPost,
+        tally: &SignedPollTally,
+        author_public_key: &str,
+    ) -> Result<TallyCheck, StorageError> {
+        if !tally.verify(author_public_key).map_err(StorageError::Backend)? {
+            return Err(StorageError::Backend("tally signature is invalid".into()));
+        }
+        tally
+            .tally
+            .check(&poll.post, &self.poll_votes)
+            .map_err(StorageError::Backend)
+    }
+
+    /// Create, encrypt and sign a direct message. The recipient's encryption
+    /// key comes from their profile via `learn_contact`; without it this
+    /// fails rather than falling back to plaintext.
+    pub fn create_message(
+        &mut self,
         recipient_fingerprint: &str,
         content: &str,
+    ) -> Result<SignedMessage, StorageError> {
+        let key = self.keyring.encryption_key(recipient_fingerprint).ok_or_else(|| {
+            StorageError::Backend(format!(
+                "no encryption key for {recipient_fingerprint}; sync their profile first"
+            ))
+        })?;
+        let key = key.to_string();
+        self.build_message(recipient_fingerprint, content, Some(&key))
+    }
+
+    /// Create and sign a direct message that relays can read. Only for
+    /// callers that explicitly want no encryption.
+    pub fn create_plaintext_message(
+        &mut self,
+        recipient_fingerprint: &str,
+        content: &str,
+    ) -> Result<SignedMessage, StorageError> {
+        self.build_message(recipient_fingerprint, content, None)
+    }
+
+    fn build_message(
+        &mut self,
+        recipient_fingerprint: &str,
+        content: &str,
+        recipient_encryption_public_key: Option<&str>,
     ) -> Result<SignedMessage, StorageError> {
         let keypair = self
             .keypair
@@ -183,13 +624,716 @@ impl<S: StorageBackend> CoreService<S> {
             .as_ref()
             .ok_or_else(|| StorageError::Backend("no current profile".into()))?;
 
-        let message = Message::new_direct(
+        let mut message = Message::new_direct(
             profile.profile.fingerprint.clone(),
             recipient_fingerprint.to_string(),
             content.to_string(),
         );
-        SignedMessage::create(message, keypair)
-            .map_err(|e| StorageError::Backend(format!("sign message failed: {e}")))
+        if let Some(key) = recipient_encryption_public_key {
+            message
+                .seal_for_recipient(keypair, key)
+                .map_err(|e| StorageError::Backend(format!("encrypt message failed: {e}")))?;
+        }
+        if let Some(ttl) = self.message_timer(recipient_fingerprint) {
+            message.expire_after(ttl);
+        }
+        let mut clock = self
+            .conversation_clocks
+            .get(recipient_fingerprint)
+            .cloned()
+            .unwrap_or_default();
+        clock.stamp(&mut message);
+        let signed = SignedMessage::create(message, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign message failed: {e}")))?;
+        self.conversation_clocks.insert(recipient_fingerprint.to_string(), clock);
+        self.storage.set_json("snartnet_conversation_clocks", &self.conversation_clocks)?;
+        self.ingest.record_own(&signed.message, Utc::now());
+        self.storage.set_json("snartnet_seen_messages", &self.ingest)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| records.put_message(&MessageRecord::from_signed(recipient_fingerprint, false, &signed)?))?;
+        self.queue_message(&signed)?;
+        Ok(signed)
+    }
+
+    /// Put a conversation in causal order and list the predecessors it is
+    /// missing. Ids accepted earlier from one of the thread's participants,
+    /// e.g. since unsent or expired, do not count as missing.
+    pub fn order_thread(&self, thread: &mut [SignedMessage]) -> Vec<String> {
+        sort_causally(thread);
+        let participants: HashSet<&str> = thread
+            .iter()
+            .flat_map(|m| [m.message.sender_fingerprint.as_str(), m.message.recipient_fingerprint.as_str()])
+            .collect();
+        missing_predecessors(thread, |id| self.ingest.has_seen_from(participants.iter().copied(), id))
+    }
+
+    /// Sign new content for one of the current user's direct messages.
+    pub fn edit_message(&self, original: &SignedMessage, content: &str) -> Result<SignedMessage, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        if !original.message.can_be_changed_by(keypair) {
+            return Err(StorageError::Backend("only your own direct messages can be edited".into()));
+        }
+        let key = self
+            .keyring
+            .encryption_key(&original.message.recipient_fingerprint)
+            .ok_or_else(|| StorageError::Backend("no encryption key for the recipient".into()))?;
+        let mut edit = Message::new_edit(
+            keypair,
+            &original.message.recipient_fingerprint,
+            key,
+            &original.message.id,
+            content,
+        )
+        .map_err(StorageError::Backend)?;
+        edit.expires_at = original.message.expires_at;
+        let edit = SignedMessage::create(edit, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign edit failed: {e}")))?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| {
+            records.put_message(&MessageRecord::from_signed(&original.message.recipient_fingerprint, false, &edit)?)
+        })?;
+        Ok(edit)
+    }
+
+    /// Read a direct message or edit sent to or by the current user. Messages
+    /// from others must verify against the sender's known key first.
+    pub fn open_message(&self, message: &SignedMessage) -> Result<String, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let m = &message.message;
+        let peer = if m.sender_fingerprint == keypair.fingerprint {
+            &m.recipient_fingerprint
+        } else {
+            let public_key = self
+                .keyring
+                .public_key(&m.sender_fingerprint)
+                .ok_or_else(|| StorageError::Backend("unknown sender".into()))?;
+            if !message.verify(public_key).map_err(StorageError::Backend)? {
+                return Err(StorageError::Backend("message signature is invalid".into()));
+            }
+            &m.sender_fingerprint
+        };
+        if !m.encrypted {
+            return Ok(m.content.clone());
+        }
+        let key = self
+            .keyring
+            .encryption_key(peer)
+            .ok_or_else(|| StorageError::Backend("no encryption key for the other party".into()))?;
+        m.open_direct(keypair, key).map_err(StorageError::Backend)
+    }
+
+    /// Sign a retraction of one of the current user's direct messages.
+    pub fn unsend_message(&self, original: &SignedMessage) -> Result<SignedMessage, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        if !original.message.can_be_changed_by(keypair) {
+            return Err(StorageError::Backend("only your own direct messages can be unsent".into()));
+        }
+        let unsend = Message::new_unsend(keypair, &original.message.recipient_fingerprint, &original.message.id);
+        let unsend = SignedMessage::create(unsend, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign unsend failed: {e}")))?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| {
+            records.remove_message(&original.message.recipient_fingerprint, &original.message.id).map(|_| ())
+        })?;
+        Ok(unsend)
+    }
+
+    /// State the current user's disappearing-message preference for the
+    /// thread with `peer_fingerprint`. The returned control goes to the peer.
+    pub fn set_message_timer(
+        &mut self,
+        peer_fingerprint: &str,
+        ttl_secs: Option<u64>,
+    ) -> Result<SignedMessage, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let timer = Message::new_timer(keypair, peer_fingerprint, ttl_secs).map_err(StorageError::Backend)?;
+        let signed = SignedMessage::create(timer, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign timer failed: {e}")))?;
+        self.message_timers
+            .entry(peer_fingerprint.to_string())
+            .or_default()
+            .apply(&signed.message);
+        self.storage.set_json("snartnet_message_timers", &self.message_timers)?;
+        Ok(signed)
+    }
+
+    /// Apply a timer control received from a peer. Returns whether it
+    /// changed the thread's timer.
+    pub fn receive_message_timer(&mut self, control: &SignedMessage, sender_public_key: &str) -> Result<bool, StorageError> {
+        self.ingest
+            .check(control, sender_public_key, Utc::now())
+            .map_err(|r| StorageError::Backend(format!("timer rejected: {}", r)))?;
+        let changed = self
+            .message_timers
+            .entry(control.message.sender_fingerprint.clone())
+            .or_default()
+            .apply(&control.message);
+        if changed {
+            self.storage.set_json("snartnet_message_timers", &self.message_timers)?;
+        }
+        Ok(changed)
+    }
+
+    /// Lifetime of new messages to `peer_fingerprint`, if a timer is on.
+    pub fn message_timer(&self, peer_fingerprint: &str) -> Option<Duration> {
+        self.message_timers.get(peer_fingerprint).and_then(|t| t.effective())
+    }
+
+    pub fn groups(&self) -> &GroupBook {
+        &self.groups
+    }
+
+    /// Create a group with the current user as its only admin.
+    pub fn create_group(&mut self, name: &str) -> Result<SignedGroup, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let group = SignedGroup::create(name, keypair).map_err(StorageError::Backend)?;
+        self.groups.apply(group.clone()).map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_groups", &self.groups)?;
+        Ok(group)
+    }
+
+    /// Add a member to a group the current user administers. The returned
+    /// update must be delivered to every member; the new one needs the
+    /// whole `GroupBook::chain`.
+    pub fn add_group_member(&mut self, group_id: &str, member: GroupMember) -> Result<SignedGroup, StorageError> {
+        self.update_group(group_id, |group, keypair| group.add_member(keypair, member))
+    }
+
+    pub fn remove_group_member(&mut self, group_id: &str, fingerprint: &str) -> Result<SignedGroup, StorageError> {
+        self.update_group(group_id, |group, keypair| group.remove_member(keypair, fingerprint))
+    }
+
+    /// Leave a group. The update should still be delivered to the remaining
+    /// members so they re-key.
+    pub fn leave_group(&mut self, group_id: &str) -> Result<SignedGroup, StorageError> {
+        self.update_group(group_id, |group, keypair| group.leave(keypair))
+    }
+
+    fn update_group(
+        &mut self,
+        group_id: &str,
+        change: impl FnOnce(&SignedGroup, &KeyPair) -> Result<SignedGroup, String>,
+    ) -> Result<SignedGroup, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let current = self
+            .groups
+            .get(group_id)
+            .ok_or_else(|| StorageError::Backend("unknown group".into()))?;
+        let updated = change(current, keypair).map_err(StorageError::Backend)?;
+        self.groups.apply(updated.clone()).map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_groups", &self.groups)?;
+        Ok(updated)
+    }
+
+    /// Apply a group update received from another member. Returns whether it
+    /// was new.
+    pub fn receive_group_update(&mut self, update: SignedGroup) -> Result<bool, StorageError> {
+        let changed = self.groups.apply(update).map_err(StorageError::Backend)?;
+        if changed {
+            self.storage.set_json("snartnet_groups", &self.groups)?;
+        }
+        Ok(changed)
+    }
+
+    /// Encrypt and sign a message to a group. When the user's sender key for
+    /// the current epoch is new, its distribution is returned as well and
+    /// must reach the other members before (or with) the message.
+    pub fn create_group_message(&mut self, group_id: &str, content: &str) -> Result<GroupSend, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let (sender_key, fresh) = self
+            .groups
+            .ensure_sender_key(group_id, keypair)
+            .map_err(StorageError::Backend)?;
+        let group = &self.groups.get(group_id).expect("group checked above").group;
+        let recipients: Vec<String> = group
+            .others(&keypair.fingerprint)
+            .into_iter()
+            .map(|m| m.fingerprint.clone())
+            .collect();
+        // Any membership change moves the epoch on, so a key nobody else
+        // received never needs to be sent later.
+        let distribution = if fresh && !recipients.is_empty() {
+            Some(sender_key.distribute(group, keypair).map_err(StorageError::Backend)?)
+        } else {
+            None
+        };
+        let message = Message::new_group(&sender_key, content).map_err(StorageError::Backend)?;
+        let message = SignedMessage::create(message, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign message failed: {e}")))?;
+        if fresh {
+            self.storage.set_json("snartnet_groups", &self.groups)?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| records.put_message(&MessageRecord::from_signed(group_id, false, &message)?))?;
+        Ok(GroupSend { message, distribution, recipients })
+    }
+
+    /// Store a sender key another member distributed. Returns whether it was new.
+    pub fn receive_sender_key(&mut self, distribution: &SignedSenderKeyDistribution) -> Result<bool, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let changed = self
+            .groups
+            .receive_sender_key(distribution, keypair)
+            .map_err(StorageError::Backend)?;
+        if changed {
+            self.storage.set_json("snartnet_groups", &self.groups)?;
+        }
+        Ok(changed)
+    }
+
+    /// Verify a group message against the sender's membership and decrypt it.
+    pub fn open_group_message(&self, message: &SignedMessage) -> Result<String, StorageError> {
+        let MessageType::Group { group_id, .. } = &message.message.message_type else {
+            return Err(StorageError::Backend("not a group message".into()));
+        };
+        let group = self
+            .groups
+            .get(group_id)
+            .ok_or_else(|| StorageError::Backend("unknown group".into()))?;
+        let sender = group
+            .group
+            .member(&message.message.sender_fingerprint)
+            .ok_or_else(|| StorageError::Backend("sender is not a group member".into()))?;
+        if !message.verify(&sender.public_key).map_err(StorageError::Backend)? {
+            return Err(StorageError::Backend("message signature is invalid".into()));
+        }
+        self.groups.open(&message.message).map_err(StorageError::Backend)
+    }
+
+    /// Track delivery of a direct message once it has been pushed to the
+    /// recipient's inbox.
+    pub fn record_sent_message(&mut self, message: &SignedMessage) -> Result<(), StorageError> {
+        self.receipts.track_sent(&message.message);
+        self.storage.set_json("snartnet_receipts", &self.receipts)
+    }
+
+    pub fn message_status(&self, message_id: &str) -> Option<DeliveryStatus> {
+        self.receipts.status(message_id)
+    }
+
+    /// Messages and posts waiting for peers, with their delivery state.
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    /// Keep a signed message until a peer has it. Direct messages from
+    /// `create_message` are queued already; this is for receipts, controls
+    /// and the like. Returns false if it was queued before.
+    pub fn queue_message(&mut self, message: &SignedMessage) -> Result<bool, StorageError> {
+        self.queue(OutboxPayload::Message { message: message.clone() })
+    }
+
+    /// Keep a signed post until a peer has it.
+    pub fn queue_post(&mut self, post: &SignedPost) -> Result<bool, StorageError> {
+        self.queue(OutboxPayload::Post { post: post.clone() })
+    }
+
+    fn queue(&mut self, payload: OutboxPayload) -> Result<bool, StorageError> {
+        let now = Utc::now();
+        self.outbox.prune(now);
+        let added = self.outbox.enqueue(payload, now);
+        self.storage.set_json("snartnet_outbox", &self.outbox)?;
+        Ok(added)
+    }
+
+    /// Outbox items the host should push now.
+    pub fn outbox_due(&self) -> Vec<OutboxPayload> {
+        self.outbox.due(Utc::now())
+    }
+
+    /// Report how many peers took an outbox item, or why it could not be
+    /// written; items no peer took are retried with backoff.
+    pub fn record_outbox_push(&mut self, id: &str, result: Result<usize, String>) -> Result<(), StorageError> {
+        if !self.outbox.record_push(id, result, Utc::now()) {
+            return Err(StorageError::Backend(format!("no outbox item {id}")));
+        }
+        self.storage.set_json("snartnet_outbox", &self.outbox)
+    }
+
+    /// Retry queued items right away, e.g. when a peer comes online.
+    pub fn outbox_peers_appeared(&mut self) -> Result<(), StorageError> {
+        self.outbox.peers_appeared(Utc::now());
+        self.storage.set_json("snartnet_outbox", &self.outbox)
+    }
+
+    /// Mark outbox messages that `recipient_fingerprint` acknowledged in
+    /// their inbox. Returns how many changed.
+    pub fn apply_inbox_acks_to_outbox(
+        &mut self,
+        recipient_fingerprint: &str,
+        acks: &[SignedInboxAck],
+    ) -> Result<usize, StorageError> {
+        let changed = self.outbox.acknowledge_inbox(recipient_fingerprint, acks);
+        if changed > 0 {
+            self.storage.set_json("snartnet_outbox", &self.outbox)?;
+        }
+        Ok(changed)
+    }
+
+    /// Sign a receipt for messages received from `sender_fingerprint`, leaving
+    /// out any already acknowledged. Returns `None` when there is nothing to
+    /// send, including read receipts while they are turned off.
+    pub fn acknowledge_messages(
+        &mut self,
+        sender_fingerprint: &str,
+        kind: ReceiptKind,
+        message_ids: &[String],
+        sender_encryption_public_key: Option<&str>,
+    ) -> Result<Option<SignedMessage>, StorageError> {
+        if kind == ReceiptKind::Read && !self.receipt_preferences.send_read_receipts {
+            return Ok(None);
+        }
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let pending = self.receipts.pending_acks(kind, message_ids);
+        if pending.is_empty() {
+            return Ok(None);
+        }
+        let receipt = Message::new_receipt(
+            keypair,
+            sender_fingerprint,
+            kind,
+            &pending,
+            sender_encryption_public_key,
+        )
+        .map_err(StorageError::Backend)?;
+        let signed = SignedMessage::create(receipt, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign receipt failed: {e}")))?;
+        self.receipts.mark_acknowledged(kind, &pending);
+        self.storage.set_json("snartnet_receipts", &self.receipts)?;
+        Ok(Some(signed))
+    }
+
+    /// Accept a direct message from the inbox once: the signature must
+    /// verify against `sender_public_key`, which must be the sender's, and
+    /// `created_at` must be plausible. The error names the reason otherwise.
+    pub fn receive_message(&mut self, message: &SignedMessage, sender_public_key: &str) -> Result<(), StorageError> {
+        self.ingest
+            .admit(message, sender_public_key, Utc::now())
+            .map_err(|r| StorageError::Backend(format!("message rejected: {}", r)))?;
+        self.storage.set_json("snartnet_seen_messages", &self.ingest)?;
+        if is_causal(&message.message) {
+            self.conversation_clocks
+                .entry(message.message.sender_fingerprint.clone())
+                .or_default()
+                .observe(message);
+            self.storage.set_json("snartnet_conversation_clocks", &self.conversation_clocks)?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| {
+            let m = &message.message;
+            let conversation = match &m.message_type {
+                MessageType::Group { group_id, .. } => group_id,
+                _ => &m.sender_fingerprint,
+            };
+            match &m.message_type {
+                MessageType::Unsend { target_id } => records.remove_message(conversation, target_id).map(|_| ()),
+                _ => records.put_message(&MessageRecord::from_signed(conversation, true, message)?),
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Sign an acknowledgement for entries taken out of our inbox, so
+    /// relays can delete them. It also covers everything older than the
+    /// ingestion window, which `receive_message` would refuse anyway.
+    pub fn acknowledge_inbox(&self, items: &[AckedItem]) -> Result<SignedInboxAck, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let watermark = Utc::now() - Duration::days(MESSAGE_MAX_AGE_DAYS);
+        SignedInboxAck::create(keypair, items, Some(watermark)).map_err(StorageError::Backend)
+    }
+
+    /// Apply a receipt from the inbox. Returns how many sent messages
+    /// changed status.
+    pub fn receive_receipt(
+        &mut self,
+        receipt: &SignedMessage,
+        sender_public_key: &str,
+        sender_encryption_public_key: Option<&str>,
+    ) -> Result<usize, StorageError> {
+        let kind = receipt
+            .message
+            .receipt_kind()
+            .ok_or_else(|| StorageError::Backend("not a receipt".into()))?;
+        self.ingest
+            .check(receipt, sender_public_key, Utc::now())
+            .map_err(|r| StorageError::Backend(format!("receipt rejected: {}", r)))?;
+        let ids = receipt
+            .message
+            .receipt_message_ids(self.keypair.as_ref(), sender_encryption_public_key)
+            .map_err(StorageError::Backend)?;
+        let changed = self
+            .receipts
+            .apply(&receipt.message.sender_fingerprint, kind, &ids);
+        if changed > 0 {
+            self.storage.set_json("snartnet_receipts", &self.receipts)?;
+        }
+        if self.outbox.acknowledge(&receipt.message.sender_fingerprint, &ids) > 0 {
+            self.storage.set_json("snartnet_outbox", &self.outbox)?;
+        }
+        Ok(changed)
+    }
+
+    pub fn receipt_preferences(&self) -> &ReceiptPreferences {
+        &self.receipt_preferences
+    }
+
+    pub fn set_receipt_preferences(&mut self, preferences: ReceiptPreferences) -> Result<(), StorageError> {
+        self.receipt_preferences = preferences;
+        self.storage.set_json("snartnet_receipt_preferences", &self.receipt_preferences)
+    }
+
+    /// Create and sign a liveness heartbeat for the current profile.
+    pub fn create_heartbeat(&self) -> Result<SignedHeartbeat, StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        let profile = self
+            .current_profile
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no current profile".into()))?;
+
+        let heartbeat = Heartbeat::new(profile.profile.fingerprint.clone(), profile.profile.version);
+        SignedHeartbeat::create(heartbeat, keypair)
+            .map_err(|e| StorageError::Backend(format!("sign heartbeat failed: {e}")))
+    }
+
+    /// The local petname book shared by every UI surface.
+    pub fn petnames(&self) -> &PetnameBook {
+        &self.petnames
+    }
+
+    /// Parse post or message markup for display, resolving `@petname`
+    /// mentions with the local petname book.
+    pub fn parse_markup(&self, content: &str) -> Document {
+        let mut document = Document::parse(content);
+        document.resolve_mentions(&self.petnames);
+        document
+    }
+
+    /// Resolve the name to show for `fingerprint`.
+    pub fn resolve_name(&self, fingerprint: &str) -> ResolvedName {
+        self.petnames.resolve(fingerprint)
+    }
+
+    /// Set or clear the user's own petname for `fingerprint` and persist it.
+    pub fn set_petname(
+        &mut self,
+        fingerprint: &str,
+        petname: Option<String>,
+    ) -> Result<(), StorageError> {
+        self.petnames
+            .set_petname(fingerprint, petname)
+            .map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_petnames", &self.petnames)
+    }
+
+    /// Record the name a named contact uses for `fingerprint` and persist it.
+    pub fn add_introduction(
+        &mut self,
+        fingerprint: &str,
+        introducer_fingerprint: &str,
+        name: &str,
+    ) -> Result<(), StorageError> {
+        self.petnames
+            .add_introduction(fingerprint, introducer_fingerprint, name)
+            .map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_petnames", &self.petnames)
+    }
+
+    /// Record the names a verified profile suggests for itself and persist them.
+    pub fn record_suggested_name(&mut self, profile: &SignedProfile) -> Result<(), StorageError> {
+        if !profile.verify().unwrap_or(false) {
+            return Err(StorageError::Backend("profile signature invalid".into()));
+        }
+        self.petnames.set_suggested(
+            &profile.profile.fingerprint,
+            Some(profile.profile.username.clone()),
+            profile.profile.display_name.clone(),
+        );
+        self.storage.set_json("snartnet_petnames", &self.petnames)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.write_records(|records| records.put_profile(profile))?;
+        Ok(())
+    }
+
+    /// Remember a contact's keys and suggested name from their verified
+    /// profile. Returns whether the keys changed.
+    pub fn learn_contact(&mut self, profile: &SignedProfile) -> Result<bool, StorageError> {
+        let changed = self.keyring.learn(profile).map_err(StorageError::Backend)?;
+        if changed {
+            self.storage.set_json("snartnet_keyring", &self.keyring)?;
+            #[cfg(not(target_arch = "wasm32"))]
+            self.write_records(|records| {
+                let fingerprint = &profile.profile.fingerprint;
+                match self.keyring.get(fingerprint) {
+                    Some(keys) => records.put_contact(&ContactRecord::from_keys(fingerprint, keys)?),
+                    None => Ok(()),
+                }
+            })?;
+        }
+        self.record_suggested_name(profile)?;
+        Ok(changed)
+    }
+
+    pub fn keyring(&self) -> &KeyRing {
+        &self.keyring
+    }
+
+    /// The user's private circles of contacts.
+    pub fn circles(&self) -> &CircleBook {
+        &self.circles
+    }
+
+    /// Add `fingerprint` to a circle, creating it if needed, and persist.
+    pub fn add_to_circle(&mut self, circle: &str, fingerprint: &str) -> Result<(), StorageError> {
+        self.circles
+            .add_member(circle, fingerprint)
+            .map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_circles", &self.circles)
+    }
+
+    pub fn remove_from_circle(&mut self, circle: &str, fingerprint: &str) -> Result<(), StorageError> {
+        self.circles.remove_member(circle, fingerprint);
+        self.storage.set_json("snartnet_circles", &self.circles)
+    }
+
+    pub fn remove_circle(&mut self, circle: &str) -> Result<(), StorageError> {
+        self.circles.remove(circle);
+        self.storage.set_json("snartnet_circles", &self.circles)
+    }
+
+    /// The merged timeline of the user's own and contacts' posts.
+    pub fn feed(&self) -> &Feed {
+        &self.feed
+    }
+
+    /// Merge a contact's published posts and revisions into the feed and
+    /// persist it. Returns the ids of newly added posts.
+    pub fn ingest_posts(
+        &mut self,
+        author_public_key: &str,
+        posts: Vec<SignedPost>,
+        revisions: PostRevisions,
+    ) -> Result<Vec<String>, StorageError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let deleted: Vec<String> = revisions.deletes.iter().map(|d| d.delete.post_id.clone()).collect();
+        let added = self
+            .feed
+            .ingest(author_public_key, posts, revisions)
+            .map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_feed", &self.feed)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.record_feed_merge(author_public_key, &added, &deleted)?;
+        Ok(added)
+    }
+
+    /// Add the user's own posts and revisions to the feed, already read.
+    pub fn record_own_posts(
+        &mut self,
+        posts: Vec<SignedPost>,
+        revisions: PostRevisions,
+    ) -> Result<(), StorageError> {
+        let keypair = self
+            .keypair
+            .as_ref()
+            .ok_or_else(|| StorageError::Backend("no keypair".into()))?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let deleted: Vec<String> = revisions.deletes.iter().map(|d| d.delete.post_id.clone()).collect();
+        #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+        let added = self
+            .feed
+            .ingest_own(keypair, posts, revisions)
+            .map_err(StorageError::Backend)?;
+        self.storage.set_json("snartnet_feed", &self.feed)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.record_feed_merge(&keypair.public_key, &added, &deleted)?;
+        Ok(())
+    }
+
+    /// A page of the feed, with restricted posts decrypted for the user and
+    /// flagged posts collapsed according to their preferences.
+    pub fn feed_page(&self, query: &FeedQuery) -> Result<FeedPage, StorageError> {
+        let mut page = self
+            .feed
+            .page(query, self.keypair.as_ref())
+            .map_err(StorageError::Backend)?;
+        page.apply_preferences(&self.content_preferences);
+        Ok(page)
+    }
+
+    pub fn content_preferences(&self) -> &ContentPreferences {
+        &self.content_preferences
+    }
+
+    pub fn set_content_preferences(&mut self, preferences: ContentPreferences) -> Result<(), StorageError> {
+        self.content_preferences = preferences;
+        self.storage.set_json("snartnet_content_preferences", &self.content_preferences)
+    }
+
+    /// Mark a feed post read or unread. Returns false if it is not in the feed.
+    pub fn mark_post_read(&mut self, author: &str, post_id: &str, read: bool) -> Result<bool, StorageError> {
+        let found = if read {
+            self.feed.mark_read(author, post_id)
+        } else {
+            self.feed.mark_unread(author, post_id)
+        };
+        if found {
+            self.storage.set_json("snartnet_feed", &self.feed)?;
+        }
+        Ok(found)
+    }
+
+    pub fn mark_all_read(&mut self) -> Result<usize, StorageError> {
+        let changed = self.feed.mark_all_read();
+        self.storage.set_json("snartnet_feed", &self.feed)?;
+        Ok(changed)
+    }
+
+    /// Bring `index` in line with the feed and the user's own profile, and
+    /// drop expired documents. Returns the number of documents changed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn reindex_search(&self, index: &SearchIndex) -> Result<usize, StorageError> {
+        let mut changed = index
+            .sync_feed(&self.feed, self.keypair.as_ref())
+            .map_err(StorageError::Backend)?;
+        if let Some(profile) = &self.current_profile {
+            changed += usize::from(index.index_profile(profile).map_err(StorageError::Backend)?);
+        }
+        changed += index
+            .purge_expired(chrono::Utc::now())
+            .map_err(StorageError::Backend)?;
+        Ok(changed)
     }
 
     pub fn get_public_key(&self) -> Option<&str> {
@@ -215,20 +1359,24 @@ impl<S: StorageBackend> CoreService<S> {
     }
 }
 
-impl<S: StorageBackend> Default for CoreService<S> {
+impl<S: StorageBackend + Default> Default for CoreService<S> {
     fn default() -> Self {
-        Self::new()
+        Self::new(S::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::group::GroupRole;
+    use crate::inbox_ack::InboxItem;
+    use crate::outbox::OutboxState;
+    use crate::reaction::{ReactionSet, LIKE_EMOJI};
     use crate::storage::MemoryStorage;
 
     #[test]
     fn signature_verifies_after_magnet_uri_annotation() {
-        let mut svc = CoreService::<MemoryStorage>::new();
+        let mut svc = CoreService::new(MemoryStorage::new());
         svc.create_profile("eve", None, None).unwrap();
         let signed = svc.current_profile.as_ref().unwrap();
         assert!(signed.verify().expect("verify failed"));
@@ -236,7 +1384,7 @@ mod tests {
 
     #[test]
     fn create_profile_roundtrip() {
-        let mut svc = CoreService::<MemoryStorage>::new();
+        let mut svc = CoreService::new(MemoryStorage::new());
         let magnet = svc
             .create_profile("alice", Some("Alice A.".into()), None)
             .expect("create_profile failed");
@@ -250,13 +1398,13 @@ mod tests {
 
     #[test]
     fn init_reloads_persisted_profile() {
-        let mut svc = CoreService::<MemoryStorage>::new();
+        let mut svc = CoreService::new(MemoryStorage::new());
         let magnet1 = svc
             .create_profile("bob", None, None)
             .expect("create_profile failed");
 
         // Re-initialise from same in-memory store
-        let mut svc2 = CoreService::<MemoryStorage>::new();
+        let mut svc2 = CoreService::new(svc.into_storage());
         svc2.init().expect("init failed");
         assert!(svc2.has_profile());
 
@@ -269,7 +1417,7 @@ mod tests {
 
     #[test]
     fn update_profile_persists() {
-        let mut svc = CoreService::<MemoryStorage>::new();
+        let mut svc = CoreService::new(MemoryStorage::new());
         svc.create_profile("carol", None, None).unwrap();
         svc.update_profile(Some("Carol C.".into()), Some("bio".into()))
             .unwrap();
@@ -279,7 +1427,7 @@ mod tests {
 
     #[test]
     fn create_post_and_message() {
-        let mut svc = CoreService::<MemoryStorage>::new();
+        let mut svc = CoreService::new(MemoryStorage::new());
         svc.create_profile("dave", None, None).unwrap();
 
         let post = svc
@@ -287,9 +1435,459 @@ mod tests {
             .unwrap();
         assert_eq!(post.post.content, "Hello world");
 
+        assert!(svc.create_message("other-fingerprint", "hi").is_err());
         let msg = svc
-            .create_message("other-fingerprint", "hi")
+            .create_plaintext_message("other-fingerprint", "hi")
             .unwrap();
         assert_eq!(msg.message.content, "hi");
+        assert!(!msg.message.encrypted);
+    }
+
+    #[test]
+    fn every_post_kind_takes_the_same_options() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        assert!(svc.create_ephemeral_post("brb", None, None, Duration::hours(1)).is_err());
+        svc.create_profile("erin", None, None).unwrap();
+
+        let parent = svc.create_post("going out", None, None).unwrap();
+        let reply = svc
+            .create_ephemeral_post("back soon", None, Some(parent.post.id.clone()), Duration::hours(1))
+            .unwrap();
+        assert_eq!(reply.post.reply_to.as_deref(), Some(parent.post.id.as_str()));
+        assert!(reply.post.expires_at.is_some());
+        assert_eq!(reply.post.author_fingerprint, parent.post.author_fingerprint);
+    }
+
+    fn introduce(a: &mut CoreService<MemoryStorage>, b: &mut CoreService<MemoryStorage>) {
+        let a_profile = a.get_signed_profile().unwrap().clone();
+        let b_profile = b.get_signed_profile().unwrap().clone();
+        a.learn_contact(&b_profile).unwrap();
+        b.learn_contact(&a_profile).unwrap();
+    }
+
+    #[test]
+    fn messages_are_encrypted_and_open_for_both_parties() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let bob_fp = bob.get_fingerprint().unwrap().to_string();
+        introduce(&mut alice, &mut bob);
+
+        let sent = alice.create_message(&bob_fp, "secret").unwrap();
+        assert!(sent.message.encrypted);
+        assert!(!sent.message.content.contains("secret"));
+        assert_eq!(bob.open_message(&sent).unwrap(), "secret");
+        assert_eq!(alice.open_message(&sent).unwrap(), "secret");
+
+        let edit = alice.edit_message(&sent, "new secret").unwrap();
+        assert!(edit.message.encrypted);
+        assert_eq!(bob.open_message(&edit).unwrap(), "new secret");
+
+        let mut tampered = sent.clone();
+        tampered.message.created_at += Duration::seconds(1);
+        assert!(bob.open_message(&tampered).is_err());
+        let carol = CoreService::new(MemoryStorage::new());
+        assert!(carol.open_message(&sent).is_err());
+    }
+
+    #[test]
+    fn edit_and_delete_own_post() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("ivy", None, None).unwrap();
+        let post = svc.create_post("typo", None, None).unwrap();
+        let pk = svc.get_public_key().unwrap().to_string();
+
+        let edit = svc.edit_post(&post, "fixed", None).unwrap();
+        assert!(edit.applies_to(&post.post, &pk));
+        let delete = svc.delete_post(&post).unwrap();
+        assert!(delete.applies_to(&post.post, &pk));
+    }
+
+    #[test]
+    fn share_post_embeds_verified_original() {
+        let author = KeyPair::generate().unwrap();
+        let original = SignedPost::create(
+            Post::new(author.fingerprint.clone(), "first".into(), None, None),
+            &author,
+        )
+        .unwrap();
+
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("kim", None, None).unwrap();
+        let pk = svc.get_public_key().unwrap().to_string();
+        let embed = EmbeddedPost::new(original.clone(), author.public_key.clone());
+
+        let repost = svc.share_post(embed.clone(), "").unwrap();
+        assert!(repost.post.is_repost());
+        assert!(repost.verify(&pk).unwrap());
+        let quote = svc.share_post(embed, "so true").unwrap();
+        assert!(quote.post.is_quote());
+
+        let wrong_key = EmbeddedPost::new(original, pk);
+        assert!(svc.share_post(wrong_key, "").is_err());
+    }
+
+    #[test]
+    fn circle_post_readable_by_members() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("dana", None, None).unwrap();
+        let friend = KeyPair::generate().unwrap();
+        let stranger = KeyPair::generate().unwrap();
+        let known = [Recipient::from_keypair(&friend).unwrap()];
+
+        assert!(svc.create_circle_post("hi", None, None, "friends", &known).is_err());
+        svc.add_to_circle("friends", &friend.fingerprint).unwrap();
+        svc.add_to_circle("friends", &stranger.fingerprint).unwrap();
+        let err = svc.create_circle_post("hi", None, None, "friends", &known).unwrap_err();
+        assert!(err.to_string().contains(&stranger.fingerprint));
+        svc.remove_from_circle("friends", &stranger.fingerprint).unwrap();
+
+        let post = svc
+            .create_circle_post("just us", None, None, "Friends", &known)
+            .unwrap();
+        assert!(post.verify(svc.get_public_key().unwrap()).unwrap());
+        assert_eq!(post.post.unseal(&friend).unwrap().unwrap().content, "just us");
+        assert!(post.post.unseal(svc.keypair.as_ref().unwrap()).unwrap().is_some());
+        assert!(post.post.unseal(&stranger).unwrap().is_none());
+        assert!(svc.edit_post(&post, "changed", None).is_err());
+    }
+
+    #[test]
+    fn react_and_unreact() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("joy", None, None).unwrap();
+        let post = svc.create_post("hello", None, None).unwrap();
+        let pk = svc.get_public_key().unwrap().to_string();
+
+        let mut set = ReactionSet::new();
+        let like = svc.react(ReactionTarget::Post, &post.post.id, LIKE_EMOJI).unwrap();
+        assert!(like.verify(&pk).unwrap());
+        set.add(like.clone());
+        assert_eq!(set.summary(ReactionTarget::Post, &post.post.id)[0].count, 1);
+
+        set.add(svc.unreact(&like.reaction).unwrap());
+        assert!(set.summary(ReactionTarget::Post, &post.post.id).is_empty());
+    }
+
+    #[test]
+    fn create_heartbeat_verifies_against_profile() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("hank", None, None).unwrap();
+        let hb = svc.create_heartbeat().unwrap();
+        let profile = &svc.get_signed_profile().unwrap().profile;
+        assert!(hb.verify_for_profile(profile).unwrap());
+    }
+
+    #[test]
+    fn services_with_their_own_storage_do_not_share_state() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut other = CoreService::new(MemoryStorage::new());
+        other.init().unwrap();
+        assert!(!other.has_profile());
+        assert!(alice.storage().get_item("snartnet_current_profile").unwrap().is_some());
+    }
+
+    #[test]
+    fn petnames_persist_and_resolve() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.set_petname("fp-petname-test", Some("Grandma".into())).unwrap();
+
+        let mut svc2 = CoreService::new(svc.into_storage());
+        svc2.init().unwrap();
+        assert_eq!(svc2.resolve_name("fp-petname-test").name, "Grandma");
+    }
+
+    #[test]
+    fn feed_merges_own_and_contact_posts() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("fern", None, None).unwrap();
+        let mine = svc.create_post("my post", None, None).unwrap();
+        svc.record_own_posts(vec![mine.clone()], PostRevisions::new()).unwrap();
+
+        let friend = KeyPair::generate().unwrap();
+        let theirs = SignedPost::create(
+            Post::new(friend.fingerprint.clone(), "their post".into(), None, None),
+            &friend,
+        )
+        .unwrap();
+        let added = svc
+            .ingest_posts(&friend.public_key, vec![theirs.clone()], PostRevisions::new())
+            .unwrap();
+        assert_eq!(added, vec![theirs.post.id.clone()]);
+
+        let unread = svc
+            .feed_page(&FeedQuery { unread_only: true, ..Default::default() })
+            .unwrap();
+        let ids: Vec<_> = unread.items.iter().map(|i| i.post.original.post.id.clone()).collect();
+        assert!(ids.contains(&theirs.post.id) && !ids.contains(&mine.post.id));
+        assert!(svc.mark_post_read(&theirs.post.author_fingerprint, &theirs.post.id, true).unwrap());
+        assert!(svc.feed().get(&theirs.post.author_fingerprint, &theirs.post.id).unwrap().read);
+    }
+
+    #[test]
+    fn attached_records_get_a_row_per_item() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let early = alice.create_post("before the database", None, None).unwrap();
+        alice.record_own_posts(vec![early.clone()], PostRevisions::new()).unwrap();
+        let report = alice.attach_records(RelationalStore::open_in_memory().unwrap()).unwrap().unwrap();
+        assert_eq!((report.profiles, report.posts), (1, 1));
+
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        bob.attach_records(RelationalStore::open_in_memory().unwrap()).unwrap();
+        introduce(&mut alice, &mut bob);
+        let alice_fp = alice.get_fingerprint().unwrap().to_string();
+        let alice_pk = alice.get_public_key().unwrap().to_string();
+        let bob_fp = bob.get_fingerprint().unwrap().to_string();
+        assert!(alice.records().unwrap().profile(&bob_fp).unwrap().is_some());
+        let contact = alice.records().unwrap().contact(&bob_fp).unwrap().unwrap();
+        assert_eq!(contact.public_key.as_deref(), bob.get_public_key());
+
+        let sent = alice.create_message(&bob_fp, "hi").unwrap();
+        bob.receive_message(&sent, &alice_pk).unwrap();
+        assert!(!alice.records().unwrap().message(&bob_fp, &sent.message.id).unwrap().unwrap().incoming);
+        assert!(bob.records().unwrap().message(&alice_fp, &sent.message.id).unwrap().unwrap().incoming);
+        let unsend = alice.unsend_message(&sent).unwrap();
+        bob.receive_message(&unsend, &alice_pk).unwrap();
+        assert!(alice.records().unwrap().message(&bob_fp, &sent.message.id).unwrap().is_none());
+        assert!(bob.records().unwrap().message(&alice_fp, &sent.message.id).unwrap().is_none());
+
+        let later = alice.create_post("after", None, None).unwrap();
+        alice.record_own_posts(vec![later], PostRevisions::new()).unwrap();
+        assert_eq!(alice.records().unwrap().posts_by(&alice_fp, 10).unwrap().len(), 2);
+        let delete = alice.delete_post(&early).unwrap();
+        alice
+            .record_own_posts(Vec::new(), PostRevisions { deletes: vec![delete], ..PostRevisions::new() })
+            .unwrap();
+        assert!(alice.records().unwrap().post(&alice_fp, &early.post.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn poll_votes_are_recorded_and_tallied() {
+        let mut author = CoreService::new(MemoryStorage::new());
+        author.create_profile("pollster", None, None).unwrap();
+        let closes_at = Utc::now() + Duration::hours(1);
+        let poll = author
+            .create_poll("Lunch?", vec!["Soup".into(), "Salad".into()], closes_at, false)
+            .unwrap();
+        assert!(author.create_poll("Lunch?", vec!["Soup".into()], closes_at, false).is_err());
+
+        let mut voter = CoreService::new(MemoryStorage::new());
+        voter.create_profile("hungry", None, None).unwrap();
+        let vote = voter.vote_in_poll(&poll, &[1]).unwrap();
+        assert!(voter.vote_in_poll(&poll, &[0, 1]).is_err());
+
+        assert!(author.receive_poll_vote(&poll, vote.clone()).unwrap());
+        assert!(!author.receive_poll_vote(&poll, vote).unwrap());
+        assert_eq!(author.poll_results(&poll).counts, vec![0, 1]);
+
+        let tally = author.tally_poll(&poll).unwrap();
+        let author_pk = author.get_public_key().unwrap().to_string();
+        assert_eq!(voter.check_poll_tally(&poll, &tally, &author_pk).unwrap(), TallyCheck::Verified);
+        let voter_pk = voter.get_public_key().unwrap().to_string();
+        assert!(voter.check_poll_tally(&poll, &tally, &voter_pk).is_err());
+    }
+
+    #[test]
+    fn receipts_track_delivery_and_respect_preferences() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let alice_fp = alice.get_fingerprint().unwrap().to_string();
+        let bob_fp = bob.get_fingerprint().unwrap().to_string();
+        let bob_pk = bob.get_public_key().unwrap().to_string();
+        let alice_enc = alice.keypair.as_ref().unwrap().enc_public_key.clone();
+        let bob_enc = bob.keypair.as_ref().unwrap().enc_public_key.clone();
+        introduce(&mut alice, &mut bob);
+
+        let sent = alice.create_message(&bob_fp, "ping").unwrap();
+        alice.record_sent_message(&sent).unwrap();
+        assert_eq!(alice.message_status(&sent.message.id), Some(DeliveryStatus::Sent));
+
+        let ids = vec![sent.message.id.clone()];
+        let delivered = bob
+            .acknowledge_messages(&alice_fp, ReceiptKind::Delivered, &ids, alice_enc.as_deref())
+            .unwrap()
+            .unwrap();
+        assert!(bob
+            .acknowledge_messages(&alice_fp, ReceiptKind::Delivered, &ids, alice_enc.as_deref())
+            .unwrap()
+            .is_none());
+        assert_eq!(alice.receive_receipt(&delivered, &bob_pk, bob_enc.as_deref()).unwrap(), 1);
+        assert_eq!(alice.message_status(&sent.message.id), Some(DeliveryStatus::Delivered));
+        let alice_pk = alice.get_public_key().unwrap().to_string();
+        assert!(alice.receive_receipt(&delivered, &alice_pk, bob_enc.as_deref()).is_err());
+
+        bob.set_receipt_preferences(ReceiptPreferences { send_read_receipts: false }).unwrap();
+        assert!(bob
+            .acknowledge_messages(&alice_fp, ReceiptKind::Read, &ids, alice_enc.as_deref())
+            .unwrap()
+            .is_none());
+        bob.set_receipt_preferences(ReceiptPreferences::default()).unwrap();
+        let read = bob
+            .acknowledge_messages(&alice_fp, ReceiptKind::Read, &ids, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice.receive_receipt(&read, &bob_pk, None).unwrap(), 1);
+        assert_eq!(alice.message_status(&sent.message.id), Some(DeliveryStatus::Read));
+    }
+
+    #[test]
+    fn outbox_keeps_messages_until_peers_take_them() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let alice_pk = alice.get_public_key().unwrap().to_string();
+        let bob_fp = bob.get_fingerprint().unwrap().to_string();
+        introduce(&mut alice, &mut bob);
+
+        let sent = alice.create_message(&bob_fp, "anyone there?").unwrap();
+        let id = sent.message.id.clone();
+        assert_eq!(alice.outbox_due().len(), 1);
+        alice.record_outbox_push(&id, Ok(0)).unwrap();
+        assert!(alice.outbox_due().is_empty());
+        alice.outbox_peers_appeared().unwrap();
+        assert_eq!(alice.outbox_due().len(), 1);
+        alice.record_outbox_push(&id, Ok(3)).unwrap();
+        assert_eq!(alice.outbox().state(&id), Some(OutboxState::Pushed { peers: 3 }));
+        assert!(alice.record_outbox_push("missing", Ok(1)).is_err());
+
+        bob.receive_message(&sent, &alice_pk).unwrap();
+        let ack = bob.acknowledge_inbox(&[sent.acked_item()]).unwrap();
+        assert_eq!(alice.apply_inbox_acks_to_outbox(&bob_fp, &[ack]).unwrap(), 1);
+        assert_eq!(alice.outbox().state(&id), Some(OutboxState::Acknowledged));
+    }
+
+    #[test]
+    fn conversation_is_stamped_ordered_and_gaps_found() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let alice_fp = alice.get_fingerprint().unwrap().to_string();
+        let bob_fp = bob.get_fingerprint().unwrap().to_string();
+        let alice_pk = alice.get_public_key().unwrap().to_string();
+        introduce(&mut alice, &mut bob);
+
+        let first = alice.create_message(&bob_fp, "one").unwrap();
+        let second = alice.create_message(&bob_fp, "two").unwrap();
+        assert_eq!((first.message.lamport, second.message.lamport), (1, 2));
+        assert_eq!(second.message.previous_ids, vec![first.message.id.clone()]);
+
+        bob.receive_message(&second, &alice_pk).unwrap();
+        let reply = bob.create_message(&alice_fp, "three").unwrap();
+        assert_eq!(reply.message.lamport, 3);
+        assert_eq!(reply.message.previous_ids, vec![second.message.id.clone()]);
+
+        let mut thread = vec![reply.clone(), second.clone()];
+        assert_eq!(bob.order_thread(&mut thread), vec![first.message.id.clone()]);
+        assert_eq!(thread[0].message.id, second.message.id);
+        bob.receive_message(&first, &alice_pk).unwrap();
+        assert!(bob.order_thread(&mut thread).is_empty());
+    }
+
+    #[test]
+    fn receive_message_accepts_once_and_names_rejections() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let alice_fp = alice.get_fingerprint().unwrap().to_string();
+        let alice_pk = alice.get_public_key().unwrap().to_string();
+        let bob_pk = bob.get_public_key().unwrap().to_string();
+        introduce(&mut alice, &mut bob);
+
+        let hello = bob.create_message(&alice_fp, "hello").unwrap();
+        // Our own messages count as seen, so an echo is not taken as new.
+        assert!(bob.receive_message(&hello, &bob_pk).is_err());
+        alice.receive_message(&hello, &bob_pk).unwrap();
+        let replay = alice.receive_message(&hello, &bob_pk).unwrap_err();
+        assert!(replay.to_string().contains("already received"), "{replay}");
+        let forged = alice.receive_message(&hello, &alice_pk).unwrap_err();
+        assert!(forged.to_string().contains("sender fingerprint"), "{forged}");
+    }
+
+    #[test]
+    fn message_controls_and_negotiated_timer() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let alice_fp = alice.get_fingerprint().unwrap().to_string();
+        let bob_fp = bob.get_fingerprint().unwrap().to_string();
+        let alice_pk = alice.get_public_key().unwrap().to_string();
+        introduce(&mut alice, &mut bob);
+
+        let sent = alice.create_message(&bob_fp, "see you at 5").unwrap();
+        assert!(sent.message.expires_at.is_none());
+        let edit = alice.edit_message(&sent, "see you at 6").unwrap();
+        assert_eq!(edit.message.control_target(), Some(sent.message.id.as_str()));
+        assert!(bob.edit_message(&sent, "nope").is_err());
+        let unsend = alice.unsend_message(&sent).unwrap();
+        assert!(unsend.message.is_control());
+
+        let timer = bob.set_message_timer(&alice_fp, Some(3_600)).unwrap();
+        assert_eq!(bob.message_timer(&alice_fp), Some(Duration::hours(1)));
+        let bob_pk = bob.get_public_key().unwrap().to_string();
+        assert!(alice.receive_message_timer(&timer, &alice_pk).is_err());
+        assert!(alice.receive_message_timer(&timer, &bob_pk).unwrap());
+        let later = alice.create_message(&bob_fp, "gone soon").unwrap();
+        assert_eq!(later.message.expires_at, Some(later.message.created_at + Duration::hours(1)));
+    }
+
+    #[test]
+    fn group_messages_fan_out_and_rekey() {
+        let mut alice = CoreService::new(MemoryStorage::new());
+        alice.create_profile("alice", None, None).unwrap();
+        let mut bob = CoreService::new(MemoryStorage::new());
+        bob.create_profile("bob", None, None).unwrap();
+        let bob_member = GroupMember::from_keypair(bob.keypair.as_ref().unwrap(), GroupRole::Member).unwrap();
+        let bob_fp = bob_member.fingerprint.clone();
+
+        let created = alice.create_group("Hikers").unwrap();
+        let group_id = created.group.id.clone();
+        let added = alice.add_group_member(&group_id, bob_member).unwrap();
+        assert!(bob.receive_group_update(created).unwrap());
+        assert!(bob.receive_group_update(added).unwrap());
+        assert!(bob.remove_group_member(&group_id, alice.get_fingerprint().unwrap()).is_err());
+
+        let first = alice.create_group_message(&group_id, "Saturday?").unwrap();
+        assert_eq!(first.recipients, vec![bob_fp.clone()]);
+        assert!(bob.open_group_message(&first.message).is_err());
+        assert!(bob.receive_sender_key(first.distribution.as_ref().unwrap()).unwrap());
+        assert_eq!(bob.open_group_message(&first.message).unwrap(), "Saturday?");
+        let second = alice.create_group_message(&group_id, "9am").unwrap();
+        assert!(second.distribution.is_none());
+
+        let removed = alice.remove_group_member(&group_id, &bob_fp).unwrap();
+        let third = alice.create_group_message(&group_id, "Just me now").unwrap();
+        assert!(third.distribution.is_none());
+        assert!(third.recipients.is_empty());
+        assert!(bob.receive_group_update(removed).unwrap());
+        assert!(bob.groups().joined(&bob_fp).is_empty());
+        assert!(bob.create_group_message(&group_id, "hello?").is_err());
+    }
+
+    #[test]
+    fn reindex_search_covers_feed_and_profile() {
+        let mut svc = CoreService::new(MemoryStorage::new());
+        svc.create_profile("sage", None, Some("Herbalist".into())).unwrap();
+        let post = svc.create_post("Drying rosemary", None, None).unwrap();
+        svc.record_own_posts(vec![post], PostRevisions::new()).unwrap();
+
+        let index = SearchIndex::open_in_memory().unwrap();
+        assert!(svc.reindex_search(&index).unwrap() >= 2);
+        let hits = |text: &str| {
+            index
+                .search(&crate::search::SearchQuery { text: text.into(), ..Default::default() })
+                .unwrap()
+        };
+        assert_eq!(hits("rosemary").len(), 1);
+        assert_eq!(hits("herbal").len(), 1);
     }
 }
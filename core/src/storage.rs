@@ -1,7 +1,7 @@
 use serde::{Serialize, de::DeserializeOwned};
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::{Mutex, OnceLock};
+use std::sync::Mutex;
 
 #[derive(Debug, Clone)]
 pub enum StorageError {
@@ -22,19 +22,27 @@ impl fmt::Display for StorageError {
 
 impl std::error::Error for StorageError {}
 
-pub trait StorageBackend {
-    fn set_item(key: &str, value: &str) -> Result<(), StorageError>;
-    fn get_item(key: &str) -> Result<Option<String>, StorageError>;
-    fn remove_item(key: &str) -> Result<(), StorageError>;
-
-    fn set_json<T: Serialize>(key: &str, value: &T) -> Result<(), StorageError> {
+/// A key-value store. Each value is its own instance, so a process can keep
+/// several databases open side by side, e.g. one per identity.
+pub trait StorageBackend: Send + Sync {
+    fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError>;
+    fn get_item(&self, key: &str) -> Result<Option<String>, StorageError>;
+    fn remove_item(&self, key: &str) -> Result<(), StorageError>;
+
+    fn set_json<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StorageError>
+    where
+        Self: Sized,
+    {
         let json = serde_json::to_string(value)
             .map_err(|e| StorageError::Serialization(e.to_string()))?;
-        Self::set_item(key, &json)
+        self.set_item(key, &json)
     }
 
-    fn get_json<T: DeserializeOwned>(key: &str) -> Result<Option<T>, StorageError> {
-        match Self::get_item(key)? {
+    fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StorageError>
+    where
+        Self: Sized,
+    {
+        match self.get_item(key)? {
             Some(json) => {
                 let value = serde_json::from_str(&json)
                     .map_err(|e| StorageError::Serialization(e.to_string()))?;
@@ -62,24 +70,42 @@ pub mod browser {
                 .map_err(|e| StorageError::Backend(format!("localStorage access failed: {e:?}")))?
                 .ok_or_else(|| StorageError::Unavailable("No localStorage available".to_string()))
         }
+
+        /// Every key in localStorage, e.g. to convert them all with
+        /// `EncryptedStorage::encrypt_existing`.
+        pub fn keys(&self) -> Result<Vec<String>, StorageError> {
+            let storage = Self::get_storage()?;
+            let len = storage
+                .length()
+                .map_err(|e| StorageError::Backend(format!("length failed: {e:?}")))?;
+            let mut keys = Vec::new();
+            for index in 0..len {
+                let key = storage
+                    .key(index)
+                    .map_err(|e| StorageError::Backend(format!("key failed: {e:?}")))?;
+                keys.extend(key);
+            }
+            keys.sort();
+            Ok(keys)
+        }
     }
 
     impl StorageBackend for BrowserStorage {
-        fn set_item(key: &str, value: &str) -> Result<(), StorageError> {
+        fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
             let storage = Self::get_storage()?;
             storage
                 .set_item(key, value)
                 .map_err(|e| StorageError::Backend(format!("set_item failed: {e:?}")))
         }
 
-        fn get_item(key: &str) -> Result<Option<String>, StorageError> {
+        fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
             let storage = Self::get_storage()?;
             storage
                 .get_item(key)
                 .map_err(|e| StorageError::Backend(format!("get_item failed: {e:?}")))
         }
 
-        fn remove_item(key: &str) -> Result<(), StorageError> {
+        fn remove_item(&self, key: &str) -> Result<(), StorageError> {
             let storage = Self::get_storage()?;
             storage
                 .remove_item(key)
@@ -91,23 +117,23 @@ pub mod browser {
 
     impl LocalStorage {
         pub fn set_item(key: &str, value: &str) -> Result<(), JsValue> {
-            BrowserStorage::set_item(key, value).map_err(|e| JsValue::from_str(&e.to_string()))
+            BrowserStorage.set_item(key, value).map_err(|e| JsValue::from_str(&e.to_string()))
         }
 
         pub fn get_item(key: &str) -> Result<Option<String>, JsValue> {
-            BrowserStorage::get_item(key).map_err(|e| JsValue::from_str(&e.to_string()))
+            BrowserStorage.get_item(key).map_err(|e| JsValue::from_str(&e.to_string()))
         }
 
         pub fn remove_item(key: &str) -> Result<(), JsValue> {
-            BrowserStorage::remove_item(key).map_err(|e| JsValue::from_str(&e.to_string()))
+            BrowserStorage.remove_item(key).map_err(|e| JsValue::from_str(&e.to_string()))
         }
 
         pub fn set_json<T: serde::Serialize>(key: &str, value: &T) -> Result<(), JsValue> {
-            BrowserStorage::set_json(key, value).map_err(|e| JsValue::from_str(&e.to_string()))
+            BrowserStorage.set_json(key, value).map_err(|e| JsValue::from_str(&e.to_string()))
         }
 
         pub fn get_json<T: serde::de::DeserializeOwned>(key: &str) -> Result<Option<T>, JsValue> {
-            BrowserStorage::get_json(key).map_err(|e| JsValue::from_str(&e.to_string()))
+            BrowserStorage.get_json(key).map_err(|e| JsValue::from_str(&e.to_string()))
         }
     }
 
@@ -132,31 +158,41 @@ pub mod browser {
 
 #[cfg(not(target_arch = "wasm32"))]
 mod native {
-    use super::{HashMap, Mutex, OnceLock, StorageBackend, StorageError};
+    use super::{HashMap, Mutex, StorageBackend, StorageError};
     use std::path::{Path, PathBuf};
 
     // ---- In-memory backend (used by tests and as a fallback) ----
 
-    use std::cell::RefCell;
-
-    thread_local! {
-        static STORE: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    /// Keeps everything in memory; each instance starts empty.
+    #[derive(Default)]
+    pub struct NativeMemoryStorage {
+        items: Mutex<HashMap<String, String>>,
     }
 
-    pub struct NativeMemoryStorage;
+    impl NativeMemoryStorage {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        fn items(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, String>>, StorageError> {
+            self.items
+                .lock()
+                .map_err(|e| StorageError::Backend(format!("memory store lock poisoned: {e}")))
+        }
+    }
 
     impl StorageBackend for NativeMemoryStorage {
-        fn set_item(key: &str, value: &str) -> Result<(), StorageError> {
-            STORE.with(|s| s.borrow_mut().insert(key.to_string(), value.to_string()));
+        fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
+            self.items()?.insert(key.to_string(), value.to_string());
             Ok(())
         }
 
-        fn get_item(key: &str) -> Result<Option<String>, StorageError> {
-            Ok(STORE.with(|s| s.borrow().get(key).cloned()))
+        fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
+            Ok(self.items()?.get(key).cloned())
         }
 
-        fn remove_item(key: &str) -> Result<(), StorageError> {
-            STORE.with(|s| s.borrow_mut().remove(key));
+        fn remove_item(&self, key: &str) -> Result<(), StorageError> {
+            self.items()?.remove(key);
             Ok(())
         }
     }
@@ -188,6 +224,31 @@ mod native {
             Self::new(Self::default_dir()?)
         }
 
+        /// The storage directory, e.g. to keep other local databases beside it.
+        pub fn dir(&self) -> &Path {
+            &self.dir
+        }
+
+        /// Every key with a file in the directory, e.g. to convert them all
+        /// with `EncryptedStorage::encrypt_existing`.
+        pub fn keys(&self) -> Result<Vec<String>, StorageError> {
+            let entries = std::fs::read_dir(&self.dir)
+                .map_err(|e| StorageError::Backend(format!("failed to list storage dir: {e}")))?;
+            let mut keys = Vec::new();
+            for entry in entries {
+                let entry = entry.map_err(|e| StorageError::Backend(format!("failed to list storage dir: {e}")))?;
+                let name = entry.file_name();
+                let Some(safe_key) = name.to_str().and_then(|n| n.strip_suffix(".json")) else {
+                    continue;
+                };
+                if entry.path().is_file() {
+                    keys.push(decode_key(safe_key)?);
+                }
+            }
+            keys.sort();
+            Ok(keys)
+        }
+
         fn key_path(&self, key: &str) -> PathBuf {
             // Percent-encode characters that are unsafe in filenames.
             // Using percent-encoding (e.g. '/' → "%2F") rather than replacing with '_'
@@ -205,13 +266,34 @@ mod native {
             }
             self.dir.join(format!("{safe_key}.json"))
         }
+    }
+
+    /// Undo the percent-encoding of `FileStorage::key_path`.
+    fn decode_key(safe_key: &str) -> Result<String, StorageError> {
+        let invalid = || StorageError::Backend(format!("invalid storage file name: {safe_key}.json"));
+        let bytes = safe_key.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                let hex = safe_key.get(i + 1..i + 3).ok_or_else(invalid)?;
+                out.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                i += 3;
+            } else {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(out).map_err(|_| invalid())
+    }
 
-        pub fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
+    impl StorageBackend for FileStorage {
+        fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
             std::fs::write(self.key_path(key), value)
                 .map_err(|e| StorageError::Backend(format!("write failed for {key}: {e}")))
         }
 
-        pub fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
+        fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
             let path = self.key_path(key);
             if !path.exists() {
                 return Ok(None);
@@ -221,7 +303,7 @@ mod native {
             Ok(Some(value))
         }
 
-        pub fn remove_item(&self, key: &str) -> Result<(), StorageError> {
+        fn remove_item(&self, key: &str) -> Result<(), StorageError> {
             let path = self.key_path(key);
             if path.exists() {
                 std::fs::remove_file(&path)
@@ -230,22 +312,12 @@ mod native {
             Ok(())
         }
 
-        pub fn set_json<T: serde::Serialize>(&self, key: &str, value: &T) -> Result<(), StorageError> {
+        /// Pretty-printed, so the files stay readable by hand.
+        fn set_json<T: serde::Serialize>(&self, key: &str, value: &T) -> Result<(), StorageError> {
             let json = serde_json::to_string_pretty(value)
                 .map_err(|e| StorageError::Serialization(e.to_string()))?;
             self.set_item(key, &json)
         }
-
-        pub fn get_json<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StorageError> {
-            match self.get_item(key)? {
-                Some(json) => {
-                    let value = serde_json::from_str(&json)
-                        .map_err(|e| StorageError::Serialization(e.to_string()))?;
-                    Ok(Some(value))
-                }
-                None => Ok(None),
-            }
-        }
     }
 
     pub type LocalStorage = NativeMemoryStorage;
@@ -254,61 +326,61 @@ mod native {
 
     use rusqlite::{Connection, params};
 
-    /// Returns the configured database path (default: `"snartnet.db"`).
-    /// Call `SqliteStorage::open` before the first storage operation to override it.
-    fn configured_path() -> &'static Mutex<String> {
-        static PATH: OnceLock<Mutex<String>> = OnceLock::new();
-        PATH.get_or_init(|| Mutex::new("snartnet.db".to_string()))
+    /// Key-value storage in one SQLite database file.
+    pub struct SqliteStorage {
+        conn: Mutex<Connection>,
     }
 
-    fn db() -> &'static Mutex<Connection> {
-        static DB: OnceLock<Mutex<Connection>> = OnceLock::new();
-        DB.get_or_init(|| {
-            let path = configured_path()
-                .lock()
-                .expect("path lock poisoned")
-                .clone();
-            let conn = Connection::open(&path)
-                .expect("failed to open SQLite database");
+    impl SqliteStorage {
+        /// Open (or create) the database at `path` and initialise the schema.
+        pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+            let conn = Connection::open(path.as_ref())
+                .map_err(|e| StorageError::Unavailable(format!("failed to open SQLite database: {e}")))?;
+            Self::from_connection(conn)
+        }
+
+        /// A private database that lives as long as the value.
+        pub fn open_in_memory() -> Result<Self, StorageError> {
+            let conn = Connection::open_in_memory()
+                .map_err(|e| StorageError::Unavailable(format!("failed to open SQLite database: {e}")))?;
+            Self::from_connection(conn)
+        }
+
+        fn from_connection(conn: Connection) -> Result<Self, StorageError> {
             conn.execute_batch(
                 "CREATE TABLE IF NOT EXISTS kv_store (
                      key   TEXT PRIMARY KEY,
                      value TEXT NOT NULL
                  );",
             )
-            .expect("failed to create kv_store table");
-            Mutex::new(conn)
-        })
-    }
+            .map_err(|e| StorageError::Backend(format!("failed to create kv_store table: {e}")))?;
+            Ok(Self { conn: Mutex::new(conn) })
+        }
 
-    pub struct SqliteStorage;
+        fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, StorageError> {
+            self.conn
+                .lock()
+                .map_err(|e| StorageError::Backend(format!("db lock poisoned: {e}")))
+        }
 
-    impl SqliteStorage {
-        /// Configure the database path and initialise the schema.
-        ///
-        /// **Must be called before any `StorageBackend` methods** – once the
-        /// first storage operation runs, the path is locked in and subsequent
-        /// `open` calls have no effect.
-        pub fn open(path: &str) -> Result<(), StorageError> {
-            // Store the desired path so that `db()` picks it up on first use.
-            {
-                let mut guard = configured_path()
-                    .lock()
-                    .map_err(|e| StorageError::Backend(format!("path lock poisoned: {e}")))?;
-                *guard = path.to_string();
-            }
-            // Force initialisation now, using the path we just set.
-            let _ = db();
-            Ok(())
+        /// Every stored key, e.g. to convert them all with
+        /// `EncryptedStorage::encrypt_existing`.
+        pub fn keys(&self) -> Result<Vec<String>, StorageError> {
+            let guard = self.conn()?;
+            let mut stmt = guard
+                .prepare("SELECT key FROM kv_store ORDER BY key")
+                .map_err(|e| StorageError::Backend(format!("SQLite prepare failed: {e}")))?;
+            let rows = stmt
+                .query_map([], |r| r.get::<_, String>(0))
+                .map_err(|e| StorageError::Backend(format!("SQLite query failed: {e}")))?;
+            rows.collect::<Result<Vec<_>, _>>()
+                .map_err(|e| StorageError::Backend(format!("SQLite query failed: {e}")))
         }
     }
 
     impl StorageBackend for SqliteStorage {
-        fn set_item(key: &str, value: &str) -> Result<(), StorageError> {
-            let guard = db()
-                .lock()
-                .map_err(|e| StorageError::Backend(format!("db lock poisoned: {e}")))?;
-            guard
+        fn set_item(&self, key: &str, value: &str) -> Result<(), StorageError> {
+            self.conn()?
                 .execute(
                     "INSERT INTO kv_store (key, value) VALUES (?1, ?2)
                      ON CONFLICT(key) DO UPDATE SET value = excluded.value",
@@ -318,10 +390,8 @@ mod native {
             Ok(())
         }
 
-        fn get_item(key: &str) -> Result<Option<String>, StorageError> {
-            let guard = db()
-                .lock()
-                .map_err(|e| StorageError::Backend(format!("db lock poisoned: {e}")))?;
+        fn get_item(&self, key: &str) -> Result<Option<String>, StorageError> {
+            let guard = self.conn()?;
             let mut stmt = guard
                 .prepare("SELECT value FROM kv_store WHERE key = ?1")
                 .map_err(|e| StorageError::Backend(format!("SQLite prepare failed: {e}")))?;
@@ -342,11 +412,8 @@ mod native {
             }
         }
 
-        fn remove_item(key: &str) -> Result<(), StorageError> {
-            let guard = db()
-                .lock()
-                .map_err(|e| StorageError::Backend(format!("db lock poisoned: {e}")))?;
-            guard
+        fn remove_item(&self, key: &str) -> Result<(), StorageError> {
+            self.conn()?
                 .execute("DELETE FROM kv_store WHERE key = ?1", params![key])
                 .map_err(|e| StorageError::Backend(format!("SQLite remove_item failed: {e}")))?;
             Ok(())
@@ -370,22 +437,47 @@ mod tests {
 
     #[test]
     fn native_memory_storage_roundtrip() {
-        // NativeMemoryStorage uses a global static — use unique keys per test.
-        LocalStorage::set_item("test_mem_key", "hello").expect("set failed");
-        let val = LocalStorage::get_item("test_mem_key").expect("get failed");
+        let store = LocalStorage::new();
+        store.set_item("test_mem_key", "hello").expect("set failed");
+        let val = store.get_item("test_mem_key").expect("get failed");
         assert_eq!(val.as_deref(), Some("hello"));
-        LocalStorage::remove_item("test_mem_key").expect("remove failed");
-        let val = LocalStorage::get_item("test_mem_key").expect("get after remove failed");
+        store.remove_item("test_mem_key").expect("remove failed");
+        let val = store.get_item("test_mem_key").expect("get after remove failed");
         assert!(val.is_none());
     }
 
     #[test]
     fn native_memory_storage_json_roundtrip() {
+        let store = LocalStorage::new();
         let map: HashMap<String, u32> = [("a".to_string(), 1u32)].into_iter().collect();
-        LocalStorage::set_json("test_mem_json", &map).expect("set_json failed");
-        let loaded: Option<HashMap<String, u32>> = LocalStorage::get_json("test_mem_json").expect("get_json failed");
+        store.set_json("test_mem_json", &map).expect("set_json failed");
+        let loaded: Option<HashMap<String, u32>> = store.get_json("test_mem_json").expect("get_json failed");
         assert_eq!(loaded.as_ref().and_then(|m| m.get("a")).copied(), Some(1));
-        LocalStorage::remove_item("test_mem_json").ok();
+    }
+
+    #[test]
+    fn memory_storage_is_per_instance_and_shared_across_threads() {
+        let first = MemoryStorage::new();
+        let second = MemoryStorage::new();
+        std::thread::scope(|scope| {
+            scope.spawn(|| first.set_item("k", "from thread").unwrap());
+        });
+        assert_eq!(first.get_item("k").unwrap().as_deref(), Some("from thread"));
+        assert!(second.get_item("k").unwrap().is_none());
+    }
+
+    #[test]
+    fn sqlite_storages_at_different_paths_are_independent() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let a = SqliteStorage::open(dir.path().join("a.db")).expect("open a failed");
+        let b = SqliteStorage::open(dir.path().join("b.db")).expect("open b failed");
+        a.set_item("who", "a").unwrap();
+        b.set_item("who", "b").unwrap();
+        assert_eq!(a.get_item("who").unwrap().as_deref(), Some("a"));
+        assert_eq!(b.get_item("who").unwrap().as_deref(), Some("b"));
+        drop(a);
+        let reopened = SqliteStorage::open(dir.path().join("a.db")).expect("reopen failed");
+        assert_eq!(reopened.get_item("who").unwrap().as_deref(), Some("a"));
     }
 
     #[test]
@@ -420,4 +512,22 @@ mod tests {
         assert_eq!(v1.as_deref(), Some("slash"));
         assert_eq!(v2.as_deref(), Some("underscore"));
     }
+
+    #[test]
+    fn file_storage_lists_keys() {
+        let dir = tempfile::tempdir().expect("tempdir failed");
+        let fs = FileStorage::new(dir.path()).expect("FileStorage::new failed");
+        fs.set_item("foo_bar", "underscore").expect("set failed");
+        fs.set_item("foo/bar", "slash").expect("set failed");
+        std::fs::write(dir.path().join("notes.txt"), "not a key").unwrap();
+        assert_eq!(fs.keys().unwrap(), vec!["foo/bar", "foo_bar"]);
+    }
+
+    #[test]
+    fn sqlite_storage_lists_its_keys() {
+        let store = SqliteStorage::open_in_memory().unwrap();
+        store.set_item("b", "2").unwrap();
+        store.set_item("a", "1").unwrap();
+        assert_eq!(store.keys().unwrap(), vec!["a", "b"]);
+    }
 }
@@ -0,0 +1,460 @@
+use crate::post::SignedPost;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// Deepest nesting a built thread has. Replies further down are shown as
+/// siblings at this depth, so a long reply chain cannot make walking or
+/// dropping a tree overflow the stack.
+pub const MAX_THREAD_DEPTH: usize = 64;
+
+/// One position in a reply tree.
+///
+/// `post` is `None` for a placeholder: a parent that some reply points at via
+/// `reply_to` but that has not been received (yet), or that cannot be told
+/// apart from another author's post with the same ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadNode {
+    pub post_id: String,
+    pub post: Option<SignedPost>,
+    /// Set on a placeholder whose ID belongs to posts by several authors,
+    /// none of them by the replying author, so the parent is unknown.
+    #[serde(default)]
+    pub ambiguous: bool,
+    /// Direct replies, oldest first.
+    pub replies: Vec<ThreadNode>,
+}
+
+impl ThreadNode {
+    pub fn is_placeholder(&self) -> bool {
+        self.post.is_none()
+    }
+
+    /// Number of known posts in this subtree, excluding placeholders.
+    pub fn post_count(&self) -> usize {
+        self.flatten().iter().filter(|(_, n)| n.post.is_some()).count()
+    }
+
+    /// Newest `created_at` in this subtree.
+    pub fn last_activity(&self) -> Option<DateTime<Utc>> {
+        self.flatten()
+            .iter()
+            .filter_map(|(_, n)| n.post.as_ref().map(|p| p.post.created_at))
+            .max()
+    }
+
+    /// Depth-first walk in display order, paired with each node's depth
+    /// (the root is depth 0). Suitable for rendering an indented conversation.
+    pub fn flatten(&self) -> Vec<(usize, &ThreadNode)> {
+        let mut out = Vec::new();
+        let mut stack = vec![(0, self)];
+        while let Some((depth, node)) = stack.pop() {
+            out.push((depth, node));
+            stack.extend(node.replies.iter().rev().map(|r| (depth + 1, r)));
+        }
+        out
+    }
+}
+
+/// A position while building: a known post by author and ID, or a
+/// placeholder for a parent known only by the ID in `reply_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Slot<'a> {
+    Post(&'a str, &'a str),
+    Placeholder(&'a str),
+}
+
+/// Assembles reply trees from posts by any number of authors.
+///
+/// Posts may arrive in any order and from different peers; the builder only
+/// needs `Post.reply_to` links. Posts are keyed by author and ID, and since
+/// `reply_to` names an ID alone, it resolves to the replying author's own
+/// post with that ID, else to the only post with that ID. When several
+/// authors' posts share the ID the parent is an ambiguous placeholder.
+///
+/// Cycles (which honest clients cannot produce, but a malicious one can) are
+/// broken at the oldest post in the cycle, which then becomes a root.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadBuilder {
+    posts: HashMap<(String, String), SignedPost>,
+    /// Authors of the posts with each ID.
+    authors: HashMap<String, Vec<String>>,
+}
+
+impl ThreadBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a post. Duplicates (same author and ID) are ignored.
+    pub fn add(&mut self, post: SignedPost) {
+        let key = (post.post.author_fingerprint.clone(), post.post.id.clone());
+        if let Entry::Vacant(entry) = self.posts.entry(key) {
+            self.authors
+                .entry(post.post.id.clone())
+                .or_default()
+                .push(post.post.author_fingerprint.clone());
+            entry.insert(post);
+        }
+    }
+
+    pub fn extend<I: IntoIterator<Item = SignedPost>>(&mut self, posts: I) {
+        for post in posts {
+            self.add(post);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.posts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty()
+    }
+
+    pub fn get(&self, author_fingerprint: &str, post_id: &str) -> Option<&SignedPost> {
+        self.posts
+            .get(&(author_fingerprint.to_string(), post_id.to_string()))
+    }
+
+    fn post_at(&self, slot: Slot) -> Option<&SignedPost> {
+        match slot {
+            Slot::Post(author, id) => self.get(author, id),
+            Slot::Placeholder(_) => None,
+        }
+    }
+
+    fn slots(&self) -> impl Iterator<Item = Slot<'_>> {
+        self.posts.keys().map(|(author, id)| Slot::Post(author, id))
+    }
+
+    /// Whether posts by more than one author use `post_id`.
+    fn is_ambiguous(&self, post_id: &str) -> bool {
+        self.authors.get(post_id).is_some_and(|a| a.len() > 1)
+    }
+
+    /// Where `reply_to` of the post at `slot` points, ignoring cycles.
+    fn parent<'a>(&'a self, slot: Slot<'a>) -> Option<Slot<'a>> {
+        let Slot::Post(author, id) = slot else {
+            return None;
+        };
+        let parent = self.get(author, id)?.post.reply_to.as_deref()?;
+        if parent == id {
+            return None;
+        }
+        if self.get(author, parent).is_some() {
+            return Some(Slot::Post(author, parent));
+        }
+        match self.authors.get(parent).map(Vec::as_slice) {
+            Some([only]) => Some(Slot::Post(only, parent)),
+            _ => Some(Slot::Placeholder(parent)),
+        }
+    }
+
+    /// The parent used for tree building: `parent`, unless `slot` is in
+    /// `breaks`, the posts at which `reply_to` cycles are cut.
+    fn effective_parent<'a>(&'a self, slot: Slot<'a>, breaks: &HashSet<Slot>) -> Option<Slot<'a>> {
+        if breaks.contains(&slot) {
+            return None;
+        }
+        self.parent(slot)
+    }
+
+    /// The break point of every `reply_to` cycle. Each post has one parent,
+    /// so following the links from every post once finds them all.
+    fn cycle_breaks(&self) -> HashSet<Slot<'_>> {
+        let mut breaks = HashSet::new();
+        let mut done: HashSet<Slot> = HashSet::new();
+        for start in self.slots() {
+            let mut path: Vec<Slot> = Vec::new();
+            let mut on_path: HashMap<Slot, usize> = HashMap::new();
+            let mut current = Some(start);
+            while let Some(slot @ Slot::Post(..)) = current {
+                if done.contains(&slot) {
+                    break;
+                }
+                if let Some(&at) = on_path.get(&slot) {
+                    breaks.insert(self.cycle_break(&path[at..]));
+                    break;
+                }
+                on_path.insert(slot, path.len());
+                path.push(slot);
+                current = self.parent(slot);
+            }
+            done.extend(path);
+        }
+        breaks
+    }
+
+    /// Oldest member of a cycle, ties broken by ID and author so every peer
+    /// agrees.
+    fn cycle_break<'a>(&self, cycle: &[Slot<'a>]) -> Slot<'a> {
+        cycle
+            .iter()
+            .copied()
+            .min_by_key(|slot| (self.post_at(*slot).map(|p| p.post.created_at), sort_key(*slot)))
+            .expect("cycles are not empty")
+    }
+
+    /// The root of the thread containing the post at `slot`. This may be a
+    /// placeholder when the oldest known ancestor's parent is missing or
+    /// ambiguous.
+    fn root_of<'a>(&'a self, slot: Slot<'a>, breaks: &HashSet<Slot>) -> Slot<'a> {
+        let mut current = slot;
+        let mut seen = HashSet::new();
+        while seen.insert(current) {
+            match self.effective_parent(current, breaks) {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        current
+    }
+
+    fn children(&self, breaks: &HashSet<Slot>) -> HashMap<Slot<'_>, Vec<Slot<'_>>> {
+        let mut children: HashMap<Slot, Vec<Slot>> = HashMap::new();
+        for slot in self.slots() {
+            if let Some(parent) = self.effective_parent(slot, breaks) {
+                children.entry(parent).or_default().push(slot);
+            }
+        }
+        for replies in children.values_mut() {
+            replies.sort_by_key(|slot| (self.post_at(*slot).map(|p| p.post.created_at), sort_key(*slot)));
+        }
+        children
+    }
+
+    /// The tree under `root`, built without recursion: a pre-order walk
+    /// records where each node hangs, then nodes are assembled leaves first.
+    fn build_node<'a>(
+        &'a self,
+        root: Slot<'a>,
+        children: &HashMap<Slot<'a>, Vec<Slot<'a>>>,
+        visited: &mut HashSet<Slot<'a>>,
+    ) -> ThreadNode {
+        visited.insert(root);
+        let mut order: Vec<(Slot, Option<usize>)> = Vec::new();
+        let mut stack: Vec<(Slot, Option<usize>, usize)> = vec![(root, None, 0)];
+        while let Some((slot, parent, depth)) = stack.pop() {
+            let index = order.len();
+            order.push((slot, parent));
+            // Past the cap, replies join their parent's siblings.
+            let (hang_under, reply_depth) = match parent {
+                Some(parent) if depth >= MAX_THREAD_DEPTH => (parent, depth),
+                _ => (index, depth + 1),
+            };
+            let replies = children.get(&slot).map(Vec::as_slice).unwrap_or_default();
+            let unvisited: Vec<Slot> = replies.iter().copied().filter(|r| visited.insert(*r)).collect();
+            stack.extend(unvisited.into_iter().rev().map(|r| (r, Some(hang_under), reply_depth)));
+        }
+
+        let mut replies: Vec<Vec<ThreadNode>> = order.iter().map(|_| Vec::new()).collect();
+        for (index, (slot, parent)) in order.into_iter().enumerate().rev() {
+            let mut own = std::mem::take(&mut replies[index]);
+            own.reverse();
+            let (post_id, ambiguous) = match slot {
+                Slot::Post(_, id) => (id, false),
+                Slot::Placeholder(id) => (id, self.is_ambiguous(id)),
+            };
+            let node = ThreadNode {
+                post_id: post_id.to_string(),
+                post: self.post_at(slot).cloned(),
+                ambiguous,
+                replies: own,
+            };
+            match parent {
+                Some(parent) => replies[parent].push(node),
+                None => return node,
+            }
+        }
+        unreachable!("the walk starts at the root")
+    }
+
+    /// The full thread containing `author_fingerprint`'s post `post_id`: its
+    /// root plus every known reply.
+    pub fn thread(&self, author_fingerprint: &str, post_id: &str) -> Option<ThreadNode> {
+        let (author, id) = self
+            .posts
+            .get_key_value(&(author_fingerprint.to_string(), post_id.to_string()))?
+            .0;
+        let breaks = self.cycle_breaks();
+        let root = self.root_of(Slot::Post(author, id), &breaks);
+        let children = self.children(&breaks);
+        Some(self.build_node(root, &children, &mut HashSet::new()))
+    }
+
+    /// Every thread, most recently active first.
+    pub fn threads(&self) -> Vec<ThreadNode> {
+        let breaks = self.cycle_breaks();
+        let children = self.children(&breaks);
+        let mut roots: Vec<Slot> = self
+            .slots()
+            .filter_map(|slot| match self.effective_parent(slot, &breaks) {
+                None => Some(slot),
+                Some(parent @ Slot::Placeholder(_)) => Some(parent),
+                Some(Slot::Post(..)) => None,
+            })
+            .collect();
+        roots.sort_by_key(|slot| sort_key(*slot));
+        roots.dedup();
+
+        let mut visited = HashSet::new();
+        let mut threads: Vec<ThreadNode> = roots
+            .into_iter()
+            .map(|root| self.build_node(root, &children, &mut visited))
+            .collect();
+        threads.sort_by_cached_key(|t| {
+            (
+                std::cmp::Reverse(t.last_activity()),
+                t.post_id.clone(),
+                t.post.as_ref().map(|p| p.post.author_fingerprint.clone()),
+            )
+        });
+        threads
+    }
+}
+
+/// Order by ID, then author, with a placeholder before posts sharing its ID.
+fn sort_key(slot: Slot<'_>) -> (&str, Option<&str>) {
+    match slot {
+        Slot::Post(author, id) => (id, Some(author)),
+        Slot::Placeholder(id) => (id, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::post::Post;
+    use chrono::Duration;
+
+    fn post(kp: &KeyPair, id: &str, reply_to: Option<&str>, minutes_ago: i64) -> SignedPost {
+        let mut p = Post::new(kp.fingerprint.clone(), id.to_string(), None, reply_to.map(String::from));
+        p.id = id.to_string();
+        p.created_at = Utc::now() - Duration::minutes(minutes_ago);
+        SignedPost::create(p, kp).expect("sign post")
+    }
+
+    #[test]
+    fn builds_tree_across_authors_in_order() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let mut builder = ThreadBuilder::new();
+        // Inserted out of order on purpose.
+        builder.add(post(&bob, "c", Some("a"), 1));
+        builder.add(post(&alice, "d", Some("b"), 2));
+        builder.add(post(&bob, "b", Some("a"), 5));
+        builder.add(post(&alice, "a", None, 10));
+
+        let thread = builder.thread(&alice.fingerprint, "d").expect("thread");
+        assert_eq!(thread.post_id, "a");
+        assert_eq!(thread.post_count(), 4);
+        let order: Vec<(usize, &str)> = thread
+            .flatten()
+            .into_iter()
+            .map(|(depth, n)| (depth, n.post_id.as_str()))
+            .collect();
+        assert_eq!(order, vec![(0, "a"), (1, "b"), (2, "d"), (1, "c")]);
+    }
+
+    #[test]
+    fn missing_parent_becomes_placeholder_root() {
+        let kp = KeyPair::generate().unwrap();
+        let mut builder = ThreadBuilder::new();
+        builder.add(post(&kp, "r1", Some("missing"), 3));
+        builder.add(post(&kp, "r2", Some("missing"), 2));
+
+        let thread = builder.thread(&kp.fingerprint, "r2").expect("thread");
+        assert!(thread.is_placeholder());
+        assert_eq!(thread.post_id, "missing");
+        assert!(!thread.ambiguous);
+        assert_eq!(thread.replies.len(), 2);
+        assert_eq!(builder.threads().len(), 1);
+    }
+
+    #[test]
+    fn posts_are_keyed_by_author_and_id() {
+        let alice = KeyPair::generate().unwrap();
+        let bob = KeyPair::generate().unwrap();
+        let carol = KeyPair::generate().unwrap();
+        let mut builder = ThreadBuilder::new();
+        builder.add(post(&alice, "a", None, 10));
+        builder.add(post(&bob, "a", None, 9));
+        // Replies by the authors themselves find their own post.
+        builder.add(post(&alice, "alice-reply", Some("a"), 5));
+        builder.add(post(&bob, "bob-reply", Some("a"), 4));
+        // Carol's reply could mean either post.
+        builder.add(post(&carol, "carol-reply", Some("a"), 3));
+        assert_eq!(builder.len(), 5);
+
+        let alices = builder.thread(&alice.fingerprint, "a").expect("thread");
+        assert_eq!(alices.post.as_ref().unwrap().post.author_fingerprint, alice.fingerprint);
+        let ids: Vec<&str> = alices.flatten().into_iter().map(|(_, n)| n.post_id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "alice-reply"]);
+        let bobs = builder.thread(&bob.fingerprint, "bob-reply").expect("thread");
+        assert_eq!(bobs.post.as_ref().unwrap().post.author_fingerprint, bob.fingerprint);
+        assert_eq!(bobs.post_count(), 2);
+
+        let carols = builder.thread(&carol.fingerprint, "carol-reply").expect("thread");
+        assert!(carols.is_placeholder());
+        assert!(carols.ambiguous);
+        assert_eq!(carols.post_id, "a");
+        assert_eq!(builder.threads().len(), 3);
+
+        // With only one post by that ID, anyone's reply finds it.
+        let mut single = ThreadBuilder::new();
+        single.add(post(&alice, "a", None, 10));
+        single.add(post(&carol, "carol-reply", Some("a"), 3));
+        assert_eq!(single.thread(&carol.fingerprint, "carol-reply").unwrap().post_count(), 2);
+    }
+
+    #[test]
+    fn cycles_are_broken_at_oldest_post() {
+        let kp = KeyPair::generate().unwrap();
+        let mut builder = ThreadBuilder::new();
+        builder.add(post(&kp, "x", Some("y"), 10));
+        builder.add(post(&kp, "y", Some("z"), 5));
+        builder.add(post(&kp, "z", Some("x"), 1));
+        builder.add(post(&kp, "self", Some("self"), 1));
+
+        let thread = builder.thread(&kp.fingerprint, "z").expect("thread");
+        assert_eq!(thread.post_id, "x");
+        assert_eq!(thread.post_count(), 3);
+        assert_eq!(builder.threads().len(), 2);
+    }
+
+    #[test]
+    fn threads_sorted_by_latest_activity() {
+        let kp = KeyPair::generate().unwrap();
+        let mut builder = ThreadBuilder::new();
+        builder.add(post(&kp, "old", None, 60));
+        builder.add(post(&kp, "new", None, 30));
+        builder.add(post(&kp, "old-reply", Some("old"), 1));
+
+        let threads = builder.threads();
+        assert_eq!(threads[0].post_id, "old");
+        assert_eq!(threads[1].post_id, "new");
+    }
+
+    #[test]
+    fn long_reply_chains_are_capped_in_depth() {
+        let kp = KeyPair::generate().unwrap();
+        let mut builder = ThreadBuilder::new();
+        let chain = 2_000;
+        builder.add(post(&kp, "p0", None, chain));
+        for i in 1..chain {
+            let parent = format!("p{}", i - 1);
+            builder.add(post(&kp, &format!("p{i}"), Some(&parent), chain - i));
+        }
+
+        let thread = builder.thread(&kp.fingerprint, &format!("p{}", chain - 1)).expect("thread");
+        assert_eq!(thread.post_id, "p0");
+        assert_eq!(thread.post_count(), chain as usize);
+        let flat = thread.flatten();
+        assert_eq!(flat.iter().map(|(depth, _)| *depth).max(), Some(MAX_THREAD_DEPTH));
+        // Display order still follows the chain.
+        assert!(flat.iter().enumerate().all(|(i, (_, n))| n.post_id == format!("p{i}")));
+    }
+}
@@ -1,27 +1,80 @@
 use wasm_bindgen::prelude::*;
+use crate::encrypted_storage::{EncryptedStorage, EncryptionOptions, MaybeEncrypted};
 use crate::storage::browser::BrowserStorage;
 use crate::service::{
     CoreService, CreateProfileRequest, UpdateProfileRequest, ProfileEnvelope, CapabilityDescriptor,
 };
+use crate::message::SignedMessage;
+use crate::profile::SignedProfile;
 use crate::storage::StorageError;
 
 fn storage_err(e: StorageError) -> JsValue {
     JsValue::from_str(&e.to_string())
 }
 
-/// WASM host: thin wrapper around `CoreService<BrowserStorage>`.
+/// localStorage, unlocked first if it is encrypted.
+type Storage = MaybeEncrypted<BrowserStorage>;
+
+/// WASM host: thin wrapper around `CoreService<MaybeEncrypted<BrowserStorage>>`.
 #[wasm_bindgen]
 pub struct SnartNetCore {
-    inner: CoreService<BrowserStorage>,
+    inner: CoreService<Storage>,
 }
 
 #[wasm_bindgen]
 impl SnartNetCore {
+    /// Open plain localStorage. Fails if it is encrypted; use `unlock`.
     #[wasm_bindgen(constructor)]
-    pub fn new() -> Self {
-        Self {
-            inner: CoreService::new(),
+    pub fn new() -> Result<SnartNetCore, JsValue> {
+        Self::open(None)
+    }
+
+    /// Open localStorage encrypted with `passphrase`.
+    #[wasm_bindgen]
+    pub fn unlock(passphrase: &str) -> Result<SnartNetCore, JsValue> {
+        Self::open(Some(passphrase))
+    }
+
+    /// Whether localStorage is encrypted, so the page knows to ask for a
+    /// passphrase before constructing the core.
+    #[wasm_bindgen]
+    pub fn is_storage_encrypted() -> Result<bool, JsValue> {
+        EncryptedStorage::is_initialized(&BrowserStorage).map_err(storage_err)
+    }
+
+    fn open(passphrase: Option<&str>) -> Result<SnartNetCore, JsValue> {
+        let storage = MaybeEncrypted::open(BrowserStorage, passphrase).map_err(storage_err)?;
+        Ok(Self { inner: CoreService::new(storage) })
+    }
+
+    #[wasm_bindgen]
+    pub fn is_encrypted(&self) -> bool {
+        self.inner.storage().is_encrypted()
+    }
+
+    /// Encrypt the plain localStorage in place and reopen it unlocked.
+    /// Returns how many keys were converted.
+    #[wasm_bindgen]
+    pub fn encrypt_storage(&mut self, passphrase: &str) -> Result<u32, JsValue> {
+        if self.is_encrypted() {
+            return Err(JsValue::from_str("Storage is already encrypted"));
         }
+        let keys = BrowserStorage.keys().map_err(storage_err)?;
+        let store = EncryptedStorage::create(BrowserStorage, passphrase, EncryptionOptions::default())
+            .map_err(storage_err)?;
+        let converted = store.encrypt_existing(&keys).map_err(storage_err)?;
+        let mut fresh = CoreService::new(MaybeEncrypted::Encrypted(store));
+        fresh.init().map_err(storage_err)?;
+        self.inner = fresh;
+        Ok(converted as u32)
+    }
+
+    #[wasm_bindgen]
+    pub fn change_passphrase(&self, new_passphrase: &str) -> Result<(), JsValue> {
+        let MaybeEncrypted::Encrypted(store) = self.inner.storage() else {
+            return Err(JsValue::from_str("Storage is not encrypted"));
+        };
+        store.change_passphrase(new_passphrase).map_err(storage_err)
     }
 
     #[wasm_bindgen]
@@ -103,7 +156,7 @@ impl SnartNetCore {
 
     #[wasm_bindgen]
     pub fn get_capabilities(&self) -> Result<JsValue, JsValue> {
-        serde_wasm_bindgen::to_value(&CoreService::<BrowserStorage>::capabilities())
+        serde_wasm_bindgen::to_value(&CoreService::<Storage>::capabilities())
             .map_err(|e| JsValue::from_str(&format!("Serialization error: {e}")))
     }
 
@@ -125,7 +178,7 @@ impl SnartNetCore {
 
     #[wasm_bindgen]
     pub fn create_message(
-        &self,
+        &mut self,
         recipient_fingerprint: &str,
         content: &str,
     ) -> Result<JsValue, JsValue> {
@@ -137,6 +190,42 @@ impl SnartNetCore {
             .map_err(|e| JsValue::from_str(&format!("Serialization error: {e}")))
     }
 
+    /// Decrypt a direct message to or from the current user.
+    #[wasm_bindgen]
+    pub fn open_message_json(&self, message_json: &str) -> Result<String, JsValue> {
+        let message: SignedMessage = serde_json::from_str(message_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid message: {e}")))?;
+        self.inner.open_message(&message).map_err(storage_err)
+    }
+
+    /// Learn a contact's keys from their signed profile so messages to them
+    /// can be encrypted.
+    #[wasm_bindgen]
+    pub fn learn_contact_json(&mut self, profile_json: &str) -> Result<bool, JsValue> {
+        let profile: SignedProfile = serde_json::from_str(profile_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid profile: {e}")))?;
+        self.inner.learn_contact(&profile).map_err(storage_err)
+    }
+
+    // ---- Petnames ----
+
+    #[wasm_bindgen]
+    pub fn set_petname(&mut self, fingerprint: &str, petname: Option<String>) -> Result<(), JsValue> {
+        self.inner.set_petname(fingerprint, petname).map_err(storage_err)
+    }
+
+    #[wasm_bindgen]
+    pub fn resolve_name(&self, fingerprint: &str) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.inner.resolve_name(fingerprint))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {e}")))
+    }
+
+    #[wasm_bindgen]
+    pub fn parse_markup(&self, content: &str) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.inner.parse_markup(content))
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {e}")))
+    }
+
     // ---- Utility ----
 
     #[wasm_bindgen]
@@ -7,39 +7,95 @@
 //! - per-contact sync of profile, posts, and inbox messages
 //! - periodic polling subscription with unread counters per thread
 //! - contact identity verification and trust indicators
+//! - signed liveness heartbeats and profile expiry ("last seen")
+//! - local petnames with impersonation warnings for every displayed name
+//! - signed post edits and deletions applied during sync
+//! - threaded conversation view across local and synced posts
+//! - signed reactions published alongside posts
+//! - reposts and quote posts, collapsed and credited to the original author
+//! - chunked, hash-verified attachments fetched from peers on demand
+//! - markdown-lite rendering with mentions and hashtags
+//! - posts restricted to private circles of contacts, decrypted on read
+//! - status posts that expire after 24 hours and are purged everywhere
+//! - a persisted, paginated timeline with tag and unread filters
+//! - a local full-text index shared with `snartnet search`
+//! - signed content warnings, collapsed per reader preference
+//! - polls with signed votes and author tallies checked against seen votes
+//! - group chats with signed membership and per-member sender keys
+//! - signed delivery and read receipts, with read receipts optional
+//! - message edits, unsends and negotiated disappearing timers
+//! - replay, sender-binding and clock-skew checks on inbox ingestion
+//! - causally ordered chats with Lamport clocks and gap detection
+//! - signed inbox acknowledgements that let peers prune delivered messages
+//! - a persistent outbox that retries pushes with backoff and shows their state
 
 mod transport;
 
 use iced::{
-    time,
-    widget::{button, column, container, image, row, scrollable, svg, text, text_input},
-    Alignment, Element, Length, Subscription, Task,
+    font, time,
+    widget::{
+        button, checkbox, column, container, image, pick_list, rich_text, row, scrollable, span, svg, text,
+        text_input, Space,
+    },
+    Alignment, Color, Element, Font, Length, Subscription, Task,
 };
 use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
 use serde::{Deserialize, Serialize};
 use snartnet_core::{
-    profile_fingerprint_from_magnet_uri, ContactInvite, FileStorage, KeyPair,
-    Message as CoreMessage, Post, Profile, SignedMessage, SignedPost, SignedProfile,
+    apply_retractions, collapse_reposts, AckedItem, AttachmentManifest, missing_predecessors, sort_causally, CausalEntry, LamportClock, fetch_attachment, purge_expired, Block, CircleBook, ContentPreferences, ContentWarning, Document, Feed, FeedItem, FeedQuery, Inline, profile_fingerprint_from_magnet_uri, BlobStore, ContactInvite, direct_conversation_id, EmbeddedPost, EncryptedStorage, InboxItem, FileStorage, MaybeEncrypted, StorageBackend, Heartbeat, KeyPair, Liveness,
+    DisappearingTimer, GroupBook, MessageIngest, Outbox, OutboxPayload, OutboxState, GroupMember, GroupRole, Message as CoreMessage, MessageType, PetnameBook, Poll, PollVote, PollVotes, Post, PostDelete, PostEdit, PostRevisions, Profile,
+    Reaction, ReactionSet, ReactionTarget, ReceiptKind, Rejection, ReceiptPreferences, Recipient, DeliveryStatus, RevisedPost, SearchIndex, SearchKind, SensitiveCategory, SignedHeartbeat, SignedMessage, SignedPost,
+    SignedGroup, SignedPollTally, SignedPollVote, SignedPostDelete, SignedPostEdit, SignedProfile, SignedReaction, SignedInboxAck,
+    ThreadBuilder, ThreadNode,
+    FetchProgress, HEARTBEAT_INTERVAL_SECS, LIKE_EMOJI, MAX_FEED_ENTRIES, MAX_INBOX_ACK_IDS, MESSAGE_MAX_AGE_DAYS, STATUS_POST_TTL_HOURS,
 };
 use std::{
-    collections::HashSet,
+    cell::RefCell,
+    rc::Rc,
+    collections::{HashMap, HashSet},
     env,
     io::Cursor,
     path::PathBuf,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use transport::{
-    dedupe_inbox, DiscoveredPeer, LanAnnounce, LanDiscovery, NetworkTransport, SwarmPostsBlob,
-    SwarmProfileBlob, TcpSwarmTransport,
+    dedupe_inbox, DiscoveredPeer, LanAnnounce, LanDiscovery, NetworkTransport, SwarmHeartbeatBlob,
+    SwarmInboxBlob, SwarmPostsBlob, SwarmProfileBlob, TcpSwarmTransport,
 };
 
 const STORAGE_KEYPAIR: &str = "keypair";
 const STORAGE_PROFILE: &str = "profile";
 const STORAGE_POSTS: &str = "local_posts";
+const STORAGE_POST_REVISIONS: &str = "local_post_revisions";
+const STORAGE_REACTIONS: &str = "local_reactions";
 const STORAGE_CONTACTS: &str = "contacts";
 const STORAGE_THREADS: &str = "threads";
+const STORAGE_PETNAMES: &str = "petnames";
+const STORAGE_CIRCLES: &str = "circles";
+const STORAGE_FEED: &str = "feed";
+const STORAGE_CONTENT_PREFERENCES: &str = "content_preferences";
+const STORAGE_POLL_VOTES: &str = "poll_votes";
+const STORAGE_POLL_TALLIES: &str = "poll_tallies";
+const STORAGE_GROUPS: &str = "groups";
+const STORAGE_GROUP_THREADS: &str = "group_threads";
+const STORAGE_RECEIPT_PREFERENCES: &str = "receipt_preferences";
+const STORAGE_SEEN_MESSAGES: &str = "seen_messages";
+const STORAGE_OUTBOX: &str = "outbox";
+/// Default lifetime of a poll created from the composer.
+const DEFAULT_POLL_HOURS: i64 = 24;
+const FEED_PAGE_SIZE: usize = 30;
+/// Parsed bodies kept for rendering before the cache is cleared.
+const MARKUP_CACHE_ENTRIES: usize = 1024;
 const AVATAR_PREVIEW_SIZE: f32 = 72.0;
 const LOCAL_SWARM_FILE_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+/// Lifetime of a freshly signed profile before peers stop serving it.
+const PROFILE_TTL_DAYS: i64 = 90;
+/// Re-sign the local profile once it is this close to expiring.
+const PROFILE_RENEWAL_WINDOW_DAYS: i64 = 14;
+
+/// Composer audience entry for unrestricted posts.
+const AUDIENCE_PUBLIC: &str = "Public";
 
 /// Number of characters shown in the truncated invite-code preview.
 const INVITE_CODE_PREVIEW_LENGTH: usize = 60;
@@ -94,6 +150,8 @@ impl VerificationState {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Contact {
     fingerprint: String,
+    /// Legacy free-form name; offered as an unconfirmed petname on load.
+    /// Display code should use `PetnameBook::display_label` instead.
     alias: String,
     magnet_uri: Option<String>,
     #[serde(default)]
@@ -114,6 +172,12 @@ struct Contact {
     known_encryption_public_key: Option<String>,
     #[serde(default)]
     last_sync_error: Option<String>,
+    /// Signed `issued_at` of the newest verified heartbeat.
+    #[serde(default)]
+    last_seen_at: Option<DateTime<Utc>>,
+    /// Signed expiry of the contact's last verified profile.
+    #[serde(default)]
+    profile_expires_at: Option<DateTime<Utc>>,
 }
 
 impl Default for Contact {
@@ -133,6 +197,21 @@ impl Default for Contact {
             known_public_key: None,
             known_encryption_public_key: None,
             last_sync_error: None,
+            last_seen_at: None,
+            profile_expires_at: None,
+        }
+    }
+}
+
+impl Contact {
+    fn liveness(&self) -> Liveness {
+        Liveness::classify(self.last_seen_at, self.profile_expires_at)
+    }
+
+    fn last_seen_label(&self) -> String {
+        match self.last_seen_at {
+            Some(ts) => format!("last seen {}", ts.format("%Y-%m-%d %H:%M UTC")),
+            None => "never seen".to_string(),
         }
     }
 }
@@ -153,6 +232,47 @@ struct ChatItem {
     created_label: String,
     #[serde(default)]
     verified_sender: bool,
+    /// Signed expiry of the underlying message; purged once passed.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+    /// Outgoing only: what the recipient's receipts have reported.
+    #[serde(default)]
+    status: DeliveryStatus,
+    /// Incoming only: the strongest receipt sent back for this message.
+    #[serde(default)]
+    acknowledged: Option<ReceiptKind>,
+    /// Content was replaced by a signed edit.
+    #[serde(default)]
+    edited: bool,
+    /// Signed time and ID of the edit now shown, so an older edit that
+    /// arrives later does not bring back earlier text.
+    #[serde(default)]
+    last_edit: Option<(DateTime<Utc>, String)>,
+    /// Signed send time; missing on items stored before it was kept.
+    #[serde(default)]
+    sent_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    lamport: u64,
+    #[serde(default)]
+    previous_ids: Vec<String>,
+}
+
+impl CausalEntry for ChatItem {
+    fn causal_id(&self) -> &str {
+        &self.id
+    }
+
+    fn lamport(&self) -> u64 {
+        self.lamport
+    }
+
+    fn previous_ids(&self) -> &[String] {
+        &self.previous_ids
+    }
+
+    fn sent_at(&self) -> Option<DateTime<Utc>> {
+        self.sent_at
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -161,6 +281,52 @@ struct ChatThread {
     messages: Vec<ChatItem>,
     #[serde(default)]
     unread_count: u32,
+    /// Both parties' disappearing-message preferences.
+    #[serde(default)]
+    timer: DisappearingTimer,
+}
+
+/// Disappearing-message timers offered in the chat view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimerChoice {
+    Off,
+    Hour,
+    Day,
+    Week,
+}
+
+impl TimerChoice {
+    const ALL: [TimerChoice; 4] = [TimerChoice::Off, TimerChoice::Hour, TimerChoice::Day, TimerChoice::Week];
+
+    fn ttl_secs(self) -> Option<u64> {
+        match self {
+            TimerChoice::Off => None,
+            TimerChoice::Hour => Some(60 * 60),
+            TimerChoice::Day => Some(24 * 60 * 60),
+            TimerChoice::Week => Some(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl std::fmt::Display for TimerChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TimerChoice::Off => "Off",
+            TimerChoice::Hour => "1 hour",
+            TimerChoice::Day => "1 day",
+            TimerChoice::Week => "1 week",
+        })
+    }
+}
+
+/// Messages of one group, kept encrypted and opened with the group's
+/// sender keys when shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GroupThread {
+    group_id: String,
+    messages: Vec<SignedMessage>,
+    #[serde(default)]
+    unread_count: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -173,6 +339,10 @@ struct NetworkState {
     poll_interval_secs: u64,
     lan_discovery_active: bool,
     discovered_peer_count: usize,
+    /// Unix seconds of the last heartbeat we published.
+    last_heartbeat_published: u64,
+    /// Transport peers at the last tick, to notice new ones.
+    transport_peer_count: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -196,12 +366,16 @@ impl Default for NetworkState {
             poll_interval_secs: 4,
             lan_discovery_active: false,
             discovered_peer_count: 0,
+            last_heartbeat_published: 0,
+            transport_peer_count: 0,
         }
     }
 }
 
 #[derive(Debug, Clone, Default)]
 struct FormState {
+    /// Passphrase typed on the unlock screen; cleared once tried.
+    unlock_passphrase_input: String,
     username_input: String,
     display_name_input: String,
     bio_input: String,
@@ -212,6 +386,8 @@ struct FormState {
     compose_post_input: String,
     compose_message_input: String,
     selected_contact_for_chat: Option<String>,
+    /// Own direct message currently being edited in the composer, if any.
+    editing_message_id: Option<String>,
     /// Add-contact mode selector in the Contacts panel.
     add_contact_mode: AddContactMode,
     /// Invite code string pasted by the user.
@@ -220,6 +396,40 @@ struct FormState {
     magnet_uri_input: String,
     /// Whether to show the QR code in the Profile panel.
     show_profile_qr: bool,
+    /// Petname being assigned to the selected contact.
+    petname_input: String,
+    /// Own post currently being edited in the composer, if any.
+    editing_post_id: Option<String>,
+    /// Post the composer is replying to, if any.
+    replying_to: Option<String>,
+    /// Author and ID of the post the composer is quoting, if any.
+    quoting: Option<(String, String)>,
+    /// Path of a local file to attach to the next post.
+    attachment_path: String,
+    /// Circle the next post is restricted to; `None` posts publicly.
+    post_audience: Option<String>,
+    /// Whether the next post is a status that disappears after a day.
+    post_ephemeral: bool,
+    /// Content warning summary for the next post.
+    post_cw_input: String,
+    /// Sensitive-content flags for the next post.
+    post_cw_categories: Vec<SensitiveCategory>,
+    /// Options separated by `|`; non-empty turns the next post into a poll.
+    post_poll_options: String,
+    /// Hours until the poll closes; empty for the default.
+    post_poll_hours: String,
+    post_poll_multiple: bool,
+    /// Circle name to add the selected contact to.
+    circle_input: String,
+    /// Hashtag the timeline is filtered by; empty shows everything.
+    feed_tag_filter: String,
+    feed_unread_only: bool,
+    /// Leave posts with a content warning out of the timeline.
+    feed_hide_warned: bool,
+    /// Name for the next group created.
+    group_name_input: String,
+    selected_group: Option<String>,
+    compose_group_input: String,
 }
 
 #[derive(Debug, Clone)]
@@ -227,13 +437,28 @@ struct StartupData {
     keypair: Option<KeyPair>,
     profile: Option<SignedProfile>,
     local_posts: Vec<SignedPost>,
+    local_revisions: PostRevisions,
+    local_reactions: ReactionSet,
     contacts: Vec<Contact>,
     threads: Vec<ChatThread>,
+    petnames: PetnameBook,
+    circles: CircleBook,
+    feed: Feed,
+    content_preferences: ContentPreferences,
+    poll_votes: PollVotes,
+    poll_tallies: Vec<SignedPollTally>,
+    receipt_preferences: ReceiptPreferences,
+    groups: GroupBook,
+    group_threads: Vec<GroupThread>,
+    ingest: MessageIngest,
+    outbox: Outbox,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
-    StartupLoaded(StartupData),
+    StartupLoaded(Box<StartupData>),
+    UnlockPassphraseChanged(String),
+    UnlockStorage,
     Tick(Instant),
     RunSyncNow,
     SwitchPanel(Panel),
@@ -266,46 +491,198 @@ enum Message {
     AddDiscoveredPeer(String),
     ContactAdded(Result<Contact, String>),
     SelectChatContact(String),
+    PetnameChanged(String),
+    SetPetname,
+    ConfirmPetname,
+    CircleInputChanged(String),
+    AddToCircle,
+    RemoveFromCircle(String),
 
     ComposePostChanged(String),
+    AudienceSelected(String),
+    EphemeralPostToggled(bool),
+    ContentWarningChanged(String),
+    ContentCategoryToggled(SensitiveCategory, bool),
+    PollOptionsChanged(String),
+    PollHoursChanged(String),
+    PollMultipleToggled(bool),
+    /// Poll author and id, option index, selected.
+    PollChoiceToggled(String, String, usize, bool),
+    /// Poll author and id.
+    CastVote(String, String),
+    VoteSigned(Result<SignedPollVote, String>),
+    PublishTally(String),
     CreatePost,
     PostCreated(Result<SignedPost, String>),
+    EditPost(String),
+    CancelEditPost,
+    PostEdited(Result<SignedPostEdit, String>),
+    DeletePost(String),
+    PostDeleted(Result<SignedPostDelete, String>),
+    TogglePostHistory(String),
+    ToggleLike(String),
+    ReactionSigned(Result<SignedReaction, String>),
+    /// Post author and id.
+    OpenThread(String, String),
+    CloseThread,
+    ReplyToPost(String),
+    CancelReply,
+    AttachmentPathChanged(String),
+    FetchAttachment(String),
+    AttachmentFetched(Result<(String, FetchProgress), String>),
+    SaveAttachment(String),
+    RepostPost(String, String),
+    QuotePost(String, String),
+    CancelQuote,
+    FeedTagFilterChanged(String),
+    FeedUnreadOnlyToggled(bool),
+    FeedHideWarnedToggled(bool),
+    ToggleWarning(String),
+    AutoExpandToggled(SensitiveCategory, bool),
+    ExpandAllWarningsToggled(bool),
+    /// Post author and id.
+    MarkPostRead(String, String),
+    MarkAllRead,
+    LoadMoreFeed,
 
     ComposeMessageChanged(String),
     ToggleMessageView(String),
     SendMessage,
     MessageSent(Result<SignedMessage, String>),
+    ReadReceiptsToggled(bool),
+    EditChat(String),
+    CancelChatEdit,
+    UnsendChat(String),
+    ChatTimerSelected(TimerChoice),
+    GroupNameChanged(String),
+    CreateGroup,
+    SelectGroup(String),
+    AddContactToGroup,
+    RemoveGroupMember(String),
+    LeaveGroup,
+    ComposeGroupChanged(String),
+    SendToGroup,
 
     ToggleBittorrent,
     LanDiscoveryToggle,
     CleanupLocalFiles,
 }
 
+/// What a cached conversation was built from: the opened post's author
+/// and ID, and change counters for the feed and our own posts.
+type ConversationKey = (String, String, (usize, usize, usize), (usize, usize, usize));
+
+/// A conversation as shown, kept between frames; see `App::conversation`.
+struct Conversation {
+    key: ConversationKey,
+    /// `None` once the opened post is no longer known.
+    root: Option<ThreadNode>,
+    /// Posts in the thread with edits applied, by author and ID.
+    known: HashMap<(String, String), RevisedPost>,
+}
+
 struct App {
     panel: Panel,
     keypair: Option<KeyPair>,
     profile: Option<SignedProfile>,
     local_posts: Vec<SignedPost>,
+    local_revisions: PostRevisions,
+    local_reactions: ReactionSet,
     contacts: Vec<Contact>,
     threads: Vec<ChatThread>,
+    petnames: PetnameBook,
+    circles: CircleBook,
     network: NetworkState,
     forms: FormState,
-    storage: FileStorage,
+    storage: MaybeEncrypted<FileStorage>,
+    /// The data directory is encrypted and waits for its passphrase; until
+    /// then `storage` must not be used and every other message is ignored.
+    locked: bool,
     transport: TcpSwarmTransport,
     lan_discovery: LanDiscovery,
     /// Snapshot of LAN-discovered peers, refreshed on every tick.
     discovered_peers: Vec<DiscoveredPeer>,
     /// Message IDs currently shown as decrypted; runtime only, never persisted.
     revealed_message_ids: HashSet<String>,
+    /// Post IDs whose edit history is expanded in the feed; runtime only.
+    expanded_post_history: HashSet<String>,
+    /// Verified own and contacts' posts, merged across syncs.
+    feed: Feed,
+    /// Number of timeline pages shown; grows with "Load more".
+    feed_pages: usize,
+    /// Which flagged posts open without a click.
+    content_preferences: ContentPreferences,
+    /// Flagged post IDs the user has expanded; runtime only.
+    expanded_warnings: HashSet<String>,
+    /// Verified votes: our own, those sent to our polls and those
+    /// contacts published.
+    poll_votes: PollVotes,
+    /// Signed tallies of our own polls, published with our posts.
+    poll_tallies: Vec<SignedPollTally>,
+    /// Newest verified tally per contact poll, by author and poll ID;
+    /// runtime only.
+    synced_poll_tallies: HashMap<(String, String), SignedPollTally>,
+    /// Options ticked but not yet voted, by poll author and ID; runtime only.
+    poll_selections: HashMap<(String, String), Vec<usize>>,
+    /// Whether reading a thread sends read receipts.
+    receipt_preferences: ReceiptPreferences,
+    /// Groups with their sender keys.
+    groups: GroupBook,
+    group_threads: Vec<GroupThread>,
+    /// Replay cache and checks every incoming message goes through.
+    ingest: MessageIngest,
+    /// Messages and posts kept until a peer has taken them.
+    outbox: Outbox,
+    /// Full-text index beside the other data files, or in memory while the
+    /// data directory is encrypted; `None` if it could not be opened.
+    search: Option<SearchIndex>,
+    /// Verified reactions published by contacts; runtime only.
+    synced_reactions: ReactionSet,
+    /// Originals embedded in contacts' reposts and quotes, keyed by author
+    /// and post ID; runtime only. Lets us show and re-share posts by people
+    /// we don't follow.
+    shared_originals: HashMap<(String, String), EmbeddedPost>,
+    /// Parsed markup by body text, so `view()` does not re-parse every post
+    /// on each frame; runtime only.
+    markup_cache: RefCell<HashMap<String, Document>>,
+    /// Author and ID of the post whose conversation is shown instead of the
+    /// feed list.
+    open_thread: Option<(String, String)>,
+    /// The last conversation built for `view()`; runtime only.
+    conversation_cache: RefCell<Option<Rc<Conversation>>>,
+    /// Manifest and number of missing chunks per attachment, as last read
+    /// from the blob store, so `view()` does not touch the disk on every
+    /// frame; runtime only. Cleared when a download finishes.
+    attachment_states: RefCell<HashMap<String, Option<(AttachmentManifest, usize)>>>,
     status_line: String,
 }
 
 impl App {
     fn new() -> (Self, Task<Message>) {
-        let storage = FileStorage::open_default().unwrap_or_else(|e| {
+        let files = FileStorage::open_default().unwrap_or_else(|e| {
             eprintln!("Warning: could not open default storage: {e}");
             FileStorage::new(std::env::temp_dir().join("snartnet")).expect("temp storage")
         });
+        let encrypted = EncryptedStorage::is_initialized(&files).unwrap_or_else(|e| {
+            eprintln!("Warning: could not read storage header: {e}");
+            false
+        });
+        // An encrypted directory is unlocked from SNARTNET_PASSPHRASE or the
+        // unlock screen; a wrong variable falls back to the screen.
+        let (storage, locked) = match env::var("SNARTNET_PASSPHRASE") {
+            Ok(passphrase) if encrypted => {
+                let dir = files.dir().to_path_buf();
+                match MaybeEncrypted::open(files, Some(&passphrase)) {
+                    Ok(storage) => (storage, false),
+                    Err(e) => {
+                        eprintln!("Warning: SNARTNET_PASSPHRASE did not unlock storage: {e}");
+                        (MaybeEncrypted::Plain(FileStorage::new(dir).expect("storage dir")), true)
+                    }
+                }
+            }
+            _ => (MaybeEncrypted::Plain(files), encrypted),
+        };
+        let search = open_search_index(&storage);
         let transport = TcpSwarmTransport::from_env()
             .expect("transport init failed");
         transport.start_server();
@@ -315,23 +692,88 @@ impl App {
             keypair: None,
             profile: None,
             local_posts: Vec::new(),
+            local_revisions: PostRevisions::new(),
+            local_reactions: ReactionSet::new(),
             contacts: Vec::new(),
             threads: Vec::new(),
+            petnames: PetnameBook::new(),
+            circles: CircleBook::new(),
             network: NetworkState::default(),
             forms: FormState::default(),
             storage,
+            locked,
             transport,
             lan_discovery: LanDiscovery::new(),
             discovered_peers: Vec::new(),
             revealed_message_ids: HashSet::new(),
-            status_line: "Loading local state...".to_string(),
+            expanded_post_history: HashSet::new(),
+            feed: Feed::new(),
+            feed_pages: 1,
+            content_preferences: ContentPreferences::new(),
+            expanded_warnings: HashSet::new(),
+            poll_votes: PollVotes::new(),
+            poll_tallies: Vec::new(),
+            synced_poll_tallies: HashMap::new(),
+            poll_selections: HashMap::new(),
+            receipt_preferences: ReceiptPreferences::default(),
+            groups: GroupBook::new(),
+            group_threads: Vec::new(),
+            ingest: MessageIngest::new(),
+            outbox: Outbox::new(),
+            search,
+            synced_reactions: ReactionSet::new(),
+            shared_originals: HashMap::new(),
+            markup_cache: RefCell::new(HashMap::new()),
+            open_thread: None,
+            conversation_cache: RefCell::new(None),
+            attachment_states: RefCell::new(HashMap::new()),
+            status_line: if locked {
+                "Storage is encrypted; enter the passphrase to unlock".to_string()
+            } else {
+                "Loading local state...".to_string()
+            },
         };
 
-        (app, Task::perform(load_startup_async(), Message::StartupLoaded))
+        let startup = if app.locked {
+            Task::none()
+        } else {
+            Task::done(Message::StartupLoaded(Box::new(load_startup(&app.storage))))
+        };
+        (app, startup)
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
+        if self.locked && !matches!(message, Message::UnlockPassphraseChanged(_) | Message::UnlockStorage) {
+            return Task::none();
+        }
         match message {
+            Message::UnlockPassphraseChanged(value) => {
+                self.forms.unlock_passphrase_input = value;
+                Task::none()
+            }
+            Message::UnlockStorage => {
+                let passphrase = std::mem::take(&mut self.forms.unlock_passphrase_input);
+                let files = match FileStorage::new(self.storage.inner().dir()) {
+                    Ok(files) => files,
+                    Err(e) => {
+                        self.status_line = format!("Unlock failed: {e}");
+                        return Task::none();
+                    }
+                };
+                match MaybeEncrypted::open(files, Some(&passphrase)) {
+                    Ok(storage) => {
+                        self.storage = storage;
+                        self.locked = false;
+                        self.search = open_search_index(&self.storage);
+                        self.status_line = "Loading local state...".to_string();
+                        Task::done(Message::StartupLoaded(Box::new(load_startup(&self.storage))))
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Unlock failed: {e}");
+                        Task::none()
+                    }
+                }
+            }
             Message::StartupLoaded(data) => {
                 self.keypair = data.keypair;
                 if let Some(kp) = &mut self.keypair {
@@ -343,8 +785,24 @@ impl App {
                 }
                 self.profile = data.profile;
                 self.local_posts = data.local_posts;
+                self.local_revisions = data.local_revisions;
+                self.local_reactions = data.local_reactions;
                 self.contacts = data.contacts;
                 self.threads = data.threads;
+                self.petnames = data.petnames;
+                self.circles = data.circles;
+                self.feed = data.feed;
+                self.content_preferences = data.content_preferences;
+                self.poll_votes = data.poll_votes;
+                self.poll_tallies = data.poll_tallies;
+                self.receipt_preferences = data.receipt_preferences;
+                self.groups = data.groups;
+                self.group_threads = data.group_threads;
+                self.ingest = data.ingest;
+                self.outbox = data.outbox;
+                self.seed_petnames_from_aliases();
+                self.record_own_posts_in_feed();
+                self.rebuild_search_index();
 
                 if let Some(sp) = &self.profile {
                     self.forms.username_input = sp.profile.username.clone();
@@ -362,8 +820,10 @@ impl App {
                         self.contacts.len(),
                         self.local_posts.len()
                     );
+                    self.renew_local_profile_if_expiring();
                     self.publish_local_profile_to_swarm();
                     self.publish_local_posts_to_swarm();
+                    self.publish_heartbeat_if_due();
                     self.start_lan_discovery();
                 }
 
@@ -377,6 +837,14 @@ impl App {
                 self.discovered_peers = self.lan_discovery.get_discovered();
                 self.network.discovered_peer_count = self.discovered_peers.len();
                 self.refresh_transport_peers_from_discovery();
+                let peer_count = self.transport.peer_snapshot().len();
+                if peer_count > self.network.transport_peer_count {
+                    self.outbox.peers_appeared(Utc::now());
+                }
+                self.network.transport_peer_count = peer_count;
+                self.publish_heartbeat_if_due();
+                self.purge_expired_local();
+                self.flush_outbox();
                 self.run_peer_sync();
                 Task::none()
             }
@@ -463,6 +931,7 @@ impl App {
                         }
 
                         self.publish_local_profile_to_swarm();
+                        self.index_for_search(|index| index.index_profile(&sp));
                         self.recalculate_network();
                         // (Re)start LAN discovery with the updated profile.
                         self.lan_discovery.stop();
@@ -631,6 +1100,22 @@ impl App {
                 match result {
                     Ok(contact) => {
                         if !self.contacts.iter().any(|c| c.fingerprint == contact.fingerprint) {
+                            // Only a name the user typed is a petname; names taken
+                            // from invites or LAN announcements are claims and are
+                            // picked up as suggestions once the profile verifies.
+                            let typed_alias = self.forms.add_contact_mode == AddContactMode::Manual
+                                && !self.forms.contact_alias_input.trim().is_empty();
+                            let mut petname_warning = None;
+                            if typed_alias {
+                                if let Err(e) = self
+                                    .petnames
+                                    .set_petname(&contact.fingerprint, Some(contact.alias.clone()))
+                                {
+                                    petname_warning = Some(e);
+                                }
+                            }
+                            let conflicts = self.petnames.check_name(&contact.fingerprint, &contact.alias);
+
                             self.contacts.push(contact.clone());
                             self.forms.contact_fingerprint_input.clear();
                             self.forms.contact_alias_input.clear();
@@ -642,7 +1127,19 @@ impl App {
                             self.ensure_thread(&contact.fingerprint);
                             self.persist_contacts();
                             self.persist_threads();
-                            self.status_line = format!("Contact added: {}", contact.alias);
+                            self.persist_petnames();
+                            self.status_line = format!(
+                                "Contact added: {}",
+                                self.petnames.display_label(&contact.fingerprint)
+                            );
+                            if let Some(conflict) = conflicts.first() {
+                                self.status_line.push_str(&format!(
+                                    " (warning: name resembles existing contact {})",
+                                    self.petnames.display_label(&conflict.other_fingerprint)
+                                ));
+                            } else if let Some(e) = petname_warning {
+                                self.status_line.push_str(&format!(" (petname not set: {e})"));
+                            }
 
                             self.run_peer_sync();
                         } else {
@@ -661,12 +1158,213 @@ impl App {
                 self.mark_thread_read(&fp);
                 Task::none()
             }
+            Message::PetnameChanged(v) => {
+                self.forms.petname_input = v;
+                Task::none()
+            }
+            Message::SetPetname => {
+                let Some(fp) = self.forms.selected_contact_for_chat.clone() else {
+                    self.status_line = "Select a contact before setting a petname".to_string();
+                    return Task::none();
+                };
+                let petname = non_empty(self.forms.petname_input.clone());
+                match self.petnames.set_petname(&fp, petname.clone()) {
+                    Ok(()) => {
+                        if let Some(c) = self.contacts.iter_mut().find(|c| c.fingerprint == fp) {
+                            c.alias = petname.unwrap_or_default();
+                        }
+                        self.forms.petname_input.clear();
+                        self.persist_petnames();
+                        self.persist_contacts();
+                        self.status_line =
+                            format!("Petname set: {}", self.petnames.display_label(&fp));
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Petname failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::ConfirmPetname => {
+                let Some(fp) = self.forms.selected_contact_for_chat.clone() else {
+                    return Task::none();
+                };
+                match self.petnames.confirm_petname(&fp) {
+                    Ok(()) => {
+                        self.persist_petnames();
+                        self.status_line =
+                            format!("Petname confirmed: {}", self.petnames.display_label(&fp));
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Petname failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+
+            Message::CircleInputChanged(v) => {
+                self.forms.circle_input = v;
+                Task::none()
+            }
+            Message::AddToCircle => {
+                let Some(fp) = self.forms.selected_contact_for_chat.clone() else {
+                    self.status_line = "Select a contact before adding it to a circle".to_string();
+                    return Task::none();
+                };
+                let name = self.forms.circle_input.clone();
+                match self.circles.add_member(&name, &fp) {
+                    Ok(()) => {
+                        self.forms.circle_input.clear();
+                        self.persist_circles();
+                        self.status_line = format!(
+                            "Added {} to circle {}",
+                            self.petnames.display_label(&fp),
+                            name.trim()
+                        );
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Circle update failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::RemoveFromCircle(name) => {
+                if let Some(fp) = self.forms.selected_contact_for_chat.clone() {
+                    self.circles.remove_member(&name, &fp);
+                    if self.circles.get(&name).is_some_and(|c| c.members.is_empty()) {
+                        self.circles.remove(&name);
+                        if self.forms.post_audience.as_deref() == Some(name.as_str()) {
+                            self.forms.post_audience = None;
+                        }
+                    }
+                    self.persist_circles();
+                }
+                Task::none()
+            }
 
             Message::ComposePostChanged(v) => {
                 self.forms.compose_post_input = v;
                 Task::none()
             }
+            Message::EphemeralPostToggled(on) => {
+                self.forms.post_ephemeral = on;
+                Task::none()
+            }
+            Message::ContentWarningChanged(v) => {
+                self.forms.post_cw_input = v;
+                Task::none()
+            }
+            Message::ContentCategoryToggled(category, on) => {
+                self.forms.post_cw_categories.retain(|c| *c != category);
+                if on {
+                    self.forms.post_cw_categories.push(category);
+                }
+                Task::none()
+            }
+            Message::PollOptionsChanged(v) => {
+                self.forms.post_poll_options = v;
+                Task::none()
+            }
+            Message::PollHoursChanged(v) => {
+                self.forms.post_poll_hours = v;
+                Task::none()
+            }
+            Message::PollMultipleToggled(on) => {
+                self.forms.post_poll_multiple = on;
+                Task::none()
+            }
+            Message::PollChoiceToggled(author, post_id, choice, on) => {
+                let multiple = self
+                    .feed
+                    .get(&author, &post_id)
+                    .and_then(|e| e.post.post.poll.as_ref())
+                    .is_some_and(|p| p.multiple_choice);
+                let selection = self.poll_selections.entry((author, post_id)).or_default();
+                if !multiple {
+                    selection.clear();
+                }
+                selection.retain(|c| *c != choice);
+                if on {
+                    selection.push(choice);
+                }
+                Task::none()
+            }
+            Message::CastVote(author, post_id) => {
+                let (Some(kp), Some(entry)) = (self.keypair.clone(), self.feed.get(&author, &post_id)) else {
+                    return Task::none();
+                };
+                let choices = self.poll_selections.get(&(author, post_id)).cloned().unwrap_or_default();
+                Task::perform(
+                    cast_vote_async(entry.post.post.clone(), choices, kp),
+                    Message::VoteSigned,
+                )
+            }
+            Message::VoteSigned(result) => {
+                let recorded = result.and_then(|vote| {
+                    let entry = self
+                        .feed
+                        .get(&vote.vote.poll_author_fingerprint, &vote.vote.poll_id)
+                        .ok_or("Poll is no longer in the feed")?;
+                    self.poll_votes.add(vote.clone(), &entry.post.post, Utc::now())?;
+                    Ok(vote)
+                });
+                match recorded {
+                    Ok(vote) => {
+                        self.poll_selections
+                            .remove(&(vote.vote.poll_author_fingerprint.clone(), vote.vote.poll_id.clone()));
+                        self.persist_poll_votes();
+                        self.publish_local_posts_to_swarm();
+                        self.deliver_vote_to_author(&vote);
+                        self.status_line = "Vote sent".to_string();
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Vote failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::PublishTally(post_id) => {
+                let Some(kp) = &self.keypair else {
+                    return Task::none();
+                };
+                let Some(entry) = self.feed.get(&kp.fingerprint, &post_id) else {
+                    return Task::none();
+                };
+                match SignedPollTally::create(self.poll_votes.tally(&entry.post.post), kp) {
+                    Ok(tally) => {
+                        self.poll_tallies.retain(|t| t.tally.poll_id != post_id);
+                        self.poll_tallies.push(tally);
+                        if let Err(e) = self.storage.set_json(STORAGE_POLL_TALLIES, &self.poll_tallies) {
+                            self.status_line = format!("Persist tallies failed: {e}");
+                        }
+                        self.publish_local_posts_to_swarm();
+                        self.status_line = "Tally published".to_string();
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Tally failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::AudienceSelected(choice) => {
+                self.forms.post_audience = (choice != AUDIENCE_PUBLIC).then_some(choice);
+                Task::none()
+            }
             Message::CreatePost => {
+                if let Some(post_id) = self.forms.editing_post_id.clone() {
+                    let Some(original) =
+                        self.local_posts.iter().find(|p| p.post.id == post_id).cloned()
+                    else {
+                        self.forms.editing_post_id = None;
+                        self.status_line = "Edited post no longer exists".to_string();
+                        return Task::none();
+                    };
+                    let content = self.forms.compose_post_input.clone();
+                    return Task::perform(
+                        edit_post_async(original, content, self.keypair.clone()),
+                        Message::PostEdited,
+                    );
+                }
                 let kp = self.keypair.clone();
                 let author = self
                     .profile
@@ -674,16 +1372,95 @@ impl App {
                     .map(|p| p.profile.fingerprint.clone())
                     .unwrap_or_default();
                 let content = self.forms.compose_post_input.clone();
-                Task::perform(create_post_async(author, content, kp), Message::PostCreated)
+                let reply_to = self.forms.replying_to.clone();
+                let embedded = match &self.forms.quoting {
+                    Some((author, post_id)) => match self.embed_post(author, post_id) {
+                        Some(embedded) => Some(embedded),
+                        None => {
+                            self.forms.quoting = None;
+                            self.status_line = "Quoted post is no longer available".to_string();
+                            return Task::none();
+                        }
+                    },
+                    None => None,
+                };
+                let audience = match &self.forms.post_audience {
+                    Some(circle) => match self.circle_recipients(circle) {
+                        Ok(recipients) => Some(recipients),
+                        Err(e) => {
+                            self.status_line = format!("Post failed: {e}");
+                            return Task::none();
+                        }
+                    },
+                    None => None,
+                };
+                let content_warning = if self.forms.post_cw_input.trim().is_empty()
+                    && self.forms.post_cw_categories.is_empty()
+                {
+                    None
+                } else {
+                    match ContentWarning::new(&self.forms.post_cw_input, &self.forms.post_cw_categories) {
+                        Ok(warning) => Some(warning),
+                        Err(e) => {
+                            self.status_line = format!("Post failed: {e}");
+                            return Task::none();
+                        }
+                    }
+                };
+                let poll = match self.composer_poll() {
+                    Ok(poll) => poll,
+                    Err(e) => {
+                        self.status_line = format!("Post failed: {e}");
+                        return Task::none();
+                    }
+                };
+                let attachments = match self.import_attachment() {
+                    Ok(hashes) => hashes,
+                    Err(e) => {
+                        self.status_line = format!("Attachment failed: {e}");
+                        return Task::none();
+                    }
+                };
+                let draft = PostDraft {
+                    content,
+                    reply_to,
+                    embedded,
+                    attachments,
+                    audience,
+                    expires_in: self
+                        .forms
+                        .post_ephemeral
+                        .then(|| ChronoDuration::hours(STATUS_POST_TTL_HOURS)),
+                    content_warning,
+                    poll,
+                };
+                Task::perform(
+                    create_post_async(author, draft, self.petnames.clone(), kp),
+                    Message::PostCreated,
+                )
             }
             Message::PostCreated(result) => {
                 match result {
                     Ok(post) => {
                         self.local_posts.insert(0, post.clone());
                         self.forms.compose_post_input.clear();
+                        self.forms.replying_to = None;
+                        self.forms.quoting = None;
+                        self.forms.attachment_path.clear();
+                        self.forms.post_ephemeral = false;
+                        self.forms.post_cw_input.clear();
+                        self.forms.post_cw_categories.clear();
+                        self.forms.post_poll_options.clear();
+                        self.forms.post_poll_hours.clear();
+                        self.forms.post_poll_multiple = false;
                         self.persist_posts();
+                        self.record_own_posts_in_feed();
                         self.publish_one_post_to_swarm(&post);
-                        self.status_line = "Post published to peer swarm".to_string();
+                        self.status_line = if self.outbox.state(&post.post.id) == Some(OutboxState::Queued) {
+                            "Post saved; queued until a peer is reachable".to_string()
+                        } else {
+                            "Post published to peer swarm".to_string()
+                        };
                     }
                     Err(e) => {
                         self.status_line = format!("Post failed: {e}");
@@ -693,101 +1470,522 @@ impl App {
                 Task::none()
             }
 
-            Message::ComposeMessageChanged(v) => {
-                self.forms.compose_message_input = v;
+            Message::EditPost(post_id) => {
+                let current = self.keypair.as_ref().and_then(|kp| {
+                    self.local_revisions
+                        .apply(&self.local_posts, &kp.public_key)
+                        .into_iter()
+                        .find(|r| r.original.post.id == post_id)
+                });
+                if let Some(revised) = current {
+                    self.forms.compose_post_input = revised.content;
+                    self.forms.editing_post_id = Some(post_id);
+                    self.status_line = "Editing post; publish to save the edit".to_string();
+                }
                 Task::none()
             }
-            Message::ToggleMessageView(message_id) => {
-                if !self.revealed_message_ids.remove(&message_id) {
-                    self.revealed_message_ids.insert(message_id);
-                }
+            Message::CancelEditPost => {
+                self.forms.editing_post_id = None;
+                self.forms.compose_post_input.clear();
                 Task::none()
             }
-            Message::SendMessage => {
-                let recipient = self.forms.selected_contact_for_chat.clone();
-                let content = self.forms.compose_message_input.clone();
-                let kp = self.keypair.clone();
-                let sender = self
-                    .profile
-                    .as_ref()
-                    .map(|p| p.profile.fingerprint.clone())
-                    .unwrap_or_default();
-
-                if recipient.is_none() {
-                    self.status_line = "Select a contact before messaging".to_string();
-                    return Task::none();
-                }
-
-                let recipient = recipient.unwrap_or_default();
-                let recipient_enc_public = self
-                    .contacts
-                    .iter()
-                    .find(|c| c.fingerprint == recipient)
-                    .and_then(|c| c.known_encryption_public_key.clone());
-
-                if recipient_enc_public.is_none() {
-                    self.status_line =
-                        "Recipient profile missing encryption key. Run sync and try again.".to_string();
-                    return Task::none();
+            Message::PostEdited(result) => {
+                match result {
+                    Ok(edit) => {
+                        self.local_revisions.add_edit(edit);
+                        self.forms.editing_post_id = None;
+                        self.forms.compose_post_input.clear();
+                        self.persist_post_revisions();
+                        self.record_own_posts_in_feed();
+                        self.publish_local_posts_to_swarm();
+                        self.status_line = "Post edit published to peer swarm".to_string();
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Post edit failed: {e}");
+                    }
                 }
-
-                Task::perform(
-                    create_message_async(
-                        sender,
-                        recipient,
-                        content,
-                        kp,
-                        recipient_enc_public.unwrap_or_default(),
+                Task::none()
+            }
+            Message::DeletePost(post_id) => {
+                match self.local_posts.iter().find(|p| p.post.id == post_id).cloned() {
+                    Some(original) => Task::perform(
+                        delete_post_async(original, self.keypair.clone()),
+                        Message::PostDeleted,
                     ),
-                    Message::MessageSent,
-                )
+                    None => Task::none(),
+                }
             }
-            Message::MessageSent(result) => {
+            Message::PostDeleted(result) => {
                 match result {
-                    Ok(signed) => {
-                        let recipient = signed.message.recipient_fingerprint.clone();
-                        self.ensure_thread(&recipient);
-
-                        if let Some(thread) = self
-                            .threads
-                            .iter_mut()
-                            .find(|t| t.contact_fingerprint == recipient)
-                        {
-                            if !thread.messages.iter().any(|m| m.id == signed.message.id) {
-                                thread.messages.push(ChatItem {
-                                    id: signed.message.id.clone(),
-                                    incoming: false,
-                                    content: signed.message.content.clone(),
-                                    encrypted: signed.message.encrypted,
-                                    encryption_alg: signed.message.body_enc.clone(),
-                                    nonce_b64: signed.message.nonce_b64.clone(),
-                                    pushed_via_bittorrent: self.network.bittorrent_running,
-                                    created_label: ts_label(),
-                                    verified_sender: true,
-                                });
-                            }
+                    Ok(delete) => {
+                        let post_id = delete.delete.post_id.clone();
+                        self.local_revisions.add_delete(delete);
+                        if let Some(kp) = &self.keypair {
+                            self.local_revisions
+                                .prune(&mut self.local_posts, &kp.public_key);
                         }
-
-                        self.forms.compose_message_input.clear();
-                        self.persist_threads();
-
-                        self.publish_outgoing_message_to_swarm(&signed);
-
-                        if self.network.bittorrent_running {
-                            self.network.last_push_status = format!(
-                                "Pushed message {} to recipient inbox",
-                                signed.message.id
-                            );
-                            self.status_line = "Message sent via BitTorrent push".to_string();
-                        } else {
-                            self.network.last_push_status =
-                                "Queued (BitTorrent transport offline)".to_string();
-                            self.status_line =
-                                "Message queued; start BitTorrent transport".to_string();
+                        if self.forms.editing_post_id.as_deref() == Some(post_id.as_str()) {
+                            self.forms.editing_post_id = None;
+                            self.forms.compose_post_input.clear();
                         }
+                        self.persist_posts();
+                        self.persist_post_revisions();
+                        self.record_own_posts_in_feed();
+                        self.publish_local_posts_to_swarm();
+                        self.status_line = "Post deleted; tombstone published".to_string();
                     }
                     Err(e) => {
-                        self.status_line = format!("Message failed: {e}");
+                        self.status_line = format!("Post delete failed: {e}");
+                    }
+                }
+                self.recalculate_network();
+                Task::none()
+            }
+            Message::OpenThread(author, post_id) => {
+                if self.feed.mark_read(&author, &post_id) {
+                    self.persist_feed();
+                }
+                self.open_thread = Some((author, post_id));
+                Task::none()
+            }
+            Message::FeedTagFilterChanged(tag) => {
+                self.forms.feed_tag_filter = tag;
+                self.feed_pages = 1;
+                Task::none()
+            }
+            Message::FeedUnreadOnlyToggled(on) => {
+                self.forms.feed_unread_only = on;
+                self.feed_pages = 1;
+                Task::none()
+            }
+            Message::FeedHideWarnedToggled(on) => {
+                self.forms.feed_hide_warned = on;
+                self.feed_pages = 1;
+                Task::none()
+            }
+            Message::ToggleWarning(post_id) => {
+                if !self.expanded_warnings.remove(&post_id) {
+                    self.expanded_warnings.insert(post_id);
+                }
+                Task::none()
+            }
+            Message::AutoExpandToggled(category, on) => {
+                let mut preferences = self.content_preferences.clone();
+                preferences.set_auto_expand(category, on);
+                self.set_content_preferences(preferences);
+                Task::none()
+            }
+            Message::ExpandAllWarningsToggled(on) => {
+                let mut preferences = self.content_preferences.clone();
+                preferences.expand_all = on;
+                self.set_content_preferences(preferences);
+                Task::none()
+            }
+            Message::MarkPostRead(author, post_id) => {
+                if self.feed.mark_read(&author, &post_id) {
+                    self.persist_feed();
+                }
+                Task::none()
+            }
+            Message::MarkAllRead => {
+                let changed = self.feed.mark_all_read();
+                self.persist_feed();
+                self.status_line = format!("Marked {changed} post(s) read");
+                Task::none()
+            }
+            Message::LoadMoreFeed => {
+                self.feed_pages += 1;
+                Task::none()
+            }
+            Message::CloseThread => {
+                self.open_thread = None;
+                Task::none()
+            }
+            Message::ReplyToPost(post_id) => {
+                self.forms.editing_post_id = None;
+                self.forms.replying_to = Some(post_id);
+                Task::none()
+            }
+            Message::CancelReply => {
+                self.forms.replying_to = None;
+                Task::none()
+            }
+            Message::AttachmentPathChanged(v) => {
+                self.forms.attachment_path = v;
+                Task::none()
+            }
+            Message::FetchAttachment(hash) => {
+                self.status_line = "Fetching attachment from peers…".to_string();
+                Task::perform(
+                    fetch_attachment_async(self.transport.clone(), hash),
+                    Message::AttachmentFetched,
+                )
+            }
+            Message::AttachmentFetched(result) => {
+                self.attachment_states.borrow_mut().clear();
+                self.status_line = match result {
+                    Ok((_, progress)) if progress.is_complete() => "Attachment downloaded".to_string(),
+                    Ok((_, progress)) => format!(
+                        "Attachment partially downloaded ({}/{} chunks); retry to resume",
+                        progress.have_chunks, progress.total_chunks
+                    ),
+                    Err(e) => format!("Attachment fetch failed: {e}"),
+                };
+                Task::none()
+            }
+            Message::SaveAttachment(hash) => {
+                let store = self.transport.blob_store();
+                let result = store.assemble(&hash).and_then(|data| {
+                    let name = store
+                        .get_manifest(&hash)
+                        .and_then(|m| m.file_name)
+                        .and_then(|n| {
+                            std::path::Path::new(&n)
+                                .file_name()
+                                .map(|f| f.to_string_lossy().to_string())
+                        })
+                        .unwrap_or_else(|| hash.chars().take(16).collect());
+                    let path = app_output_dir().join(name);
+                    std::fs::write(&path, data)
+                        .map(|_| path)
+                        .map_err(|e| e.to_string())
+                });
+                self.status_line = match result {
+                    Ok(path) => format!("Attachment saved to {}", path.display()),
+                    Err(e) => format!("Attachment save failed: {e}"),
+                };
+                Task::none()
+            }
+            Message::RepostPost(original_author, post_id) => {
+                let Some(embedded) = self.embed_post(&original_author, &post_id) else {
+                    self.status_line = "Post is no longer available".to_string();
+                    return Task::none();
+                };
+                let author = self
+                    .profile
+                    .as_ref()
+                    .map(|p| p.profile.fingerprint.clone())
+                    .unwrap_or_default();
+                let draft = PostDraft {
+                    embedded: Some(embedded),
+                    ..PostDraft::default()
+                };
+                Task::perform(
+                    create_post_async(author, draft, self.petnames.clone(), self.keypair.clone()),
+                    Message::PostCreated,
+                )
+            }
+            Message::QuotePost(author, post_id) => {
+                self.forms.editing_post_id = None;
+                self.forms.replying_to = None;
+                self.forms.quoting = Some((author, post_id));
+                Task::none()
+            }
+            Message::CancelQuote => {
+                self.forms.quoting = None;
+                Task::none()
+            }
+            Message::ToggleLike(post_id) => {
+                let Some(kp) = self.keypair.clone() else {
+                    return Task::none();
+                };
+                let existing = self
+                    .local_reactions
+                    .active(&kp.fingerprint, ReactionTarget::Post, &post_id, LIKE_EMOJI)
+                    .map(|r| r.reaction.clone());
+                Task::perform(
+                    toggle_like_async(post_id, existing, kp),
+                    Message::ReactionSigned,
+                )
+            }
+            Message::ReactionSigned(result) => {
+                match result {
+                    Ok(reaction) => {
+                        self.local_reactions.add(reaction);
+                        self.persist_reactions();
+                        self.publish_local_posts_to_swarm();
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Reaction failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::TogglePostHistory(post_id) => {
+                if !self.expanded_post_history.remove(&post_id) {
+                    self.expanded_post_history.insert(post_id);
+                }
+                Task::none()
+            }
+
+            Message::ComposeMessageChanged(v) => {
+                self.forms.compose_message_input = v;
+                Task::none()
+            }
+            Message::ReadReceiptsToggled(on) => {
+                self.receipt_preferences.send_read_receipts = on;
+                if let Err(e) = self
+                    .storage
+                    .set_json(STORAGE_RECEIPT_PREFERENCES, &self.receipt_preferences)
+                {
+                    self.status_line = format!("Persist receipt preferences failed: {e}");
+                }
+                Task::none()
+            }
+            Message::EditChat(message_id) => {
+                match self.chat_item_plaintext(&message_id) {
+                    Ok(plaintext) => {
+                        self.forms.compose_message_input = plaintext;
+                        self.forms.editing_message_id = Some(message_id);
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Cannot edit message: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::CancelChatEdit => {
+                self.forms.editing_message_id = None;
+                self.forms.compose_message_input.clear();
+                Task::none()
+            }
+            Message::UnsendChat(message_id) => {
+                self.status_line = match self.unsend_chat_message(&message_id) {
+                    Ok(()) => "Message unsent".to_string(),
+                    Err(e) => format!("Unsend failed: {e}"),
+                };
+                Task::none()
+            }
+            Message::ChatTimerSelected(choice) => {
+                self.status_line = match self.set_chat_timer(choice) {
+                    Ok(Some(ttl)) => format!(
+                        "Disappearing messages on: {} in this chat",
+                        format_ttl(ttl)
+                    ),
+                    Ok(None) => "Disappearing messages off in this chat".to_string(),
+                    Err(e) => format!("Timer change failed: {e}"),
+                };
+                Task::none()
+            }
+            Message::GroupNameChanged(v) => {
+                self.forms.group_name_input = v;
+                Task::none()
+            }
+            Message::CreateGroup => {
+                let Some(kp) = self.keypair.clone() else {
+                    self.status_line = "Create your profile before starting a group".to_string();
+                    return Task::none();
+                };
+                let created = SignedGroup::create(&self.forms.group_name_input, &kp)
+                    .and_then(|group| self.groups.apply(group.clone()).map(|_| group));
+                match created {
+                    Ok(group) => {
+                        self.persist_groups();
+                        self.forms.group_name_input.clear();
+                        self.forms.selected_group = Some(group.group.id.clone());
+                        self.status_line = format!("Created group {}", group.group.name);
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Create group failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::SelectGroup(group_id) => {
+                self.forms.selected_group = Some(group_id.clone());
+                self.mark_group_read(&group_id);
+                Task::none()
+            }
+            Message::AddContactToGroup => {
+                let member = self
+                    .forms
+                    .selected_contact_for_chat
+                    .as_ref()
+                    .and_then(|fp| self.contacts.iter().find(|c| &c.fingerprint == fp))
+                    .and_then(|c| {
+                        Some((c.known_public_key.clone()?, c.known_encryption_public_key.clone()?))
+                    })
+                    .ok_or_else(|| "Select a synced contact to add".to_string())
+                    .and_then(|(pk, enc)| GroupMember::new(pk, enc, GroupRole::Member));
+                match member.and_then(|m| self.change_group(|group, kp| group.add_member(kp, m))) {
+                    Ok(update) => {
+                        self.status_line = format!(
+                            "Added member to {}; {} members now",
+                            update.group.name,
+                            update.group.members.len()
+                        );
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Group update failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::RemoveGroupMember(fp) => {
+                match self.change_group(|group, kp| group.remove_member(kp, &fp)) {
+                    Ok(update) => {
+                        self.status_line = format!(
+                            "Removed {} from {}",
+                            self.petnames.display_label(&fp),
+                            update.group.name
+                        );
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Group update failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::LeaveGroup => {
+                match self.change_group(|group, kp| group.leave(kp)) {
+                    Ok(update) => {
+                        self.forms.selected_group = None;
+                        self.status_line = format!("Left {}", update.group.name);
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Leave group failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::ComposeGroupChanged(v) => {
+                self.forms.compose_group_input = v;
+                Task::none()
+            }
+            Message::SendToGroup => {
+                match self.send_group_message() {
+                    Ok(recipients) => {
+                        self.forms.compose_group_input.clear();
+                        self.status_line = format!("Group message delivered to {recipients} member inbox(es)");
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Group message failed: {e}");
+                    }
+                }
+                Task::none()
+            }
+            Message::ToggleMessageView(message_id) => {
+                if !self.revealed_message_ids.remove(&message_id) {
+                    self.revealed_message_ids.insert(message_id);
+                }
+                Task::none()
+            }
+            Message::SendMessage => {
+                if let Some(message_id) = self.forms.editing_message_id.clone() {
+                    let content = self.forms.compose_message_input.clone();
+                    self.status_line = match self.edit_chat_message(&message_id, &content) {
+                        Ok(()) => {
+                            self.forms.editing_message_id = None;
+                            self.forms.compose_message_input.clear();
+                            "Message edited".to_string()
+                        }
+                        Err(e) => format!("Edit failed: {e}"),
+                    };
+                    return Task::none();
+                }
+                let recipient = self.forms.selected_contact_for_chat.clone();
+                let content = self.forms.compose_message_input.clone();
+                let kp = self.keypair.clone();
+                let sender = self
+                    .profile
+                    .as_ref()
+                    .map(|p| p.profile.fingerprint.clone())
+                    .unwrap_or_default();
+
+                if recipient.is_none() {
+                    self.status_line = "Select a contact before messaging".to_string();
+                    return Task::none();
+                }
+
+                let recipient = recipient.unwrap_or_default();
+                let recipient_enc_public = self
+                    .contacts
+                    .iter()
+                    .find(|c| c.fingerprint == recipient)
+                    .and_then(|c| c.known_encryption_public_key.clone());
+
+                if recipient_enc_public.is_none() {
+                    self.status_line =
+                        "Recipient profile missing encryption key. Run sync and try again.".to_string();
+                    return Task::none();
+                }
+
+                let thread = self.threads.iter().find(|t| t.contact_fingerprint == recipient);
+                let ttl = thread.and_then(|t| t.timer.effective());
+                let clock = thread
+                    .map(|t| LamportClock::from_entries(&t.messages))
+                    .unwrap_or_default();
+                Task::perform(
+                    create_message_async(
+                        sender,
+                        recipient,
+                        content,
+                        kp,
+                        recipient_enc_public.unwrap_or_default(),
+                        ttl,
+                        clock,
+                    ),
+                    Message::MessageSent,
+                )
+            }
+            Message::MessageSent(result) => {
+                match result {
+                    Ok(signed) => {
+                        let recipient = signed.message.recipient_fingerprint.clone();
+                        self.ensure_thread(&recipient);
+
+                        if let Some(thread) = self
+                            .threads
+                            .iter_mut()
+                            .find(|t| t.contact_fingerprint == recipient)
+                        {
+                            if !thread.messages.iter().any(|m| m.id == signed.message.id) {
+                                thread.messages.push(ChatItem {
+                                    id: signed.message.id.clone(),
+                                    incoming: false,
+                                    content: signed.message.content.clone(),
+                                    encrypted: signed.message.encrypted,
+                                    encryption_alg: signed.message.body_enc.clone(),
+                                    nonce_b64: signed.message.nonce_b64.clone(),
+                                    pushed_via_bittorrent: self.network.bittorrent_running,
+                                    created_label: sent_label(signed.message.created_at),
+                                    verified_sender: true,
+                                    expires_at: signed.message.expires_at,
+                                    status: DeliveryStatus::Sent,
+                                    acknowledged: None,
+                                    edited: false,
+                                    last_edit: None,
+                                    sent_at: Some(signed.message.created_at),
+                                    lamport: signed.message.lamport,
+                                    previous_ids: signed.message.previous_ids.clone(),
+                                });
+                                sort_causally(&mut thread.messages);
+                            }
+                        }
+                        self.ingest.record_own(&signed.message, Utc::now());
+                        self.persist_seen_messages();
+
+                        let plaintext = std::mem::take(&mut self.forms.compose_message_input);
+                        self.index_for_search(|index| index.index_message(&signed.message, &plaintext));
+                        self.persist_threads();
+
+                        self.publish_outgoing_message_to_swarm(&signed);
+
+                        match self.outbox.state(&signed.message.id) {
+                            Some(OutboxState::Queued) | None => {
+                                self.network.last_push_status =
+                                    format!("Queued message {} until a peer is reachable", signed.message.id);
+                                self.status_line = "Message queued; it will be retried when peers are reachable".to_string();
+                            }
+                            Some(state) => {
+                                self.network.last_push_status =
+                                    format!("Message {}: {}", signed.message.id, state.label());
+                                self.status_line = "Message sent via BitTorrent push".to_string();
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        self.status_line = format!("Message failed: {e}");
                     }
                 }
                 Task::none()
@@ -829,7 +2027,7 @@ impl App {
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        if self.network.bittorrent_running {
+        if self.network.bittorrent_running && !self.locked {
             time::every(Duration::from_secs(self.network.poll_interval_secs)).map(Message::Tick)
         } else {
             Subscription::none()
@@ -837,6 +2035,9 @@ impl App {
     }
 
     fn view(&self) -> Element<'_, Message> {
+        if self.locked {
+            return self.view_unlock();
+        }
         let menu = self.view_menu();
         let content = match self.panel {
             Panel::Feed => self.view_feed(),
@@ -861,6 +2062,25 @@ impl App {
             .into()
     }
 
+    fn view_unlock(&self) -> Element<'_, Message> {
+        let form = column![
+            text("Unlock SnartNet").size(24),
+            text(format!("The data in {} is encrypted.", self.storage.inner().dir().display())).size(13),
+            text_input("Passphrase", &self.forms.unlock_passphrase_input)
+                .secure(true)
+                .on_input(Message::UnlockPassphraseChanged)
+                .on_submit(Message::UnlockStorage),
+            button("Unlock").on_press(Message::UnlockStorage),
+            text(self.status_line.clone()).size(13),
+        ]
+        .spacing(12)
+        .max_width(420);
+
+        container(form)
+            .center(Length::Fill)
+            .into()
+    }
+
     fn view_menu(&self) -> Element<'_, Message> {
         let unread = self.total_unread_count();
         let msg_label = if unread > 0 {
@@ -912,6 +2132,21 @@ impl App {
         .spacing(10)
         .max_width(620);
 
+        let mut auto_expand = row![
+            checkbox("Everything", self.content_preferences.expand_all)
+                .on_toggle(Message::ExpandAllWarningsToggled),
+        ]
+        .spacing(12);
+        for category in SensitiveCategory::ALL {
+            auto_expand = auto_expand.push(
+                checkbox(category.label(), self.content_preferences.auto_expand.contains(&category))
+                    .on_toggle(move |on| Message::AutoExpandToggled(category, on)),
+            );
+        }
+        form = form
+            .push(text("── Content warnings: expand automatically ──").size(13))
+            .push(auto_expand);
+
         let profile_avatar = self
             .forms
             .avatar_data_url
@@ -1081,14 +2316,26 @@ impl App {
                     col = col.push(text("No peers discovered yet. Make sure LAN discovery is active in the Network panel.").size(12));
                 } else {
                     for peer in &self.discovered_peers {
+                        let warning = self
+                            .petnames
+                            .check_name(&peer.fingerprint, &peer.username)
+                            .first()
+                            .map(|c| {
+                                format!(
+                                    " ⚠ resembles {}",
+                                    self.petnames.display_label(&c.other_fingerprint)
+                                )
+                            })
+                            .unwrap_or_default();
                         let label = format!(
-                            "@{}{} — {}",
+                            "@{}{} — {}{}",
                             peer.username,
                             peer.display_name
                                 .as_ref()
                                 .map(|d| format!(" ({})", d))
                                 .unwrap_or_default(),
                             short_fp(&peer.fingerprint),
+                            warning,
                         );
                         col = col.push(
                             row![
@@ -1120,16 +2367,18 @@ impl App {
                         .unwrap_or(0);
 
                     let header = format!(
-                        "{} | {} | trust {} | {} | unread {}",
-                        c.alias,
+                        "{} | {} | trust {} | {} | {} | unread {}",
+                        self.petnames.display_label(&c.fingerprint),
                         short_fp(&c.fingerprint),
                         c.trust_score,
                         c.verification.label(),
+                        c.liveness().label(),
                         unread
                     );
                     let sync = format!(
-                        "{} | posts {} | {}",
+                        "{} | {} | posts {} | {}",
                         c.last_sync_label,
+                        c.last_seen_label(),
                         c.synced_post_count,
                         c.profile_summary
                     );
@@ -1162,6 +2411,17 @@ impl App {
                         }
                     }
 
+                    for conflict in self.petnames.resolve(&c.fingerprint).conflicts {
+                        body = body.push(
+                            text(format!(
+                                "⚠ name \"{}\" resembles {}",
+                                conflict.other_name,
+                                self.petnames.display_label(&conflict.other_fingerprint)
+                            ))
+                            .size(12),
+                        );
+                    }
+
                     if !err.is_empty() {
                         body = body.push(text(err).size(12));
                     }
@@ -1172,10 +2432,74 @@ impl App {
             scrollable(column(cards).spacing(8)).into()
         };
 
-        column![add_form, list].spacing(14).padding(16).into()
+        let selected_label = self
+            .forms
+            .selected_contact_for_chat
+            .as_deref()
+            .map(|fp| self.petnames.display_label(fp))
+            .unwrap_or_else(|| "no contact selected".to_string());
+        let pending = self
+            .forms
+            .selected_contact_for_chat
+            .as_deref()
+            .and_then(|fp| self.petnames.get(fp))
+            .and_then(|e| e.pending_petname.clone());
+        let mut petname_row = row![
+            text(format!("Petname for {selected_label}:")).size(13),
+            text_input("Your private name for this contact", &self.forms.petname_input)
+                .on_input(Message::PetnameChanged),
+            button("Set petname").on_press(Message::SetPetname),
+        ];
+        if let Some(name) = pending {
+            petname_row = petname_row
+                .push(button(text(format!("Confirm \"{name}\""))).on_press(Message::ConfirmPetname));
+        }
+        let petname_row = petname_row
+        .spacing(8)
+        .align_y(Alignment::Center);
+
+        let mut circle_row = row![
+            text(format!("Circles for {selected_label}:")).size(13),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center);
+        if let Some(fp) = &self.forms.selected_contact_for_chat {
+            for name in self.circles.circles_of(fp) {
+                circle_row = circle_row.push(
+                    button(text(format!("{name} ✕")).size(12))
+                        .on_press(Message::RemoveFromCircle(name.to_string())),
+                );
+            }
+        }
+        circle_row = circle_row
+            .push(
+                text_input("Circle name", &self.forms.circle_input)
+                    .on_input(Message::CircleInputChanged),
+            )
+            .push(button("Add to circle").on_press(Message::AddToCircle));
+
+        column![add_form, petname_row, circle_row, list].spacing(14).padding(16).into()
     }
 
-    fn view_messages(&self) -> Element<'_, Message> {
+    /// Picker for who the next post is visible to.
+    fn view_audience_picker(&self) -> Element<'_, Message> {
+        let mut options = vec![AUDIENCE_PUBLIC.to_string()];
+        options.extend(self.circles.names());
+        let selected = self
+            .forms
+            .post_audience
+            .clone()
+            .unwrap_or_else(|| AUDIENCE_PUBLIC.to_string());
+        row![
+            text("Audience").size(13),
+            pick_list(options, Some(selected), Message::AudienceSelected),
+        ]
+        .spacing(8)
+        .align_y(Alignment::Center)
+        .into()
+    }
+
+    fn view_messages(&self) -> Element<'_, Message> {
         let selected = self.forms.selected_contact_for_chat.as_ref();
         let contact = selected.and_then(|fp| self.contacts.iter().find(|c| &c.fingerprint == fp));
         let recipient_has_encryption_key = contact
@@ -1184,11 +2508,12 @@ impl App {
 
         let contact_line = if let Some(c) = contact {
             format!(
-                "Chat with {} ({}) | trust {} | {}",
-                c.alias,
+                "Chat with {} ({}) | trust {} | {} | {}",
+                self.petnames.display_label(&c.fingerprint),
                 short_fp(&c.fingerprint),
                 c.trust_score,
-                c.verification.label()
+                c.verification.label(),
+                c.last_seen_label()
             )
         } else {
             "Select a contact in Contacts panel".to_string()
@@ -1235,9 +2560,24 @@ impl App {
                             String::new()
                         };
 
+                        let delivery = if m.incoming {
+                            String::new()
+                        } else {
+                            match self.outbox.get(&m.id) {
+                                // Until a receipt arrives, show how far the push got.
+                                Some(item) if m.status == DeliveryStatus::Sent => match &item.last_error {
+                                    Some(e) if item.state == OutboxState::Queued => {
+                                        format!(" - queued, retrying ({e})")
+                                    }
+                                    _ => format!(" - {}", item.state.label()),
+                                },
+                                _ => format!(" - {}", m.status.label()),
+                            }
+                        };
+                        let edited = if m.edited { " (edited)" } else { "" };
                         let mut message_row = row![
                             text(format!(
-                                "[{direction}] {} ({push}, {verified}{enc_meta}) - {}",
+                                "[{direction}] {}{edited} ({push}, {verified}{enc_meta}) - {}{delivery}",
                                 body, m.created_label
                             ))
                             .size(14)
@@ -1255,6 +2595,11 @@ impl App {
                                 .on_press(Message::ToggleMessageView(m.id.clone())),
                             );
                         }
+                        if !m.incoming {
+                            message_row = message_row
+                                .push(button("Edit").on_press(Message::EditChat(m.id.clone())))
+                                .push(button("Unsend").on_press(Message::UnsendChat(m.id.clone())));
+                        }
 
                         container(message_row).padding(6).into()
                     })
@@ -1267,6 +2612,19 @@ impl App {
         } else {
             scrollable(column(thread_messages).spacing(6)).into()
         };
+        let missing = selected
+            .and_then(|fp| self.threads.iter().find(|t| &t.contact_fingerprint == fp))
+            .map(|thread| {
+                let own = self.keypair.as_ref().map(|kp| kp.fingerprint.as_str());
+                let participants = own.into_iter().chain([thread.contact_fingerprint.as_str()]);
+                missing_predecessors(&thread.messages, |id| self.ingest.has_seen_from(participants.clone(), id)).len()
+            })
+            .unwrap_or(0);
+        let gap_note = if missing > 0 {
+            format!("{missing} earlier message(s) not received yet; they will be placed in order when they arrive")
+        } else {
+            String::new()
+        };
 
         let send_status = if contact.is_none() {
             "Select a contact to message".to_string()
@@ -1276,26 +2634,197 @@ impl App {
             "Recipient encryption key missing: run sync before sending".to_string()
         };
 
+        let editing = self.forms.editing_message_id.is_some();
+        let send_label = if editing { "Save edit" } else { "Send (BitTorrent push)" };
         let send_button = if contact.is_some() && recipient_has_encryption_key {
-            button("Send (BitTorrent push)").on_press(Message::SendMessage)
+            button(send_label).on_press(Message::SendMessage)
         } else {
-            button("Send (BitTorrent push)")
+            button(send_label)
+        };
+        let mut send_row = row![send_button].spacing(12).align_y(Alignment::Center);
+        if editing {
+            send_row = send_row.push(button("Cancel edit").on_press(Message::CancelChatEdit));
+        }
+        send_row = send_row.push(
+            checkbox("Send read receipts", self.receipt_preferences.send_read_receipts)
+                .on_toggle(Message::ReadReceiptsToggled),
+        );
+
+        let thread_timer = selected
+            .and_then(|fp| self.threads.iter().find(|t| &t.contact_fingerprint == fp))
+            .map(|t| &t.timer);
+        let me = self.keypair.as_ref().map(|kp| kp.fingerprint.as_str()).unwrap_or_default();
+        let own_choice = thread_timer.map(|timer| timer.preference_of(me)).and_then(|secs| {
+            TimerChoice::ALL.into_iter().find(|c| c.ttl_secs() == secs)
+        });
+        let effective = match thread_timer.and_then(|timer| timer.effective()) {
+            Some(ttl) => format!("messages disappear after {}", format_ttl(ttl)),
+            None => "messages are kept".to_string(),
         };
+        let mut timer_row = row![text("Disappearing messages").size(13)]
+            .spacing(8)
+            .align_y(Alignment::Center);
+        if contact.is_some() {
+            timer_row = timer_row.push(pick_list(TimerChoice::ALL, own_choice, Message::ChatTimerSelected));
+        }
+        timer_row = timer_row.push(text(effective).size(12));
 
         column![
             text("Messaging").size(28),
             text(contact_line).size(14),
             text(send_status).size(12),
+            text(gap_note).size(12),
             list,
             text_input("Type a message", &self.forms.compose_message_input)
                 .on_input(Message::ComposeMessageChanged),
-            send_button,
+            send_row,
+            timer_row,
+            self.view_groups(),
         ]
         .spacing(10)
         .padding(16)
         .into()
     }
 
+    fn view_groups(&self) -> Element<'_, Message> {
+        let me = self.keypair.as_ref().map(|kp| kp.fingerprint.as_str()).unwrap_or_default();
+        let group_buttons: Vec<Element<Message>> = self
+            .groups
+            .joined(me)
+            .into_iter()
+            .map(|g| {
+                let unread = self
+                    .group_threads
+                    .iter()
+                    .find(|t| t.group_id == g.group.id)
+                    .map(|t| t.unread_count)
+                    .unwrap_or(0);
+                let badge = if unread > 0 { format!(" [{unread}]") } else { String::new() };
+                button(text(format!("{} ({}){badge}", g.group.name, g.group.members.len())))
+                    .on_press(Message::SelectGroup(g.group.id.clone()))
+                    .into()
+            })
+            .collect();
+
+        let mut section = column![
+            text("── Groups ───────────────────────").size(13),
+            row(group_buttons).spacing(6),
+            row![
+                text_input("New group name", &self.forms.group_name_input)
+                    .on_input(Message::GroupNameChanged),
+                button("Create group").on_press(Message::CreateGroup),
+            ]
+            .spacing(8),
+        ]
+        .spacing(8);
+
+        let Some(group) = self
+            .forms
+            .selected_group
+            .as_ref()
+            .and_then(|id| self.groups.get(id))
+            .map(|g| &g.group)
+        else {
+            return section.into();
+        };
+        if !group.is_member(me) {
+            return section
+                .push(text(format!("You are no longer a member of {}", group.name)).size(14))
+                .into();
+        }
+        let admin = group.is_admin(me);
+
+        let mut members = column![text(format!(
+            "{} | epoch {} | {}",
+            group.name,
+            group.epoch,
+            if admin { "you are an admin" } else { "you are a member" }
+        ))
+        .size(14)]
+        .spacing(4);
+        for member in &group.members {
+            let label = if member.fingerprint == me {
+                "You".to_string()
+            } else {
+                self.petnames.display_label(&member.fingerprint)
+            };
+            let role = match member.role {
+                GroupRole::Admin => "admin",
+                GroupRole::Member => "member",
+            };
+            let mut member_row = row![text(format!("{label} ({}) - {role}", short_fp(&member.fingerprint))).size(12)]
+                .spacing(8)
+                .align_y(Alignment::Center);
+            if admin && member.fingerprint != me {
+                member_row = member_row
+                    .push(button("Remove").on_press(Message::RemoveGroupMember(member.fingerprint.clone())));
+            }
+            members = members.push(member_row);
+        }
+
+        let contact_addable = self
+            .forms
+            .selected_contact_for_chat
+            .as_ref()
+            .filter(|fp| !group.is_member(fp))
+            .and_then(|fp| self.contacts.iter().find(|c| &c.fingerprint == fp))
+            .is_some_and(|c| c.known_public_key.is_some() && c.known_encryption_public_key.is_some());
+        let mut actions = row![].spacing(8);
+        if admin {
+            actions = actions.push(if contact_addable {
+                button("Add selected contact").on_press(Message::AddContactToGroup)
+            } else {
+                button("Add selected contact")
+            });
+        }
+        actions = actions.push(button("Leave group").on_press(Message::LeaveGroup));
+
+        let messages: Vec<Element<Message>> = self
+            .group_threads
+            .iter()
+            .find(|t| t.group_id == group.id)
+            .map(|thread| {
+                thread
+                    .messages
+                    .iter()
+                    .map(|m| {
+                        let sender = if m.message.sender_fingerprint == me {
+                            "You".to_string()
+                        } else {
+                            self.petnames.display_label(&m.message.sender_fingerprint)
+                        };
+                        let body = self
+                            .groups
+                            .open(&m.message)
+                            .unwrap_or_else(|e| format!("[{e}]"));
+                        text(format!(
+                            "{sender}: {body} - {}",
+                            m.message.created_at.format("%Y-%m-%d %H:%M")
+                        ))
+                        .size(14)
+                        .into()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let list: Element<Message> = if messages.is_empty() {
+            text("No group messages yet").size(14).into()
+        } else {
+            scrollable(column(messages).spacing(6)).height(220).into()
+        };
+
+        section = section.push(members).push(actions).push(list).push(
+            row![
+                text_input("Message the group", &self.forms.compose_group_input)
+                    .on_input(Message::ComposeGroupChanged)
+                    .on_submit(Message::SendToGroup),
+                button("Send to group").on_press(Message::SendToGroup),
+            ]
+            .spacing(8),
+        );
+        section.into()
+    }
+
     fn view_network(&self) -> Element<'_, Message> {
         let state = if self.network.bittorrent_running {
             "Running"
@@ -1346,6 +2875,11 @@ impl App {
             text(format!("Configured peers: {configured_peers}")),
             text(format!("Active swarms: {}", self.network.active_swarms)),
             text(format!("Last push: {}", self.network.last_push_status)),
+            text(format!(
+                "Outbox: {} queued, {} tracked",
+                self.outbox.queued(),
+                self.outbox.len()
+            )),
             text(format!("Last poll: {}", self.network.last_poll_label)),
             text(format!("Poll interval: {}s", self.network.poll_interval_secs)),
             text("── Shared / Downloaded Swarm Files ───────────────────────").size(13),
@@ -1504,6 +3038,7 @@ impl App {
             };
 
             let is_swarm_json = (file_name.starts_with("profile_")
+                || file_name.starts_with("heartbeat_")
                 || file_name.starts_with("posts_")
                 || file_name.starts_with("inbox_"))
                 && file_name.ends_with(".json");
@@ -1545,6 +3080,7 @@ impl App {
         if let Some(profile) = &self.profile {
             let fp = &profile.profile.fingerprint;
             active.insert(format!("profile_{fp}.json"));
+            active.insert(format!("heartbeat_{fp}.json"));
             active.insert(format!("posts_{fp}.json"));
             active.insert(format!("inbox_{fp}.json"));
         }
@@ -1552,6 +3088,7 @@ impl App {
         for contact in &self.contacts {
             let fp = &contact.fingerprint;
             active.insert(format!("profile_{fp}.json"));
+            active.insert(format!("heartbeat_{fp}.json"));
             active.insert(format!("posts_{fp}.json"));
             active.insert(format!("inbox_{fp}.json"));
         }
@@ -1566,46 +3103,679 @@ impl App {
             .map(|p| format!("@{}", p.profile.username))
             .unwrap_or_else(|| "No profile".to_string());
 
+        let composer_actions = if self.forms.editing_post_id.is_some() {
+            row![
+                button("Save edit").on_press(Message::CreatePost),
+                button("Cancel edit").on_press(Message::CancelEditPost),
+            ]
+        } else if self.forms.replying_to.is_some() {
+            row![
+                button("Publish reply").on_press(Message::CreatePost),
+                button("Cancel reply").on_press(Message::CancelReply),
+            ]
+        } else if self.forms.quoting.is_some() {
+            row![
+                button("Publish quote").on_press(Message::CreatePost),
+                button("Cancel quote").on_press(Message::CancelQuote),
+            ]
+        } else {
+            row![button("Publish post").on_press(Message::CreatePost)]
+        };
         let composer = column![
             text(format!("Feed ({author})")).size(28),
             text_input("Share an update", &self.forms.compose_post_input)
                 .on_input(Message::ComposePostChanged),
-            button("Publish post").on_press(Message::CreatePost),
+            text_input("Attach file (optional path)", &self.forms.attachment_path)
+                .on_input(Message::AttachmentPathChanged),
+            self.view_warning_picker(),
+            row![
+                text_input("Poll options, separated by |", &self.forms.post_poll_options)
+                    .on_input(Message::PollOptionsChanged),
+                text_input("Hours open", &self.forms.post_poll_hours)
+                    .on_input(Message::PollHoursChanged)
+                    .width(Length::Fixed(90.0)),
+                checkbox("Multiple choice", self.forms.post_poll_multiple)
+                    .on_toggle(Message::PollMultipleToggled),
+            ]
+            .spacing(12)
+            .align_y(Alignment::Center),
+            row![
+                self.view_audience_picker(),
+                checkbox("Disappear after 24 hours", self.forms.post_ephemeral)
+                    .on_toggle(Message::EphemeralPostToggled),
+            ]
+            .spacing(16)
+            .align_y(Alignment::Center),
+            composer_actions.spacing(8),
         ]
         .spacing(10);
 
-        let mut all_items: Vec<(String, String)> = self
-            .local_posts
+        if let Some((author, post_id)) = &self.open_thread {
+            return column![composer, self.view_conversation(author, post_id)]
+                .spacing(12)
+                .padding(16)
+                .into();
+        }
+
+        let own_fp = self.keypair.as_ref().map(|kp| kp.fingerprint.as_str());
+        let (timeline, has_more) = self.feed_timeline();
+        let unread = self.feed.unread_count();
+        let filters = row![
+            text_input("Filter by #tag", &self.forms.feed_tag_filter)
+                .on_input(Message::FeedTagFilterChanged)
+                .width(Length::Fixed(200.0)),
+            checkbox("Unread only", self.forms.feed_unread_only)
+                .on_toggle(Message::FeedUnreadOnlyToggled),
+            checkbox("Hide flagged", self.forms.feed_hide_warned)
+                .on_toggle(Message::FeedHideWarnedToggled),
+            text(format!("{unread} unread")).size(12),
+            button("Mark all read").on_press(Message::MarkAllRead),
+        ]
+        .spacing(12)
+        .align_y(Alignment::Center);
+
+        let mut items: Vec<Element<Message>> = Vec::new();
+
+        for item in &timeline {
+            let revised = &item.post;
+            let post = &revised.original.post;
+            let own = own_fp == Some(post.author_fingerprint.as_str());
+            // Contacts' plain reposts are grouped below, credited to the original.
+            if !own && post.is_repost() {
+                continue;
+            }
+            items.push(self.view_feed_item(revised, own, item.read, item.collapsed));
+        }
+        if has_more {
+            items.push(button("Load more").on_press(Message::LoadMoreFeed).into());
+        }
+
+        let synced: Vec<SignedPost> = timeline
             .iter()
-            .map(|p| {
-                (
-                    format!("You {}", p.post.created_at.format("%Y-%m-%d %H:%M UTC")),
-                    p.post.content.clone(),
-                )
-            })
+            .filter(|item| own_fp != Some(item.post.original.post.author_fingerprint.as_str()))
+            .map(|item| item.post.original.clone())
             .collect();
-
-        for c in &self.contacts {
-            if !c.latest_post_preview.is_empty() {
-                all_items.push((format!("{} synced", c.alias), c.latest_post_preview.clone()));
+        for shared in collapse_reposts(&synced).into_iter().filter(|c| c.is_reposted()) {
+            let post_id = shared.post.post.id.clone();
+            let original_author = shared.post.post.author_fingerprint.clone();
+            let reposters: Vec<String> = shared
+                .reposted_by
+                .iter()
+                .map(|fp| self.petnames.display_label(fp))
+                .collect();
+            let mut body = column![
+                text(format!(
+                    "{} · reposted by {}",
+                    self.petnames.display_label(&shared.post.post.author_fingerprint),
+                    reposters.join(", ")
+                ))
+                .size(12),
+                self.view_markup(&shared.post.post.content, 15),
+            ]
+            .spacing(4);
+            if let Some(summary) = self.reaction_summary(&post_id) {
+                body = body.push(text(summary).size(12));
             }
+            body = body.push(
+                row![
+                    button("Repost").on_press(Message::RepostPost(original_author.clone(), post_id.clone())),
+                    button("Quote").on_press(Message::QuotePost(original_author.clone(), post_id.clone())),
+                    self.like_button(&post_id),
+                ]
+                .spacing(8),
+            );
+            items.push(container(body).padding(8).into());
         }
 
-        let list: Element<Message> = if all_items.is_empty() {
+        let list: Element<Message> = if items.is_empty() {
             text("No activity yet").size(14).into()
         } else {
-            let items: Vec<Element<Message>> = all_items
-                .into_iter()
-                .map(|(meta, body)| {
-                    container(column![text(meta).size(12), text(body).size(15)].spacing(4))
-                        .padding(8)
-                        .into()
-                })
-                .collect();
             scrollable(column(items).spacing(8)).into()
         };
 
-        column![composer, list].spacing(12).padding(16).into()
+        column![composer, filters, list].spacing(12).padding(16).into()
+    }
+
+    fn view_poll(&self, post: &Post, own: bool) -> Option<Element<'_, Message>> {
+        let poll = post.poll.as_ref()?;
+        let open = poll.is_open();
+        let results = self.poll_votes.results(post);
+        let cast = self
+            .keypair
+            .as_ref()
+            .and_then(|kp| self.poll_votes.vote_of(&post.author_fingerprint, &post.id, &kp.fingerprint))
+            .map(|v| v.vote.choices.clone())
+            .unwrap_or_default();
+        let key = (post.author_fingerprint.clone(), post.id.clone());
+        let selected = self.poll_selections.get(&key).unwrap_or(&cast);
+
+        let mut body = column![].spacing(4);
+        for (i, option) in poll.options.iter().enumerate() {
+            let label = format!("{option} — {}", results.counts[i]);
+            let mut choice = checkbox(label, selected.contains(&i));
+            if open {
+                let (author, post_id) = (post.author_fingerprint.clone(), post.id.clone());
+                choice = choice.on_toggle(move |on| Message::PollChoiceToggled(author.clone(), post_id.clone(), i, on));
+            }
+            body = body.push(choice);
+        }
+
+        let state = if open { "closes" } else { "closed" };
+        let mut footer = row![text(format!(
+            "{} voter(s) seen · {state} {}",
+            results.voters,
+            poll.closes_at.format("%Y-%m-%d %H:%M UTC")
+        ))
+        .size(12)]
+        .spacing(8)
+        .align_y(Alignment::Center);
+        if open && self.poll_selections.get(&key).is_some_and(|s| !s.is_empty()) {
+            footer = footer.push(
+                button(if cast.is_empty() { "Vote" } else { "Change vote" })
+                    .on_press(Message::CastVote(post.author_fingerprint.clone(), post.id.clone())),
+            );
+        }
+        if own {
+            footer = footer.push(button("Publish tally").on_press(Message::PublishTally(post.id.clone())));
+        }
+        body = body.push(footer);
+
+        let tally = if own {
+            self.poll_tallies.iter().find(|t| t.tally.poll_id == post.id)
+        } else {
+            self.synced_poll_tallies.get(&key)
+        };
+        if let Some(tally) = tally {
+            let check = tally
+                .tally
+                .check(post, &self.poll_votes)
+                .map(|c| c.label())
+                .unwrap_or_else(|e| e);
+            let counts: Vec<String> = tally.tally.counts.iter().map(|c| c.to_string()).collect();
+            body = body.push(
+                text(format!(
+                    "{} tally {} ({}): {check}",
+                    if tally.tally.is_final { "Final" } else { "Interim" },
+                    counts.join(" / "),
+                    tally.tally.created_at.format("%Y-%m-%d %H:%M UTC"),
+                ))
+                .size(12),
+            );
+        }
+        Some(body.into())
+    }
+
+    fn view_warning_picker(&self) -> Element<'_, Message> {
+        let mut picker = row![
+            text_input("Content warning (optional)", &self.forms.post_cw_input)
+                .on_input(Message::ContentWarningChanged)
+                .width(Length::Fixed(220.0)),
+        ]
+        .spacing(12)
+        .align_y(Alignment::Center);
+        for category in SensitiveCategory::ALL {
+            picker = picker.push(
+                checkbox(category.label(), self.forms.post_cw_categories.contains(&category))
+                    .on_toggle(move |on| Message::ContentCategoryToggled(category, on)),
+            );
+        }
+        picker.into()
+    }
+
+    fn view_feed_item(
+        &self,
+        revised: &RevisedPost,
+        own: bool,
+        read: bool,
+        collapsed: bool,
+    ) -> Element<'_, Message> {
+        let post = &revised.original.post;
+        let post_id = post.id.clone();
+        let restricted = post.is_restricted();
+        let author = if own {
+            "You".to_string()
+        } else {
+            self.petnames.display_label(&post.author_fingerprint)
+        };
+        let mut meta = format!("{author} {}", post.created_at.format("%Y-%m-%d %H:%M UTC"));
+        if !read {
+            meta.insert_str(0, "● ");
+        }
+        if restricted {
+            meta.push_str(" · 🔒 circle");
+        }
+        if let Some(expires_at) = post.expires_at {
+            meta.push_str(&format!(" · ⏳ until {}", expires_at.format("%Y-%m-%d %H:%M UTC")));
+        }
+        if let Some(edited_at) = revised.edited_at {
+            meta.push_str(&format!(" (edited {})", edited_at.format("%Y-%m-%d %H:%M UTC")));
+        }
+
+        let mut actions = row![].spacing(8);
+        if own {
+            actions = actions.push(button("Edit").on_press(Message::EditPost(post_id.clone())));
+            actions = actions.push(button("Delete").on_press(Message::DeletePost(post_id.clone())));
+        } else {
+            actions = actions.push(button("Reply").on_press(Message::ReplyToPost(post_id.clone())));
+        }
+        actions = actions.push(
+            button("Conversation").on_press(Message::OpenThread(post.author_fingerprint.clone(), post_id.clone())),
+        );
+        // Circle posts stay within their audience.
+        if !own && !restricted {
+            actions = actions
+                .push(button("Repost").on_press(Message::RepostPost(post.author_fingerprint.clone(), post_id.clone())))
+                .push(button("Quote").on_press(Message::QuotePost(post.author_fingerprint.clone(), post_id.clone())));
+        }
+        actions = actions.push(self.like_button(&post_id));
+        if revised.is_edited() {
+            let expanded = self.expanded_post_history.contains(&post_id);
+            actions = actions.push(
+                button(text(if expanded {
+                    "Hide history".to_string()
+                } else {
+                    format!("History ({})", revised.history.len())
+                }))
+                .on_press(Message::TogglePostHistory(post_id.clone())),
+            );
+        }
+        if !read {
+            actions = actions.push(button("Mark read").on_press(Message::MarkPostRead(post.author_fingerprint.clone(), post_id.clone())));
+        }
+
+        let mut body = column![text(meta).size(12)].spacing(4);
+        let hidden = collapsed && !self.expanded_warnings.contains(&post_id);
+        if let Some(warning) = &post.content_warning {
+            body = body.push(
+                row![
+                    text(format!("⚠ {}", warning.label())).size(14),
+                    button(if hidden { "Show" } else { "Hide" })
+                        .on_press(Message::ToggleWarning(post_id.clone())),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            );
+        }
+        if !hidden {
+            if !revised.content.trim().is_empty() {
+                body = body.push(self.view_markup(&revised.content, 15));
+            }
+            if let Some(embedded) = &post.embedded {
+                body = body.push(self.view_embedded(embedded));
+            }
+            if let Some(attachments) = self.view_attachments(post) {
+                body = body.push(attachments);
+            }
+            if let Some(poll) = self.view_poll(post, own) {
+                body = body.push(poll);
+            }
+        }
+        if let Some(summary) = self.reaction_summary(&post_id) {
+            body = body.push(text(summary).size(12));
+        }
+        body = body.push(actions);
+        if self.expanded_post_history.contains(&post_id) {
+            body = body.push(text(format!("original: {}", post.content)).size(12));
+            for edit in revised.history.iter().rev().skip(1).rev() {
+                body = body.push(
+                    text(format!(
+                        "{}: {}",
+                        edit.edited_at.format("%Y-%m-%d %H:%M UTC"),
+                        edit.content
+                    ))
+                    .size(12),
+                );
+            }
+        }
+        container(body).padding(8).into()
+    }
+
+    /// `author`'s post `post_id` packaged for a repost or quote, together
+    /// with their public key.
+    fn embed_post(&self, author: &str, post_id: &str) -> Option<EmbeddedPost> {
+        let key = (author.to_string(), post_id.to_string());
+        if let Some(embedded) = self.shared_originals.get(&key) {
+            return Some(embedded.clone());
+        }
+        let original = self.known_posts().remove(&key)?.original;
+        let author = &original.post.author_fingerprint;
+        let public_key = match &self.keypair {
+            Some(kp) if &kp.fingerprint == author => kp.public_key.clone(),
+            _ => self
+                .contacts
+                .iter()
+                .find(|c| &c.fingerprint == author)?
+                .known_public_key
+                .clone()?,
+        };
+        // Reposts of reposts share the underlying original.
+        Some(EmbeddedPost::new(original, public_key).innermost())
+    }
+
+    /// Chunk the file named in the composer into the blob store, returning
+    /// manifest hashes for `Post.attachment_hashes`.
+    fn import_attachment(&self) -> Result<Vec<String>, String> {
+        let path = self.forms.attachment_path.trim();
+        if path.is_empty() {
+            return Ok(Vec::new());
+        }
+        let data = std::fs::read(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let file_name = std::path::Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string());
+        let hash = self.transport.blob_store().import(&data, None, file_name)?;
+        Ok(vec![hash])
+    }
+
+    fn view_attachments(&self, post: &Post) -> Option<Element<'_, Message>> {
+        if post.attachment_hashes.is_empty() {
+            return None;
+        }
+        let store = self.transport.blob_store();
+        let mut states = self.attachment_states.borrow_mut();
+        let rows: Vec<Element<Message>> = post
+            .attachment_hashes
+            .iter()
+            .map(|hash| {
+                let state = states.entry(hash.clone()).or_insert_with(|| {
+                    store.get_manifest(hash).map(|manifest| {
+                        let missing = store.missing_chunks(&manifest).len();
+                        (manifest, missing)
+                    })
+                });
+                let Some((manifest, missing)) = state.as_ref() else {
+                    return row![
+                        text("📎 attachment").size(12),
+                        button("Download").on_press(Message::FetchAttachment(hash.clone())),
+                    ]
+                    .spacing(8)
+                    .into();
+                };
+                let mut label = format!(
+                    "📎 {} · {} · {} KiB",
+                    manifest.file_name.as_deref().unwrap_or("attachment"),
+                    manifest.mime_type,
+                    manifest.size.div_ceil(1024)
+                );
+                if let (Some(w), Some(h)) = (manifest.width, manifest.height) {
+                    label.push_str(&format!(" · {w}×{h}"));
+                }
+                let action = if *missing == 0 {
+                    button("Save").on_press(Message::SaveAttachment(hash.clone()))
+                } else {
+                    button(text(format!(
+                        "Download ({}/{})",
+                        manifest.chunks.len() - missing,
+                        manifest.chunks.len()
+                    )))
+                    .on_press(Message::FetchAttachment(hash.clone()))
+                };
+                row![text(label).size(12), action].spacing(8).into()
+            })
+            .collect();
+        Some(column(rows).spacing(4).into())
+    }
+
+    /// Render post markup: emphasis, code, links, and mentions shown with the
+    /// reader's petnames so they cannot be spoofed by display names.
+    fn view_markup(&self, content: &str, size: u16) -> Element<'_, Message> {
+        const ACCENT: Color = Color::from_rgb(0.25, 0.5, 0.9);
+
+        fn spans(
+            inlines: &[Inline],
+            font: Font,
+            petnames: &PetnameBook,
+            out: &mut Vec<iced::widget::text::Span<'static, Message>>,
+        ) {
+            for inline in inlines {
+                match inline {
+                    Inline::Text { text } => out.push(span(text.clone()).font(font)),
+                    Inline::Emphasis { children } => spans(
+                        children,
+                        Font { style: font::Style::Italic, ..font },
+                        petnames,
+                        out,
+                    ),
+                    Inline::Strong { children } => spans(
+                        children,
+                        Font { weight: font::Weight::Bold, ..font },
+                        petnames,
+                        out,
+                    ),
+                    Inline::Code { text } => out.push(span(text.clone()).font(Font::MONOSPACE)),
+                    Inline::Link { text, url } => {
+                        let label = if text == url { text.clone() } else { format!("{text} <{url}>") };
+                        out.push(span(label).font(font).color(ACCENT).underline(true));
+                    }
+                    Inline::Mention { handle, fingerprint } => {
+                        let label = match fingerprint {
+                            Some(fp) => format!("@{}", petnames.display_label(fp)),
+                            None => format!("@{handle}"),
+                        };
+                        out.push(span(label).font(font).color(ACCENT));
+                    }
+                    Inline::Hashtag { tag } => {
+                        out.push(span(format!("#{tag}")).font(font).color(ACCENT))
+                    }
+                    Inline::LineBreak => out.push(span("\n")),
+                }
+            }
+        }
+
+        let mut document = {
+            let mut cache = self.markup_cache.borrow_mut();
+            if cache.len() >= MARKUP_CACHE_ENTRIES && !cache.contains_key(content) {
+                cache.clear();
+            }
+            cache
+                .entry(content.to_string())
+                .or_insert_with(|| Document::parse(content))
+                .clone()
+        };
+        // Petnames can change between frames, so mentions resolve per view.
+        document.resolve_mentions(&self.petnames);
+        let blocks: Vec<Element<Message>> = document
+            .blocks
+            .iter()
+            .map(|block| match block {
+                Block::Paragraph { children } => {
+                    let mut out = Vec::new();
+                    spans(children, Font::default(), &self.petnames, &mut out);
+                    rich_text(out).size(size).into()
+                }
+                Block::CodeBlock { text: code } => container(
+                    text(code.clone()).font(Font::MONOSPACE).size(size.saturating_sub(2)),
+                )
+                .padding(4)
+                .into(),
+            })
+            .collect();
+        column(blocks).spacing(6).into()
+    }
+
+    fn view_embedded(&self, embedded: &EmbeddedPost) -> Element<'_, Message> {
+        let original = &embedded.original.post;
+        container(
+            column![
+                text(format!(
+                    "🔁 {} {}",
+                    self.petnames.display_label(&original.author_fingerprint),
+                    original.created_at.format("%Y-%m-%d %H:%M UTC")
+                ))
+                .size(12),
+                self.view_markup(&original.content, 14),
+            ]
+            .spacing(2),
+        )
+        .padding(6)
+        .into()
+    }
+
+    /// Own and contacts' reactions combined.
+    fn all_reactions(&self) -> ReactionSet {
+        let mut all = self.local_reactions.clone();
+        all.merge(self.synced_reactions.clone());
+        all
+    }
+
+    /// e.g. "👍 3 · 🔥 1", or `None` when nobody has reacted.
+    fn reaction_summary(&self, post_id: &str) -> Option<String> {
+        let summary = self.all_reactions().summary(ReactionTarget::Post, post_id);
+        if summary.is_empty() {
+            return None;
+        }
+        Some(
+            summary
+                .iter()
+                .map(|c| format!("{} {}", c.emoji, c.count))
+                .collect::<Vec<_>>()
+                .join(" · "),
+        )
+    }
+
+    fn like_button(&self, post_id: &str) -> Element<'_, Message> {
+        let liked = self
+            .keypair
+            .as_ref()
+            .is_some_and(|kp| self.local_reactions.has_reacted(&kp.fingerprint, ReactionTarget::Post, post_id, LIKE_EMOJI));
+        button(if liked { "Unlike" } else { "Like" })
+            .on_press(Message::ToggleLike(post_id.to_string()))
+            .into()
+    }
+
+    /// Own posts with edits applied and circle posts decrypted.
+    fn own_posts(&self) -> Vec<RevisedPost> {
+        let Some(kp) = &self.keypair else {
+            return Vec::new();
+        };
+        self.local_revisions
+            .apply(&self.local_posts, &kp.public_key)
+            .into_iter()
+            .filter_map(|r| r.reveal(kp))
+            .collect()
+    }
+
+    /// Own and feed posts, keyed by author and ID, with edits applied.
+    fn known_posts(&self) -> HashMap<(String, String), RevisedPost> {
+        let all = FeedQuery {
+            limit: MAX_FEED_ENTRIES,
+            ..Default::default()
+        };
+        let feed = self
+            .feed
+            .page(&all, self.keypair.as_ref())
+            .map(|page| page.items)
+            .unwrap_or_default();
+        self.own_posts()
+            .into_iter()
+            .chain(feed.into_iter().map(|item| item.post))
+            .map(|r| ((r.original.post.author_fingerprint.clone(), r.original.post.id.clone()), r))
+            .collect()
+    }
+
+    /// The thread around `author`'s post `post_id`, rebuilt only when the
+    /// posts it is built from have changed since the last frame.
+    fn conversation(&self, author: &str, post_id: &str) -> Rc<Conversation> {
+        let revisions = (
+            self.local_posts.len(),
+            self.local_revisions.edits.len(),
+            self.local_revisions.deletes.len(),
+        );
+        let key = (author.to_string(), post_id.to_string(), feed_size(&self.feed), revisions);
+        if let Some(cached) = self.conversation_cache.borrow().as_ref().filter(|c| c.key == key) {
+            return Rc::clone(cached);
+        }
+
+        let mut known = self.known_posts();
+        let mut builder = ThreadBuilder::new();
+        builder.extend(known.values().map(|r| r.original.clone()));
+        let root = builder.thread(author, post_id);
+        let in_thread: HashSet<(String, String)> = root
+            .iter()
+            .flat_map(|root| root.flatten())
+            .filter_map(|(_, node)| node.post.as_ref())
+            .map(|p| (p.post.author_fingerprint.clone(), p.post.id.clone()))
+            .collect();
+        known.retain(|k, _| in_thread.contains(k));
+
+        let conversation = Rc::new(Conversation { key, root, known });
+        *self.conversation_cache.borrow_mut() = Some(Rc::clone(&conversation));
+        conversation
+    }
+
+    fn view_conversation(&self, author: &str, post_id: &str) -> Element<'_, Message> {
+        let conversation = self.conversation(author, post_id);
+
+        let header = row![
+            text("Conversation").size(20),
+            button("Back to feed").on_press(Message::CloseThread),
+        ]
+        .spacing(12);
+
+        let Some(root) = &conversation.root else {
+            return column![header, text("Post is no longer available").size(14)]
+                .spacing(8)
+                .into();
+        };
+
+        let own_fp = self.profile.as_ref().map(|p| p.profile.fingerprint.as_str());
+        let items: Vec<Element<Message>> = root
+            .flatten()
+            .into_iter()
+            .map(|(depth, node)| {
+                let indent = Space::with_width(Length::Fixed(depth as f32 * 24.0));
+                let Some(post) = &node.post else {
+                    let placeholder = if node.ambiguous {
+                        "[replying to a post ID used by several authors]"
+                    } else {
+                        "[earlier post not yet received]"
+                    };
+                    return row![indent, text(placeholder).size(13)].into();
+                };
+                let author = if Some(post.post.author_fingerprint.as_str()) == own_fp {
+                    "You".to_string()
+                } else {
+                    self.petnames.display_label(&post.post.author_fingerprint)
+                };
+                let (content, edited) = conversation
+                    .known
+                    .get(&(post.post.author_fingerprint.clone(), node.post_id.clone()))
+                    .map(|r| (r.content.clone(), r.is_edited()))
+                    .unwrap_or_else(|| (post.post.content.clone(), false));
+                let mut meta = format!(
+                    "{author} {}",
+                    post.post.created_at.format("%Y-%m-%d %H:%M UTC")
+                );
+                if post.post.is_restricted() {
+                    meta.push_str(" · 🔒 circle");
+                }
+                if edited {
+                    meta.push_str(" (edited)");
+                }
+                let mut body = column![text(meta).size(12), self.view_markup(&content, 15)].spacing(4);
+                if node.post_id == post_id && post.post.author_fingerprint == author {
+                    body = body.push(text("▲ selected").size(11));
+                }
+                if let Some(attachments) = self.view_attachments(&post.post) {
+                    body = body.push(attachments);
+                }
+                if let Some(summary) = self.reaction_summary(&node.post_id) {
+                    body = body.push(text(summary).size(12));
+                }
+                body = body.push(
+                    row![
+                        button("Reply").on_press(Message::ReplyToPost(node.post_id.clone())),
+                        self.like_button(&node.post_id),
+                    ]
+                    .spacing(8),
+                );
+                row![indent, container(body).padding(8)].into()
+            })
+            .collect();
+
+        column![header, scrollable(column(items).spacing(6))]
+            .spacing(8)
+            .into()
     }
 
     fn run_peer_sync(&mut self) {
@@ -1618,9 +3788,18 @@ impl App {
         };
 
         let local_fp = local_profile.profile.fingerprint.clone();
+        let keypair = self.keypair.clone();
         let mut inbox = self.transport.load_inbox(&local_fp).unwrap_or_default();
         let mut any_change = false;
+        let mut feed_changed = false;
+        let mut votes_changed = false;
         let mut incoming_count = 0u32;
+        let mut receipts_due: HashSet<String> = HashSet::new();
+        let mut ingest_changed = false;
+        // Inbox entries we are done with, acknowledged so relays drop them.
+        let mut handled: Vec<AckedItem> = Vec::new();
+        let mut outbox_changed = false;
+        let now = Utc::now();
 
         let contact_fingerprints: Vec<String> =
             self.contacts.iter().map(|c| c.fingerprint.clone()).collect();
@@ -1645,6 +3824,12 @@ impl App {
                         contact.known_encryption_public_key =
                             peer_profile.profile.profile.encryption_public_key.clone();
                         contact.magnet_uri = peer_profile.profile.profile.magnet_uri.clone();
+                        contact.profile_expires_at = peer_profile.profile.profile.expires_at;
+                        self.petnames.set_suggested(
+                            &contact.fingerprint,
+                            Some(peer_profile.profile.profile.username.clone()),
+                            peer_profile.profile.profile.display_name.clone(),
+                        );
                         contact.avatar_data_url = peer_profile.profile.profile.avatar_data_url.clone();
                         contact.profile_summary = format!(
                             "@{} {}",
@@ -1659,7 +3844,23 @@ impl App {
                         .trim()
                         .to_string();
                         contact.trust_score = contact.trust_score.saturating_add(3).min(100);
-                    } else {
+                        if let Some(index) = &self.search {
+                            let _ = index.index_profile(&peer_profile.profile);
+                        }
+
+                        if let Some(hb) = self.transport.load_heartbeat(&contact.fingerprint) {
+                            if hb
+                                .heartbeat
+                                .verify_for_profile(&peer_profile.profile.profile)
+                                .unwrap_or(false)
+                            {
+                                let issued_at = hb.heartbeat.heartbeat.issued_at;
+                                if contact.last_seen_at.map(|t| issued_at > t).unwrap_or(true) {
+                                    contact.last_seen_at = Some(issued_at);
+                                }
+                            }
+                        }
+                    } else {
                         contact.verification = VerificationState::SignatureInvalid;
                         contact.trust_score = contact.trust_score.saturating_sub(10);
                         contact.last_sync_error = Some("invalid profile signature".to_string());
@@ -1673,44 +3874,191 @@ impl App {
                 }
             }
 
-            let verified_posts = if let Some(peer_posts) = self.transport.load_posts(&contact.fingerprint) {
+            if let Some(peer_posts) = self.transport.load_posts(&contact.fingerprint) {
                 if let Some(pk) = &contact.known_public_key {
-                    peer_posts
-                        .posts
-                        .into_iter()
-                        .filter(|sp| sp.verify(pk).unwrap_or(false))
-                        .collect::<Vec<_>>()
-                } else {
-                    Vec::new()
+                    let mut reactions = peer_posts.reactions.clone();
+                    reactions.retain_signed_by(pk);
+                    self.synced_reactions.merge(reactions);
+                    let before = feed_size(&self.feed);
+                    if let Err(e) = self.feed.ingest(pk, peer_posts.posts, peer_posts.revisions) {
+                        contact.last_sync_error = Some(format!("post sync failed: {e}"));
+                    }
+                    feed_changed |= feed_size(&self.feed) != before;
+
+                    // Votes are self-verifying; only the contact's own count.
+                    for vote in peer_posts.poll_votes {
+                        if vote.vote.voter_fingerprint != contact.fingerprint {
+                            continue;
+                        }
+                        if let Some(entry) = self.feed.get(&vote.vote.poll_author_fingerprint, &vote.vote.poll_id) {
+                            votes_changed |= self.poll_votes.add(vote, &entry.post.post, Utc::now()).unwrap_or(false);
+                        }
+                    }
+                    for tally in peer_posts.poll_tallies {
+                        if !tally.verify(pk).unwrap_or(false) {
+                            continue;
+                        }
+                        let key = (tally.tally.author_fingerprint.clone(), tally.tally.poll_id.clone());
+                        let newer = self
+                            .synced_poll_tallies
+                            .get(&key)
+                            .is_none_or(|t| tally.tally.created_at > t.tally.created_at);
+                        if newer {
+                            self.synced_poll_tallies.insert(key, tally);
+                        }
+                    }
                 }
-            } else {
-                Vec::new()
-            };
+            }
 
+            // Circle posts we are not in the audience of are left out here.
+            let by_contact = FeedQuery {
+                author: Some(contact.fingerprint.clone()),
+                limit: MAX_FEED_ENTRIES,
+                ..Default::default()
+            };
+            let verified_posts = self
+                .feed
+                .page(&by_contact, keypair.as_ref())
+                .map(|page| page.items)
+                .unwrap_or_default();
+            for item in &verified_posts {
+                if let Some(embedded) = &item.post.original.post.embedded {
+                    let original = &embedded.original.post;
+                    self.shared_originals.insert(
+                        (original.author_fingerprint.clone(), original.id.clone()),
+                        (**embedded).clone(),
+                    );
+                }
+            }
             contact.synced_post_count = verified_posts.len();
             contact.latest_post_preview = verified_posts
                 .first()
-                .map(|p| p.post.content.clone())
+                .map(|item| {
+                    let p = &item.post;
+                    let content = match &p.original.post.embedded {
+                        Some(embedded) if p.original.post.is_repost() => format!(
+                            "🔁 {}: {}",
+                            self.petnames
+                                .display_label(&embedded.original.post.author_fingerprint),
+                            embedded.original.post.content
+                        ),
+                        _ => p.content.clone(),
+                    };
+                    if p.is_edited() {
+                        format!("{content} (edited)")
+                    } else {
+                        content
+                    }
+                })
                 .unwrap_or_else(|| "No synced posts".to_string());
 
+            // Retracted messages and stale edits leave our inbox copy too.
+            if let Some(pk) = &contact.known_public_key {
+                apply_retractions(&mut inbox.messages, &contact.fingerprint, pk);
+            }
+            let mut rejected: HashSet<String> = HashSet::new();
+
             if let Some(thread) = self
                 .threads
                 .iter_mut()
                 .find(|t| t.contact_fingerprint == contact.fingerprint)
             {
                 for msg in &inbox.messages {
-                    if msg.message.sender_fingerprint != contact.fingerprint {
+                    if msg.message.is_group() || msg.message.sender_fingerprint != contact.fingerprint {
+                        continue;
+                    }
+                    if let Some(kind) = msg.message.receipt_kind() {
+                        let verified = contact
+                            .known_public_key
+                            .as_ref()
+                            .is_some_and(|pk| self.ingest.check(msg, pk, now).is_ok());
+                        let ids = msg
+                            .message
+                            .receipt_message_ids(keypair.as_ref(), contact.known_encryption_public_key.as_deref())
+                            .unwrap_or_default();
+                        for item in &mut thread.messages {
+                            if verified && !item.incoming && ids.contains(&item.id) {
+                                any_change |= item.status.advance(kind);
+                            }
+                        }
+                        if verified {
+                            outbox_changed |= self.outbox.acknowledge(&contact.fingerprint, &ids) > 0;
+                            handled.push(msg.acked_item());
+                        }
+                        continue;
+                    }
+                    if msg.message.is_control() {
+                        let verified = contact
+                            .known_public_key
+                            .as_ref()
+                            .is_some_and(|pk| self.ingest.check(msg, pk, now).is_ok());
+                        if !verified {
+                            continue;
+                        }
+                        let applied = apply_chat_control(thread, &msg.message);
+                        // An edit may arrive before its message; keep it until then.
+                        if msg.message.control_target().is_none_or(|target| {
+                            self.ingest.has_seen(&msg.message.sender_fingerprint, target)
+                                || thread.messages.iter().any(|m| m.id == target)
+                        }) {
+                            handled.push(msg.acked_item());
+                        }
+                        if !applied {
+                            continue;
+                        }
+                        any_change = true;
+                        let (Some(index), Some(target)) = (&self.search, msg.message.control_target()) else {
+                            continue;
+                        };
+                        match thread.messages.iter().find(|m| m.id == target) {
+                            // Re-index the edited text under the original id.
+                            Some(item) => {
+                                if let Ok(plaintext) = decrypt_for_display(
+                                    item,
+                                    keypair.as_ref(),
+                                    contact.known_encryption_public_key.as_deref(),
+                                ) {
+                                    let mut indexed = msg.message.clone();
+                                    indexed.id = target.to_string();
+                                    let _ = index.index_message(&indexed, &plaintext);
+                                }
+                            }
+                            None => {
+                                let _ = index.remove(SearchKind::Message, &msg.message.conversation_id(), target);
+                            }
+                        }
                         continue;
                     }
                     if thread.messages.iter().any(|m| m.id == msg.message.id) {
+                        handled.push(msg.acked_item());
                         continue;
                     }
-
-                    let verified_sender = contact
-                        .known_public_key
-                        .as_ref()
-                        .map(|pk| msg.verify(pk).unwrap_or(false))
-                        .unwrap_or(false);
+                    // Wait for a verified profile before accepting anything.
+                    let Some(pk) = contact.known_public_key.as_deref() else {
+                        continue;
+                    };
+                    let admitted = self.ingest.admit(msg, pk, now);
+                    if !matches!(admitted, Err(Rejection::FromFuture { .. })) {
+                        handled.push(msg.acked_item());
+                    }
+                    match admitted {
+                        Ok(()) => ingest_changed = true,
+                        // Shown before, then unsent or expired here.
+                        Err(Rejection::Replayed) => continue,
+                        // Possibly our clock; try again next sync.
+                        Err(rejection @ Rejection::FromFuture { .. }) => {
+                            contact.last_sync_error = Some(format!("message held back: {rejection}"));
+                            continue;
+                        }
+                        Err(rejection) => {
+                            if matches!(rejection, Rejection::BadSignature | Rejection::SenderMismatch) {
+                                contact.trust_score = contact.trust_score.saturating_sub(2);
+                            }
+                            contact.last_sync_error = Some(format!("message rejected: {rejection}"));
+                            rejected.insert(msg.message.id.clone());
+                            continue;
+                        }
+                    }
 
                     thread.messages.push(ChatItem {
                         id: msg.message.id.clone(),
@@ -1720,9 +4068,27 @@ impl App {
                         encryption_alg: msg.message.body_enc.clone(),
                         nonce_b64: msg.message.nonce_b64.clone(),
                         pushed_via_bittorrent: true,
-                        created_label: ts_label(),
-                        verified_sender,
+                        created_label: sent_label(msg.message.created_at),
+                        verified_sender: true,
+                        expires_at: msg.message.expires_at,
+                        status: DeliveryStatus::Sent,
+                        acknowledged: None,
+                        edited: false,
+                        last_edit: None,
+                        sent_at: Some(msg.message.created_at),
+                        lamport: msg.message.lamport,
+                        previous_ids: msg.message.previous_ids.clone(),
                     });
+                    // Only verified messages are indexed, and only on this device.
+                    if let (Some(index), Some(item)) = (&self.search, thread.messages.last()) {
+                        if let Ok(plaintext) = decrypt_for_display(
+                            item,
+                            keypair.as_ref(),
+                            contact.known_encryption_public_key.as_deref(),
+                        ) {
+                            let _ = index.index_message(&msg.message, &plaintext);
+                        }
+                    }
 
                     if !(self.panel == Panel::Messages
                         && self
@@ -1735,28 +4101,148 @@ impl App {
                         thread.unread_count = thread.unread_count.saturating_add(1);
                     }
 
-                    if verified_sender {
-                        contact.trust_score = contact.trust_score.saturating_add(1).min(100);
-                    } else {
-                        contact.trust_score = contact.trust_score.saturating_sub(2);
-                    }
-
+                    contact.trust_score = contact.trust_score.saturating_add(1).min(100);
                     incoming_count = incoming_count.saturating_add(1);
                     any_change = true;
+                    receipts_due.insert(contact.fingerprint.clone());
                 }
+                sort_causally(&mut thread.messages);
             }
+            // Rejected messages would only be rejected again.
+            inbox.messages.retain(|m| !rejected.contains(&m.message.id));
 
             contact.last_sync_label = format!("synced {}", ts_label());
         }
 
+        // Group membership first, so sender keys and messages are checked
+        // against the epoch they were made for.
+        let mut group_updates = inbox.group_updates.clone();
+        group_updates.sort_by_key(|g| g.group.epoch);
+        let mut groups_changed = false;
+        for update in group_updates {
+            let item = update.acked_item();
+            if let Ok(changed) = self.groups.apply(update) {
+                groups_changed |= changed;
+                handled.push(item);
+            }
+        }
+        if let Some(kp) = &keypair {
+            for distribution in &inbox.sender_keys {
+                if let Ok(changed) = self.groups.receive_sender_key(distribution, kp) {
+                    groups_changed |= changed;
+                    handled.push(distribution.acked_item());
+                }
+            }
+        }
+        let mut group_threads_changed = false;
+        for msg in &inbox.messages {
+            let MessageType::Group { group_id, .. } = &msg.message.message_type else {
+                continue;
+            };
+            let Some(sender) = self
+                .groups
+                .get(group_id)
+                .and_then(|g| g.group.member(&msg.message.sender_fingerprint))
+            else {
+                continue;
+            };
+            let known = self
+                .group_threads
+                .iter()
+                .any(|t| &t.group_id == group_id && t.messages.iter().any(|m| m.message.id == msg.message.id));
+            if known {
+                handled.push(msg.acked_item());
+                continue;
+            }
+            match self.ingest.admit(msg, &sender.public_key, now) {
+                Ok(()) => handled.push(msg.acked_item()),
+                Err(Rejection::FromFuture { .. }) => continue,
+                Err(_) => {
+                    handled.push(msg.acked_item());
+                    continue;
+                }
+            }
+            ingest_changed = true;
+            let index = match self.group_threads.iter().position(|t| &t.group_id == group_id) {
+                Some(i) => i,
+                None => {
+                    self.group_threads.push(GroupThread {
+                        group_id: group_id.clone(),
+                        messages: Vec::new(),
+                        unread_count: 0,
+                    });
+                    self.group_threads.len() - 1
+                }
+            };
+            let thread = &mut self.group_threads[index];
+            thread.messages.push(msg.clone());
+            if !(self.panel == Panel::Messages && self.forms.selected_group.as_ref() == Some(group_id)) {
+                thread.unread_count = thread.unread_count.saturating_add(1);
+            }
+            if let (Some(index), Ok(plaintext)) = (&self.search, self.groups.open(&msg.message)) {
+                let _ = index.index_message(&msg.message, &plaintext);
+            }
+            incoming_count = incoming_count.saturating_add(1);
+            group_threads_changed = true;
+        }
+
+        // Votes sent to our own polls, from anyone.
+        for vote in &inbox.poll_votes {
+            if vote.vote.poll_author_fingerprint != local_fp {
+                continue;
+            }
+            if let Some(entry) = self.feed.get(&vote.vote.poll_author_fingerprint, &vote.vote.poll_id) {
+                if let Ok(changed) = self.poll_votes.add(vote.clone(), &entry.post.post, now) {
+                    votes_changed |= changed;
+                    handled.push(vote.acked_item());
+                }
+            }
+        }
+
         // Prune duplicate inbox entries by id so polling remains linear over time.
         dedupe_inbox(&mut inbox);
+        if let Some(kp) = &keypair {
+            let watermark = now - ChronoDuration::days(MESSAGE_MAX_AGE_DAYS);
+            for ids in handled.chunks(MAX_INBOX_ACK_IDS) {
+                if let Ok(ack) = SignedInboxAck::create(kp, ids, Some(watermark)) {
+                    inbox.acks.push(ack);
+                }
+            }
+        }
         let _ = self.transport.save_inbox(&local_fp, &inbox);
 
         if any_change {
             self.persist_threads();
         }
+        if ingest_changed {
+            self.persist_seen_messages();
+        }
+        if outbox_changed {
+            self.persist_outbox();
+        }
+        for fp in receipts_due {
+            self.send_receipts(&fp, ReceiptKind::Delivered);
+        }
+        if self.panel == Panel::Messages {
+            if let Some(fp) = self.forms.selected_contact_for_chat.clone() {
+                self.send_receipts(&fp, ReceiptKind::Read);
+            }
+        }
+        if feed_changed {
+            self.persist_feed();
+            self.reindex_feed();
+        }
+        if votes_changed {
+            self.persist_poll_votes();
+        }
+        if groups_changed {
+            self.persist_groups();
+        }
+        if group_threads_changed {
+            self.persist_group_threads();
+        }
         self.persist_contacts();
+        self.persist_petnames();
 
         if incoming_count > 0 {
             self.status_line = format!("Synced {} new incoming message(s)", incoming_count);
@@ -1773,6 +4259,49 @@ impl App {
         }
     }
 
+    /// Drop expired posts and messages from local state and storage, and
+    /// republish our posts so the swarm copy loses them too.
+    fn purge_expired_local(&mut self) {
+        let now = Utc::now();
+        if purge_expired(&mut self.local_posts, now) > 0 {
+            let posts = &self.local_posts;
+            self.local_revisions
+                .edits
+                .retain(|e| posts.iter().any(|p| p.post.id == e.edit.post_id));
+            self.persist_posts();
+            self.persist_post_revisions();
+            self.publish_local_posts_to_swarm();
+        }
+        if self.feed.purge_expired(now) > 0 {
+            self.persist_feed();
+        }
+        self.index_for_search(|index| index.purge_expired(now));
+
+        let mut threads_changed = false;
+        for thread in &mut self.threads {
+            let before = thread.messages.len();
+            thread
+                .messages
+                .retain(|m| m.expires_at.is_none_or(|exp| exp > now));
+            threads_changed |= thread.messages.len() != before;
+        }
+        if threads_changed {
+            self.persist_threads();
+        }
+    }
+
+    fn persist_post_revisions(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_POST_REVISIONS, &self.local_revisions) {
+            self.status_line = format!("Persist post revisions failed: {e}");
+        }
+    }
+
+    fn persist_reactions(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_REACTIONS, &self.local_reactions) {
+            self.status_line = format!("Persist reactions failed: {e}");
+        }
+    }
+
     fn persist_contacts(&mut self) {
         if let Err(e) = self.storage.set_json(STORAGE_CONTACTS, &self.contacts) {
             self.status_line = format!("Persist contacts failed: {e}");
@@ -1785,6 +4314,232 @@ impl App {
         }
     }
 
+    fn persist_seen_messages(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_SEEN_MESSAGES, &self.ingest) {
+            self.status_line = format!("Persist seen messages failed: {e}");
+        }
+    }
+
+    fn persist_petnames(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_PETNAMES, &self.petnames) {
+            self.status_line = format!("Persist petnames failed: {e}");
+        }
+    }
+
+    fn persist_feed(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_FEED, &self.feed) {
+            self.status_line = format!("Persist feed failed: {e}");
+        }
+    }
+
+    /// Merge own posts and revisions into the feed, so it reflects creates,
+    /// edits and deletions made on this device.
+    fn record_own_posts_in_feed(&mut self) {
+        let Some(kp) = &self.keypair else {
+            return;
+        };
+        if let Err(e) = self
+            .feed
+            .ingest_own(kp, self.local_posts.clone(), self.local_revisions.clone())
+        {
+            self.status_line = format!("Feed update failed: {e}");
+            return;
+        }
+        self.persist_feed();
+        self.reindex_feed();
+    }
+
+    /// Fill the search index from the decrypted threads, own profile and
+    /// feed. Unchanged documents are skipped, so this is cheap on a current
+    /// index and refills the in-memory one used while storage is encrypted.
+    fn rebuild_search_index(&mut self) {
+        let Some(index) = &self.search else {
+            return;
+        };
+        let kp = self.keypair.as_ref();
+        let own_fp = kp.map(|kp| kp.fingerprint.clone()).unwrap_or_default();
+        let mut result = Ok(true);
+        for thread in &self.threads {
+            let peer_key = self
+                .contacts
+                .iter()
+                .find(|c| c.fingerprint == thread.contact_fingerprint)
+                .and_then(|c| c.known_encryption_public_key.as_deref());
+            for item in &thread.messages {
+                let Ok(plaintext) = decrypt_for_display(item, kp, peer_key) else {
+                    continue;
+                };
+                let (sender, recipient) = if item.incoming {
+                    (thread.contact_fingerprint.clone(), own_fp.clone())
+                } else {
+                    (own_fp.clone(), thread.contact_fingerprint.clone())
+                };
+                let message = CoreMessage {
+                    id: item.id.clone(),
+                    sender_fingerprint: sender,
+                    recipient_fingerprint: recipient,
+                    content: String::new(),
+                    created_at: item.sent_at.unwrap_or_else(Utc::now),
+                    encrypted: false,
+                    body_enc: None,
+                    nonce_b64: None,
+                    message_type: MessageType::Direct,
+                    expires_at: item.expires_at,
+                    lamport: item.lamport,
+                    previous_ids: Vec::new(),
+                };
+                result = result.and(index.index_message(&message, &plaintext));
+            }
+        }
+        for thread in &self.group_threads {
+            for msg in &thread.messages {
+                if let Ok(plaintext) = self.groups.open(&msg.message) {
+                    result = result.and(index.index_message(&msg.message, &plaintext));
+                }
+            }
+        }
+        if let Some(sp) = &self.profile {
+            result = result.and(index.index_profile(sp));
+        }
+        if let Err(e) = result {
+            self.status_line = format!("Search index rebuild failed: {e}");
+        }
+        self.reindex_feed();
+    }
+
+    fn reindex_feed(&mut self) {
+        let (Some(index), Some(kp)) = (&self.search, &self.keypair) else {
+            return;
+        };
+        if let Err(e) = index.sync_feed(&self.feed, Some(kp)) {
+            self.status_line = format!("Search index update failed: {e}");
+        }
+    }
+
+    /// Run an update against the search index, if there is one. Failures
+    /// only affect search, so they are reported and otherwise ignored.
+    fn index_for_search<T>(&mut self, update: impl FnOnce(&SearchIndex) -> Result<T, String>) {
+        if let Some(index) = &self.search {
+            if let Err(e) = update(index) {
+                self.status_line = format!("Search index update failed: {e}");
+            }
+        }
+    }
+
+    /// The first `feed_pages` pages of the timeline under the current
+    /// filters, and whether more posts follow.
+    fn feed_timeline(&self) -> (Vec<FeedItem>, bool) {
+        let tag = self.forms.feed_tag_filter.trim();
+        let mut query = FeedQuery {
+            tag: (!tag.is_empty()).then(|| tag.to_string()),
+            unread_only: self.forms.feed_unread_only,
+            hide_warned: self.forms.feed_hide_warned,
+            limit: FEED_PAGE_SIZE,
+            ..Default::default()
+        };
+        let mut items = Vec::new();
+        for _ in 0..self.feed_pages {
+            let Ok(mut page) = self.feed.page(&query, self.keypair.as_ref()) else {
+                break;
+            };
+            page.apply_preferences(&self.content_preferences);
+            items.extend(page.items);
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return (items, false),
+            }
+        }
+        (items, true)
+    }
+
+    fn persist_poll_votes(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_POLL_VOTES, &self.poll_votes) {
+            self.status_line = format!("Persist votes failed: {e}");
+        }
+    }
+
+    /// The poll described in the composer, if any options were entered.
+    fn composer_poll(&self) -> Result<Option<Poll>, String> {
+        let options: Vec<String> = self
+            .forms
+            .post_poll_options
+            .split('|')
+            .map(|o| o.trim().to_string())
+            .filter(|o| !o.is_empty())
+            .collect();
+        if options.is_empty() {
+            return Ok(None);
+        }
+        let hours = match self.forms.post_poll_hours.trim() {
+            "" => DEFAULT_POLL_HOURS,
+            raw => raw
+                .parse::<i64>()
+                .ok()
+                .filter(|h| *h > 0)
+                .ok_or("Poll must stay open a whole number of hours")?,
+        };
+        let closes_at = Utc::now() + ChronoDuration::hours(hours);
+        Poll::new(options, closes_at, self.forms.post_poll_multiple).map(Some)
+    }
+
+    fn set_content_preferences(&mut self, preferences: ContentPreferences) {
+        self.content_preferences = preferences;
+        if let Err(e) = self
+            .storage
+            .set_json(STORAGE_CONTENT_PREFERENCES, &self.content_preferences)
+        {
+            self.status_line = format!("Persist content preferences failed: {e}");
+        }
+    }
+
+    fn persist_circles(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_CIRCLES, &self.circles) {
+            self.status_line = format!("Persist circles failed: {e}");
+        }
+    }
+
+    /// Recipients for a post restricted to `circle`: its members, using the
+    /// encryption keys from their verified profiles, plus ourselves.
+    fn circle_recipients(&self, circle: &str) -> Result<Vec<Recipient>, String> {
+        let kp = self.keypair.as_ref().ok_or("No keypair available")?;
+        let known: Vec<Recipient> = self
+            .contacts
+            .iter()
+            .filter_map(|c| {
+                let key = c.known_encryption_public_key.clone()?;
+                Some(Recipient::new(c.fingerprint.clone(), key))
+            })
+            .collect();
+        let mut recipients = self.circles.recipients(circle, &known).map_err(|e| {
+            // Name contacts by petname rather than raw fingerprint.
+            self.contacts.iter().fold(e, |e, c| {
+                e.replace(&c.fingerprint, &self.petnames.display_label(&c.fingerprint))
+            })
+        })?;
+        recipients.push(Recipient::from_keypair(kp)?);
+        Ok(recipients)
+    }
+
+    /// Carry aliases from before the petname book existed over as petnames.
+    fn seed_petnames_from_aliases(&mut self) {
+        let mut changed = false;
+        for c in &self.contacts {
+            // Aliases may have come from an invite or a discovered peer's own
+            // name, so they only become petnames once the user confirms them.
+            let unnamed = self
+                .petnames
+                .get(&c.fingerprint)
+                .is_none_or(|e| e.petname.is_none() && e.pending_petname.is_none());
+            if unnamed && !c.alias.trim().is_empty() {
+                self.petnames.suggest_petname(&c.fingerprint, &c.alias);
+                changed = true;
+            }
+        }
+        if changed {
+            self.persist_petnames();
+        }
+    }
+
     fn ensure_thread(&mut self, fingerprint: &str) {
         if !self
             .threads
@@ -1795,6 +4550,7 @@ impl App {
                 contact_fingerprint: fingerprint.to_string(),
                 messages: Vec::new(),
                 unread_count: 0,
+                timer: DisappearingTimer::new(),
             });
         }
     }
@@ -1808,16 +4564,290 @@ impl App {
             thread.unread_count = 0;
             self.persist_threads();
         }
+        self.send_receipts(fingerprint, ReceiptKind::Read);
+    }
+
+    /// Acknowledge verified messages from `fingerprint` that have not had a
+    /// `kind` receipt yet. With read receipts turned off, reading only
+    /// confirms delivery.
+    fn send_receipts(&mut self, fingerprint: &str, kind: ReceiptKind) {
+        let kind = if kind == ReceiptKind::Read && !self.receipt_preferences.send_read_receipts {
+            ReceiptKind::Delivered
+        } else {
+            kind
+        };
+        let Some(kp) = self.keypair.clone() else {
+            return;
+        };
+        let Some(thread) = self.threads.iter().find(|t| t.contact_fingerprint == fingerprint) else {
+            return;
+        };
+        let ids: Vec<String> = thread
+            .messages
+            .iter()
+            .filter(|m| m.incoming && m.verified_sender && m.acknowledged.is_none_or(|sent| sent < kind))
+            .map(|m| m.id.clone())
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        let peer_enc_public = self
+            .contacts
+            .iter()
+            .find(|c| c.fingerprint == fingerprint)
+            .and_then(|c| c.known_encryption_public_key.clone());
+        let signed = CoreMessage::new_receipt(&kp, fingerprint, kind, &ids, peer_enc_public.as_deref())
+            .and_then(|receipt| SignedMessage::create(receipt, &kp));
+        match signed {
+            Ok(signed) => {
+                self.publish_outgoing_message_to_swarm(&signed);
+                if let Some(thread) = self.threads.iter_mut().find(|t| t.contact_fingerprint == fingerprint) {
+                    for item in &mut thread.messages {
+                        if ids.contains(&item.id) {
+                            item.acknowledged = Some(kind);
+                        }
+                    }
+                }
+                self.persist_threads();
+            }
+            Err(e) => {
+                self.status_line = format!("Receipt failed: {e}");
+            }
+        }
+    }
+
+    /// Decrypted text of one of our own messages in the selected chat.
+    fn chat_item_plaintext(&self, message_id: &str) -> Result<String, String> {
+        let fp = self.forms.selected_contact_for_chat.as_ref().ok_or("Select a contact first")?;
+        let item = self
+            .threads
+            .iter()
+            .find(|t| &t.contact_fingerprint == fp)
+            .and_then(|t| t.messages.iter().find(|m| m.id == message_id && !m.incoming))
+            .ok_or("Only your own messages can be changed")?;
+        let peer_enc_public = self
+            .contacts
+            .iter()
+            .find(|c| &c.fingerprint == fp)
+            .and_then(|c| c.known_encryption_public_key.as_deref());
+        decrypt_for_display(item, self.keypair.as_ref(), peer_enc_public)
+    }
+
+    /// Replace the text of one of our own messages in the selected chat and
+    /// push the signed edit in place of any older one.
+    fn edit_chat_message(&mut self, message_id: &str, content: &str) -> Result<(), String> {
+        let mut kp = self.keypair.clone().ok_or("No keypair available")?;
+        kp.ensure_encryption_keys();
+        let fp = self.forms.selected_contact_for_chat.clone().ok_or("Select a contact first")?;
+        let peer_enc_public = self
+            .contacts
+            .iter()
+            .find(|c| c.fingerprint == fp)
+            .and_then(|c| c.known_encryption_public_key.clone())
+            .ok_or("Recipient encryption key missing: run sync first")?;
+        let item = self
+            .threads
+            .iter_mut()
+            .find(|t| t.contact_fingerprint == fp)
+            .and_then(|t| t.messages.iter_mut().find(|m| m.id == message_id && !m.incoming))
+            .ok_or("Only your own messages can be edited")?;
+
+        let mut edit = CoreMessage::new_edit(&kp, &fp, &peer_enc_public, message_id, content)?;
+        edit.expires_at = item.expires_at;
+        let signed = SignedMessage::create(edit, &kp)?;
+        item.content = signed.message.content.clone();
+        item.encrypted = signed.message.encrypted;
+        item.encryption_alg = signed.message.body_enc.clone();
+        item.nonce_b64 = signed.message.nonce_b64.clone();
+        item.edited = true;
+        item.last_edit = Some((signed.message.created_at, signed.message.id.clone()));
+
+        let mut indexed = signed.message.clone();
+        indexed.id = message_id.to_string();
+        self.index_for_search(|index| index.index_message(&indexed, content));
+        self.persist_threads();
+        self.publish_control(&signed, &kp);
+        Ok(())
+    }
+
+    /// Retract one of our own messages in the selected chat, locally and
+    /// from the recipient's inbox.
+    fn unsend_chat_message(&mut self, message_id: &str) -> Result<(), String> {
+        let kp = self.keypair.clone().ok_or("No keypair available")?;
+        let fp = self.forms.selected_contact_for_chat.clone().ok_or("Select a contact first")?;
+        let thread = self
+            .threads
+            .iter_mut()
+            .find(|t| t.contact_fingerprint == fp)
+            .ok_or("No chat with this contact")?;
+        let position = thread
+            .messages
+            .iter()
+            .position(|m| m.id == message_id && !m.incoming)
+            .ok_or("Only your own messages can be unsent")?;
+
+        let signed = SignedMessage::create(CoreMessage::new_unsend(&kp, &fp, message_id), &kp)?;
+        thread.messages.remove(position);
+        if self.forms.editing_message_id.as_deref() == Some(message_id) {
+            self.forms.editing_message_id = None;
+            self.forms.compose_message_input.clear();
+        }
+        let conversation = direct_conversation_id(&kp.fingerprint, &fp);
+        self.index_for_search(|index| index.remove(SearchKind::Message, &conversation, message_id));
+        self.persist_threads();
+        self.publish_control(&signed, &kp);
+        Ok(())
+    }
+
+    /// State our disappearing-message preference for the selected chat.
+    /// Returns the timer now in effect.
+    fn set_chat_timer(&mut self, choice: TimerChoice) -> Result<Option<ChronoDuration>, String> {
+        let kp = self.keypair.clone().ok_or("No keypair available")?;
+        let fp = self.forms.selected_contact_for_chat.clone().ok_or("Select a contact first")?;
+        let signed = SignedMessage::create(CoreMessage::new_timer(&kp, &fp, choice.ttl_secs())?, &kp)?;
+        self.ensure_thread(&fp);
+        let thread = self
+            .threads
+            .iter_mut()
+            .find(|t| t.contact_fingerprint == fp)
+            .ok_or("No chat with this contact")?;
+        thread.timer.apply(&signed.message);
+        let effective = thread.timer.effective();
+        self.persist_threads();
+        self.publish_outgoing_message_to_swarm(&signed);
+        Ok(effective)
+    }
+
+    /// Push an edit or unsend to the recipient's inbox and drop whatever it
+    /// retracts from there.
+    fn publish_control(&mut self, control: &SignedMessage, keypair: &KeyPair) {
+        let recipient = control.message.recipient_fingerprint.clone();
+        self.deliver_to_inbox(&recipient, |inbox| {
+            if !inbox.messages.iter().any(|m| m.message.id == control.message.id) {
+                inbox.messages.push(control.clone());
+            }
+            apply_retractions(&mut inbox.messages, &keypair.fingerprint, &keypair.public_key);
+        });
     }
 
     fn mark_selected_thread_read(&mut self) {
         if let Some(fp) = self.forms.selected_contact_for_chat.clone() {
             self.mark_thread_read(&fp);
         }
+        if let Some(group_id) = self.forms.selected_group.clone() {
+            self.mark_group_read(&group_id);
+        }
+    }
+
+    fn mark_group_read(&mut self, group_id: &str) {
+        if let Some(thread) = self.group_threads.iter_mut().find(|t| t.group_id == group_id) {
+            thread.unread_count = 0;
+            self.persist_group_threads();
+        }
     }
 
     fn total_unread_count(&self) -> u32 {
-        self.threads.iter().map(|t| t.unread_count).sum()
+        let direct: u32 = self.threads.iter().map(|t| t.unread_count).sum();
+        let group: u32 = self.group_threads.iter().map(|t| t.unread_count).sum();
+        direct + group
+    }
+
+    fn persist_groups(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_GROUPS, &self.groups) {
+            self.status_line = format!("Persist groups failed: {e}");
+        }
+    }
+
+    fn persist_group_threads(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_GROUP_THREADS, &self.group_threads) {
+            self.status_line = format!("Persist group threads failed: {e}");
+        }
+    }
+
+    /// Apply our own change to the selected group and deliver the version
+    /// chain to everyone who was or now is a member, so removed members
+    /// learn they are out and new ones can verify from the start.
+    fn change_group(
+        &mut self,
+        change: impl FnOnce(&SignedGroup, &KeyPair) -> Result<SignedGroup, String>,
+    ) -> Result<SignedGroup, String> {
+        let kp = self.keypair.clone().ok_or("No keypair available")?;
+        let group_id = self.forms.selected_group.clone().ok_or("Select a group first")?;
+        let current = self.groups.get(&group_id).ok_or("Unknown group")?.clone();
+        let update = change(&current, &kp)?;
+        self.groups.apply(update.clone())?;
+        self.persist_groups();
+
+        let chain = self.groups.chain(&group_id);
+        let mut recipients: Vec<String> = current
+            .group
+            .members
+            .iter()
+            .chain(&update.group.members)
+            .map(|m| m.fingerprint.clone())
+            .filter(|fp| fp != &kp.fingerprint)
+            .collect();
+        recipients.sort();
+        recipients.dedup();
+        for fp in recipients {
+            self.deliver_to_inbox(&fp, |inbox| inbox.group_updates.extend(chain.iter().cloned()));
+        }
+        Ok(update)
+    }
+
+    /// Encrypt the composed message under our sender key and fan it out to
+    /// the other members' inboxes, with the key itself when it is new.
+    /// Returns how many inboxes it went to.
+    fn send_group_message(&mut self) -> Result<usize, String> {
+        let kp = self.keypair.clone().ok_or("No keypair available")?;
+        let group_id = self.forms.selected_group.clone().ok_or("Select a group first")?;
+        let content = self.forms.compose_group_input.trim().to_string();
+        if content.is_empty() {
+            return Err("Message is empty".to_string());
+        }
+        let (sender_key, fresh) = self.groups.ensure_sender_key(&group_id, &kp)?;
+        let group = self.groups.get(&group_id).ok_or("Unknown group")?.group.clone();
+        let recipients: Vec<String> = group
+            .others(&kp.fingerprint)
+            .into_iter()
+            .map(|m| m.fingerprint.clone())
+            .collect();
+        let distribution = if fresh && !recipients.is_empty() {
+            Some(sender_key.distribute(&group, &kp)?)
+        } else {
+            None
+        };
+        let signed = SignedMessage::create(CoreMessage::new_group(&sender_key, &content)?, &kp)?;
+        if fresh {
+            self.persist_groups();
+        }
+
+        for fp in &recipients {
+            self.deliver_to_inbox(fp, |inbox| {
+                inbox.sender_keys.extend(distribution.clone());
+                inbox.messages.push(signed.clone());
+            });
+        }
+        match self.group_threads.iter_mut().find(|t| t.group_id == group_id) {
+            Some(thread) => thread.messages.push(signed.clone()),
+            None => self.group_threads.push(GroupThread {
+                group_id,
+                messages: vec![signed.clone()],
+                unread_count: 0,
+            }),
+        }
+        self.persist_group_threads();
+        self.index_for_search(|index| index.index_message(&signed.message, &content));
+        Ok(recipients.len())
+    }
+
+    fn deliver_to_inbox(&mut self, recipient: &str, add: impl FnOnce(&mut SwarmInboxBlob)) {
+        let mut inbox = self.transport.load_inbox(recipient).unwrap_or_default();
+        add(&mut inbox);
+        inbox.updated_at = unix_secs();
+        if let Err(e) = self.transport.save_inbox(recipient, &inbox) {
+            self.status_line = format!("Inbox delivery failed: {e}");
+        }
     }
 
     fn publish_local_profile_to_swarm(&mut self) {
@@ -1832,10 +4862,68 @@ impl App {
         }
     }
 
+    /// Re-sign the local profile with a fresh expiry when it is about to lapse,
+    /// so peers keep serving it while this client is in use.
+    fn renew_local_profile_if_expiring(&mut self) {
+        let (Some(kp), Some(sp)) = (&self.keypair, &self.profile) else {
+            return;
+        };
+        let window = ChronoDuration::days(PROFILE_RENEWAL_WINDOW_DAYS);
+        if sp.profile.expires_at.is_some() && !sp.profile.needs_renewal(window) {
+            return;
+        }
+
+        let mut profile = sp.profile.clone();
+        profile.renew_expiry(ChronoDuration::days(PROFILE_TTL_DAYS));
+        profile.magnet_uri = None;
+        match SignedProfile::create(profile, kp) {
+            Ok(mut signed) => {
+                signed.profile.magnet_uri = Some(signed.profile.generate_magnet_uri());
+                if let Err(e) = self.storage.set_json(STORAGE_PROFILE, &signed) {
+                    self.status_line = format!("Profile renewal persist failed: {e}");
+                }
+                self.profile = Some(signed);
+            }
+            Err(e) => {
+                self.status_line = format!("Profile renewal failed: {e}");
+            }
+        }
+    }
+
+    fn publish_heartbeat_if_due(&mut self) {
+        if !self.network.bittorrent_running {
+            return;
+        }
+        let now = unix_secs();
+        if now.saturating_sub(self.network.last_heartbeat_published) < HEARTBEAT_INTERVAL_SECS as u64 {
+            return;
+        }
+        let (Some(kp), Some(sp)) = (&self.keypair, &self.profile) else {
+            return;
+        };
+
+        let heartbeat = Heartbeat::new(sp.profile.fingerprint.clone(), sp.profile.version);
+        let result = SignedHeartbeat::create(heartbeat, kp).and_then(|signed| {
+            let blob = SwarmHeartbeatBlob {
+                heartbeat: signed,
+                updated_at: now,
+            };
+            self.transport.save_heartbeat(&sp.profile.fingerprint, &blob)
+        });
+        match result {
+            Ok(()) => self.network.last_heartbeat_published = now,
+            Err(e) => self.status_line = format!("Heartbeat publish failed: {e}"),
+        }
+    }
+
     fn publish_local_posts_to_swarm(&mut self) {
         if let Some(profile) = &self.profile {
             let blob = SwarmPostsBlob {
                 posts: self.local_posts.clone(),
+                revisions: self.local_revisions.clone(),
+                reactions: self.local_reactions.clone(),
+                poll_votes: self.poll_votes.cast_by(&profile.profile.fingerprint),
+                poll_tallies: self.poll_tallies.clone(),
                 updated_at: unix_secs(),
             };
             if let Err(e) = self.transport.save_posts(&profile.profile.fingerprint, &blob) {
@@ -1845,22 +4933,88 @@ impl App {
     }
 
     fn publish_one_post_to_swarm(&mut self, signed_post: &SignedPost) {
+        self.outbox.enqueue(OutboxPayload::Post { post: signed_post.clone() }, Utc::now());
+        self.flush_outbox();
+    }
+
+    /// Push what the outbox has due. Anything no peer takes stays queued and
+    /// is retried with backoff, or at once when a new peer shows up.
+    fn flush_outbox(&mut self) {
+        let now = Utc::now();
+        let pruned = self.outbox.prune(now);
+        let due = self.outbox.due(now);
+        for payload in &due {
+            let result = match payload {
+                OutboxPayload::Message { message } => self.push_message_to_inbox(message),
+                OutboxPayload::Post { post } => self.push_post_to_author(post),
+            };
+            if let Err(e) = &result {
+                self.status_line = format!("Push failed, will retry: {e}");
+            }
+            self.outbox.record_push(payload.id(), result, now);
+        }
+        if pruned > 0 || !due.is_empty() {
+            self.persist_outbox();
+        }
+    }
+
+    fn push_post_to_author(&mut self, signed_post: &SignedPost) -> Result<usize, String> {
         let fp = signed_post.post.author_fingerprint.clone();
         let mut blob = self.transport.load_posts(&fp).unwrap_or_default();
-
         if !blob.posts.iter().any(|p| p.post.id == signed_post.post.id) {
             blob.posts.insert(0, signed_post.clone());
-            blob.updated_at = unix_secs();
-            if let Err(e) = self.transport.save_posts(&fp, &blob) {
-                self.status_line = format!("Post publish failed: {e}");
-            }
+        }
+        let mut revisions = self.local_revisions.clone();
+        match &self.keypair {
+            Some(kp) => revisions.merge_verified(blob.revisions, &kp.public_key),
+            None => revisions.merge(blob.revisions),
+        }
+        blob.revisions = revisions;
+        let mut reactions = self.local_reactions.clone();
+        match &self.keypair {
+            Some(kp) => reactions.merge_verified(blob.reactions, &kp.public_key),
+            None => reactions.fill_from(blob.reactions),
+        }
+        blob.reactions = reactions;
+        blob.updated_at = unix_secs();
+        self.transport.save_posts(&fp, &blob)
+    }
+
+    fn persist_outbox(&mut self) {
+        if let Err(e) = self.storage.set_json(STORAGE_OUTBOX, &self.outbox) {
+            self.status_line = format!("Persist outbox failed: {e}");
+        }
+    }
+
+    /// Push a vote to the poll author's inbox, replacing any earlier vote
+    /// we sent for the same poll.
+    fn deliver_vote_to_author(&mut self, vote: &SignedPollVote) {
+        let author = vote.vote.poll_author_fingerprint.clone();
+        if self.keypair.as_ref().is_some_and(|kp| kp.fingerprint == author) {
+            return;
+        }
+        let mut inbox = self.transport.load_inbox(&author).unwrap_or_default();
+        inbox.poll_votes.retain(|v| {
+            v.vote.poll_id != vote.vote.poll_id || v.vote.voter_fingerprint != vote.vote.voter_fingerprint
+        });
+        inbox.poll_votes.push(vote.clone());
+        inbox.updated_at = unix_secs();
+        if let Err(e) = self.transport.save_inbox(&author, &inbox) {
+            self.status_line = format!("Vote delivery failed: {e}");
         }
     }
 
     fn publish_outgoing_message_to_swarm(&mut self, signed_message: &SignedMessage) {
+        self.outbox
+            .enqueue(OutboxPayload::Message { message: signed_message.clone() }, Utc::now());
+        self.flush_outbox();
+    }
+
+    fn push_message_to_inbox(&mut self, signed_message: &SignedMessage) -> Result<usize, String> {
         let recipient = signed_message.message.recipient_fingerprint.clone();
         let mut inbox = self.transport.load_inbox(&recipient).unwrap_or_default();
-
+        // The recipient may have taken it already on an earlier attempt.
+        self.outbox.acknowledge_inbox(&recipient, &inbox.acks);
         if !inbox
             .messages
             .iter()
@@ -1868,10 +5022,8 @@ impl App {
         {
             inbox.messages.push(signed_message.clone());
             inbox.updated_at = unix_secs();
-            if let Err(e) = self.transport.save_inbox(&recipient, &inbox) {
-                self.status_line = format!("Push publish failed: {e}");
-            }
         }
+        self.transport.save_inbox(&recipient, &inbox)
     }
 
     fn recalculate_network(&mut self) {
@@ -1925,11 +5077,18 @@ impl App {
     }
 }
 
-async fn load_startup_async() -> StartupData {
-    let storage = FileStorage::open_default()
-        .or_else(|_| FileStorage::new(std::env::temp_dir().join("snartnet")))
-        .expect("storage unavailable");
+/// The search index for `storage`: beside the data files, or in memory when
+/// they are encrypted so no indexed plaintext is written to disk.
+fn open_search_index(storage: &MaybeEncrypted<FileStorage>) -> Option<SearchIndex> {
+    let index = if storage.is_encrypted() {
+        SearchIndex::open_in_memory()
+    } else {
+        SearchIndex::open(storage.inner().dir().join("search.db"))
+    };
+    index.map_err(|e| eprintln!("Warning: search index unavailable: {e}")).ok()
+}
 
+fn load_startup(storage: &impl StorageBackend) -> StartupData {
     let keypair = storage.get_json(STORAGE_KEYPAIR).ok().flatten();
     let profile = storage.get_json(STORAGE_PROFILE).ok().flatten();
     let local_posts = storage
@@ -1937,6 +5096,16 @@ async fn load_startup_async() -> StartupData {
         .ok()
         .flatten()
         .unwrap_or_default();
+    let local_revisions = storage
+        .get_json(STORAGE_POST_REVISIONS)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let local_reactions = storage
+        .get_json(STORAGE_REACTIONS)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
     let contacts = storage
         .get_json(STORAGE_CONTACTS)
         .ok()
@@ -1947,13 +5116,81 @@ async fn load_startup_async() -> StartupData {
         .ok()
         .flatten()
         .unwrap_or_default();
+    let petnames = storage
+        .get_json(STORAGE_PETNAMES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let circles = storage
+        .get_json(STORAGE_CIRCLES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let feed = storage
+        .get_json(STORAGE_FEED)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let content_preferences = storage
+        .get_json(STORAGE_CONTENT_PREFERENCES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let poll_votes = storage
+        .get_json(STORAGE_POLL_VOTES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let poll_tallies = storage
+        .get_json(STORAGE_POLL_TALLIES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let receipt_preferences = storage
+        .get_json(STORAGE_RECEIPT_PREFERENCES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let groups = storage
+        .get_json(STORAGE_GROUPS)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let group_threads = storage
+        .get_json(STORAGE_GROUP_THREADS)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let ingest = storage
+        .get_json(STORAGE_SEEN_MESSAGES)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let outbox = storage
+        .get_json(STORAGE_OUTBOX)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
 
     StartupData {
         keypair,
         profile,
         local_posts,
+        local_revisions,
+        local_reactions,
         contacts,
         threads,
+        petnames,
+        circles,
+        feed,
+        content_preferences,
+        poll_votes,
+        poll_tallies,
+        receipt_preferences,
+        groups,
+        group_threads,
+        ingest,
+        outbox,
     }
 }
 
@@ -1995,6 +5232,7 @@ async fn create_profile_async(
         .map(|v| blake3::hash(v.as_bytes()).to_hex().to_string());
     profile.avatar_data_url = avatar_data_url;
     profile.encryption_public_key = kp.enc_public_key.clone();
+    profile.expires_at = Some(Utc::now() + ChronoDuration::days(PROFILE_TTL_DAYS));
     // magnet_uri is derived after signing and must not be in signed bytes.
     profile.magnet_uri = None;
 
@@ -2030,20 +5268,128 @@ async fn add_contact_async(fingerprint: String, alias: String) -> Result<Contact
         known_public_key: None,
         known_encryption_public_key: None,
         last_sync_error: None,
+        last_seen_at: None,
+        profile_expires_at: None,
     })
 }
 
+/// Everything the composer contributes to a new post.
+#[derive(Debug, Clone, Default)]
+struct PostDraft {
+    content: String,
+    reply_to: Option<String>,
+    /// Original being reposted (empty `content`) or quoted.
+    embedded: Option<EmbeddedPost>,
+    attachments: Vec<String>,
+    /// Recipients when restricted to a circle; `None` for a public post.
+    audience: Option<Vec<Recipient>>,
+    /// Lifetime of an ephemeral post; `None` keeps it until deleted.
+    expires_in: Option<ChronoDuration>,
+    content_warning: Option<ContentWarning>,
+    poll: Option<Poll>,
+}
+
 async fn create_post_async(
     author_fingerprint: String,
-    content: String,
+    draft: PostDraft,
+    petnames: PetnameBook,
     keypair: Option<KeyPair>,
 ) -> Result<SignedPost, String> {
+    let kp = keypair.ok_or("No keypair available")?;
+    let PostDraft {
+        content,
+        reply_to,
+        embedded,
+        attachments,
+        audience,
+        expires_in,
+        content_warning,
+        poll,
+    } = draft;
+    let mut post = match embedded {
+        Some(original) => {
+            if !original.verify()? {
+                return Err("Original post failed verification".to_string());
+            }
+            if original.original.post.is_restricted() {
+                return Err("Circle posts cannot be shared".to_string());
+            }
+            if content.trim().is_empty() {
+                Post::repost(author_fingerprint, original)
+            } else {
+                Post::quote(author_fingerprint, content, None, original)
+            }
+        }
+        None => {
+            if content.trim().is_empty() && attachments.is_empty() {
+                return Err("Post cannot be empty".to_string());
+            }
+            Post::new(author_fingerprint, content, None, reply_to)
+        }
+    };
+    for hash in attachments {
+        post.add_attachment(hash);
+    }
+    post.resolve_mentions(&petnames);
+    if let Some(ttl) = expires_in {
+        post.expire_after(ttl);
+    }
+    if let Some(warning) = content_warning {
+        post.set_content_warning(warning);
+    }
+    if let Some(poll) = poll {
+        post.set_poll(poll)?;
+    }
+    if let Some(recipients) = audience {
+        post.seal_for(&recipients)?;
+    }
+    SignedPost::create(post, &kp)
+}
+
+async fn fetch_attachment_async(
+    transport: TcpSwarmTransport,
+    manifest_hash: String,
+) -> Result<(String, FetchProgress), String> {
+    let progress = fetch_attachment(transport.blob_store(), &transport, &manifest_hash)?;
+    Ok((manifest_hash, progress))
+}
+
+async fn edit_post_async(
+    original: SignedPost,
+    content: String,
+    keypair: Option<KeyPair>,
+) -> Result<SignedPostEdit, String> {
     let kp = keypair.ok_or("No keypair available")?;
     if content.trim().is_empty() {
         return Err("Post cannot be empty".to_string());
     }
-    let post = Post::new(author_fingerprint, content, None, None);
-    SignedPost::create(post, &kp)
+    let edit = PostEdit::new(&original.post, content, None);
+    SignedPostEdit::create(edit, &original.post, &kp)
+}
+
+async fn delete_post_async(
+    original: SignedPost,
+    keypair: Option<KeyPair>,
+) -> Result<SignedPostDelete, String> {
+    let kp = keypair.ok_or("No keypair available")?;
+    SignedPostDelete::create(PostDelete::new(&original.post), &kp)
+}
+
+async fn toggle_like_async(
+    post_id: String,
+    existing: Option<Reaction>,
+    keypair: KeyPair,
+) -> Result<SignedReaction, String> {
+    let reaction = match existing {
+        Some(active) => active.retraction(),
+        None => Reaction::like(keypair.fingerprint.clone(), ReactionTarget::Post, post_id),
+    };
+    SignedReaction::create(reaction, &keypair)
+}
+
+async fn cast_vote_async(poll_post: Post, choices: Vec<usize>, keypair: KeyPair) -> Result<SignedPollVote, String> {
+    let vote = PollVote::new(&keypair, &poll_post, &choices)?;
+    SignedPollVote::create(vote, &keypair)
 }
 
 async fn create_message_async(
@@ -2052,6 +5398,8 @@ async fn create_message_async(
     content: String,
     keypair: Option<KeyPair>,
     recipient_encryption_public_key: String,
+    ttl: Option<ChronoDuration>,
+    mut clock: LamportClock,
 ) -> Result<SignedMessage, String> {
     let mut kp = keypair.ok_or("No keypair available")?;
     kp.ensure_encryption_keys();
@@ -2059,16 +5407,53 @@ async fn create_message_async(
         return Err("Message cannot be empty".to_string());
     }
 
-    let (ciphertext_b64, nonce_b64, alg) =
-        kp.encrypt_for_recipient(&recipient_encryption_public_key, &content)?;
-
-    let mut msg = CoreMessage::new_direct(sender_fingerprint, recipient_fingerprint, ciphertext_b64);
-    msg.encrypted = true;
-    msg.body_enc = Some(alg);
-    msg.nonce_b64 = Some(nonce_b64);
+    let mut msg = CoreMessage::new_direct(sender_fingerprint, recipient_fingerprint, content);
+    msg.seal_for_recipient(&kp, &recipient_encryption_public_key)?;
+    clock.stamp(&mut msg);
+    if let Some(ttl) = ttl {
+        msg.expire_after(ttl);
+    }
     SignedMessage::create(msg, &kp)
 }
 
+/// Apply a verified edit, unsend or timer from the thread's contact.
+/// Returns whether the thread changed.
+fn apply_chat_control(thread: &mut ChatThread, control: &CoreMessage) -> bool {
+    match &control.message_type {
+        MessageType::Edit { target_id } => {
+            let Some(item) = thread.messages.iter_mut().find(|m| m.incoming && &m.id == target_id) else {
+                return false;
+            };
+            let edit = (control.created_at, control.id.clone());
+            if item.last_edit.as_ref().is_some_and(|applied| *applied >= edit) {
+                return false;
+            }
+            item.content = control.content.clone();
+            item.encrypted = control.encrypted;
+            item.encryption_alg = control.body_enc.clone();
+            item.nonce_b64 = control.nonce_b64.clone();
+            item.edited = true;
+            item.last_edit = Some(edit);
+            true
+        }
+        MessageType::Unsend { target_id } => {
+            let before = thread.messages.len();
+            thread.messages.retain(|m| !(m.incoming && &m.id == target_id));
+            thread.messages.len() != before
+        }
+        MessageType::Timer { .. } => thread.timer.apply(control),
+        _ => false,
+    }
+}
+
+fn format_ttl(ttl: ChronoDuration) -> String {
+    match TimerChoice::ALL.into_iter().find(|c| c.ttl_secs() == Some(ttl.num_seconds() as u64)) {
+        Some(choice) => choice.to_string(),
+        None if ttl.num_hours() > 0 => format!("{} hours", ttl.num_hours()),
+        None => format!("{} minutes", ttl.num_minutes().max(1)),
+    }
+}
+
 fn default_trust() -> u8 {
     20
 }
@@ -2120,10 +5505,21 @@ fn decrypt_for_display(
     kp.decrypt_from_peer(peer_key, nonce, &item.content)
 }
 
+/// Cheap change detector so unchanged syncs don't rewrite the feed file.
+fn feed_size(feed: &Feed) -> (usize, usize, usize) {
+    let revisions = feed.revisions();
+    (feed.len(), revisions.edits.len(), revisions.deletes.len())
+}
+
 fn ts_label() -> String {
     format!("t={}", unix_secs())
 }
 
+/// Label for a message's signed send time, in the same form as `ts_label`.
+fn sent_label(sent_at: DateTime<Utc>) -> String {
+    format!("t={}", sent_at.timestamp())
+}
+
 fn unix_secs() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -2274,6 +5670,8 @@ async fn import_invite_async(code: String) -> Result<Contact, String> {
         known_public_key: None,
         known_encryption_public_key: None,
         last_sync_error: None,
+        last_seen_at: None,
+        profile_expires_at: None,
     })
 }
 
@@ -2295,6 +5693,8 @@ async fn import_magnet_async(uri: String) -> Result<Contact, String> {
         known_public_key: None,
         known_encryption_public_key: None,
         last_sync_error: None,
+        last_seen_at: None,
+        profile_expires_at: None,
     })
 }
 
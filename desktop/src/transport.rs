@@ -1,5 +1,12 @@
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use snartnet_core::{SignedMessage, SignedPost, SignedProfile};
+use snartnet_core::{
+    content_hash, fingerprint_from_public_key, purge_expired, AttachmentManifest, BlobStore,
+    ChunkSource, Expiring, FileBlobStore, InboxAckSet, PostRevisions, ReactionSet, SignedGroup,
+    SignedHeartbeat, SignedInboxAck, SignedMessage, SignedPollTally, SignedPollVote, SignedPost,
+    SignedProfile, SignedSenderKeyDistribution, MESSAGE_MAX_AGE_DAYS,
+};
 use std::io::{Read, Write};
 use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
@@ -13,15 +20,46 @@ pub struct SwarmProfileBlob {
     pub updated_at: u64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwarmHeartbeatBlob {
+    pub heartbeat: SignedHeartbeat,
+    pub updated_at: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SwarmPostsBlob {
     pub posts: Vec<SignedPost>,
+    /// Author-signed edits and tombstones for `posts`.
+    #[serde(default)]
+    pub revisions: PostRevisions,
+    /// The author's own reactions to other posts and messages.
+    #[serde(default)]
+    pub reactions: ReactionSet,
+    /// The author's own votes, published so tallies can be checked.
+    #[serde(default)]
+    pub poll_votes: Vec<SignedPollVote>,
+    /// Signed tallies of the author's polls.
+    #[serde(default)]
+    pub poll_tallies: Vec<SignedPollTally>,
     pub updated_at: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SwarmInboxBlob {
     pub messages: Vec<SignedMessage>,
+    /// Votes in the inbox owner's polls.
+    #[serde(default)]
+    pub poll_votes: Vec<SignedPollVote>,
+    /// Membership changes of groups the inbox owner belongs to.
+    #[serde(default)]
+    pub group_updates: Vec<SignedGroup>,
+    /// Group members' sender keys, sealed for the inbox owner.
+    #[serde(default)]
+    pub sender_keys: Vec<SignedSenderKeyDistribution>,
+    /// The inbox owner's acknowledgements; messages they cover are deleted
+    /// and not taken back.
+    #[serde(default)]
+    pub acks: Vec<SignedInboxAck>,
     pub updated_at: u64,
 }
 
@@ -29,11 +67,16 @@ pub trait NetworkTransport {
     fn load_profile(&self, fingerprint: &str) -> Option<SwarmProfileBlob>;
     fn save_profile(&self, fingerprint: &str, blob: &SwarmProfileBlob) -> Result<(), String>;
 
+    fn load_heartbeat(&self, fingerprint: &str) -> Option<SwarmHeartbeatBlob>;
+    fn save_heartbeat(&self, fingerprint: &str, blob: &SwarmHeartbeatBlob) -> Result<(), String>;
+
     fn load_posts(&self, fingerprint: &str) -> Option<SwarmPostsBlob>;
-    fn save_posts(&self, fingerprint: &str, blob: &SwarmPostsBlob) -> Result<(), String>;
+    /// Saves locally, then returns how many peers took the blob.
+    fn save_posts(&self, fingerprint: &str, blob: &SwarmPostsBlob) -> Result<usize, String>;
 
     fn load_inbox(&self, recipient_fingerprint: &str) -> Option<SwarmInboxBlob>;
-    fn save_inbox(&self, recipient_fingerprint: &str, blob: &SwarmInboxBlob) -> Result<(), String>;
+    /// Saves locally, then returns how many peers took the blob.
+    fn save_inbox(&self, recipient_fingerprint: &str, blob: &SwarmInboxBlob) -> Result<usize, String>;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,10 +84,14 @@ pub trait NetworkTransport {
 enum TransportRequest {
     GetProfile { fingerprint: String },
     PutProfile { fingerprint: String, blob: SwarmProfileBlob },
+    GetHeartbeat { fingerprint: String },
+    PutHeartbeat { fingerprint: String, blob: SwarmHeartbeatBlob },
     GetPosts { fingerprint: String },
     PutPosts { fingerprint: String, blob: SwarmPostsBlob },
     GetInbox { recipient_fingerprint: String },
     PutInbox { recipient_fingerprint: String, blob: SwarmInboxBlob },
+    GetManifest { hash: String },
+    GetChunk { hash: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,8 +99,12 @@ enum TransportRequest {
 enum TransportResponse {
     Ok,
     Profile { blob: Option<SwarmProfileBlob> },
+    Heartbeat { blob: Option<SwarmHeartbeatBlob> },
     Posts { blob: Option<SwarmPostsBlob> },
     Inbox { blob: Option<SwarmInboxBlob> },
+    Manifest { manifest: Option<AttachmentManifest> },
+    /// Chunk bytes, base64 encoded.
+    Chunk { data: Option<String> },
     Err { message: String },
 }
 
@@ -65,6 +116,7 @@ pub struct TcpSwarmTransport {
 #[derive(Debug)]
 struct Inner {
     swarm_dir: PathBuf,
+    blobs: FileBlobStore,
     bind_addr: SocketAddr,
     base_peers: Vec<SocketAddr>,
     peers: Mutex<Vec<SocketAddr>>,
@@ -87,10 +139,12 @@ impl TcpSwarmTransport {
             .unwrap_or_default();
 
         let swarm_dir = swarm_root_dir()?;
+        let blobs = FileBlobStore::new(swarm_dir.join("blobs"))?;
 
         Ok(Self {
             inner: Arc::new(Inner {
                 swarm_dir,
+                blobs,
                 bind_addr,
                 base_peers: peers,
                 peers: Mutex::new(Vec::new()),
@@ -98,6 +152,11 @@ impl TcpSwarmTransport {
         })
     }
 
+    /// Local attachment chunks and manifests, served to peers on request.
+    pub fn blob_store(&self) -> &FileBlobStore {
+        &self.inner.blobs
+    }
+
     pub fn set_peers(&self, peers: Vec<SocketAddr>) {
         let mut guard = self.inner.peers.lock().unwrap();
         *guard = peers;
@@ -158,6 +217,15 @@ impl TcpSwarmTransport {
                     Err(e) => TransportResponse::Err { message: e },
                 }
             }
+            TransportRequest::GetHeartbeat { fingerprint } => TransportResponse::Heartbeat {
+                blob: self.load_heartbeat_local(&fingerprint),
+            },
+            TransportRequest::PutHeartbeat { fingerprint, blob } => {
+                match self.save_heartbeat_local(&fingerprint, &blob) {
+                    Ok(_) => TransportResponse::Ok,
+                    Err(e) => TransportResponse::Err { message: e },
+                }
+            }
             TransportRequest::GetPosts { fingerprint } => TransportResponse::Posts {
                 blob: self.load_posts_local(&fingerprint),
             },
@@ -175,11 +243,22 @@ impl TcpSwarmTransport {
                 mut blob,
             } => {
                 dedupe_inbox(&mut blob);
+                prune_inbox_votes(&recipient_fingerprint, &mut blob);
                 match self.save_inbox_local(&recipient_fingerprint, &blob) {
                     Ok(_) => TransportResponse::Ok,
                     Err(e) => TransportResponse::Err { message: e },
                 }
             }
+            TransportRequest::GetManifest { hash } => TransportResponse::Manifest {
+                manifest: self.inner.blobs.get_manifest(&hash),
+            },
+            TransportRequest::GetChunk { hash } => TransportResponse::Chunk {
+                data: self
+                    .inner
+                    .blobs
+                    .get_chunk(&hash)
+                    .map(|bytes| general_purpose::STANDARD.encode(bytes)),
+            },
         }
     }
 
@@ -198,10 +277,12 @@ impl TcpSwarmTransport {
         serde_json::from_slice::<TransportResponse>(&out).ok()
     }
 
-    fn fanout_put(&self, req: &TransportRequest) {
-        for peer in self.peer_snapshot() {
-            let _ = self.request_peer(peer, req);
-        }
+    /// Send a put to every peer; returns how many accepted it.
+    fn fanout_put(&self, req: &TransportRequest) -> usize {
+        self.peer_snapshot()
+            .into_iter()
+            .filter(|peer| matches!(self.request_peer(*peer, req), Some(TransportResponse::Ok)))
+            .count()
     }
 
     fn profile_path(&self, fingerprint: &str) -> PathBuf {
@@ -210,6 +291,12 @@ impl TcpSwarmTransport {
             .join(format!("profile_{}.json", sanitize_component(fingerprint)))
     }
 
+    fn heartbeat_path(&self, fingerprint: &str) -> PathBuf {
+        self.inner
+            .swarm_dir
+            .join(format!("heartbeat_{}.json", sanitize_component(fingerprint)))
+    }
+
     fn posts_path(&self, fingerprint: &str) -> PathBuf {
         self.inner
             .swarm_dir
@@ -222,28 +309,151 @@ impl TcpSwarmTransport {
             .join(format!("inbox_{}.json", sanitize_component(recipient_fingerprint)))
     }
 
+    /// Expired profiles are deleted on read so they are never served again.
     fn load_profile_local(&self, fingerprint: &str) -> Option<SwarmProfileBlob> {
-        load_json_file(&self.profile_path(fingerprint)).ok().flatten()
+        let path = self.profile_path(fingerprint);
+        let blob: SwarmProfileBlob = load_json_file(&path).ok().flatten()?;
+        if blob.profile.profile.is_expired() {
+            let _ = std::fs::remove_file(&path);
+            return None;
+        }
+        Some(blob)
     }
 
     fn save_profile_local(&self, fingerprint: &str, blob: &SwarmProfileBlob) -> Result<(), String> {
+        if blob.profile.profile.is_expired() {
+            return Err("refusing to store expired profile".to_string());
+        }
         save_json_file(&self.profile_path(fingerprint), blob)
     }
 
+    fn load_heartbeat_local(&self, fingerprint: &str) -> Option<SwarmHeartbeatBlob> {
+        load_json_file(&self.heartbeat_path(fingerprint)).ok().flatten()
+    }
+
+    /// Whether `blob` is signed by the cached profile of `fingerprint` and
+    /// not dated beyond the allowed clock skew.
+    fn heartbeat_is_valid(&self, fingerprint: &str, blob: &SwarmHeartbeatBlob) -> bool {
+        self.load_profile_local(fingerprint).is_some_and(|blob_profile| {
+            let profile = &blob_profile.profile;
+            profile.profile.fingerprint == fingerprint
+                && fingerprint_from_public_key(&profile.profile.public_key).is_ok_and(|fp| fp == fingerprint)
+                && profile.verify().unwrap_or(false)
+                && blob.heartbeat.verify_for_profile(&profile.profile).unwrap_or(false)
+        })
+    }
+
+    /// Keeps whichever valid heartbeat has the newest signed `issued_at`, so
+    /// a replayed older heartbeat cannot roll back "last seen" and a forged
+    /// or future-dated one cannot hold the slot.
+    fn save_heartbeat_local(&self, fingerprint: &str, blob: &SwarmHeartbeatBlob) -> Result<(), String> {
+        if !self.heartbeat_is_valid(fingerprint, blob) {
+            return Err("heartbeat does not verify against a cached profile".to_string());
+        }
+        if let Some(existing) = self.load_heartbeat_local(fingerprint) {
+            if existing.heartbeat.heartbeat.issued_at >= blob.heartbeat.heartbeat.issued_at
+                && self.heartbeat_is_valid(fingerprint, &existing)
+            {
+                return Ok(());
+            }
+        }
+        save_json_file(&self.heartbeat_path(fingerprint), blob)
+    }
+
+    /// Expired posts are dropped on read so they are never served again.
     fn load_posts_local(&self, fingerprint: &str) -> Option<SwarmPostsBlob> {
-        load_json_file(&self.posts_path(fingerprint)).ok().flatten()
+        let path = self.posts_path(fingerprint);
+        let mut blob: SwarmPostsBlob = load_json_file(&path).ok().flatten()?;
+        if purge_expired_posts(&mut blob) > 0 {
+            let _ = save_json_file(&path, &blob);
+        }
+        Some(blob)
     }
 
+    /// Tombstones and reaction retractions already held locally are merged
+    /// into every write so a stale copy cannot resurrect them. Stored records
+    /// come first, and once the author's profile has been cached incoming
+    /// ones are only taken if the author signed them.
     fn save_posts_local(&self, fingerprint: &str, blob: &SwarmPostsBlob) -> Result<(), String> {
-        save_json_file(&self.posts_path(fingerprint), blob)
+        let mut blob = blob.clone();
+        let public_key = self
+            .load_profile_local(fingerprint)
+            .map(|profile| profile.profile.profile.public_key);
+        if let Some(existing) = self.load_posts_local(fingerprint) {
+            let mut revisions = existing.revisions;
+            let mut reactions = existing.reactions;
+            match &public_key {
+                Some(public_key) => {
+                    revisions.merge_verified(blob.revisions, public_key);
+                    reactions.merge_verified(blob.reactions, public_key);
+                }
+                None => {
+                    revisions.merge(blob.revisions);
+                    reactions.fill_from(blob.reactions);
+                }
+            }
+            blob.revisions = revisions;
+            blob.reactions = reactions;
+        }
+        if let Some(public_key) = &public_key {
+            blob.revisions.prune(&mut blob.posts, public_key);
+            blob.reactions.retain_signed_by(public_key);
+        }
+        purge_expired_posts(&mut blob);
+        save_json_file(&self.posts_path(fingerprint), &blob)
     }
 
     fn load_inbox_local(&self, recipient_fingerprint: &str) -> Option<SwarmInboxBlob> {
-        load_json_file(&self.inbox_path(recipient_fingerprint)).ok().flatten()
+        let path = self.inbox_path(recipient_fingerprint);
+        let mut blob: SwarmInboxBlob = load_json_file(&path).ok().flatten()?;
+        let pruned = prune_acknowledged(recipient_fingerprint, &mut blob);
+        if purge_expired(&mut blob.messages, Utc::now()) + pruned > 0 {
+            let _ = save_json_file(&path, &blob);
+        }
+        Some(blob)
     }
 
     fn save_inbox_local(&self, recipient_fingerprint: &str, blob: &SwarmInboxBlob) -> Result<(), String> {
-        save_json_file(&self.inbox_path(recipient_fingerprint), blob)
+        let mut blob = blob.clone();
+        prune_acknowledged(recipient_fingerprint, &mut blob);
+        purge_expired(&mut blob.messages, Utc::now());
+        save_json_file(&self.inbox_path(recipient_fingerprint), &blob)
+    }
+}
+
+/// Asks peers in turn. Each peer's answer is checked against the requested
+/// hash before it is accepted, so one peer serving bad data does not stop the
+/// others being asked.
+impl ChunkSource for TcpSwarmTransport {
+    fn fetch_manifest(&self, hash: &str) -> Option<AttachmentManifest> {
+        let req = TransportRequest::GetManifest {
+            hash: hash.to_string(),
+        };
+        self.peer_snapshot().into_iter().find_map(|peer| {
+            match self.request_peer(peer, &req) {
+                Some(TransportResponse::Manifest { manifest: Some(manifest) })
+                    if manifest.hash().is_ok_and(|h| h == hash) && manifest.validate().is_ok() =>
+                {
+                    Some(manifest)
+                }
+                _ => None,
+            }
+        })
+    }
+
+    fn fetch_chunk(&self, hash: &str) -> Option<Vec<u8>> {
+        let req = TransportRequest::GetChunk {
+            hash: hash.to_string(),
+        };
+        self.peer_snapshot().into_iter().find_map(|peer| {
+            match self.request_peer(peer, &req) {
+                Some(TransportResponse::Chunk { data: Some(data) }) => general_purpose::STANDARD
+                    .decode(data)
+                    .ok()
+                    .filter(|bytes| content_hash(bytes) == hash),
+                _ => None,
+            }
+        })
     }
 }
 
@@ -275,6 +485,38 @@ impl NetworkTransport for TcpSwarmTransport {
         Ok(())
     }
 
+    fn load_heartbeat(&self, fingerprint: &str) -> Option<SwarmHeartbeatBlob> {
+        let mut best = self
+            .load_heartbeat_local(fingerprint)
+            .filter(|blob| self.heartbeat_is_valid(fingerprint, blob));
+
+        for peer in self.peer_snapshot() {
+            let req = TransportRequest::GetHeartbeat {
+                fingerprint: fingerprint.to_string(),
+            };
+            if let Some(TransportResponse::Heartbeat { blob: Some(blob) }) = self.request_peer(peer, &req) {
+                let newer = best
+                    .as_ref()
+                    .map(|b| blob.heartbeat.heartbeat.issued_at > b.heartbeat.heartbeat.issued_at)
+                    .unwrap_or(true);
+                if newer && self.save_heartbeat_local(fingerprint, &blob).is_ok() {
+                    best = Some(blob);
+                }
+            }
+        }
+        best
+    }
+
+    fn save_heartbeat(&self, fingerprint: &str, blob: &SwarmHeartbeatBlob) -> Result<(), String> {
+        self.save_heartbeat_local(fingerprint, blob)?;
+        let req = TransportRequest::PutHeartbeat {
+            fingerprint: fingerprint.to_string(),
+            blob: blob.clone(),
+        };
+        self.fanout_put(&req);
+        Ok(())
+    }
+
     fn load_posts(&self, fingerprint: &str) -> Option<SwarmPostsBlob> {
         if let Some(v) = self.load_posts_local(fingerprint) {
             return Some(v);
@@ -284,7 +526,8 @@ impl NetworkTransport for TcpSwarmTransport {
             let req = TransportRequest::GetPosts {
                 fingerprint: fingerprint.to_string(),
             };
-            if let Some(TransportResponse::Posts { blob: Some(blob) }) = self.request_peer(peer, &req) {
+            if let Some(TransportResponse::Posts { blob: Some(mut blob) }) = self.request_peer(peer, &req) {
+                purge_expired_posts(&mut blob);
                 let _ = self.save_posts_local(fingerprint, &blob);
                 return Some(blob);
             }
@@ -292,14 +535,13 @@ impl NetworkTransport for TcpSwarmTransport {
         None
     }
 
-    fn save_posts(&self, fingerprint: &str, blob: &SwarmPostsBlob) -> Result<(), String> {
+    fn save_posts(&self, fingerprint: &str, blob: &SwarmPostsBlob) -> Result<usize, String> {
         self.save_posts_local(fingerprint, blob)?;
         let req = TransportRequest::PutPosts {
             fingerprint: fingerprint.to_string(),
             blob: blob.clone(),
         };
-        self.fanout_put(&req);
-        Ok(())
+        Ok(self.fanout_put(&req))
     }
 
     fn load_inbox(&self, recipient_fingerprint: &str) -> Option<SwarmInboxBlob> {
@@ -311,10 +553,17 @@ impl NetworkTransport for TcpSwarmTransport {
                 recipient_fingerprint: recipient_fingerprint.to_string(),
             };
             if let Some(TransportResponse::Inbox { blob: Some(mut remote) }) = self.request_peer(peer, &req) {
-                let before = local.messages.len();
+                let before = inbox_counts(&local);
                 local.messages.append(&mut remote.messages);
+                local.poll_votes.append(&mut remote.poll_votes);
+                local.group_updates.append(&mut remote.group_updates);
+                local.sender_keys.append(&mut remote.sender_keys);
+                local.acks.append(&mut remote.acks);
                 dedupe_inbox(&mut local);
-                if local.messages.len() != before {
+                prune_inbox_votes(recipient_fingerprint, &mut local);
+                prune_acknowledged(recipient_fingerprint, &mut local);
+                purge_expired(&mut local.messages, Utc::now());
+                if inbox_counts(&local) != before {
                     changed = true;
                 }
             }
@@ -327,7 +576,7 @@ impl NetworkTransport for TcpSwarmTransport {
         Some(local)
     }
 
-    fn save_inbox(&self, recipient_fingerprint: &str, blob: &SwarmInboxBlob) -> Result<(), String> {
+    fn save_inbox(&self, recipient_fingerprint: &str, blob: &SwarmInboxBlob) -> Result<usize, String> {
         let mut cloned = blob.clone();
         dedupe_inbox(&mut cloned);
 
@@ -336,8 +585,7 @@ impl NetworkTransport for TcpSwarmTransport {
             recipient_fingerprint: recipient_fingerprint.to_string(),
             blob: cloned,
         };
-        self.fanout_put(&req);
-        Ok(())
+        Ok(self.fanout_put(&req))
     }
 }
 
@@ -379,9 +627,80 @@ fn save_json_file<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
     std::fs::write(path, text).map_err(|e| format!("write failed: {e}"))
 }
 
+/// Drop expired posts along with their edits, which would otherwise keep
+/// the content around. Returns how many posts were removed.
+fn purge_expired_posts(blob: &mut SwarmPostsBlob) -> usize {
+    let now = Utc::now();
+    // Edits can arrive before their post, so only those of posts seen
+    // expiring are dropped.
+    let expired: std::collections::HashSet<String> = blob
+        .posts
+        .iter()
+        .filter(|p| p.is_expired_at(now))
+        .map(|p| p.post.id.clone())
+        .collect();
+    let removed = purge_expired(&mut blob.posts, now);
+    if removed > 0 {
+        blob.revisions.edits.retain(|e| !expired.contains(&e.edit.post_id));
+    }
+    removed
+}
+
 pub fn dedupe_inbox(inbox: &mut SwarmInboxBlob) {
     let mut seen = std::collections::HashSet::new();
     inbox.messages.retain(|m| seen.insert(m.message.id.clone()));
+    let mut seen_votes = std::collections::HashSet::new();
+    inbox.poll_votes.retain(|v| seen_votes.insert(v.vote.id.clone()));
+    let mut seen_groups = std::collections::HashSet::new();
+    inbox
+        .group_updates
+        .retain(|g| seen_groups.insert((g.group.id.clone(), g.group.epoch)));
+    let mut seen_keys = std::collections::HashSet::new();
+    inbox.sender_keys.retain(|k| {
+        let d = &k.distribution;
+        seen_keys.insert((d.group_id.clone(), d.epoch, d.sender_fingerprint.clone()))
+    });
+}
+
+/// Keep only verified votes in the inbox owner's polls, the newest per
+/// voter and poll, and none older than messages are kept for.
+fn prune_inbox_votes(recipient_fingerprint: &str, inbox: &mut SwarmInboxBlob) {
+    let now = Utc::now();
+    let cutoff = now - chrono::Duration::days(MESSAGE_MAX_AGE_DAYS);
+    let latest = now + chrono::Duration::minutes(5);
+    inbox.poll_votes.retain(|v| {
+        v.vote.poll_author_fingerprint == recipient_fingerprint
+            && v.vote.created_at > cutoff
+            && v.vote.created_at <= latest
+            && v.verify().unwrap_or(false)
+    });
+    inbox
+        .poll_votes
+        .sort_by(|a, b| (&b.vote.created_at, &b.vote.id).cmp(&(&a.vote.created_at, &a.vote.id)));
+    let mut slots = std::collections::HashSet::new();
+    inbox
+        .poll_votes
+        .retain(|v| slots.insert((v.vote.poll_id.clone(), v.vote.voter_fingerprint.clone())));
+}
+
+/// Drop everything the inbox owner has acknowledged, along with acks that
+/// are not theirs or are superseded. Returns how many entries were removed.
+fn prune_acknowledged(recipient_fingerprint: &str, inbox: &mut SwarmInboxBlob) -> usize {
+    let acked = InboxAckSet::compact(recipient_fingerprint, &mut inbox.acks);
+    acked.prune(&mut inbox.messages)
+        + acked.prune(&mut inbox.poll_votes)
+        + acked.prune(&mut inbox.group_updates)
+        + acked.prune(&mut inbox.sender_keys)
+}
+
+fn inbox_counts(inbox: &SwarmInboxBlob) -> (usize, usize, usize, usize, usize) {
+    (
+        inbox.messages.len(),
+        inbox.poll_votes.len(),
+        inbox.group_updates.len(),
+        inbox.sender_keys.len(),
+        inbox.acks.len(),
+    )
 }
 
 // ---------------------------------------------------------------------------